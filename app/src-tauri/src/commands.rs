@@ -1,31 +1,324 @@
-use crate::image_cache::ImageCache;
-use reader_core::fs::{archive as fs_archive, folder as fs_folder};
-use reader_core::stats::{PerfSnapshot, StatsCollector};
-use reader_core::store::progress as progress_store;
-use reader_core::types::{PageId as CorePageId, SourceId as CoreSourceId};
+use crate::error::{self, CommandError, CommandResult};
+use crate::image_cache::{CachedImage, ImageCache};
+use crate::protocol::AccessToken;
+use reader_core::cache::key::params_hash;
+use reader_core::capabilities::Capabilities;
+use reader_core::codec::DecodedImage;
+use reader_core::codec::encode::{EncodeFormat, encode};
+use reader_core::codec::sandbox::{self, DecodeSandbox};
+use reader_core::error::CoreError;
+use reader_core::fs::PageByteSource;
+use reader_core::fs::nonblocking as nonblocking_fs;
+use reader_core::fs::registry::{self as source_registry, SourceRegistry};
+#[cfg(feature = "opds")]
+use reader_core::fs::remote::opds::{Feed, FeedEntry, OpdsClient};
+use reader_core::fs::remote::{CachedSource, trim_copies};
+use reader_core::keymap::Keymap;
+use reader_core::log::{Diagnostics, RequestId};
+use reader_core::meta::describe::{Describers, PageDescription};
+use reader_core::pipeline::bench::{self as core_bench, BenchConfig, BenchReport};
+use reader_core::pipeline::fit;
+use reader_core::pipeline::pool::{CancelFlag, PrefetchJob, WorkerPool};
+use reader_core::pipeline::render::{render_thumbnail, scale_to_display};
+use reader_core::pipeline::resize::ResizeFilter;
+use reader_core::pipeline::spread::compose_spread;
+use reader_core::stats::{
+    self as core_stats, DecodeLabelStats, DecodeLabels, PerfSnapshot, StatsCollector,
+};
+use reader_core::store::annotations::{Annotation, AnnotationRect, AnnotationStore};
+use reader_core::store::backup::{self as backup_store, BackupInfo, BackupReason};
+use reader_core::store::coalesce::PeriodicFlush;
+use reader_core::store::collections::{Collection, CollectionStore};
+use reader_core::store::crypto::{self as crypto_store, EncryptionStatus, KeySource, StoreKey};
+use reader_core::store::events::{EventBus, StoreEvent};
+use reader_core::store::export::{self as export_store, ExportFormat};
+use reader_core::store::history::{self as history_store, HistoryStore};
+use reader_core::store::keymap::KeymapStore;
+use reader_core::store::library::{LibraryEntry, LibraryStore, ReadingStatus, SeriesStatus};
+use reader_core::store::profile::{self as profile_store, ProfileName};
+use reader_core::store::progress::ProgressStore;
+use reader_core::store::recent::RecentStore;
+use reader_core::store::recovery::{self as recovery_store, RecoveryAction};
+use reader_core::store::settings::{self as pipeline_settings, PipelineSettings};
+pub use reader_core::types::{
+    ActionId, InputGesture, PageId, PageLayout, PageMeta, PrefetchPolicy, ReadingDirection,
+    RenderParams, SourceId,
+};
+use reader_core::types::{FitMode, ImageDimensions, ImageKey, ImageVariant};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
 use tauri::State;
 
 pub struct AppState {
     cache: Arc<ImageCache>,
     metrics: Arc<StatsCollector>,
+    stores: Stores,
+    prefetcher: Arc<WorkerPool>,
+    pipeline_events: std::sync::mpsc::Sender<WindowPipelineEvent>,
+    settings: Mutex<PipelineSettings>,
+    assets: AccessToken,
+    /// Providers `open_path` opens sources through.
+    registry: Arc<SourceRegistry>,
+    /// Set while the library cover thumbnails are being generated.
+    library_thumbs_running: Arc<AtomicBool>,
+    /// Describers `get_page_description` runs over decoded pages.
+    describers: Arc<Describers>,
     inner: Mutex<InnerState>,
 }
 
+/// Persistent stores backing the commands, all rooted at the same state directory.
+pub struct Stores {
+    pub progress: Arc<ProgressStore>,
+    pub recent: Arc<RecentStore>,
+    pub history: Arc<HistoryStore>,
+    pub annotations: Arc<AnnotationStore>,
+    pub library: Arc<LibraryStore>,
+    pub collections: Arc<CollectionStore>,
+    pub keymap: Arc<KeymapStore>,
+    pub events: EventBus,
+    /// Saves the pages shown by the navigation commands as progress.
+    autosave: Autosave,
+    /// Writes buffered progress in the background; stops with a final flush when dropped.
+    _progress_flush: PeriodicFlush,
+}
+
+/// How often buffered progress saves are written to disk.
+const PROGRESS_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+impl Stores {
+    pub fn open(root: &std::path::Path) -> reader_core::Result<Self> {
+        let events = EventBus::new();
+        let progress = Arc::new(ProgressStore::new(root)?.with_events(events.clone()));
+        let progress_flush = progress.spawn_flusher(PROGRESS_FLUSH_INTERVAL)?;
+        let library = Arc::new(LibraryStore::new(root)?.with_events(events.clone()));
+        Ok(Self {
+            autosave: Autosave::spawn(Arc::clone(&progress), Arc::clone(&library))?,
+            progress,
+            _progress_flush: progress_flush,
+            recent: Arc::new(RecentStore::new(root)?),
+            history: Arc::new(HistoryStore::new(root)?),
+            annotations: Arc::new(AnnotationStore::new(root)?.with_events(events.clone())),
+            library,
+            collections: Arc::new(CollectionStore::new(root)?.with_events(events.clone())),
+            keymap: Arc::new(KeymapStore::new(root)?.with_events(events.clone())),
+            events,
+        })
+    }
+
+    /// Write any buffered changes; called before the app exits.
+    pub fn flush(&self) {
+        self.autosave.save_all();
+        match self.progress.flush() {
+            Ok(written) => {
+                tracing::debug!(target: "commands::stores", written, "flushed progress");
+            }
+            Err(err) => {
+                tracing::warn!(target: "commands::stores", "failed to flush progress: {err:#}")
+            }
+        }
+    }
+
+    /// Make `profile` the active one. Pages shown under the current profile are saved to it
+    /// first; the stores resolve the active profile on every write, so saving them later would
+    /// put them in the new one.
+    pub fn switch_profile(&self, profile: &ProfileName) -> reader_core::Result<()> {
+        self.flush();
+        profile_store::switch(self.progress.root(), profile)
+    }
+}
+
+/// How often pages shown since the last autosave are recorded as progress.
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Records the page last shown per source as its progress, so the reading position survives a
+/// renderer crash even if the UI never called `save_progress`. Pages are noted by
+/// `get_page_url` and `get_spread_url`, and saved on an interval, when their source is closed
+/// and before exit.
+struct Autosave {
+    shown: Arc<ShownPages>,
+    _flush: PeriodicFlush,
+}
+
+struct ShownPages {
+    /// Unsaved page per source id.
+    pages: Mutex<HashMap<String, ShownPage>>,
+    progress: Arc<ProgressStore>,
+    library: Arc<LibraryStore>,
+}
+
+#[derive(Clone, Debug)]
+struct ShownPage {
+    page: u32,
+    page_count: u32,
+    location: Option<std::path::PathBuf>,
+}
+
+impl ShownPage {
+    fn of(source: &SourceData, page: u32) -> Self {
+        Self {
+            page,
+            page_count: source.pages.len() as u32,
+            location: source.kind.path().map(std::path::Path::to_path_buf),
+        }
+    }
+}
+
+impl Autosave {
+    fn spawn(progress: Arc<ProgressStore>, library: Arc<LibraryStore>) -> std::io::Result<Self> {
+        let shown = Arc::new(ShownPages { pages: Mutex::default(), progress, library });
+        let flush = PeriodicFlush::spawn("autosave", AUTOSAVE_INTERVAL, {
+            let shown = Arc::clone(&shown);
+            move || {
+                shown.save_all();
+            }
+        })?;
+        Ok(Self { shown, _flush: flush })
+    }
+
+    /// Remember `page` as the one now shown for `source_id`.
+    fn note(&self, source_id: &SourceId, page: ShownPage) {
+        self.shown.lock().insert(source_id.as_str().to_string(), page);
+    }
+
+    /// Drop the unsaved page of `source_id`; its progress was just saved explicitly.
+    fn discard(&self, source_id: &SourceId) {
+        self.shown.lock().remove(source_id.as_str());
+    }
+
+    /// Save the unsaved page of `source_id`, which is being closed.
+    fn save_source(&self, source_id: &SourceId) {
+        let page = self.shown.lock().remove(source_id.as_str());
+        if let Some(page) = page {
+            self.shown.save(source_id.as_str().to_string(), page);
+        }
+    }
+
+    fn save_all(&self) -> usize {
+        self.shown.save_all()
+    }
+}
+
+impl ShownPages {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, ShownPage>> {
+        self.pages.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Save every unsaved page; returns how many were saved.
+    fn save_all(&self) -> usize {
+        let pages = std::mem::take(&mut *self.lock());
+        let count = pages.len();
+        for (source_id, page) in pages {
+            self.save(source_id, page);
+        }
+        if count > 0 {
+            tracing::debug!(target: "commands::autosave", count, "autosaved progress");
+        }
+        count
+    }
+
+    fn save(&self, source_id: String, shown: ShownPage) {
+        if let Some(location) = &shown.location
+            && let Err(err) = self.library.record_position(location, shown.page, shown.page_count)
+        {
+            tracing::warn!(target: "commands::autosave", "failed to update library status: {err:#}");
+        }
+        let page = PageId { source_id: SourceId::new(source_id), index: shown.page };
+        if let Err(err) = self.progress.save(&page) {
+            tracing::warn!(target: "commands::autosave", "failed to autosave progress: {err:#}");
+        }
+    }
+}
+
 #[derive(Default)]
 struct InnerState {
-    next_source_id: u64,
+    next_window_id: u64,
     sources: HashMap<String, SourceData>,
-    pending_prefetch: HashSet<String>,
+    windows: HashMap<String, WindowState>,
+    /// Stops the stats pusher of a window when dropped; see `start_stats_push`.
+    stats_push: HashMap<String, std::sync::mpsc::Sender<()>>,
+}
+
+impl InnerState {
+    /// Point window `label` at `source_id`; returns the source it was reading before.
+    fn show_source(&mut self, label: &str, source_id: &SourceId) -> Option<SourceId> {
+        let replaced =
+            self.windows.insert(label.to_string(), WindowState::reading(source_id.clone()));
+        replaced.and_then(|window| window.source_id)
+    }
+}
+
+/// What one reader window, keyed by its label, is reading.
+#[derive(Clone, Debug, Default)]
+struct WindowState {
+    source_id: Option<SourceId>,
+    /// Last saved position in `source_id`.
+    cursor: Option<ReadingCursor>,
+    /// Last prefetch center, to notice when navigation reverses.
+    prefetch: Option<PrefetchCursor>,
+}
+
+impl WindowState {
+    fn reading(source_id: SourceId) -> Self {
+        Self { source_id: Some(source_id), ..Self::default() }
+    }
+
+    /// Move the prefetch center to `center`. Returns the direction given up when this turns
+    /// around the way the window was paging.
+    fn turn_prefetch(&mut self, center: &PageId) -> Option<PageDirection> {
+        let previous =
+            self.prefetch.take().filter(|cursor| cursor.source_id == center.source_id.as_str());
+        let moved = previous.as_ref().and_then(|cursor| match center.index.cmp(&cursor.index) {
+            std::cmp::Ordering::Greater => Some(PageDirection::Forward),
+            std::cmp::Ordering::Less => Some(PageDirection::Backward),
+            std::cmp::Ordering::Equal => None,
+        });
+        let was = previous.and_then(|cursor| cursor.direction);
+        self.prefetch = Some(PrefetchCursor {
+            source_id: center.source_id.as_str().to_string(),
+            index: center.index,
+            direction: moved.or(was),
+        });
+        moved.zip(was).filter(|(now, was)| now != was).map(|(_, was)| was)
+    }
+}
+
+/// Page a window last prefetched around and which way it got there.
+#[derive(Clone, Debug)]
+struct PrefetchCursor {
+    source_id: String,
+    index: u32,
+    direction: Option<PageDirection>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PageDirection {
+    Forward,
+    Backward,
 }
 
+/// Last saved position of a window, used to derive pages read and time spent.
+#[derive(Clone, Copy, Debug)]
+struct ReadingCursor {
+    page: u32,
+    at: Instant,
+}
+
+/// Forward jumps larger than this (e.g. via the thumbnail strip) are not counted as reading.
+const MAX_PAGES_PER_STEP: u32 = 2;
+/// Gaps between page turns longer than this are treated as idle time.
+const MAX_READING_GAP: Duration = Duration::from_secs(5 * 60);
+
 #[derive(Clone, Debug)]
 enum SourceKind {
-    Folder { root: std::path::PathBuf },
-    Archive { path: std::path::PathBuf },
-    SingleFile { path: std::path::PathBuf },
+    /// Opened through the source registry. `path` is set for sources on the local disk.
+    Provided {
+        path: Option<std::path::PathBuf>,
+        source: Arc<dyn PageByteSource>,
+    },
     Mock,
 }
 
@@ -36,15 +329,48 @@ struct SourceData {
 }
 
 impl AppState {
-    pub fn new(cache: Arc<ImageCache>, metrics: Arc<StatsCollector>) -> Self {
-        Self { cache, metrics, inner: Mutex::new(InnerState::default()) }
+    pub fn new(
+        cache: Arc<ImageCache>,
+        metrics: Arc<StatsCollector>,
+        stores: Stores,
+        prefetcher: WorkerPool,
+        pipeline_events: std::sync::mpsc::Sender<WindowPipelineEvent>,
+        settings: PipelineSettings,
+        assets: AccessToken,
+    ) -> Self {
+        Self {
+            cache,
+            metrics,
+            stores,
+            prefetcher: Arc::new(prefetcher),
+            pipeline_events,
+            settings: Mutex::new(settings),
+            assets,
+            registry: Arc::new(SourceRegistry::with_builtin()),
+            library_thumbs_running: Arc::new(AtomicBool::new(false)),
+            describers: Arc::new(Describers::with_builtin()),
+            inner: Mutex::new(InnerState::default()),
+        }
     }
 
-    fn with_lock<F, T>(&self, f: F) -> Result<T, String>
+    pub fn stores(&self) -> &Stores {
+        &self.stores
+    }
+
+    /// Write buffered progress, then stop the prefetch workers and flush the core; called once
+    /// as the app exits. Cache entries are written as they are rendered, and archives are only
+    /// open while a page is read, so neither needs closing here.
+    pub fn shutdown(&self) {
+        self.stores.flush();
+        reader_core::shutdown(&[&self.prefetcher]);
+    }
+
+    fn with_lock<F, T>(&self, f: F) -> CommandResult<T>
     where
-        F: FnOnce(&mut InnerState) -> Result<T, String>,
+        F: FnOnce(&mut InnerState) -> CommandResult<T>,
     {
-        let mut guard = self.inner.lock().map_err(|_| "internal state poisoned".to_string())?;
+        let mut guard =
+            self.inner.lock().map_err(|_| CommandError::internal("internal state poisoned"))?;
         f(&mut guard)
     }
 
@@ -55,61 +381,117 @@ impl AppState {
     fn stats(&self) -> Arc<StatsCollector> {
         Arc::clone(&self.metrics)
     }
+
+    fn settings(&self) -> PipelineSettings {
+        *self.settings.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// `params` (or the defaults) with the configured filter, refused if the pipeline could not
+    /// render them.
+    fn render_params(&self, params: Option<&RenderParams>) -> CommandResult<RenderParams> {
+        let params = params.copied().unwrap_or_default();
+        let params = RenderParams { filter: self.settings().resize_filter, ..params };
+        Ok(params.validated().map_err(CoreError::from)?)
+    }
+
+    /// Queue `event`, caused by a request from `window`, for the relay; dropped silently once
+    /// the app is shutting down.
+    fn notify(&self, window: &str, event: PipelineEvent) {
+        let _ = self.pipeline_events.send(event.from_window(window));
+    }
+
+    /// Drop the reading state of a closed window, saving the page it was showing.
+    fn forget_window(&self, label: &str) {
+        let closed = match self.inner.lock() {
+            Ok(mut inner) => {
+                inner.stats_push.remove(label);
+                inner.windows.remove(label).and_then(|window| window.source_id)
+            }
+            Err(_) => None,
+        };
+        if let Some(source_id) = closed {
+            self.stores.autosave.save_source(&source_id);
+        }
+    }
+
+    fn progress(&self) -> &ProgressStore {
+        &self.stores.progress
+    }
+
+    fn recent(&self) -> &RecentStore {
+        &self.stores.recent
+    }
+
+    fn history(&self) -> &HistoryStore {
+        &self.stores.history
+    }
+
+    fn annotations(&self) -> &AnnotationStore {
+        &self.stores.annotations
+    }
+
+    fn library(&self) -> &LibraryStore {
+        &self.stores.library
+    }
+
+    fn collections(&self) -> &CollectionStore {
+        &self.stores.collections
+    }
+
+    fn keymap(&self) -> &KeymapStore {
+        &self.stores.keymap
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(transparent)]
-pub struct SourceId(pub String);
+impl SourceKind {
+    /// Best-effort series label: the directory containing the folder or archive.
+    fn series_hint(&self) -> Option<String> {
+        self.path().and_then(series_of)
+    }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct PageId {
-    pub source_id: SourceId,
-    pub index: u32,
+    /// Location on disk, used to key library entries.
+    fn path(&self) -> Option<&std::path::Path> {
+        match self {
+            SourceKind::Provided { path, .. } => path.as_deref(),
+            SourceKind::Mock => None,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct PageMeta {
-    pub id: PageId,
-    pub rel_path: String,
-    pub width: u32,
-    pub height: u32,
-    pub is_double_spread: bool,
+fn series_of(path: &std::path::Path) -> Option<String> {
+    path.parent()
+        .and_then(|parent| parent.file_name())
+        .map(|name| name.to_string_lossy().to_string())
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Where the thumbnail of `page` is served, and whether it is already generated.
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub enum FitMode {
-    FitWidth,
-    FitHeight,
-    FitContain,
-    Original,
-    Fill,
+pub struct ThumbUrl {
+    pub page: PageId,
+    pub url: String,
+    pub ready: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Where the cover of a library source is served, and whether it is already generated.
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct RenderParams {
-    pub fit: FitMode,
-    pub viewport_w: u32,
-    pub viewport_h: u32,
-    pub scale: f32,
-    pub rotation: i16,
-    pub dpi: f32,
+pub struct LibraryThumb {
+    pub path: String,
+    /// `None` when the source no longer exists.
+    pub url: Option<String>,
+    pub ready: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A window of a source's pages; see `list_pages_range`.
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct PrefetchPolicy {
-    pub ahead: u32,
-    pub behind: u32,
+pub struct PageRange {
+    pub total: u32,
+    pub offset: u32,
+    pub pages: Vec<PageMeta>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(transparent)]
-pub struct RequestToken(pub String);
-
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PerfStats {
@@ -119,363 +501,2221 @@ pub struct PerfStats {
     pub cached_pages: usize,
 }
 
-fn mock_pages(source_id: &SourceId, path: &str) -> Vec<PageMeta> {
-    let base_name =
-        std::path::Path::new(path).file_name().and_then(|os| os.to_str()).unwrap_or("demo");
-
-    (0..5)
-        .map(|idx| PageMeta {
-            id: PageId { source_id: source_id.clone(), index: idx },
-            rel_path: format!("{base_name}/page_{idx:03}.png"),
-            width: 1600,
-            height: 2400,
-            is_double_spread: idx % 3 == 2,
-        })
-        .collect()
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileList {
+    pub active: String,
+    pub profiles: Vec<String>,
 }
 
-fn format_image_key(source: &SourceId, index: u32) -> String {
-    format!("{}-page-{index}", source.0)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentSource {
+    pub path: String,
+    pub pinned: bool,
+    pub opened_ms: u64,
+    pub exists: bool,
 }
 
-const MIME_PNG: &str = "image/png";
-const PLACEHOLDER_BYTES: &[u8] = include_bytes!("../assets/placeholder.png");
-
-fn is_supported_image(path: &std::path::Path) -> bool {
-    reader_core::fs::is_supported_image(path)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyReading {
+    pub day: u32,
+    pub date: String,
+    pub pages: u64,
+    pub seconds: u64,
 }
 
-fn is_supported_archive(path: &std::path::Path) -> bool {
-    match path.extension().and_then(|e| e.to_str()).map(|s| s.to_ascii_lowercase()) {
-        Some(ext) if ext == "zip" || ext == "cbz" => true,
-        _ => false,
-    }
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeriesReading {
+    pub series: String,
+    pub pages: u64,
+    pub seconds: u64,
 }
 
-fn guess_mime(path: &std::path::Path) -> &str {
-    match path.extension().and_then(|e| e.to_str()).map(|s| s.to_ascii_lowercase()) {
-        Some(ext) if ext == "jpg" || ext == "jpeg" => "image/jpeg",
-        Some(ext) if ext == "png" => "image/png",
-        Some(ext) if ext == "webp" => "image/webp",
-        Some(ext) if ext == "avif" => "image/avif",
-        Some(ext) if ext == "gif" => "image/gif",
-        Some(ext) if ext == "bmp" => "image/bmp",
-        _ => "application/octet-stream",
-    }
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadingStats {
+    pub daily: Vec<DailyReading>,
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    pub lifetime_pages: u64,
+    pub lifetime_seconds: u64,
+    pub series: Vec<SeriesReading>,
 }
 
-#[tauri::command]
-pub fn open_path(path: String, state: State<AppState>) -> Result<SourceId, String> {
-    use std::path::Path;
+/// Store change pushed to every window as a [`STORE_CHANGED_EVENT`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum StoreChange {
+    ProgressUpdated { page: PageId },
+    AnnotationAdded { annotation: Annotation },
+    AnnotationEdited { annotation: Annotation },
+    AnnotationDeleted { id: u64 },
+    LibraryChanged { source: LibraryEntry },
+    CollectionChanged { collection: Collection },
+    CollectionDeleted { id: u64 },
+    KeymapChanged { bindings: Vec<KeyBinding> },
+}
 
-    // Demo shortcut preserved for UI preview
-    if path == "demo-bundle" {
-        return state.with_lock(|inner| {
-            inner.next_source_id += 1;
-            let id = SourceId(format!("src-{}", inner.next_source_id));
-            let pages = mock_pages(&id, &path);
-            inner.sources.insert(id.0.clone(), SourceData { kind: SourceKind::Mock, pages });
-            Ok(id)
-        });
+impl From<StoreEvent> for StoreChange {
+    fn from(event: StoreEvent) -> Self {
+        match event {
+            StoreEvent::ProgressUpdated { page } => StoreChange::ProgressUpdated { page },
+            StoreEvent::AnnotationAdded(annotation) => StoreChange::AnnotationAdded { annotation },
+            StoreEvent::AnnotationEdited(annotation) => {
+                StoreChange::AnnotationEdited { annotation }
+            }
+            StoreEvent::AnnotationDeleted { id } => StoreChange::AnnotationDeleted { id },
+            StoreEvent::LibraryChanged(entry) => StoreChange::LibraryChanged { source: entry },
+            StoreEvent::CollectionChanged(collection) => {
+                StoreChange::CollectionChanged { collection }
+            }
+            StoreEvent::CollectionDeleted { id } => StoreChange::CollectionDeleted { id },
+            StoreEvent::KeymapChanged(keymap) => {
+                StoreChange::KeymapChanged { bindings: key_bindings(&keymap) }
+            }
+        }
     }
+}
 
-    let path_ref = Path::new(&path);
-    let source_result = if path_ref.is_dir() {
-        let id = state.with_lock(|inner| {
-            inner.next_source_id += 1;
-            Ok(SourceId(format!("src-{}", inner.next_source_id)))
-        })?;
-
-        let pages = fs_folder::list_folder_pages(path_ref, &CoreSourceId::new(id.0.clone()))
-            .map_err(|e| e.to_string())?
-            .into_iter()
-            .map(|m| PageMeta {
-                id: PageId { source_id: id.clone(), index: m.id.index },
-                rel_path: m.rel_path.to_string_lossy().to_string(),
-                width: m.width,
-                height: m.height,
-                is_double_spread: m.is_double_spread,
-            })
-            .collect::<Vec<_>>();
-
-        state.with_lock(|inner| {
-            inner.sources.insert(
-                id.0.clone(),
-                SourceData {
-                    kind: SourceKind::Folder { root: path_ref.to_path_buf() },
-                    pages: pages.clone(),
-                },
-            );
-            Ok(id)
-        })
-    } else if path_ref.is_file() && is_supported_archive(path_ref) {
-        let id = state.with_lock(|inner| {
-            inner.next_source_id += 1;
-            Ok(SourceId(format!("src-{}", inner.next_source_id)))
-        })?;
-
-        let pages = fs_archive::list_archive_pages(path_ref, &CoreSourceId::new(id.0.clone()))
-            .map_err(|e| e.to_string())?
-            .into_iter()
-            .map(|m| PageMeta {
-                id: PageId { source_id: id.clone(), index: m.id.index },
-                rel_path: m.rel_path.to_string_lossy().to_string(),
-                width: m.width,
-                height: m.height,
-                is_double_spread: m.is_double_spread,
-            })
-            .collect::<Vec<_>>();
-
-        state.with_lock(|inner| {
-            inner.sources.insert(
-                id.0.clone(),
-                SourceData {
-                    kind: SourceKind::Archive { path: path_ref.to_path_buf() },
-                    pages: pages.clone(),
-                },
-            );
-            Ok(id)
-        })
-    } else if path_ref.is_file() && is_supported_image(path_ref) {
-        let id = state.with_lock(|inner| {
-            inner.next_source_id += 1;
-            Ok(SourceId(format!("src-{}", inner.next_source_id)))
-        })?;
-
-        let file_name =
-            path_ref.file_name().and_then(|os| os.to_str()).unwrap_or("image").to_string();
-        let page = PageMeta {
-            id: PageId { source_id: id.clone(), index: 0 },
-            rel_path: file_name,
-            width: 0,
-            height: 0,
-            is_double_spread: false,
-        };
+pub const STORE_CHANGED_EVENT: &str = "store://changed";
 
-        state.with_lock(|inner| {
-            inner.sources.insert(
-                id.0.clone(),
-                SourceData {
-                    kind: SourceKind::SingleFile { path: path_ref.to_path_buf() },
-                    pages: vec![page.clone()],
-                },
-            );
-            Ok(id)
-        })
-    } else {
-        Err("Unsupported path. Select a folder, an image file or a CBZ/ZIP archive.".to_string())
-    }?;
+/// Relay store events to every window until the bus goes away.
+fn forward_store_events<R: tauri::Runtime>(
+    handle: tauri::AppHandle<R>,
+    events: std::sync::mpsc::Receiver<StoreEvent>,
+) {
+    use tauri::Emitter;
 
-    Ok(source_result)
+    let spawned = std::thread::Builder::new().name("store-events".into()).spawn(move || {
+        for event in events {
+            if let Err(err) = handle.emit(STORE_CHANGED_EVENT, StoreChange::from(event)) {
+                tracing::warn!(target: "commands::events", "failed to emit store change: {err}");
+            }
+        }
+    });
+    if let Err(err) = spawned {
+        tracing::error!(target: "commands::events", "failed to start store event relay: {err}");
+    }
 }
 
-#[tauri::command]
-pub fn list_pages(source_id: SourceId, state: State<AppState>) -> Result<Vec<PageMeta>, String> {
-    state.with_lock(|inner| {
-        inner
-            .sources
-            .get(&source_id.0)
-            .map(|src| {
-                tracing::debug!(target: "commands::list_pages", source = %source_id.0, "listed pages");
-                src.pages.clone()
-            })
-            .ok_or_else(|| "unknown source".to_string())
-    })
+/// Background pipeline work the frontend can react to instead of polling.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum PipelineEvent {
+    /// A page was rendered ahead of time and is served from `url`.
+    #[serde(rename_all = "camelCase")]
+    PageReady { page: PageId, url: String },
+    /// A thumbnail was generated and is served from `url`.
+    #[serde(rename_all = "camelCase")]
+    ThumbReady { page: PageId, longest: u32, url: String },
+    /// A prefetched page finished; `pending` tasks are still queued.
+    #[serde(rename_all = "camelCase")]
+    PrefetchProgress { page: PageId, pending: usize, failed: bool },
+    /// The pages of a source were (re)listed.
+    #[serde(rename_all = "camelCase")]
+    SourceChanged { source_id: SourceId, pages: usize },
+    /// A page of an `export_range` was written to `path`, or failed; `done` of `total` pages
+    /// are finished.
+    #[serde(rename_all = "camelCase")]
+    ExportProgress { page: PageId, done: usize, total: usize, path: Option<String> },
+    /// The cover of the library source at `path` was generated and is served from `url`.
+    #[serde(rename_all = "camelCase")]
+    LibraryThumbReady { path: String, url: String },
 }
 
-#[tauri::command]
-pub fn get_page_url(
-    page: PageId,
-    params: RenderParams,
-    state: State<AppState>,
-) -> Result<String, String> {
-    let cache = state.cache();
+pub const PAGE_READY_EVENT: &str = "pipeline://page-ready";
+pub const THUMB_READY_EVENT: &str = "pipeline://thumb-ready";
+pub const PREFETCH_PROGRESS_EVENT: &str = "pipeline://prefetch-progress";
+pub const SOURCE_CHANGED_EVENT: &str = "pipeline://source-changed";
+pub const EXPORT_PROGRESS_EVENT: &str = "pipeline://export-progress";
+pub const LIBRARY_THUMB_READY_EVENT: &str = "pipeline://library-thumb-ready";
+
+/// A [`PipelineEvent`] tagged with the label of the window whose request caused it, so each
+/// window can ignore work it did not ask for.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WindowPipelineEvent {
+    pub window: String,
+    #[serde(flatten)]
+    pub event: PipelineEvent,
+}
 
-    enum FetchTask {
-        Disk(std::path::PathBuf),
-        Archive { archive_path: std::path::PathBuf, inner: String },
-        Mock,
+impl PipelineEvent {
+    fn from_window(self, window: &str) -> WindowPipelineEvent {
+        WindowPipelineEvent { window: window.to_string(), event: self }
     }
 
-    let (key, mime, task) = state.with_lock(|inner| {
-        let src = inner.sources.get(&page.source_id.0).ok_or_else(|| "unknown page".to_string())?;
-        let key = format_image_key(&page.source_id, page.index);
-        tracing::debug!(
-            target: "commands::get_page_url",
-            source = %page.source_id.0,
-            index = page.index,
-            fit = ?params.fit,
-            "resolved page url"
-        );
-        let rel =
-            src.pages.get(page.index as usize).map(|m| m.rel_path.clone()).unwrap_or_default();
-
-        match &src.kind {
-            SourceKind::Folder { root } => {
-                let full = std::path::Path::new(root).join(&rel);
-                let mime = guess_mime(&full).to_string();
-                Ok((key, mime, FetchTask::Disk(full)))
-            }
-            SourceKind::SingleFile { path } => {
-                let mime = guess_mime(path).to_string();
-                Ok((key, mime, FetchTask::Disk(path.clone())))
-            }
-            SourceKind::Archive { path } => {
-                let inside = rel.replace('\\', "/");
-                let mime = guess_mime(std::path::Path::new(&inside)).to_string();
-                Ok((key, mime, FetchTask::Archive { archive_path: path.clone(), inner: inside }))
-            }
-            SourceKind::Mock => Ok((key, MIME_PNG.to_string(), FetchTask::Mock)),
+    fn name(&self) -> &'static str {
+        match self {
+            PipelineEvent::PageReady { .. } => PAGE_READY_EVENT,
+            PipelineEvent::ThumbReady { .. } => THUMB_READY_EVENT,
+            PipelineEvent::PrefetchProgress { .. } => PREFETCH_PROGRESS_EVENT,
+            PipelineEvent::SourceChanged { .. } => SOURCE_CHANGED_EVENT,
+            PipelineEvent::ExportProgress { .. } => EXPORT_PROGRESS_EVENT,
+            PipelineEvent::LibraryThumbReady { .. } => LIBRARY_THUMB_READY_EVENT,
         }
-    })?;
+    }
+}
 
-    cache.ensure_bytes(&key, &mime, || match task {
-        FetchTask::Disk(full) => std::fs::read(&full).map_err(|e| e.to_string()),
-        FetchTask::Archive { archive_path, inner } => {
-            use std::fs::File;
-            use std::io::Read;
-            let file = File::open(&archive_path).map_err(|e| e.to_string())?;
-            let mut zip = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
-            let mut bytes = Vec::new();
-            if let Ok(mut entry) = zip.by_name(&inner) {
-                entry.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
-                return Ok(bytes);
-            }
-            for i in 0..zip.len() {
-                let mut entry = zip.by_index(i).map_err(|e| e.to_string())?;
-                if let Some(enclosed) = entry.enclosed_name() {
-                    let p = enclosed.to_string_lossy().replace('\\', "/");
-                    if p == inner {
-                        entry.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
-                        return Ok(bytes);
-                    }
-                }
+/// Relay pipeline events to every window until the app state goes away.
+fn forward_pipeline_events<R: tauri::Runtime>(
+    handle: tauri::AppHandle<R>,
+    events: std::sync::mpsc::Receiver<WindowPipelineEvent>,
+) {
+    use tauri::Emitter;
+
+    let spawned = std::thread::Builder::new().name("pipeline-events".into()).spawn(move || {
+        for event in events {
+            if let Err(err) = handle.emit(event.event.name(), &event) {
+                tracing::warn!(target: "commands::events", "failed to emit pipeline event: {err}");
             }
-            Err("entry not found in archive".to_string())
         }
-        FetchTask::Mock => Ok(PLACEHOLDER_BYTES.to_vec()),
-    })?;
-
-    Ok(format!("asset://localhost/img/{key}"))
+    });
+    if let Err(err) = spawned {
+        tracing::error!(target: "commands::events", "failed to start pipeline event relay: {err}");
+    }
 }
 
-#[tauri::command]
-pub fn get_thumb_url(page: PageId, longest: u32, state: State<AppState>) -> Result<String, String> {
-    let cache = state.cache();
+/// Event carrying a [`core_stats::SlowOperation`], shown as a toast in debug builds.
+pub const SLOW_OPERATION_EVENT: &str = "stats://slow-operation";
 
-    let key = state.with_lock(|inner| {
-        if inner.sources.contains_key(&page.source_id.0) {
-            let key = format!("{}-thumb-{}-{}", page.source_id.0, page.index, longest);
-            tracing::debug!(
-                target: "commands::get_thumb_url",
-                source = %page.source_id.0,
-                index = page.index,
-                longest,
-                "resolved thumbnail url"
-            );
-            Ok(key)
-        } else {
-            Err("unknown page".to_string())
-        }
-    })?;
+fn forward_slow_operations<R: tauri::Runtime>(
+    handle: tauri::AppHandle<R>,
+    slow: std::sync::mpsc::Receiver<core_stats::SlowOperation>,
+) {
+    use tauri::Emitter;
 
-    // For now, reuse full image bytes as thumbnail; pipeline can be added later.
-    let _ = get_page_url(
-        page.clone(),
-        RenderParams {
-            fit: FitMode::FitContain,
-            viewport_w: longest,
-            viewport_h: longest,
-            scale: 1.0,
-            rotation: 0,
-            dpi: 96.0,
-        },
-        state,
-    )?;
-    if cache.fetch(&key)?.is_none() {
-        if let Some(img) = cache.fetch(&format_image_key(&page.source_id, page.index))? {
-            cache.ensure_bytes(&key, &img.mime, || Ok(img.bytes))?;
-        } else {
-            cache.ensure_bytes(&key, MIME_PNG, || Ok(PLACEHOLDER_BYTES.to_vec()))?;
+    let spawned = std::thread::Builder::new().name("slow-operations".into()).spawn(move || {
+        for operation in slow {
+            if let Err(err) = handle.emit(SLOW_OPERATION_EVENT, operation) {
+                tracing::warn!(target: "commands::stats", "failed to emit slow operation: {err}");
+            }
         }
+    });
+    if let Err(err) = spawned {
+        tracing::error!(target: "commands::stats", "failed to start slow operation relay: {err}");
     }
-
-    Ok(format!("asset://localhost/img/{key}"))
 }
 
-#[tauri::command]
-pub fn prefetch(
-    center: PageId,
-    policy: PrefetchPolicy,
-    state: State<AppState>,
-) -> Result<(), String> {
-    let pending = state.with_lock(|inner| {
-        if inner.sources.contains_key(&center.source_id.0) {
-            let token = format!("prefetch-{}-{}", center.source_id.0, center.index);
-            inner.pending_prefetch.insert(token);
-            tracing::debug!(
-                target: "commands::prefetch",
-                source = %center.source_id.0,
-                index = center.index,
-                ahead = policy.ahead,
-                behind = policy.behind,
-                "scheduled prefetch"
-            );
-            Ok(inner.pending_prefetch.len())
-        } else {
-            Err("unknown source for prefetch".to_string())
+/// Record a stats snapshot every [`core_stats::HISTORY_INTERVAL`] for the HUD's history plots.
+fn sample_stats(metrics: Arc<StatsCollector>) {
+    let spawned = std::thread::Builder::new().name("stats-sampler".into()).spawn(move || {
+        loop {
+            std::thread::sleep(core_stats::HISTORY_INTERVAL);
+            metrics.sample();
         }
-    })?;
-
-    state.stats().update_prefetch_pending(pending);
-    Ok(())
+    });
+    if let Err(err) = spawned {
+        tracing::error!(target: "commands::stats", "failed to start stats sampler: {err}");
+    }
 }
 
-#[tauri::command]
-pub fn cancel(token: RequestToken, state: State<AppState>) -> Result<(), String> {
-    let pending = state.with_lock(|inner| {
-        if inner.pending_prefetch.remove(&token.0) {
-            tracing::debug!(target: "commands::cancel", token = %token.0, "cancelled prefetch");
-        } else {
-            tracing::debug!(target: "commands::cancel", token = %token.0, "cancel no-op");
+/// Event carrying a [`PerfStats`], pushed to windows that called `start_stats_push`.
+pub const PERF_SNAPSHOT_EVENT: &str = "stats://perf-snapshot";
+
+/// Emit a [`PERF_SNAPSHOT_EVENT`] to window `label` every `interval` until `stop` is dropped
+/// or the window goes away.
+fn push_stats<R: tauri::Runtime>(
+    handle: tauri::AppHandle<R>,
+    label: String,
+    interval: Duration,
+    stop: std::sync::mpsc::Receiver<()>,
+) -> std::io::Result<()> {
+    use std::sync::mpsc::RecvTimeoutError;
+    use tauri::{Emitter, Manager};
+
+    std::thread::Builder::new().name(format!("stats-push-{label}")).spawn(move || {
+        while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(interval) {
+            let snapshot = perf_stats(&handle.state::<AppState>());
+            let sent = snapshot.map_err(|err| err.to_string()).and_then(|snapshot| {
+                handle.emit_to(label.as_str(), PERF_SNAPSHOT_EVENT, snapshot).map_err(|err| err.to_string())
+            });
+            if let Err(err) = sent {
+                tracing::warn!(target: "commands::stats", window = %label, "stopped pushing stats: {err}");
+                break;
+            }
         }
-        Ok(inner.pending_prefetch.len())
     })?;
-
-    state.stats().update_prefetch_pending(pending);
     Ok(())
 }
 
-#[tauri::command]
-pub fn save_progress(source_id: SourceId, page: u32, state: State<AppState>) -> Result<(), String> {
-    let core_page = state.with_lock(|inner| {
-        if inner.sources.contains_key(&source_id.0) {
-            tracing::info!(target: "commands::progress", source = %source_id.0, page, "progress saved");
-            Ok(CorePageId { source_id: CoreSourceId::new(source_id.0.clone()), index: page })
-        } else {
-            Err("unknown source for progress".to_string())
-        }
-    })?;
+/// A damaged store file that was repaired while loading.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreRecovery {
+    pub path: String,
+    pub reason: String,
+    /// Backup the file was restored from, if one was usable.
+    pub restored_from: Option<String>,
+    /// Where the damaged file was moved when the store had to be reset.
+    pub quarantined: Option<String>,
+    pub at_ms: u64,
+}
 
-    progress_store::save(&core_page).map_err(|err| err.to_string())
+/// Gestures bound to one action, in canonical form (e.g. `keyboard:Ctrl+KeyG`).
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyBinding {
+    pub action: ActionId,
+    pub gestures: Vec<InputGesture>,
 }
 
-#[tauri::command]
-pub fn query_progress(source_id: SourceId, state: State<AppState>) -> Result<u32, String> {
-    let core_source = state.with_lock(|inner| {
-        if inner.sources.contains_key(&source_id.0) {
-            Ok(CoreSourceId::new(source_id.0.clone()))
-        } else {
-            Err("unknown source for progress".to_string())
-        }
-    })?;
+fn key_bindings(keymap: &Keymap) -> Vec<KeyBinding> {
+    keymap
+        .bindings()
+        .iter()
+        .map(|(action, gestures)| KeyBinding { action: action.clone(), gestures: gestures.clone() })
+        .collect()
+}
 
-    let stored = progress_store::load(&core_source).map_err(|err| err.to_string())?;
-    Ok(stored.map(|page| page.index).unwrap_or(0))
+/// File format of exported pages.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Webp,
 }
 
+impl ImageFormat {
+    fn encode_format(self, quality: Option<u8>) -> EncodeFormat {
+        match self {
+            ImageFormat::Png => EncodeFormat::Png,
+            ImageFormat::Jpeg => EncodeFormat::Jpeg { quality: quality.unwrap_or(90) },
+            ImageFormat::Webp => EncodeFormat::WebP,
+        }
+    }
+}
+
+/// Outcome of an `export_range`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSummary {
+    pub written: Vec<String>,
+    pub failed: Vec<u32>,
+}
+
+/// Id of the mock source opened through the `demo-bundle` path.
+const DEMO_SOURCE_ID: &str = "src-demo";
+
+fn mock_pages(source_id: &SourceId, path: &str) -> Vec<PageMeta> {
+    let base_name =
+        std::path::Path::new(path).file_name().and_then(|os| os.to_str()).unwrap_or("demo");
+
+    (0..5)
+        .map(|idx| PageMeta {
+            id: PageId { source_id: source_id.clone(), index: idx },
+            rel_path: format!("{base_name}/page_{idx:03}.png").into(),
+            mime: MIME_PNG.to_string(),
+            size_bytes: PLACEHOLDER_BYTES.len() as u64,
+            modified: None,
+            width: 1600,
+            height: 2400,
+            is_double_spread: idx % 3 == 2,
+        })
+        .collect()
+}
+
+/// Key of page `index` of `source` as stored.
+fn image_key(source: &SourceId, index: u32) -> ImageKey {
+    ImageKey::new(source.clone(), index, ImageVariant::Original)
+}
+
+/// Key of a page rendered at display size for `params`.
+fn render_key(source: &SourceId, index: u32, params: &RenderParams) -> ImageKey {
+    image_key(source, index).with_params(params_hash(params, &[]))
+}
+
+fn spread_key(
+    source: &SourceId,
+    pages: (u32, u32),
+    direction: ReadingDirection,
+    gutter: u32,
+    params: &RenderParams,
+) -> ImageKey {
+    let layout = format!("{direction:?}-g{gutter}");
+    ImageKey::new(source.clone(), pages.0, ImageVariant::Spread(pages.1))
+        .with_params(params_hash(params, layout.as_bytes()))
+}
+
+/// Where the bytes of a page are read from.
+#[derive(Clone, Debug)]
+enum PageSource {
+    Provided { source: Arc<dyn PageByteSource>, rel: std::path::PathBuf },
+    Mock,
+}
+
+impl PageSource {
+    fn read(&self) -> CommandResult<Vec<u8>> {
+        match self {
+            PageSource::Provided { source, rel } => {
+                let _span = tracing::debug_span!("fs_read", path = %rel.display()).entered();
+                source.read(rel).map_err(CommandError::from)
+            }
+            PageSource::Mock => Ok(PLACEHOLDER_BYTES.to_vec()),
+        }
+    }
+}
+
+impl SourceData {
+    /// Location and content type of page `index`.
+    fn page_source(&self, index: u32) -> (PageSource, String) {
+        let (rel, mime) = self
+            .pages
+            .get(index as usize)
+            .map(|m| (m.rel_path.clone(), m.mime.clone()))
+            .unwrap_or_default();
+        let source = match &self.kind {
+            SourceKind::Provided { source, .. } => {
+                PageSource::Provided { source: Arc::clone(source), rel }
+            }
+            SourceKind::Mock => PageSource::Mock,
+        };
+        (source, mime)
+    }
+}
+
+/// Read and decode page `index` of `source`, recording the decode under `key`.
+fn decode_page(
+    stats: &StatsCollector,
+    source_id: &SourceId,
+    source: &SourceData,
+    index: u32,
+    key: &ImageKey,
+) -> CommandResult<DecodedImage> {
+    let (page_source, _) = source.page_source(index);
+    let bytes = page_source.read()?;
+    decode_bytes(stats, source_id, source, index, &bytes, key)
+}
+
+/// Decode the already read `bytes` of page `index` of `source`, recording the decode under
+/// `key`.
+fn decode_bytes(
+    stats: &StatsCollector,
+    source_id: &SourceId,
+    source: &SourceData,
+    index: u32,
+    bytes: &[u8],
+    key: &ImageKey,
+) -> CommandResult<DecodedImage> {
+    let meta =
+        source.pages.get(index as usize).ok_or_else(|| CommandError::not_found("unknown page"))?;
+    let started = Instant::now();
+    let decoded = sandbox::decode(meta, bytes)?;
+    let format =
+        meta.rel_path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_ascii_lowercase());
+    let key = key.to_string();
+    stats.record_decode(
+        started.elapsed(),
+        DecodeLabels {
+            key: Some(&key),
+            source: Some(source_id.as_str()),
+            format: format.as_deref(),
+            pixels: Some(u64::from(decoded.width()) * u64::from(decoded.height())),
+        },
+    );
+    Ok(decoded)
+}
+
+/// Read, decode and render page `index` of `source` into the cache at display size, returning
+/// its key. Pages that are already displayed at their original size and orientation are cached
+/// as read rather than re-encoded. Racing misses for one key read and render the page once.
+/// Prefetches pass their `cancel` flag, and are abandoned between steps once it is set.
+fn render_page(
+    cache: &ImageCache,
+    stats: &StatsCollector,
+    source_id: &SourceId,
+    source: &SourceData,
+    index: u32,
+    params: &RenderParams,
+    cancel: Option<&CancelFlag>,
+) -> CommandResult<ImageKey> {
+    let check_cancelled = || match cancel {
+        Some(flag) if flag.is_cancelled() => Err(CommandError::internal("render cancelled")),
+        _ => Ok(()),
+    };
+    let key = render_key(source_id, index, params);
+    if cache.contains(&key) {
+        return Ok(key);
+    }
+    if index as usize >= source.pages.len() {
+        return Err(CommandError::not_found("unknown page"));
+    }
+    cache.ensure(&key, || {
+        let (page_source, mime) = source.page_source(index);
+        let bytes = page_source.read()?;
+        check_cancelled()?;
+        let decoded = decode_bytes(stats, source_id, source, index, &bytes, &key)?;
+        check_cancelled()?;
+        match scale_to_display(&decoded, params)? {
+            Cow::Borrowed(_) => Ok(CachedImage { bytes, mime }),
+            Cow::Owned(display) => {
+                check_cancelled()?;
+                let rendered = encode(&display, EncodeFormat::for_image(&display))?;
+                Ok(CachedImage { bytes: rendered.bytes, mime: rendered.mime.to_string() })
+            }
+        }
+    })?;
+    Ok(key)
+}
+
+/// Compose pages `pages` of `source` into one spread in `direction` and render it into the cache
+/// at display size, returning its key.
+fn render_spread(
+    cache: &ImageCache,
+    stats: &StatsCollector,
+    source_id: &SourceId,
+    source: &SourceData,
+    pages: (u32, u32),
+    layout: (ReadingDirection, u32),
+    params: &RenderParams,
+) -> CommandResult<ImageKey> {
+    let (direction, gutter) = layout;
+    let key = spread_key(source_id, pages, direction, gutter, params);
+    if cache.contains(&key) {
+        return Ok(key);
+    }
+    let decode = |index: u32| {
+        if index as usize >= source.pages.len() {
+            return Err(CommandError::not_found("unknown page"));
+        }
+        let bytes = source.page_source(index).0.read()?;
+        decode_bytes(stats, source_id, source, index, &bytes, &key)
+    };
+    cache.ensure(&key, || {
+        let first = decode(pages.0)?;
+        let second = decode(pages.1)?;
+        let spread = compose_spread(&first, &second, direction, gutter)?;
+        let display = scale_to_display(&spread, params)?;
+        let rendered = encode(&display, EncodeFormat::for_image(&display))?;
+        Ok(CachedImage { bytes: rendered.bytes, mime: rendered.mime.to_string() })
+    })?;
+    Ok(key)
+}
+
+const MIME_PNG: &str = "image/png";
+const PLACEHOLDER_BYTES: &[u8] = include_bytes!("../assets/placeholder.png");
+
+/// Run blocking IO or decoding on the blocking pool so the IPC thread stays responsive.
+async fn blocking<T, F>(work: F) -> CommandResult<T>
+where
+    F: FnOnce() -> CommandResult<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(work)
+        .await
+        .map_err(|err| CommandError::internal(format!("background task failed: {err}")))?
+}
+
+/// Open `location`, a local path or a remote URI, with the provider `registry` has for it and
+/// list its pages as source `id`. Remote sources read through a local copy of their files,
+/// kept within `budget` bytes.
+fn list_source(
+    registry: &SourceRegistry,
+    location: &str,
+    id: &SourceId,
+    budget: u64,
+) -> CommandResult<SourceData> {
+    let (path, source) = provided_source(registry.open(location)?, location, id, budget)?;
+    let pages = source.list_pages(id)?;
+    Ok(SourceData { kind: SourceKind::Provided { path, source }, pages })
+}
+
+/// [`list_source`] for commands on the async runtime; the provider's IO runs on its blocking
+/// pool.
+async fn list_source_async(
+    registry: Arc<SourceRegistry>,
+    location: &str,
+    id: &SourceId,
+    budget: u64,
+) -> CommandResult<SourceData> {
+    let source = nonblocking_fs::open_source(registry, location).await?;
+    let (path, source) = provided_source(source, location, id, budget)?;
+    let pages = nonblocking_fs::list_source_pages(Arc::clone(&source), id.clone()).await?;
+    Ok(SourceData { kind: SourceKind::Provided { path, source }, pages })
+}
+
+/// The local path of the source opened from `location`, and the source itself, read through a
+/// local copy of its files when it is remote. The copies of all remote sources share `budget`,
+/// so opening one first trims what the others left behind.
+fn provided_source(
+    source: Box<dyn PageByteSource>,
+    location: &str,
+    id: &SourceId,
+    budget: u64,
+) -> CommandResult<(Option<std::path::PathBuf>, Arc<dyn PageByteSource>)> {
+    if source_registry::is_local(location) {
+        return Ok((Some(std::path::PathBuf::from(location)), Arc::from(source)));
+    }
+    let root = reader_core::store::cache_dir()?.join("remote");
+    if let Err(err) = trim_copies(&root, budget) {
+        tracing::warn!(target: "commands::open_path", "failed to trim remote source copies: {err:#}");
+    }
+    let dir = root.join(id.as_str());
+    Ok((None, Arc::new(CachedSource::new(source, dir).with_budget(budget))))
+}
+
+#[tauri::command]
+pub async fn open_path<R: tauri::Runtime>(
+    path: String,
+    window: tauri::Window<R>,
+    state: State<'_, AppState>,
+) -> CommandResult<SourceId> {
+    open_source(&state, window.label(), path).await
+}
+
+/// Open the folder, archive, image or remote source at `path` as a new source read by window
+/// `label`. Shared by `open_path` and launches through deep links or file associations, which
+/// only pass local paths.
+pub async fn open_source(state: &AppState, label: &str, path: String) -> CommandResult<SourceId> {
+    // Demo shortcut preserved for UI preview
+    if path == "demo-bundle" {
+        let (id, replaced) = state.with_lock(|inner| {
+            let id = SourceId::new(DEMO_SOURCE_ID);
+            let pages = mock_pages(&id, &path);
+            state.notify(
+                label,
+                PipelineEvent::SourceChanged { source_id: id.clone(), pages: pages.len() },
+            );
+            inner
+                .sources
+                .insert(id.as_str().to_string(), SourceData { kind: SourceKind::Mock, pages });
+            let replaced = inner.show_source(label, &id);
+            Ok((id, replaced))
+        })?;
+        if let Some(replaced) = replaced {
+            state.stores.autosave.save_source(&replaced);
+        }
+        return Ok(id);
+    }
+
+    let recent = Arc::clone(&state.stores.recent);
+    let library = Arc::clone(&state.stores.library);
+    let registry = Arc::clone(&state.registry);
+    let id = nonblocking_fs::registry_source_id(Arc::clone(&registry), path.clone()).await?;
+    let source = list_source_async(registry, &path, &id, state.cache().budget()).await?;
+    // The stores key entries by local path; remote sources are not recorded.
+    if let Some(path_ref) = source.kind.path().map(std::path::Path::to_path_buf) {
+        let path = path.clone();
+        blocking(move || {
+            if let Err(err) = recent.record_open(&path_ref) {
+                tracing::warn!(target: "commands::open_path", path = %path, "failed to record recent path: {err:#}");
+            }
+            if let Err(err) = library.record_open(&path_ref, series_of(&path_ref).as_deref()) {
+                tracing::warn!(target: "commands::open_path", path = %path, "failed to record library entry: {err:#}");
+            }
+            Ok(())
+        })
+        .await?;
+    }
+
+    let pages = source.pages.len();
+    let replaced = state.with_lock(|inner| {
+        inner.sources.insert(id.as_str().to_string(), source);
+        Ok(inner.show_source(label, &id))
+    })?;
+    // The window no longer shows the source it was reading, so save its place now.
+    if let Some(replaced) = replaced {
+        state.stores.autosave.save_source(&replaced);
+    }
+    tracing::info!(
+        target: "commands::open_path",
+        path = %source_registry::without_userinfo(&path),
+        source_id = %id.as_str(),
+        window = label,
+        "opened source"
+    );
+    state.notify(label, PipelineEvent::SourceChanged { source_id: id.clone(), pages });
+
+    Ok(id)
+}
+
+/// An OPDS catalog and the credentials to browse it with.
+#[cfg(feature = "opds")]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsCatalog {
+    pub url: String,
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+#[cfg(feature = "opds")]
+impl OpdsCatalog {
+    fn client(&self) -> CommandResult<OpdsClient> {
+        let client = OpdsClient::new(&self.url, reader_core::store::downloads_dir()?)?;
+        Ok(match &self.user {
+            Some(user) => client.with_credentials(user, self.password.as_deref().unwrap_or("")),
+            None => client,
+        })
+    }
+}
+
+/// The feed at `href` of `catalog`, or its root feed.
+#[cfg(feature = "opds")]
+#[tauri::command]
+pub async fn opds_feed(catalog: OpdsCatalog, href: Option<String>) -> CommandResult<Feed> {
+    blocking(move || {
+        let client = catalog.client()?;
+        Ok(match href {
+            Some(href) => client.feed(&href)?,
+            None => client.root()?,
+        })
+    })
+    .await
+}
+
+/// Results of searching `catalog` for `terms` with the `search` link of one of its feeds.
+#[cfg(feature = "opds")]
+#[tauri::command]
+pub async fn opds_search(
+    catalog: OpdsCatalog,
+    search: String,
+    terms: String,
+) -> CommandResult<Feed> {
+    blocking(move || {
+        let client = catalog.client()?;
+        let feed =
+            Feed { url: client.catalog_url().to_string(), search: Some(search), ..Feed::default() };
+        Ok(client.search(&feed, &terms)?)
+    })
+    .await
+}
+
+/// Download `entry` of `catalog` unless it already was, and open the archive as a new source
+/// read by the calling window.
+#[cfg(feature = "opds")]
+#[tauri::command]
+pub async fn opds_open<R: tauri::Runtime>(
+    catalog: OpdsCatalog,
+    entry: FeedEntry,
+    window: tauri::Window<R>,
+    state: State<'_, AppState>,
+) -> CommandResult<SourceId> {
+    let path = blocking(move || Ok(catalog.client()?.download(&entry)?)).await?;
+    open_source(&state, window.label(), path.to_string_lossy().to_string()).await
+}
+
+/// Open another reader window, showing `source_id` when given, and return its label. Each
+/// window keeps its own reading position; see [`get_window_source`].
+#[tauri::command]
+pub async fn open_reader_window<R: tauri::Runtime>(
+    source_id: Option<SourceId>,
+    app: tauri::AppHandle<R>,
+    state: State<'_, AppState>,
+) -> CommandResult<String> {
+    let label = state.with_lock(|inner| {
+        if let Some(id) = &source_id
+            && !inner.sources.contains_key(id.as_str())
+        {
+            return Err(CommandError::not_found("unknown source"));
+        }
+        inner.next_window_id += 1;
+        let label = format!("reader-{}", inner.next_window_id);
+        let reading = source_id.clone().map(WindowState::reading).unwrap_or_default();
+        inner.windows.insert(label.clone(), reading);
+        Ok(label)
+    })?;
+
+    let built = tauri::WebviewWindowBuilder::new(&app, &label, tauri::WebviewUrl::default())
+        .title("Local Comic Reader")
+        .inner_size(800.0, 600.0)
+        .build();
+    if let Err(err) = built {
+        state.forget_window(&label);
+        return Err(CommandError::internal(format!("failed to open window: {err}")));
+    }
+    tracing::info!(
+        target: "commands::windows",
+        window = %label,
+        source_id = ?source_id.as_ref().map(SourceId::as_str),
+        "opened reader window"
+    );
+    Ok(label)
+}
+
+/// The source the calling window is reading, so a window opened by [`open_reader_window`] can
+/// pick it up on load.
+#[tauri::command]
+pub fn get_window_source<R: tauri::Runtime>(
+    window: tauri::Window<R>,
+    state: State<AppState>,
+) -> CommandResult<Option<SourceId>> {
+    state.with_lock(|inner| {
+        Ok(inner.windows.get(window.label()).and_then(|reading| reading.source_id.clone()))
+    })
+}
+
+#[tauri::command]
+pub fn list_pages(source_id: SourceId, state: State<AppState>) -> CommandResult<Vec<PageMeta>> {
+    state.with_lock(|inner| {
+        inner
+            .sources
+            .get(source_id.as_str())
+            .map(|src| {
+                tracing::debug!(target: "commands::list_pages", source_id = %source_id.as_str(), "listed pages");
+                src.pages.clone()
+            })
+            .ok_or_else(|| CommandError::not_found("unknown source"))
+    })
+}
+
+/// Largest window returned by `list_pages_range`.
+const MAX_PAGE_RANGE: u32 = 1_000;
+
+/// Up to `limit` pages of `source_id` starting at `offset`, with the page count of the whole
+/// source so the UI can size a virtualized list without loading every entry of a huge archive.
+/// An `offset` past the end returns no pages.
+#[tauri::command]
+pub fn list_pages_range(
+    source_id: SourceId,
+    offset: u32,
+    limit: u32,
+    state: State<AppState>,
+) -> CommandResult<PageRange> {
+    state.with_lock(|inner| {
+        let src = inner
+            .sources
+            .get(source_id.as_str())
+            .ok_or_else(|| CommandError::not_found("unknown source"))?;
+        let start = (offset as usize).min(src.pages.len());
+        let end = start.saturating_add(limit.min(MAX_PAGE_RANGE) as usize).min(src.pages.len());
+        tracing::debug!(
+            target: "commands::list_pages",
+            source_id = %source_id.as_str(),
+            offset,
+            returned = end - start,
+            "listed page range"
+        );
+        Ok(PageRange {
+            total: src.pages.len() as u32,
+            offset: start as u32,
+            pages: src.pages[start..end].to_vec(),
+        })
+    })
+}
+
+/// Indices of the pages of `source_id` whose entry name or annotations contain `query`, ignoring
+/// case, in page order. A blank query matches nothing.
+#[tauri::command]
+pub fn search_pages(
+    source_id: SourceId,
+    query: String,
+    state: State<AppState>,
+) -> CommandResult<Vec<u32>> {
+    let needle = query.trim().to_lowercase();
+    if needle.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut matches: BTreeSet<u32> = state.with_lock(|inner| {
+        let src = inner
+            .sources
+            .get(source_id.as_str())
+            .ok_or_else(|| CommandError::not_found("unknown source"))?;
+        Ok(src
+            .pages
+            .iter()
+            .filter(|page| page.rel_path.to_string_lossy().to_lowercase().contains(&needle))
+            .map(|page| page.id.index)
+            .collect())
+    })?;
+    let annotations = state.annotations().list(&source_id, None)?;
+    matches.extend(
+        annotations
+            .iter()
+            .filter(|note| note.text.to_lowercase().contains(&needle))
+            .map(|note| note.page.index),
+    );
+    tracing::debug!(
+        target: "commands::search_pages",
+        source_id = %source_id.as_str(),
+        matches = matches.len(),
+        "searched pages"
+    );
+    Ok(matches.into_iter().collect())
+}
+
+/// The concrete fit `mode` resolves to for a page of `width` by `height` as displayed, so the
+/// viewer scales pages the way the renderer sizes them; see [`fit::resolve`].
+#[tauri::command]
+pub fn resolve_fit(mode: FitMode, width: u32, height: u32) -> FitMode {
+    fit::resolve(mode, ImageDimensions { width, height })
+}
+
+#[tauri::command]
+pub async fn get_page_url(
+    page: PageId,
+    params: RenderParams,
+    state: State<'_, AppState>,
+) -> CommandResult<String> {
+    let cache = state.cache();
+    let request_id = RequestId::next();
+    let span = tracing::info_span!(
+        "page_fetch",
+        request_id = %request_id,
+        source_id = %page.source_id.as_str(),
+        page_index = page.index
+    );
+
+    let params = state.render_params(Some(&params))?;
+    let source = span.in_scope(|| {
+        state.with_lock(|inner| {
+            let src = inner
+                .sources
+                .get(page.source_id.as_str())
+                .ok_or_else(|| CommandError::not_found("unknown page"))?;
+            tracing::debug!(
+                target: "commands::get_page_url",
+                source_id = %page.source_id.as_str(),
+                page_index = page.index,
+                fit = ?params.fit,
+                rotation = params.rotation,
+                "resolved page url"
+            );
+            Ok(src.clone())
+        })
+    })?;
+
+    let stats = state.stats();
+    let shown = ShownPage::of(&source, page.index);
+    let source_id = page.source_id.clone();
+    // Pages rendered ahead of time by `prefetch` with the same params are served straight away.
+    let key = blocking(move || {
+        let _span = span.entered();
+        render_page(&cache, &stats, &page.source_id, &source, page.index, &params, None)
+    })
+    .await?;
+    state.stores.autosave.note(&source_id, shown);
+
+    // The id travels with the URL so the protocol handler can log the serve under it too.
+    Ok(state.assets.image_url(&key, Some(request_id)))
+}
+
+/// Widest gutter accepted by `get_spread_url`, in source pixels.
+const MAX_SPREAD_GUTTER: u32 = 256;
+
+/// URL of `first` and `second` composed into one spread and scaled to fit `params` as a whole.
+/// `direction` (the one in `params` by default) decides which page sits on the left, or stacks
+/// them when vertical; `gutter` is the transparent gap between them in source pixels.
+#[tauri::command]
+pub async fn get_spread_url(
+    first: PageId,
+    second: PageId,
+    params: RenderParams,
+    direction: Option<ReadingDirection>,
+    gutter: Option<u32>,
+    state: State<'_, AppState>,
+) -> CommandResult<String> {
+    if first.source_id != second.source_id {
+        return Err(CommandError::invalid_input("spread pages must come from the same source"));
+    }
+    let gutter = gutter.unwrap_or(0);
+    if gutter > MAX_SPREAD_GUTTER {
+        return Err(CommandError::invalid_input(format!(
+            "gutter must be at most {MAX_SPREAD_GUTTER} pixels"
+        )));
+    }
+    let direction = direction.unwrap_or(params.direction);
+    let cache = state.cache();
+    let request_id = RequestId::next();
+    let params = state.render_params(Some(&params))?;
+    let source = state.with_lock(|inner| {
+        let src = inner
+            .sources
+            .get(first.source_id.as_str())
+            .ok_or_else(|| CommandError::not_found("unknown page"))?;
+        tracing::debug!(
+            target: "commands::get_spread_url",
+            request_id = %request_id,
+            source_id = %first.source_id.as_str(),
+            first = first.index,
+            second = second.index,
+            ?direction,
+            gutter,
+            "resolved spread url"
+        );
+        Ok(src.clone())
+    })?;
+
+    let stats = state.stats();
+    let shown = ShownPage::of(&source, first.index.min(second.index));
+    let source_id = first.source_id.clone();
+    let key = blocking(move || {
+        let pages = (first.index, second.index);
+        render_spread(
+            &cache,
+            &stats,
+            &first.source_id,
+            &source,
+            pages,
+            (direction, gutter),
+            &params,
+        )
+    })
+    .await?;
+    state.stores.autosave.note(&source_id, shown);
+    Ok(state.assets.image_url(&key, Some(request_id)))
+}
+
+#[tauri::command]
+pub async fn get_thumb_url<R: tauri::Runtime>(
+    page: PageId,
+    longest: u32,
+    window: tauri::Window<R>,
+    state: State<'_, AppState>,
+) -> CommandResult<String> {
+    let cache = state.cache();
+
+    let (key, source) = state.with_lock(|inner| {
+        let src = inner
+            .sources
+            .get(page.source_id.as_str())
+            .ok_or_else(|| CommandError::not_found("unknown page"))?;
+        let key = ImageKey::thumb(&page, longest);
+        tracing::debug!(
+            target: "commands::get_thumb_url",
+            source_id = %page.source_id.as_str(),
+            page_index = page.index,
+            longest,
+            "resolved thumbnail url"
+        );
+        Ok((key, src.clone()))
+    })?;
+
+    let stats = state.stats();
+    let generated = blocking({
+        let (page, key) = (page.clone(), key.clone());
+        move || render_thumb(&cache, &stats, &page, &source, longest, &key)
+    })
+    .await?;
+    if generated {
+        let url = state.assets.thumb_url(&key);
+        state.notify(window.label(), PipelineEvent::ThumbReady { page, longest, url });
+    }
+
+    Ok(state.assets.thumb_url(&key))
+}
+
+/// Thumbnail URLs for `pages` in one call. Cached thumbnails are marked `ready`; the rest are
+/// generated in the background in the given order and announced with a thumb-ready event, after
+/// which their URL serves the image.
+#[tauri::command]
+pub fn get_thumb_urls<R: tauri::Runtime>(
+    pages: Vec<PageId>,
+    longest: u32,
+    window: tauri::Window<R>,
+    state: State<AppState>,
+) -> CommandResult<Vec<ThumbUrl>> {
+    let cache = state.cache();
+    let sources = state.with_lock(|inner| {
+        let mut sources = HashMap::new();
+        for page in &pages {
+            if sources.contains_key(page.source_id.as_str()) {
+                continue;
+            }
+            let src = inner
+                .sources
+                .get(page.source_id.as_str())
+                .ok_or_else(|| CommandError::not_found("unknown page"))?;
+            sources.insert(page.source_id.as_str().to_string(), Arc::new(src.clone()));
+        }
+        Ok(sources)
+    })?;
+
+    let mut urls = Vec::with_capacity(pages.len());
+    let mut pending = Vec::new();
+    for page in pages {
+        let key = ImageKey::thumb(&page, longest);
+        let ready = cache.contains(&key);
+        urls.push(ThumbUrl { page: page.clone(), url: state.assets.thumb_url(&key), ready });
+        if !ready {
+            pending.push((page, key));
+        }
+    }
+    tracing::debug!(
+        target: "commands::get_thumb_url",
+        requested = urls.len(),
+        pending = pending.len(),
+        longest,
+        "resolved thumbnail urls"
+    );
+
+    if !pending.is_empty() {
+        let stats = state.stats();
+        let events = state.pipeline_events.clone();
+        let assets = state.assets.clone();
+        let label = window.label().to_string();
+        tauri::async_runtime::spawn_blocking(move || {
+            for (page, key) in pending {
+                let source = &sources[page.source_id.as_str()];
+                match render_thumb(&cache, &stats, &page, source, longest, &key) {
+                    Ok(_) => {
+                        let url = assets.thumb_url(&key);
+                        let event = PipelineEvent::ThumbReady { page, longest, url };
+                        let _ = events.send(event.from_window(&label));
+                    }
+                    Err(err) => tracing::warn!(
+                        target: "commands::get_thumb_url",
+                        source_id = %page.source_id.as_str(),
+                        page_index = page.index,
+                        error = %err,
+                        "thumbnail generation failed"
+                    ),
+                }
+            }
+        });
+    }
+    Ok(urls)
+}
+
+/// Generate the thumbnail of `page` under `key` unless it is cached, reporting whether it was.
+fn render_thumb(
+    cache: &ImageCache,
+    stats: &StatsCollector,
+    page: &PageId,
+    source: &SourceData,
+    longest: u32,
+    key: &ImageKey,
+) -> CommandResult<bool> {
+    if cache.contains(key) {
+        return Ok(false);
+    }
+    cache.ensure(key, || {
+        let decoded = decode_page(stats, &page.source_id, source, page.index, key)?;
+        let thumb = render_thumbnail(&decoded, longest)?;
+        Ok(CachedImage { bytes: thumb.bytes, mime: thumb.mime.to_string() })
+    })?;
+    Ok(true)
+}
+
+/// Longest side of library covers. It is fixed so the bookshelf can lay out its grid before the
+/// covers load, and every cover is generated once.
+pub const LIBRARY_THUMB_LONGEST: u32 = 256;
+/// Most covers `get_library_thumbs` returns in one call.
+const MAX_LIBRARY_THUMBS_PAGE: u32 = 500;
+
+/// Covers of the library sources on page `page` of `size` entries, in the order of
+/// `list_library`. Generated covers are marked `ready`. If any is not, covers are generated for
+/// every library source in the background, this page first, and each is announced with a
+/// library-thumb-ready event.
+#[tauri::command]
+pub async fn get_library_thumbs<R: tauri::Runtime>(
+    page: u32,
+    size: u32,
+    window: tauri::Window<R>,
+    state: State<'_, AppState>,
+) -> CommandResult<Vec<LibraryThumb>> {
+    if size == 0 || size > MAX_LIBRARY_THUMBS_PAGE {
+        return Err(CommandError::invalid_input(format!(
+            "page size must be between 1 and {MAX_LIBRARY_THUMBS_PAGE}"
+        )));
+    }
+    let cache = state.cache();
+    let library = Arc::clone(&state.stores.library);
+    let start = usize::try_from(u64::from(page) * u64::from(size)).unwrap_or(usize::MAX);
+    // Keying a cover stats its source, which may sit on a slow disk or share.
+    let covers = blocking(move || {
+        let entries = library.list(false)?;
+        Ok(entries
+            .into_iter()
+            .skip(start)
+            .take(size as usize)
+            .map(|entry| {
+                let key = library_thumb_key(&entry.path);
+                let ready = key.as_ref().is_some_and(|key| cache.contains(key));
+                (entry.path, key, ready)
+            })
+            .collect::<Vec<_>>())
+    })
+    .await?;
+    let mut thumbs = Vec::with_capacity(covers.len());
+    let mut first = Vec::new();
+    for (path, key, ready) in covers {
+        if key.is_some() && !ready {
+            first.push(path.clone());
+        }
+        thumbs.push(LibraryThumb {
+            path: path.to_string_lossy().to_string(),
+            url: key.map(|key| state.assets.cover_url(&key)),
+            ready,
+        });
+    }
+    if !first.is_empty() {
+        generate_library_thumbs(&state, window.label(), first);
+    }
+    Ok(thumbs)
+}
+
+/// Key of the cover of the library source at `path`, or `None` if it is gone. Covers are keyed
+/// by the path, size and modification time of the source instead of its [`SourceId`], so
+/// resolving a page of covers never reads the sources, yet a replaced archive gets a new cover.
+fn library_thumb_key(path: &std::path::Path) -> Option<ImageKey> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_nanos());
+    let tag = format!("{}\0{}\0{modified}", path.display(), metadata.len());
+    let source = SourceId::new(format!("lib-{}", &blake3::hash(tag.as_bytes()).to_hex()[..16]));
+    Some(ImageKey::cover(&source, LIBRARY_THUMB_LONGEST))
+}
+
+/// Generate the missing covers of every library source in the background, `first` before the
+/// rest, announcing them to window `label`. Does nothing while a previous run is going; that
+/// run covers the same sources unless they were added after it started.
+fn generate_library_thumbs(state: &AppState, label: &str, first: Vec<std::path::PathBuf>) {
+    let Some(running) = RunningFlag::claim(&state.library_thumbs_running) else { return };
+    let cache = state.cache();
+    let stats = state.stats();
+    let registry = Arc::clone(&state.registry);
+    let library = Arc::clone(&state.stores.library);
+    let events = state.pipeline_events.clone();
+    let assets = state.assets.clone();
+    let label = label.to_string();
+    tauri::async_runtime::spawn_blocking(move || {
+        // Cleared on return or on a panic, so a failed run never blocks the next ones.
+        let _running = running;
+        let started = Instant::now();
+        let mut paths = first;
+        match library.list(true) {
+            Ok(entries) => {
+                let rest: Vec<_> = entries
+                    .into_iter()
+                    .map(|entry| entry.path)
+                    .filter(|path| !paths.contains(path))
+                    .collect();
+                paths.extend(rest);
+            }
+            Err(err) => tracing::warn!(
+                target: "commands::library_thumbs",
+                "failed to list library sources: {err:#}"
+            ),
+        }
+        let mut generated = 0;
+        for path in paths {
+            let Some(key) = library_thumb_key(&path) else { continue };
+            match render_library_thumb(&cache, &stats, &registry, &path, &key) {
+                Ok(false) => {}
+                Ok(true) => {
+                    generated += 1;
+                    let event = PipelineEvent::LibraryThumbReady {
+                        path: path.to_string_lossy().to_string(),
+                        url: assets.cover_url(&key),
+                    };
+                    let _ = events.send(event.from_window(&label));
+                }
+                Err(err) => tracing::warn!(
+                    target: "commands::library_thumbs",
+                    path = %path.display(),
+                    error = %err,
+                    "cover generation failed"
+                ),
+            }
+        }
+        tracing::info!(
+            target: "commands::library_thumbs",
+            generated,
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "library covers up to date"
+        );
+    });
+}
+
+/// A set [`AtomicBool`] marking a background run, cleared when dropped.
+struct RunningFlag(Arc<AtomicBool>);
+
+impl RunningFlag {
+    /// Set `flag`, or return `None` if another run already holds it.
+    fn claim(flag: &Arc<AtomicBool>) -> Option<Self> {
+        (!flag.swap(true, Ordering::AcqRel)).then(|| Self(Arc::clone(flag)))
+    }
+}
+
+impl Drop for RunningFlag {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// Generate the cover of the library source at `path` under `key` from its first page unless
+/// it is cached, reporting whether it was.
+fn render_library_thumb(
+    cache: &ImageCache,
+    stats: &StatsCollector,
+    registry: &SourceRegistry,
+    path: &std::path::Path,
+    key: &ImageKey,
+) -> CommandResult<bool> {
+    if cache.contains(key) {
+        return Ok(false);
+    }
+    let location = path.to_str().ok_or_else(|| CommandError::invalid_input("path is not UTF-8"))?;
+    let source = list_source(registry, location, &key.source, cache.budget())?;
+    if source.pages.is_empty() {
+        return Err(CommandError::not_found("source has no pages"));
+    }
+    render_thumb(cache, stats, &key.page_id(), &source, LIBRARY_THUMB_LONGEST, key)
+}
+
+/// Bytes before the pixel rows in a `get_page_pixels` response.
+pub const PIXELS_HEADER_LEN: usize = 12;
+
+/// Decoded RGBA8 pixels of a page, scaled to `params` when given, for uploading straight into a
+/// canvas or WebGL texture. The binary response starts with the little-endian `u32` width,
+/// height and row stride in bytes, followed by the rows top to bottom.
+#[tauri::command]
+pub async fn get_page_pixels(
+    page: PageId,
+    params: Option<RenderParams>,
+    state: State<'_, AppState>,
+) -> CommandResult<tauri::ipc::Response> {
+    let source = state.with_lock(|inner| {
+        inner
+            .sources
+            .get(page.source_id.as_str())
+            .cloned()
+            .ok_or_else(|| CommandError::not_found("unknown page"))
+    })?;
+    let stats = state.stats();
+    let params = params.map(|params| state.render_params(Some(&params))).transpose()?;
+    let payload = blocking(move || {
+        let key = image_key(&page.source_id, page.index);
+        let decoded = decode_page(&stats, &page.source_id, &source, page.index, &key)?;
+        let image = match params {
+            Some(params) => scale_to_display(&decoded, &params)?.into_owned(),
+            None => decoded,
+        };
+        tracing::debug!(
+            target: "commands::get_page_pixels",
+            source_id = %page.source_id.as_str(),
+            page_index = page.index,
+            width = image.width(),
+            height = image.height(),
+            "decoded page pixels"
+        );
+        Ok(pixels_payload(&image))
+    })
+    .await?;
+    Ok(tauri::ipc::Response::new(payload))
+}
+
+fn pixels_payload(image: &DecodedImage) -> Vec<u8> {
+    let stride = image.width() * 4;
+    let mut payload = Vec::with_capacity(PIXELS_HEADER_LEN + image.pixels().len());
+    for value in [image.width(), image.height(), stride] {
+        payload.extend_from_slice(&value.to_le_bytes());
+    }
+    payload.extend_from_slice(image.pixels());
+    payload
+}
+
+/// Structured description of `page` for screen readers: its position, detected panel count and
+/// text when a describer reads it. Describing decodes the page, so the UI asks for the page
+/// being announced rather than listing descriptions with `list_pages`.
+#[tauri::command]
+pub async fn get_page_description(
+    page: PageId,
+    state: State<'_, AppState>,
+) -> CommandResult<PageDescription> {
+    let source = state.with_lock(|inner| {
+        inner
+            .sources
+            .get(page.source_id.as_str())
+            .cloned()
+            .ok_or_else(|| CommandError::not_found("unknown page"))
+    })?;
+    let stats = state.stats();
+    let describers = Arc::clone(&state.describers);
+    blocking(move || {
+        let meta = source
+            .pages
+            .get(page.index as usize)
+            .ok_or_else(|| CommandError::not_found("unknown page"))?;
+        let key = image_key(&page.source_id, page.index);
+        let decoded = decode_page(&stats, &page.source_id, &source, page.index, &key)?;
+        let description = describers.describe(meta, source.pages.len() as u32, &decoded);
+        tracing::debug!(
+            target: "commands::get_page_description",
+            source_id = %page.source_id.as_str(),
+            page_index = page.index,
+            panels = ?description.panel_count,
+            "described page"
+        );
+        Ok(description)
+    })
+    .await
+}
+
+/// Decode page `index` of `source` at full size and write it into `dir` as `format`, returning
+/// the written path. Files are named after the page so exports of a range sort like the source.
+fn export_one(
+    stats: &StatsCollector,
+    source_id: &SourceId,
+    source: &SourceData,
+    index: u32,
+    dir: &std::path::Path,
+    format: EncodeFormat,
+) -> CommandResult<std::path::PathBuf> {
+    let meta =
+        source.pages.get(index as usize).ok_or_else(|| CommandError::not_found("unknown page"))?;
+    let key = image_key(source_id, index);
+    let decoded = decode_page(stats, source_id, source, index, &key)?;
+    let encoded = encode(&decoded, format)?;
+    let stem = meta
+        .rel_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| format!("page-{:04}", index + 1));
+    let path = dir.join(format!("{:04}-{stem}.{}", index + 1, format.extension()));
+    std::fs::write(&path, &encoded.bytes)?;
+    Ok(path)
+}
+
+fn export_dir(dir: &str) -> CommandResult<std::path::PathBuf> {
+    let dir = std::path::PathBuf::from(dir);
+    if !dir.is_dir() {
+        return Err(CommandError::not_found(format!("export folder {} not found", dir.display())));
+    }
+    Ok(dir)
+}
+
+/// Save `page` at full resolution into the folder `dir` as `format`. `quality` (1–100) only
+/// applies to JPEG and defaults to 90.
+#[tauri::command]
+pub async fn export_page(
+    page: PageId,
+    dir: String,
+    format: ImageFormat,
+    quality: Option<u8>,
+    state: State<'_, AppState>,
+) -> CommandResult<String> {
+    let dir = export_dir(&dir)?;
+    let source = state.with_lock(|inner| {
+        inner
+            .sources
+            .get(page.source_id.as_str())
+            .cloned()
+            .ok_or_else(|| CommandError::not_found("unknown page"))
+    })?;
+    let stats = state.stats();
+    let format = format.encode_format(quality);
+    let path =
+        blocking(move || export_one(&stats, &page.source_id, &source, page.index, &dir, format))
+            .await?;
+    tracing::info!(target: "commands::export", path = %path.display(), "exported page");
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Save pages `start..=end` of `source_id` into the folder `dir` as `format`, emitting
+/// [`EXPORT_PROGRESS_EVENT`] after each page. A page that fails is reported and skipped rather
+/// than aborting the rest of the range.
+#[tauri::command]
+pub async fn export_range<R: tauri::Runtime>(
+    source_id: SourceId,
+    start: u32,
+    end: u32,
+    dir: String,
+    format: ImageFormat,
+    quality: Option<u8>,
+    window: tauri::Window<R>,
+    state: State<'_, AppState>,
+) -> CommandResult<ExportSummary> {
+    if start > end {
+        return Err(CommandError::invalid_input("export range starts after it ends"));
+    }
+    let dir = export_dir(&dir)?;
+    let source = state.with_lock(|inner| {
+        inner
+            .sources
+            .get(source_id.as_str())
+            .cloned()
+            .ok_or_else(|| CommandError::not_found("unknown source"))
+    })?;
+    if end as usize >= source.pages.len() {
+        return Err(CommandError::invalid_input(format!(
+            "export range ends past the last page ({})",
+            source.pages.len()
+        )));
+    }
+    let stats = state.stats();
+    let events = state.pipeline_events.clone();
+    let label = window.label().to_string();
+    let format = format.encode_format(quality);
+    let summary = blocking(move || {
+        let total = (end - start + 1) as usize;
+        let mut summary = ExportSummary { written: Vec::new(), failed: Vec::new() };
+        for (done, index) in (start..=end).enumerate() {
+            let path = match export_one(&stats, &source_id, &source, index, &dir, format) {
+                Ok(path) => {
+                    let path = path.to_string_lossy().to_string();
+                    summary.written.push(path.clone());
+                    Some(path)
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        target: "commands::export",
+                        source_id = %source_id.as_str(),
+                        page_index = index,
+                        "failed to export page: {err}"
+                    );
+                    summary.failed.push(index);
+                    None
+                }
+            };
+            let page = PageId { source_id: source_id.clone(), index };
+            let event = PipelineEvent::ExportProgress { page, done: done + 1, total, path };
+            let _ = events.send(event.from_window(&label));
+        }
+        Ok(summary)
+    })
+    .await?;
+    tracing::info!(
+        target: "commands::export",
+        written = summary.written.len(),
+        failed = summary.failed.len(),
+        "exported page range"
+    );
+    Ok(summary)
+}
+
+/// Put `page` on the system clipboard as a bitmap at full resolution.
+#[tauri::command]
+pub async fn copy_page_to_clipboard<R: tauri::Runtime>(
+    page: PageId,
+    app: tauri::AppHandle<R>,
+    state: State<'_, AppState>,
+) -> CommandResult<()> {
+    use tauri_plugin_clipboard_manager::ClipboardExt;
+
+    let source = state.with_lock(|inner| {
+        inner
+            .sources
+            .get(page.source_id.as_str())
+            .cloned()
+            .ok_or_else(|| CommandError::not_found("unknown page"))
+    })?;
+    let stats = state.stats();
+    let decoded = blocking({
+        let page = page.clone();
+        move || {
+            let key = image_key(&page.source_id, page.index);
+            decode_page(&stats, &page.source_id, &source, page.index, &key)
+        }
+    })
+    .await?;
+    let (width, height) = (decoded.width(), decoded.height());
+    let image = tauri::image::Image::new_owned(decoded.pixels, width, height);
+    app.clipboard()
+        .write_image(&image)
+        .map_err(|err| CommandError::internal(format!("failed to copy page: {err}")))?;
+    tracing::debug!(
+        target: "commands::clipboard",
+        source_id = %page.source_id.as_str(),
+        page_index = page.index,
+        width,
+        height,
+        "copied page to clipboard"
+    );
+    Ok(())
+}
+
+/// Read, decode and render the pages around `center` on the prefetch workers, replacing the
+/// previous window. Pages are cached at the size `params` displays them, so `get_page_url`
+/// can serve them without touching the source again. Without a `policy`, the window from the
+/// pipeline settings is used.
+#[tauri::command]
+pub fn prefetch<R: tauri::Runtime>(
+    center: PageId,
+    policy: Option<PrefetchPolicy>,
+    params: Option<RenderParams>,
+    velocity: Option<f32>,
+    window: tauri::Window<R>,
+    state: State<AppState>,
+) -> CommandResult<()> {
+    let source = state.with_lock(|inner| {
+        inner
+            .sources
+            .get(center.source_id.as_str())
+            .cloned()
+            .ok_or_else(|| CommandError::not_found("unknown source for prefetch"))
+    })?;
+    let total_pages = source.pages.len() as u32;
+    let params = state.render_params(params.as_ref())?;
+    let policy = policy.unwrap_or_else(|| state.settings().prefetch_policy());
+
+    // After a reversal, pages rendering on the side the reader left are wasted work unless
+    // the new window still covers them; cancel them before planning.
+    let reversed = state.with_lock(|inner| {
+        let reading = inner.windows.entry(window.label().to_string()).or_default();
+        Ok(reading.turn_prefetch(&center))
+    })?;
+    if let Some(abandoned) = reversed {
+        // The planner counts the window in views of the layout; see `plan_window`.
+        let per_view = params.layout.pages_per_view();
+        let index = center.index;
+        let ahead = policy.ahead.saturating_add(1).saturating_mul(per_view) - 1;
+        let behind = policy.behind.saturating_mul(per_view);
+        // Only this window's tasks: another window may be reading the same source.
+        let cancelled = state.prefetcher.owned_by(window.label()).cancel_where(|page| {
+            page.source_id == center.source_id
+                && match abandoned {
+                    PageDirection::Forward => page.index > index.saturating_add(ahead),
+                    PageDirection::Backward => page.index.saturating_add(behind) < index,
+                }
+        });
+        tracing::debug!(
+            target: "commands::prefetch",
+            source_id = %center.source_id.as_str(),
+            page_index = center.index,
+            ?abandoned,
+            cancelled,
+            "navigation reversed"
+        );
+    }
+
+    let cache = state.cache();
+    let stats = state.stats();
+    let source_id = center.source_id.clone();
+    let source = Arc::new(source);
+    let events = state.pipeline_events.clone();
+    let assets = state.assets.clone();
+    let label = window.label().to_string();
+    let job: PrefetchJob = Arc::new(move |task, cancel| {
+        let page = PageId { source_id: source_id.clone(), index: task.page.index };
+        let result =
+            render_page(&cache, &stats, &source_id, &source, page.index, &params, Some(cancel));
+        if cancel.is_cancelled() {
+            // Navigation left the page behind; the window that asked for it no longer waits.
+            return Ok(());
+        }
+        if let Ok(key) = &result {
+            let url = assets.image_url(key, None);
+            let _ = events
+                .send(PipelineEvent::PageReady { page: page.clone(), url }.from_window(&label));
+        }
+        let pending = stats.snapshot().prefetch_pending;
+        let event = PipelineEvent::PrefetchProgress { page, pending, failed: result.is_err() };
+        let _ = events.send(event.from_window(&label));
+        result.map(drop).map_err(|err| anyhow::anyhow!(err).into())
+    });
+
+    let queued = state.prefetcher.owned_by(window.label()).plan(
+        &center,
+        total_pages,
+        policy,
+        velocity.unwrap_or(0.0),
+        params.layout,
+        job,
+    )?;
+    tracing::debug!(
+        target: "commands::prefetch",
+        source_id = %center.source_id.as_str(),
+        page_index = center.index,
+        ahead = policy.ahead,
+        behind = policy.behind,
+        queued,
+        "scheduled prefetch"
+    );
+    Ok(())
+}
+
+/// Stop prefetching the source the calling window reads: the pages it queued are dropped and
+/// the ones being rendered for it are abandoned. Prefetches of other windows carry on, even
+/// when they read the same source.
+#[tauri::command]
+pub fn cancel_prefetch<R: tauri::Runtime>(
+    window: tauri::Window<R>,
+    state: State<AppState>,
+) -> CommandResult<()> {
+    let source_id = state.with_lock(|inner| {
+        Ok(inner.windows.get(window.label()).and_then(|reading| reading.source_id.clone()))
+    })?;
+    let Some(source_id) = source_id else {
+        tracing::debug!(target: "commands::cancel", window = window.label(), "cancel no-op");
+        return Ok(());
+    };
+    let cancelled = state
+        .prefetcher
+        .owned_by(window.label())
+        .cancel_matching(|page| page.source_id == source_id);
+    tracing::debug!(
+        target: "commands::cancel",
+        window = window.label(),
+        source_id = %source_id.as_str(),
+        cancelled,
+        "cancelled prefetch"
+    );
+    Ok(())
+}
+
+#[tauri::command]
+pub fn save_progress<R: tauri::Runtime>(
+    source_id: SourceId,
+    page: u32,
+    window: tauri::Window<R>,
+    state: State<AppState>,
+) -> CommandResult<()> {
+    let (core_page, series, location, page_count, previous) = state.with_lock(|inner| {
+        let Some(src) = inner.sources.get(source_id.as_str()) else {
+            return Err(CommandError::not_found("unknown source for progress"));
+        };
+        let series = src.kind.series_hint();
+        let location = src.kind.path().map(std::path::Path::to_path_buf);
+        let page_count = src.pages.len() as u32;
+        // Each window keeps its own cursor so two windows reading at once don't count each
+        // other's page turns.
+        let reading = inner.windows.entry(window.label().to_string()).or_default();
+        if reading.source_id.as_ref() != Some(&source_id) {
+            *reading = WindowState::reading(source_id.clone());
+        }
+        let previous = reading.cursor.replace(ReadingCursor { page, at: Instant::now() });
+        tracing::info!(
+            target: "commands::progress",
+            source_id = %source_id.as_str(),
+            page_index = page,
+            window = window.label(),
+            "progress saved"
+        );
+        Ok((
+            PageId { source_id: source_id.clone(), index: page },
+            series,
+            location,
+            page_count,
+            previous,
+        ))
+    })?;
+
+    if let Some(location) = location
+        && let Err(err) = state.library().record_position(&location, page, page_count)
+    {
+        tracing::warn!(target: "commands::progress", "failed to update library status: {err:#}");
+    }
+
+    if let Some(previous) = previous {
+        let pages = page.saturating_sub(previous.page);
+        let pages = if pages > MAX_PAGES_PER_STEP { 0 } else { pages };
+        let elapsed = previous.at.elapsed();
+        let time = if elapsed > MAX_READING_GAP { Duration::ZERO } else { elapsed };
+        if let Err(err) =
+            state.history().record(&core_page.source_id, series.as_deref(), pages, time)
+        {
+            tracing::warn!(target: "commands::progress", "failed to record reading history: {err:#}");
+        }
+    }
+
+    state.stores.autosave.discard(&source_id);
+    state.progress().save(&core_page).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn query_progress(source_id: SourceId, state: State<AppState>) -> CommandResult<u32> {
+    let core_source = state.with_lock(|inner| {
+        if inner.sources.contains_key(source_id.as_str()) {
+            Ok(source_id.clone())
+        } else {
+            Err(CommandError::not_found("unknown source for progress"))
+        }
+    })?;
+
+    let stored = state.progress().load(&core_source)?;
+    Ok(stored.map(|page| page.index).unwrap_or(0))
+}
+
+/// Recently opened sources, pinned first. Entries whose path has gone away are kept and flagged
+/// with `exists: false` so the UI can offer to remove them instead of failing on open.
+#[tauri::command]
+pub fn get_recent_sources(state: State<AppState>) -> CommandResult<Vec<RecentSource>> {
+    let items = state.recent().list()?;
+    Ok(items
+        .into_iter()
+        .map(|item| RecentSource {
+            path: item.entry.path.to_string_lossy().to_string(),
+            pinned: item.entry.pinned,
+            opened_ms: item.entry.opened_ms,
+            exists: item.exists,
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub fn pin_recent(path: String, pinned: bool, state: State<AppState>) -> CommandResult<()> {
+    if state.recent().pin(std::path::Path::new(&path), pinned)? {
+        Ok(())
+    } else {
+        Err(CommandError::not_found("path is not in the recent list"))
+    }
+}
+
+/// Forget recently opened sources and return how many entries were removed. With
+/// `missing_only`, only unpinned entries whose path no longer exists are dropped; otherwise
+/// the list is emptied, keeping pinned entries when `keep_pinned` is set.
+#[tauri::command]
+pub fn clear_recent(
+    keep_pinned: Option<bool>,
+    missing_only: Option<bool>,
+    state: State<AppState>,
+) -> CommandResult<usize> {
+    let removed = if missing_only.unwrap_or(false) {
+        state.recent().prune_missing()?
+    } else {
+        state.recent().clear(keep_pinned.unwrap_or(false))?
+    };
+    tracing::debug!(target: "commands::recent", removed, "cleared recent sources");
+    Ok(removed)
+}
+
+#[tauri::command]
+pub fn list_library(
+    include_hidden: Option<bool>,
+    status: Option<ReadingStatus>,
+    collection: Option<u64>,
+    state: State<AppState>,
+) -> CommandResult<Vec<LibraryEntry>> {
+    let include_hidden = include_hidden.unwrap_or(false);
+    let entries = match collection {
+        Some(id) => {
+            let collection = state.collections().get(id)?.ok_or_else(|| {
+                CommandError::not_found(format!("collection {id} does not exist"))
+            })?;
+            state.library().list_paths(&collection.members, include_hidden)
+        }
+        None => state.library().list(include_hidden),
+    }?;
+    Ok(entries
+        .into_iter()
+        .filter(|entry| status.is_none_or(|status| entry.status == status))
+        .collect())
+}
+
+#[tauri::command]
+pub fn list_collections(state: State<AppState>) -> CommandResult<Vec<Collection>> {
+    Ok(state.collections().list()?)
+}
+
+#[tauri::command]
+pub fn create_collection(name: String, state: State<AppState>) -> CommandResult<Collection> {
+    state.collections().create(&name).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn rename_collection(
+    id: u64,
+    name: String,
+    state: State<AppState>,
+) -> CommandResult<Collection> {
+    state.collections().rename(id, &name).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn delete_collection(id: u64, state: State<AppState>) -> CommandResult<bool> {
+    state.collections().delete(id).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn add_to_collection(
+    id: u64,
+    path: String,
+    position: Option<usize>,
+    state: State<AppState>,
+) -> CommandResult<Collection> {
+    state
+        .collections()
+        .add_member(id, std::path::Path::new(&path), position)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn remove_from_collection(
+    id: u64,
+    path: String,
+    state: State<AppState>,
+) -> CommandResult<Collection> {
+    state.collections().remove_member(id, std::path::Path::new(&path)).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn reorder_collection(
+    id: u64,
+    paths: Vec<String>,
+    state: State<AppState>,
+) -> CommandResult<Collection> {
+    let order: Vec<std::path::PathBuf> = paths.into_iter().map(Into::into).collect();
+    state.collections().reorder(id, &order).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn set_source_status(
+    path: String,
+    status: ReadingStatus,
+    state: State<AppState>,
+) -> CommandResult<LibraryEntry> {
+    let entry = state
+        .library()
+        .set_status(std::path::Path::new(&path), status)?
+        .ok_or_else(|| CommandError::not_found("path is not in the library"))?;
+    Ok(entry)
+}
+
+/// Remember the reading direction and page layout of the source at `path`.
+#[tauri::command]
+pub fn set_reading_mode(
+    path: String,
+    direction: ReadingDirection,
+    layout: PageLayout,
+    state: State<AppState>,
+) -> CommandResult<LibraryEntry> {
+    let entry = state
+        .library()
+        .set_reading_mode(std::path::Path::new(&path), direction, layout)?
+        .ok_or_else(|| CommandError::not_found("path is not in the library"))?;
+    tracing::debug!(
+        target: "commands::library",
+        path = %path,
+        ?direction,
+        ?layout,
+        "set reading mode"
+    );
+    Ok(entry)
+}
+
+#[tauri::command]
+pub fn series_status(state: State<AppState>) -> CommandResult<Vec<SeriesStatus>> {
+    Ok(state.library().series_status()?)
+}
+
+/// Remove a source from the library view. Files on disk are left untouched.
+#[tauri::command]
+pub fn hide_source(path: String, state: State<AppState>) -> CommandResult<LibraryEntry> {
+    let entry = state
+        .library()
+        .hide(std::path::Path::new(&path))?
+        .ok_or_else(|| CommandError::not_found("path is not in the library"))?;
+    tracing::info!(target: "commands::library", path = %path, "hid library source");
+    Ok(entry)
+}
+
+#[tauri::command]
+pub fn restore_source(path: String, state: State<AppState>) -> CommandResult<LibraryEntry> {
+    let entry = state
+        .library()
+        .restore(std::path::Path::new(&path))?
+        .ok_or_else(|| CommandError::not_found("path is not in the library"))?;
+    tracing::info!(target: "commands::library", path = %path, "restored library source");
+    Ok(entry)
+}
+
+#[tauri::command]
+pub fn reading_stats(days: Option<u32>, state: State<AppState>) -> CommandResult<ReadingStats> {
+    let summary = state.history().summary(days.unwrap_or(30))?;
+    Ok(ReadingStats {
+        daily: summary
+            .daily
+            .into_iter()
+            .map(|record| DailyReading {
+                day: record.day,
+                date: history_store::format_day(record.day),
+                pages: record.totals.pages,
+                seconds: record.totals.seconds,
+            })
+            .collect(),
+        current_streak: summary.current_streak,
+        longest_streak: summary.longest_streak,
+        lifetime_pages: summary.lifetime.pages,
+        lifetime_seconds: summary.lifetime.seconds,
+        series: summary
+            .series
+            .into_iter()
+            .map(|entry| SeriesReading {
+                series: entry.series,
+                pages: entry.totals.pages,
+                seconds: entry.totals.seconds,
+            })
+            .collect(),
+    })
+}
+
+/// Export reading history between the optional ISO dates `from` and `to` (inclusive). The
+/// export is returned and, when `path` is given, also written to that file.
+#[tauri::command]
+pub fn export_stats(
+    format: ExportFormat,
+    from: Option<String>,
+    to: Option<String>,
+    path: Option<String>,
+    state: State<AppState>,
+) -> CommandResult<String> {
+    let parse = |date: Option<String>| {
+        date.map(|date| history_store::parse_day(&date)).transpose().map_err(CommandError::from)
+    };
+    let (from_day, to_day) = (parse(from)?, parse(to)?);
+    if let (Some(from_day), Some(to_day)) = (from_day, to_day)
+        && from_day > to_day
+    {
+        return Err(CommandError::invalid_input("export range starts after it ends"));
+    }
+
+    let content = export_store::export_history(state.history(), format, from_day, to_day)?;
+    if let Some(path) = path {
+        std::fs::write(&path, &content)?;
+        tracing::info!(target: "commands::export", path = %path, "exported reading stats");
+    }
+    Ok(content)
+}
+
+#[tauri::command]
+pub fn list_annotations(
+    source_id: SourceId,
+    page: Option<u32>,
+    state: State<AppState>,
+) -> CommandResult<Vec<Annotation>> {
+    Ok(state.annotations().list(&source_id, page)?)
+}
+
+#[tauri::command]
+pub fn add_annotation(
+    page: PageId,
+    text: String,
+    rect: Option<AnnotationRect>,
+    state: State<AppState>,
+) -> CommandResult<Annotation> {
+    state.annotations().add(&page, text, rect).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn edit_annotation(
+    id: u64,
+    text: String,
+    rect: Option<AnnotationRect>,
+    state: State<AppState>,
+) -> CommandResult<Annotation> {
+    state.annotations().edit(id, text, rect).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn delete_annotation(id: u64, state: State<AppState>) -> CommandResult<bool> {
+    state.annotations().delete(id).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn store_recovery_report() -> Vec<StoreRecovery> {
+    recovery_store::reports()
+        .into_iter()
+        .map(|report| {
+            let (restored_from, quarantined) = match report.action {
+                RecoveryAction::RestoredBackup { backup } => {
+                    (Some(backup.to_string_lossy().to_string()), None)
+                }
+                RecoveryAction::Reset { quarantined } => {
+                    (None, Some(quarantined.to_string_lossy().to_string()))
+                }
+            };
+            StoreRecovery {
+                path: report.path.to_string_lossy().to_string(),
+                reason: report.reason,
+                restored_from,
+                quarantined,
+                at_ms: report.at_ms,
+            }
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub fn dismiss_recovery_report() {
+    recovery_store::clear();
+}
+
+#[tauri::command]
+pub fn list_backups(state: State<AppState>) -> CommandResult<Vec<BackupInfo>> {
+    backup_store::list(state.progress().root()).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn create_backup(state: State<AppState>) -> CommandResult<BackupInfo> {
+    state.stores().flush();
+    backup_store::create(state.progress().root(), BackupReason::Manual, backup_store::DEFAULT_KEEP)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn restore_backup(timestamp: u64, state: State<AppState>) -> CommandResult<BackupInfo> {
+    // Buffered progress would otherwise be written over the restored files later on.
+    state.stores().flush();
+    let restored =
+        backup_store::restore(state.progress().root(), timestamp, backup_store::DEFAULT_KEEP)?;
+    tracing::info!(target: "commands::backup", timestamp, "restored store backup");
+    Ok(restored)
+}
+
+const KEYCHAIN_SERVICE: &str = "local-comic-reader";
+
+fn keychain_entry(root: &std::path::Path) -> CommandResult<keyring::Entry> {
+    let profile = profile_store::active(root)?;
+    keyring::Entry::new(KEYCHAIN_SERVICE, &format!("store-key-{}", profile.as_str()))
+        .map_err(CommandError::from)
+}
+
+/// Unlock the active profile with its keychain key, if it is encrypted that way.
+pub fn unlock_from_keychain(root: &std::path::Path) {
+    let status = match crypto_store::status(root) {
+        Ok(status) => status,
+        Err(err) => {
+            tracing::warn!(target: "commands::crypto", "failed to read encryption status: {err:#}");
+            return;
+        }
+    };
+    if status.unlocked || status.source != Some(KeySource::Keychain) {
+        return;
+    }
+
+    let result = keychain_entry(root)
+        .and_then(|entry| entry.get_password().map_err(CommandError::from))
+        .and_then(|hex| StoreKey::from_hex(&hex).map_err(CommandError::from))
+        .and_then(|key| crypto_store::unlock_with_key(root, &key).map_err(CommandError::from));
+    if let Err(err) = result {
+        tracing::warn!(target: "commands::crypto", "failed to unlock store from keychain: {err}");
+    }
+}
+
+#[tauri::command]
+pub fn encryption_status(state: State<AppState>) -> CommandResult<EncryptionStatus> {
+    crypto_store::status(state.progress().root()).map_err(CommandError::from)
+}
+
+/// Encrypt the active profile's progress, history and annotations. Without a passphrase a
+/// random key is generated and kept in the OS keychain.
+#[tauri::command]
+pub fn enable_encryption(passphrase: Option<String>, state: State<AppState>) -> CommandResult<()> {
+    let root = state.progress().root();
+    match passphrase {
+        Some(passphrase) => crypto_store::enable_with_passphrase(root, &passphrase)?,
+        None => {
+            let key = StoreKey::generate();
+            let entry = keychain_entry(root)?;
+            entry.set_password(&key.to_hex())?;
+            if let Err(err) = crypto_store::enable_with_key(root, &key) {
+                let _ = entry.delete_credential();
+                return Err(err.into());
+            }
+        }
+    }
+    tracing::info!(target: "commands::crypto", "store encryption enabled");
+    Ok(())
+}
+
+#[tauri::command]
+pub fn unlock_store(passphrase: String, state: State<AppState>) -> CommandResult<()> {
+    crypto_store::unlock_with_passphrase(state.progress().root(), &passphrase)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn lock_store(state: State<AppState>) -> CommandResult<()> {
+    crypto_store::lock(state.progress().root()).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn disable_encryption(state: State<AppState>) -> CommandResult<()> {
+    let root = state.progress().root();
+    let status = crypto_store::status(root)?;
+    crypto_store::disable(root)?;
+    if status.source == Some(KeySource::Keychain)
+        && let Err(err) = keychain_entry(root)
+            .and_then(|entry| entry.delete_credential().map_err(CommandError::from))
+    {
+        tracing::warn!(target: "commands::crypto", "failed to remove keychain entry: {err}");
+    }
+    tracing::info!(target: "commands::crypto", "store encryption disabled");
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_profiles(state: State<AppState>) -> CommandResult<ProfileList> {
+    let root = state.progress().root();
+    let active = profile_store::active(root)?;
+    let profiles = profile_store::list(root)?;
+    Ok(ProfileList {
+        active: active.as_str().to_string(),
+        profiles: profiles.iter().map(|name| name.as_str().to_string()).collect(),
+    })
+}
+
+#[tauri::command]
+pub fn switch_profile(name: String, state: State<AppState>) -> CommandResult<()> {
+    let profile = ProfileName::new(name)?;
+    state.stores().switch_profile(&profile)?;
+    unlock_from_keychain(state.progress().root());
+    // Only the reading preferences differ between profiles; the machine knobs stay as applied.
+    let settings = pipeline_settings::load(state.progress().root())?;
+    *state.settings.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = settings;
+    tracing::info!(target: "commands::profile", profile = profile.as_str(), "switched profile");
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stats(state: State<AppState>) -> CommandResult<PerfStats> {
+    perf_stats(&state)
+}
+
+/// Default and accepted range of the `start_stats_push` interval, in milliseconds.
+const STATS_PUSH_INTERVAL_MS: u64 = 1_000;
+const STATS_PUSH_INTERVAL_RANGE_MS: std::ops::RangeInclusive<u64> = 100..=10_000;
+
+/// Push a [`PERF_SNAPSHOT_EVENT`] to this window every `interval_ms` (one second by default)
+/// instead of having it poll `stats`, e.g. while the HUD is open. Calling it again changes the
+/// interval; `stop_stats_push` or closing the window stops it.
+#[tauri::command]
+pub fn start_stats_push<R: tauri::Runtime>(
+    interval_ms: Option<u64>,
+    window: tauri::Window<R>,
+    app: tauri::AppHandle<R>,
+    state: State<AppState>,
+) -> CommandResult<()> {
+    let interval_ms = interval_ms.unwrap_or(STATS_PUSH_INTERVAL_MS);
+    if !STATS_PUSH_INTERVAL_RANGE_MS.contains(&interval_ms) {
+        return Err(CommandError::invalid_input(format!(
+            "stats interval must be between {} and {} ms",
+            STATS_PUSH_INTERVAL_RANGE_MS.start(),
+            STATS_PUSH_INTERVAL_RANGE_MS.end()
+        )));
+    }
+    let label = window.label().to_string();
+    let (stop, stopped) = std::sync::mpsc::channel();
+    // Replacing the previous sender stops its pusher.
+    state.with_lock(|inner| {
+        inner.stats_push.insert(label.clone(), stop);
+        Ok(())
+    })?;
+    let interval = Duration::from_millis(interval_ms);
+    if let Err(err) = push_stats(app, label.clone(), interval, stopped) {
+        state.with_lock(|inner| {
+            inner.stats_push.remove(&label);
+            Ok(())
+        })?;
+        return Err(CommandError::internal(format!("failed to start stats push: {err}")));
+    }
+    tracing::debug!(target: "commands::stats", window = %label, interval_ms, "started stats push");
+    Ok(())
+}
+
+/// Stop the snapshots started by `start_stats_push` for this window.
 #[tauri::command]
-pub fn stats(state: State<AppState>) -> Result<PerfStats, String> {
+pub fn stop_stats_push<R: tauri::Runtime>(
+    window: tauri::Window<R>,
+    state: State<AppState>,
+) -> CommandResult<()> {
+    let stopped = state.with_lock(|inner| Ok(inner.stats_push.remove(window.label()).is_some()))?;
+    if stopped {
+        tracing::debug!(target: "commands::stats", window = window.label(), "stopped stats push");
+    }
+    Ok(())
+}
+
+fn perf_stats(state: &AppState) -> CommandResult<PerfStats> {
     let (active_sources, cached_pages) = state.with_lock(|inner| {
         Ok((inner.sources.len(), inner.sources.values().map(|src| src.pages.len()).sum::<usize>()))
     })?;
@@ -485,20 +2725,473 @@ pub fn stats(state: State<AppState>) -> Result<PerfStats, String> {
     Ok(PerfStats { snapshot, active_sources, cached_pages })
 }
 
+/// Most frame timestamps `report_frames` accepts in one call.
+const MAX_FRAME_BATCH: usize = 1_000;
+
+/// Record a batch of frame timestamps (milliseconds, e.g. `requestAnimationFrame` times) so the
+/// HUD reports real frame cadence.
+#[tauri::command]
+pub fn report_frames(batch: Vec<f64>, state: State<AppState>) -> CommandResult<()> {
+    if batch.len() > MAX_FRAME_BATCH {
+        return Err(CommandError::invalid_input(format!(
+            "frame batch exceeds {MAX_FRAME_BATCH} timestamps"
+        )));
+    }
+    state.stats().record_frame_timestamps(&batch);
+    Ok(())
+}
+
+/// Human-readable report on how the image cache budget is spent.
+#[tauri::command]
+pub fn cache_efficiency_report(state: State<AppState>) -> String {
+    state.cache().efficiency_report().to_string()
+}
+
+#[tauri::command]
+pub fn stats_history(state: State<AppState>) -> Vec<PerfSnapshot> {
+    state.stats().history()
+}
+
+/// Recent events, a performance snapshot and version information for a bug report.
+#[tauri::command]
+pub fn collect_diagnostics(state: State<AppState>) -> Diagnostics {
+    reader_core::log::collect_diagnostics(&state.stats())
+}
+
+/// Most lines `read_log_tail` returns in one call.
+const MAX_LOG_TAIL_LINES: usize = 5_000;
+
+/// The last `lines` lines of the active log file, optionally only those at `level_filter`
+/// (e.g. `"warn"`) or more severe.
+#[tauri::command]
+pub fn read_log_tail(lines: usize, level_filter: Option<String>) -> CommandResult<Vec<String>> {
+    let max_level = level_filter
+        .map(|level| level.parse::<reader_core::log::LogLevel>())
+        .transpose()
+        .map_err(|err| CommandError::invalid_input(err.to_string()))?;
+    let Some(handle) = reader_core::log::handle() else {
+        return Err(CommandError::internal("logging is not initialised"));
+    };
+    handle.tail(lines.min(MAX_LOG_TAIL_LINES), max_level).map_err(CommandError::from)
+}
+
+/// The saved log filter directive, if any.
+#[tauri::command]
+pub fn get_log_filter(state: State<AppState>) -> CommandResult<Option<String>> {
+    reader_core::store::log_settings::load_filter(state.progress().root())
+        .map_err(CommandError::from)
+}
+
+/// Formats and optional features this build supports, and the limits of its settings, so the
+/// UI can hide what would fail at open time.
+#[tauri::command]
+pub fn get_capabilities() -> Capabilities {
+    Capabilities::detect()
+}
+
+/// Cache and pipeline settings currently in effect.
+#[tauri::command]
+pub fn get_settings(state: State<AppState>) -> PipelineSettings {
+    state.settings()
+}
+
+/// Validate, save and apply `settings`: the image cache is trimmed to the new budget and the
+/// prefetch workers are resized right away. Returns the settings now in effect.
+#[tauri::command]
+pub async fn set_settings(
+    settings: PipelineSettings,
+    state: State<'_, AppState>,
+) -> CommandResult<PipelineSettings> {
+    pipeline_settings::save(state.progress().root(), &settings)?;
+    let (cache, prefetcher) = (state.cache(), Arc::clone(&state.prefetcher));
+    let evicted = blocking(move || {
+        prefetcher.set_threads(settings.worker_threads())?;
+        cache.set_budget(settings.cache_budget_bytes())
+    })
+    .await?;
+    apply_decode_sandbox(settings.sandbox_decode);
+    *state.settings.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = settings;
+    tracing::info!(
+        target: "commands::settings",
+        cache_budget_mb = settings.cache_budget_mb,
+        threads = settings.worker_threads(),
+        filter = ?settings.resize_filter,
+        sandbox = settings.sandbox_decode,
+        evicted,
+        "applied pipeline settings"
+    );
+    Ok(settings)
+}
+
+/// Decode pages in helper processes running this executable when `enabled`, or in-process
+/// otherwise.
+pub fn apply_decode_sandbox(enabled: bool) {
+    if enabled == sandbox::is_installed() {
+        return;
+    }
+    if !enabled {
+        sandbox::install(None);
+        tracing::info!(target: "commands::settings", "decoding pages in-process");
+        return;
+    }
+    match std::env::current_exe() {
+        Ok(exe) => {
+            sandbox::install(Some(DecodeSandbox::new(exe, [sandbox::HELPER_ARG])));
+            tracing::info!(target: "commands::settings", "decoding pages in a helper process");
+        }
+        Err(err) => tracing::warn!(
+            target: "commands::settings",
+            "cannot locate the executable for the decode helper, decoding in-process: {err}"
+        ),
+    }
+}
+
+/// Save a log filter directive (e.g. `info,reader_core::pipeline=trace`), or clear it with
+/// `None`, and apply it to the running logger.
+#[tauri::command]
+pub fn set_log_filter(filter: Option<String>, state: State<AppState>) -> CommandResult<()> {
+    let filter = filter.as_deref().map(str::trim).filter(|filter| !filter.is_empty());
+    reader_core::store::log_settings::save_filter(state.progress().root(), filter)?;
+    if reader_core::log::filter_from_env() {
+        tracing::info!(target: "commands::log", "saved log filter; environment filter stays active");
+        return Ok(());
+    }
+    if let Some(handle) = reader_core::log::handle() {
+        handle.set_filter(filter)?;
+    }
+    Ok(())
+}
+
+/// Translate the `userMessage` of errors into the language of the BCP 47 tag `locale` (e.g.
+/// `de-AT`), falling back to English. Returns the tag of the catalog locale now in use.
+#[tauri::command]
+pub fn set_locale(locale: String) -> &'static str {
+    let locale = error::Locale::from_tag(&locale);
+    error::set_locale(locale);
+    tracing::debug!(target: "commands::locale", locale = locale.tag(), "set error locale");
+    locale.tag()
+}
+
+/// Every action with the gestures bound to it. Windows follow changes through
+/// [`STORE_CHANGED_EVENT`].
+#[tauri::command]
+pub fn get_keymap(state: State<AppState>) -> Vec<KeyBinding> {
+    key_bindings(&state.keymap().get())
+}
+
+/// Bind exactly `gestures` to `action`, or unbind it with an empty list. Gestures bound to
+/// another action are refused rather than moved.
+#[tauri::command]
+pub fn set_binding(
+    action: String,
+    gestures: Vec<String>,
+    state: State<AppState>,
+) -> CommandResult<Vec<KeyBinding>> {
+    let keymap = state.keymap().set_binding(&action, &gestures)?;
+    tracing::debug!(target: "commands::keymap", action = %action, ?gestures, "set binding");
+    Ok(key_bindings(&keymap))
+}
+
+/// Restore the default gestures of `action`, or of every action when it is omitted.
+#[tauri::command]
+pub fn reset_keymap(
+    action: Option<String>,
+    state: State<AppState>,
+) -> CommandResult<Vec<KeyBinding>> {
+    let keymap = state.keymap().reset(action.as_deref())?;
+    tracing::debug!(target: "commands::keymap", action = ?action, "reset keymap");
+    Ok(key_bindings(&keymap))
+}
+
+/// Action bound to `gesture`, accepting any modifier order or alias, or `None` when unbound.
+#[tauri::command]
+pub fn resolve_gesture(gesture: String, state: State<AppState>) -> Option<ActionId> {
+    state.keymap().resolve(&gesture).cloned()
+}
+
+/// Clear collected stats, optionally resizing the percentile windows (in samples).
+#[tauri::command]
+pub fn reset_stats(
+    frame_window: Option<usize>,
+    decode_window: Option<usize>,
+    state: State<AppState>,
+) {
+    let stats = state.stats();
+    if frame_window.is_some() || decode_window.is_some() {
+        let current = stats.sample_windows();
+        stats.set_sample_windows(core_stats::SampleWindows {
+            frames: frame_window.unwrap_or(current.frames),
+            decodes: decode_window.unwrap_or(current.decodes),
+        });
+    }
+    stats.reset();
+    tracing::info!(target: "commands::stats", ?frame_window, ?decode_window, "reset stats");
+}
+
+/// Current metrics in OpenMetrics text format, for scraping through the frontend.
+#[tauri::command]
+pub fn stats_openmetrics(state: State<AppState>) -> String {
+    core_stats::openmetrics::render(&state.stats())
+}
+
+#[tauri::command]
+pub fn decode_stats(state: State<AppState>) -> Vec<DecodeLabelStats> {
+    state.stats().decode_breakdown()
+}
+
+/// Read, decode and resize the pages of `source_id` and report how long each stage took.
+/// Unset options fall back to the pipeline settings, so the numbers match what reading does;
+/// `threads` is capped like the decode threads setting.
+#[tauri::command]
+pub async fn run_benchmark(
+    source_id: SourceId,
+    threads: Option<usize>,
+    longest: Option<u32>,
+    filter: Option<ResizeFilter>,
+    pages: Option<usize>,
+    state: State<'_, AppState>,
+) -> CommandResult<BenchReport> {
+    let source = state.with_lock(|inner| {
+        inner
+            .sources
+            .get(source_id.as_str())
+            .cloned()
+            .ok_or_else(|| CommandError::not_found("unknown source"))
+    })?;
+    let settings = state.settings();
+    let defaults = BenchConfig::default();
+    let config = BenchConfig {
+        threads: threads
+            .unwrap_or_else(|| settings.worker_threads())
+            .clamp(1, pipeline_settings::MAX_DECODE_THREADS),
+        longest: longest.unwrap_or(defaults.longest),
+        filter: filter.unwrap_or(settings.resize_filter),
+        max_pages: pages,
+    };
+    let report = blocking(move || {
+        let read = |page: &PageMeta| source.page_source(page.id.index).0.read();
+        Ok(core_bench::run_benchmark(&source.pages, read, config)?)
+    })
+    .await?;
+    tracing::info!(
+        target: "commands::run_benchmark",
+        source_id = %source_id.as_str(),
+        pages = report.pages,
+        failed = report.failed,
+        threads = report.threads,
+        pages_per_sec = report.pages_per_sec,
+        "benchmark finished"
+    );
+    Ok(report)
+}
+
 pub fn register<R: tauri::Runtime>(
     builder: tauri::Builder<R>,
     cache: Arc<ImageCache>,
     metrics: Arc<StatsCollector>,
+    stores: Stores,
+    prefetcher: WorkerPool,
+    settings: PipelineSettings,
+    assets: AccessToken,
 ) -> tauri::Builder<R> {
-    builder.manage(AppState::new(cache, metrics)).invoke_handler(tauri::generate_handler![
-        open_path,
-        list_pages,
-        get_page_url,
-        get_thumb_url,
-        prefetch,
-        cancel,
-        save_progress,
-        query_progress,
-        stats
-    ])
+    let events = stores.events.subscribe();
+    let (pipeline_sender, pipeline_events) = std::sync::mpsc::channel();
+    sample_stats(Arc::clone(&metrics));
+    // Slow operations are always logged; only debug builds surface them in the UI.
+    let slow = cfg!(debug_assertions).then(|| metrics.subscribe_slow_ops());
+    builder
+        .setup(move |app| {
+            forward_store_events(app.handle().clone(), events);
+            forward_pipeline_events(app.handle().clone(), pipeline_events);
+            if let Some(slow) = slow {
+                forward_slow_operations(app.handle().clone(), slow);
+            }
+            Ok(())
+        })
+        .on_window_event(|window, event| {
+            if let tauri::WindowEvent::Destroyed = event {
+                use tauri::Manager;
+                window.state::<AppState>().forget_window(window.label());
+            }
+        })
+        .manage(AppState::new(
+            cache,
+            metrics,
+            stores,
+            prefetcher,
+            pipeline_sender,
+            settings,
+            assets,
+        ))
+        .invoke_handler(tauri::generate_handler![
+            open_path,
+            #[cfg(feature = "opds")]
+            opds_feed,
+            #[cfg(feature = "opds")]
+            opds_search,
+            #[cfg(feature = "opds")]
+            opds_open,
+            open_reader_window,
+            get_window_source,
+            list_pages,
+            list_pages_range,
+            search_pages,
+            resolve_fit,
+            get_page_url,
+            get_spread_url,
+            get_thumb_url,
+            get_thumb_urls,
+            get_library_thumbs,
+            get_page_pixels,
+            get_page_description,
+            export_page,
+            export_range,
+            copy_page_to_clipboard,
+            prefetch,
+            cancel_prefetch,
+            save_progress,
+            query_progress,
+            get_recent_sources,
+            pin_recent,
+            clear_recent,
+            list_library,
+            hide_source,
+            restore_source,
+            set_source_status,
+            set_reading_mode,
+            series_status,
+            list_collections,
+            create_collection,
+            rename_collection,
+            delete_collection,
+            add_to_collection,
+            remove_from_collection,
+            reorder_collection,
+            reading_stats,
+            export_stats,
+            list_annotations,
+            add_annotation,
+            edit_annotation,
+            delete_annotation,
+            store_recovery_report,
+            dismiss_recovery_report,
+            list_backups,
+            create_backup,
+            restore_backup,
+            encryption_status,
+            enable_encryption,
+            unlock_store,
+            lock_store,
+            disable_encryption,
+            list_profiles,
+            switch_profile,
+            stats,
+            start_stats_push,
+            stop_stats_push,
+            stats_history,
+            report_frames,
+            cache_efficiency_report,
+            decode_stats,
+            run_benchmark,
+            stats_openmetrics,
+            reset_stats,
+            read_log_tail,
+            get_capabilities,
+            get_settings,
+            set_settings,
+            get_log_filter,
+            set_log_filter,
+            set_locale,
+            get_keymap,
+            set_binding,
+            reset_keymap,
+            resolve_gesture,
+            collect_diagnostics
+        ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader_core::fs::SourceEntry;
+    use reader_core::types::CacheBudget;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::AtomicUsize;
+
+    /// One page whose reads are slow and counted.
+    #[derive(Debug, Default)]
+    struct CountingSource {
+        png: Vec<u8>,
+        reads: AtomicUsize,
+    }
+
+    impl PageByteSource for CountingSource {
+        fn entries(&self) -> reader_core::Result<Vec<SourceEntry>> {
+            let path = PathBuf::from("page.png");
+            Ok(vec![SourceEntry { path, size_bytes: self.png.len() as u64, modified: None }])
+        }
+
+        fn read(&self, _path: &Path) -> reader_core::Result<Vec<u8>> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(100));
+            Ok(self.png.clone())
+        }
+    }
+
+    #[test]
+    fn racing_page_renders_read_the_source_once() {
+        let temp = tempfile::tempdir().unwrap();
+        let stats = Arc::new(StatsCollector::default());
+        let cache = ImageCache::with_root(
+            temp.path().join("cache"),
+            CacheBudget::default(),
+            Arc::clone(&stats),
+        )
+        .unwrap();
+        let page = DecodedImage {
+            dimensions: ImageDimensions { width: 8, height: 8 },
+            pixels: vec![200; 8 * 8 * 4],
+        };
+        let png = encode(&page, EncodeFormat::Png).unwrap().bytes;
+        let counting = Arc::new(CountingSource { png, ..CountingSource::default() });
+        let source_id = SourceId::new("racing");
+        let pages = counting.list_pages(&source_id).unwrap();
+        let provided: Arc<dyn PageByteSource> = counting.clone();
+        let source =
+            SourceData { kind: SourceKind::Provided { path: None, source: provided }, pages };
+        let params = RenderParams::default();
+
+        let keys: Vec<ImageKey> = std::thread::scope(|scope| {
+            let renders: Vec<_> = (0..2)
+                .map(|_| {
+                    scope.spawn(|| {
+                        render_page(&cache, &stats, &source_id, &source, 0, &params, None).unwrap()
+                    })
+                })
+                .collect();
+            renders.into_iter().map(|render| render.join().unwrap()).collect()
+        });
+        assert_eq!(keys[0], keys[1]);
+        assert!(cache.contains(&keys[0]));
+        assert_eq!(counting.reads.load(Ordering::SeqCst), 1);
+    }
+    #[test]
+    fn switching_profiles_saves_shown_pages_to_the_previous_one() {
+        let temp = tempfile::tempdir().unwrap();
+        let stores = Stores::open(temp.path()).unwrap();
+        let source = SourceId::new("volume-1");
+        let shown = ShownPage { page: 7, page_count: 20, location: None };
+        stores.autosave.note(&source, shown);
+
+        let kid = ProfileName::new("kid").unwrap();
+        stores.switch_profile(&kid).unwrap();
+        // A later autosave tick has nothing left to write into the new profile.
+        assert_eq!(stores.autosave.save_all(), 0);
+        stores.flush();
+        assert_eq!(stores.progress.load(&source).unwrap(), None);
+
+        stores.switch_profile(&ProfileName::default()).unwrap();
+        let saved = stores.progress.load(&source).unwrap().expect("saved under the first profile");
+        assert_eq!(saved.index, 7);
+    }
 }
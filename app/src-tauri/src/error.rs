@@ -0,0 +1,256 @@
+//! Error returned by every command, with a friendly message from a small per-locale catalog.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use reader_core::error::{CoreError, ErrorKind};
+use serde::Serialize;
+
+/// What went wrong, for the UI to pick a message or recovery action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCode {
+    NotFound,
+    PermissionDenied,
+    UnsupportedFormat,
+    Corrupt,
+    Locked,
+    InvalidInput,
+    Io,
+    Internal,
+}
+
+impl From<ErrorKind> for ErrorCode {
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::NotFound => ErrorCode::NotFound,
+            ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+            ErrorKind::UnsupportedFormat => ErrorCode::UnsupportedFormat,
+            ErrorKind::Corrupt => ErrorCode::Corrupt,
+            ErrorKind::Locked => ErrorCode::Locked,
+            ErrorKind::InvalidInput => ErrorCode::InvalidInput,
+            ErrorKind::Io => ErrorCode::Io,
+            ErrorKind::Internal => ErrorCode::Internal,
+        }
+    }
+}
+
+impl ErrorCode {
+    /// Message shown to users for this code, in `locale`.
+    pub fn user_message(self, locale: Locale) -> &'static str {
+        use ErrorCode::*;
+        use Locale::*;
+        match (self, locale) {
+            (NotFound, En) => "The file or page could not be found.",
+            (NotFound, De) => "Die Datei oder Seite wurde nicht gefunden.",
+            (NotFound, Fr) => "Le fichier ou la page est introuvable.",
+            (NotFound, Ja) => "ファイルまたはページが見つかりません。",
+            (PermissionDenied, En) => "You don't have permission to open this file.",
+            (PermissionDenied, De) => "Sie haben keine Berechtigung, diese Datei zu öffnen.",
+            (PermissionDenied, Fr) => "Vous n'avez pas l'autorisation d'ouvrir ce fichier.",
+            (PermissionDenied, Ja) => "このファイルを開く権限がありません。",
+            (UnsupportedFormat, En) => "This file type isn't supported.",
+            (UnsupportedFormat, De) => "Dieser Dateityp wird nicht unterstützt.",
+            (UnsupportedFormat, Fr) => "Ce type de fichier n'est pas pris en charge.",
+            (UnsupportedFormat, Ja) => "このファイル形式には対応していません。",
+            (Corrupt, En) => "The file is damaged and can't be read.",
+            (Corrupt, De) => "Die Datei ist beschädigt und kann nicht gelesen werden.",
+            (Corrupt, Fr) => "Le fichier est endommagé et ne peut pas être lu.",
+            (Corrupt, Ja) => "ファイルが破損しているため読み込めません。",
+            (Locked, En) => "Your data is locked. Unlock it and try again.",
+            (Locked, De) => {
+                "Ihre Daten sind gesperrt. Entsperren Sie sie und versuchen Sie es erneut."
+            }
+            (Locked, Fr) => "Vos données sont verrouillées. Déverrouillez-les puis réessayez.",
+            (Locked, Ja) => {
+                "データがロックされています。ロックを解除してからもう一度お試しください。"
+            }
+            (InvalidInput, En) => "That request isn't valid.",
+            (InvalidInput, De) => "Diese Anfrage ist ungültig.",
+            (InvalidInput, Fr) => "Cette demande n'est pas valide.",
+            (InvalidInput, Ja) => "無効なリクエストです。",
+            (Io, En) => "The file couldn't be read or written. Check the disk and try again.",
+            (Io, De) => {
+                "Die Datei konnte nicht gelesen oder geschrieben werden. Prüfen Sie das Laufwerk."
+            }
+            (Io, Fr) => "Impossible de lire ou d'écrire le fichier. Vérifiez le disque.",
+            (Io, Ja) => "ファイルの読み書きに失敗しました。ディスクを確認してください。",
+            (Internal, En) => "Something went wrong. Please try again.",
+            (Internal, De) => "Etwas ist schiefgelaufen. Bitte versuchen Sie es erneut.",
+            (Internal, Fr) => "Une erreur s'est produite. Veuillez réessayer.",
+            (Internal, Ja) => "問題が発生しました。もう一度お試しください。",
+        }
+    }
+}
+
+/// Languages the error catalog is translated into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Fr,
+    Ja,
+}
+
+impl Locale {
+    const ALL: [Locale; 4] = [Locale::En, Locale::De, Locale::Fr, Locale::Ja];
+
+    /// Catalog locale for a BCP 47 tag such as `de-AT`, falling back to English.
+    pub fn from_tag(tag: &str) -> Self {
+        let language = tag.split(['-', '_']).next().unwrap_or_default();
+        Self::ALL
+            .into_iter()
+            .find(|locale| locale.tag().eq_ignore_ascii_case(language))
+            .unwrap_or_default()
+    }
+
+    pub fn tag(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+            Locale::Fr => "fr",
+            Locale::Ja => "ja",
+        }
+    }
+}
+
+/// Locale of the user messages of errors created from now on.
+static LOCALE: AtomicU8 = AtomicU8::new(0);
+
+pub fn locale() -> Locale {
+    Locale::ALL.get(usize::from(LOCALE.load(Ordering::Relaxed))).copied().unwrap_or_default()
+}
+
+pub fn set_locale(locale: Locale) {
+    LOCALE.store(locale as u8, Ordering::Relaxed);
+}
+
+/// Serialized as `{ code, message, userMessage, detail }`: `message` is the technical summary,
+/// `userMessage` the catalog message for `code` in the current [`locale`], and `detail` holds
+/// the full cause chain when it says more than `message`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub user_message: &'static str,
+    pub detail: Option<String>,
+}
+
+pub type CommandResult<T> = Result<T, CommandError>;
+
+impl CommandError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            user_message: code.user_message(locale()),
+            detail: None,
+        }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidInput, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.detail {
+            Some(detail) => f.write_str(detail),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl From<anyhow::Error> for CommandError {
+    fn from(err: anyhow::Error) -> Self {
+        let message = err.to_string();
+        let detail = format!("{err:#}");
+        let code = ErrorCode::from(ErrorKind::of(&err));
+        Self {
+            code,
+            detail: (detail != message).then_some(detail),
+            user_message: code.user_message(locale()),
+            message,
+        }
+    }
+}
+
+impl From<CoreError> for CommandError {
+    fn from(err: CoreError) -> Self {
+        err.into_inner().into()
+    }
+}
+
+impl From<std::io::Error> for CommandError {
+    fn from(err: std::io::Error) -> Self {
+        anyhow::Error::from(err).into()
+    }
+}
+
+impl From<zip::result::ZipError> for CommandError {
+    fn from(err: zip::result::ZipError) -> Self {
+        anyhow::Error::from(err).into()
+    }
+}
+
+impl From<keyring::Error> for CommandError {
+    fn from(err: keyring::Error) -> Self {
+        let code = match &err {
+            keyring::Error::NoEntry => ErrorCode::NotFound,
+            keyring::Error::NoStorageAccess(_) => ErrorCode::PermissionDenied,
+            _ => ErrorCode::Internal,
+        };
+        Self::new(code, err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn maps_core_errors_with_their_cause_chain() {
+        let missing = std::io::Error::new(std::io::ErrorKind::NotFound, "no such file");
+        let err: CommandError =
+            Err::<(), _>(missing).context("reading page_001.png").unwrap_err().into();
+        assert_eq!(err.code, ErrorCode::NotFound);
+        assert_eq!(err.message, "reading page_001.png");
+        assert_eq!(err.detail.as_deref(), Some("reading page_001.png: no such file"));
+
+        let json = serde_json::to_value(CommandError::invalid_input("bad range")).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "code": "invalidInput",
+                "message": "bad range",
+                "userMessage": "That request isn't valid.",
+                "detail": null
+            })
+        );
+    }
+
+    #[test]
+    fn resolves_locales_from_language_tags() {
+        assert_eq!(Locale::from_tag("de-AT"), Locale::De);
+        assert_eq!(Locale::from_tag("ja_JP"), Locale::Ja);
+        assert_eq!(Locale::from_tag("FR"), Locale::Fr);
+        assert_eq!(Locale::from_tag("pt-BR"), Locale::En);
+        assert_eq!(Locale::from_tag(""), Locale::En);
+        assert_eq!(
+            ErrorCode::Corrupt.user_message(Locale::De),
+            "Die Datei ist beschädigt und kann nicht gelesen werden."
+        );
+    }
+}
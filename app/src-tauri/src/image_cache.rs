@@ -1,11 +1,22 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use reader_core::cache::disk::DiskCache;
+use reader_core::cache::flight::{Outcome, SingleFlight};
+use reader_core::cache::memory::{CacheEntry, MemoryCache};
 use reader_core::stats::StatsCollector;
-use reader_core::types::ImageKey;
+use reader_core::stats::report::{CacheEntryUsage, CacheReport};
+use reader_core::types::{CacheBudget, ImageKey};
+
+use crate::error::{CommandError, CommandResult};
+
+/// Bytes read from the start of an unindexed entry to tell its content type.
+const SNIFF_LEN: u64 = 16;
+/// Content type of unindexed entries whose signature is not recognised.
+const FALLBACK_MIME: &str = "application/octet-stream";
 
 #[derive(Debug, Clone)]
 pub struct CachedImage {
@@ -17,33 +28,73 @@ pub struct CachedImage {
 struct CachedEntry {
     mime: String,
     size: usize,
+    hits: u64,
+    last_access_ms: Option<u64>,
+}
+
+impl CachedEntry {
+    fn new(mime: &str, size: usize) -> Self {
+        Self { mime: mime.to_string(), size, hits: 0, last_access_ms: Some(now_ms()) }
+    }
+}
+
+/// Hits and misses of one source.
+#[derive(Debug, Default, Clone, Copy)]
+struct Lookups {
+    hits: u64,
+    misses: u64,
 }
 
 #[derive(Debug)]
 pub struct ImageCache {
     disk: DiskCache,
+    /// Recently written or read entries, kept within the memory half of the budget so hot pages
+    /// skip the disk.
+    memory: Mutex<MemoryCache>,
     root: PathBuf,
-    index: RwLock<HashMap<String, CachedEntry>>,
+    index: RwLock<HashMap<ImageKey, CachedEntry>>,
+    lookups: Mutex<HashMap<String, Lookups>>,
     total_bytes: AtomicU64,
-    budget_bytes: u64,
+    budget_bytes: AtomicU64,
     stats: Arc<StatsCollector>,
+    /// Keys being produced, so racing misses for one key share a single production.
+    producing: SingleFlight,
 }
 
 impl ImageCache {
-    pub fn new(stats: Arc<StatsCollector>) -> Result<Self, String> {
+    pub fn new(budget: CacheBudget, stats: Arc<StatsCollector>) -> CommandResult<Self> {
         let root = default_cache_root();
-        Self::with_root(root, stats)
+        Self::with_root(root, budget, stats)
     }
 
-    pub fn with_root(root: PathBuf, stats: Arc<StatsCollector>) -> Result<Self, String> {
-        let disk = DiskCache::new(&root).map_err(|err| err.to_string())?;
+    /// Open the cache at `root`, first trimming what earlier sessions left on disk to the disk
+    /// half of `budget`. The memory half bounds the entries kept in memory.
+    pub fn with_root(
+        root: PathBuf,
+        budget: CacheBudget,
+        stats: Arc<StatsCollector>,
+    ) -> CommandResult<Self> {
+        let disk = DiskCache::new(&root)?.with_budget(budget);
+        // A leftover entry that cannot be removed only costs disk space; the cache still works.
+        match disk.trim_to_budget() {
+            Ok(0) => {}
+            Ok(trimmed) => {
+                tracing::debug!(target: "image_cache", trimmed, "trimmed leftover cache entries")
+            }
+            Err(err) => {
+                tracing::warn!(target: "image_cache", "failed to trim leftover cache entries: {err:#}")
+            }
+        }
         Ok(Self {
+            budget_bytes: AtomicU64::new(disk.budget()),
             disk,
+            memory: Mutex::new(MemoryCache::new(budget)),
             root,
             index: RwLock::new(HashMap::new()),
+            lookups: Mutex::new(HashMap::new()),
             total_bytes: AtomicU64::new(0),
-            budget_bytes: reader_core::types::CacheBudget::default().bytes_max as u64,
             stats,
+            producing: SingleFlight::new(),
         })
     }
 
@@ -51,80 +102,261 @@ impl ImageCache {
         &self.root
     }
 
-    pub fn ensure_bytes<F>(&self, key: &str, mime: &str, producer: F) -> Result<(), String>
+    pub fn budget(&self) -> u64 {
+        self.budget_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Change the budget and evict entries until the cache fits it. Returns how many entries
+    /// were evicted.
+    pub fn set_budget(&self, bytes: u64) -> CommandResult<usize> {
+        self.budget_bytes.store(bytes, Ordering::Relaxed);
+        let evicted = self.trim_to_budget()?;
+        self.publish_usage();
+        Ok(evicted)
+    }
+
+    /// Whether `key` is on disk, without counting a lookup.
+    pub fn contains(&self, key: &ImageKey) -> bool {
+        self.disk_path_exists(key)
+    }
+
+    /// When the entry for `key` was last written.
+    pub fn modified(&self, key: &ImageKey) -> Option<SystemTime> {
+        let path = self.disk.path_for(key);
+        std::fs::metadata(path).and_then(|meta| meta.modified()).ok()
+    }
+
+    /// Make sure an entry for `key` exists, calling `producer` on a miss. Concurrent misses for
+    /// the same key run `producer` once; the other callers wait and then find the entry (or,
+    /// if it failed, produce it themselves).
+    pub fn ensure_bytes<F>(&self, key: &ImageKey, mime: &str, producer: F) -> CommandResult<()>
     where
-        F: FnOnce() -> Result<Vec<u8>, String>,
+        F: FnOnce() -> CommandResult<Vec<u8>>,
     {
-        if self.disk_path_exists(key) {
-            self.record_existing_entry(key, mime);
-            self.stats.record_cache_lookup(true);
-            return Ok(());
+        self.ensure_with(key, Some(mime), || {
+            Ok(CachedImage { bytes: producer()?, mime: mime.to_string() })
+        })
+    }
+
+    /// [`Self::ensure_bytes`] for producers that only know the content type of the entry once
+    /// they have made it. Entries already on disk keep the type recorded for them, or the one
+    /// their signature tells.
+    pub fn ensure<F>(&self, key: &ImageKey, producer: F) -> CommandResult<()>
+    where
+        F: FnOnce() -> CommandResult<CachedImage>,
+    {
+        self.ensure_with(key, None, producer)
+    }
+
+    fn ensure_with<F>(&self, key: &ImageKey, mime: Option<&str>, producer: F) -> CommandResult<()>
+    where
+        F: FnOnce() -> CommandResult<CachedImage>,
+    {
+        let mut work = move || self.produce(key, mime, producer);
+        loop {
+            if self.hit(key, mime) {
+                return Ok(());
+            }
+            match self.producing.run(&key.to_string(), work) {
+                Outcome::Ran(result) => return result,
+                Outcome::Joined(unrun) => work = unrun,
+            }
         }
+    }
 
-        let bytes = producer()?;
-        let image_key = ImageKey::new(key.to_string());
-        self.disk.write(&image_key, &bytes).map_err(|err| err.to_string())?;
-        self.stats.record_cache_lookup(false);
+    /// Whether `key` is already on disk, counting the lookup as a hit if so.
+    fn hit(&self, key: &ImageKey, mime: Option<&str>) -> bool {
+        let exists = self.disk_path_exists(key);
+        if exists {
+            match mime {
+                Some(mime) => self.record_existing_entry(key, mime),
+                None => {
+                    let size = self.disk.size(key).ok().flatten().unwrap_or(0) as usize;
+                    self.mime_for(key, size);
+                }
+            }
+            self.record_lookup(key, true);
+        }
+        exists
+    }
+
+    fn produce<F>(&self, key: &ImageKey, mime: Option<&str>, producer: F) -> CommandResult<()>
+    where
+        F: FnOnce() -> CommandResult<CachedImage>,
+    {
+        // Another caller may have finished the entry between our miss and taking the flight.
+        if self.hit(key, mime) {
+            return Ok(());
+        }
+        let CachedImage { bytes, mime } = producer()?;
+        let started = std::time::Instant::now();
+        self.disk.write(key, &bytes)?;
+        self.stats.record_cache_write(&key.to_string(), started.elapsed());
 
         let size = bytes.len();
+        self.remember(key, bytes)?;
         let mut index = self.index.write().unwrap();
-        let previous = index.insert(key.to_string(), CachedEntry { mime: mime.to_string(), size });
+        let previous = index.insert(key.clone(), CachedEntry::new(&mime, size));
         self.adjust_total_bytes(previous.map(|entry| entry.size).unwrap_or(0), size);
+        drop(index);
+        self.record_lookup(key, false);
+        self.trim_to_budget()?;
         self.publish_usage();
         Ok(())
     }
 
-    pub fn fetch(&self, key: &str) -> Result<Option<CachedImage>, String> {
-        let image_key = ImageKey::new(key.to_string());
-        match self.disk.read(&image_key).map_err(|err| err.to_string())? {
+    pub fn fetch(&self, key: &ImageKey) -> CommandResult<Option<CachedImage>> {
+        let remembered = self.memory.lock().unwrap().get(key).map(|entry| entry.bytes.clone());
+        if let Some(bytes) = remembered {
+            let mime = self.mime_for(key, bytes.len());
+            self.record_lookup(key, true);
+            return Ok(Some(CachedImage { bytes, mime }));
+        }
+        match self.disk.read(key)? {
             Some(bytes) => {
-                self.stats.record_cache_lookup(true);
                 let mime = self.mime_for(key, bytes.len());
+                self.record_lookup(key, true);
+                self.remember(key, bytes.clone())?;
                 Ok(Some(CachedImage { bytes, mime }))
             }
             None => {
-                self.stats.record_cache_lookup(false);
+                self.record_lookup(key, false);
                 Ok(None)
             }
         }
     }
 
-    fn mime_for(&self, key: &str, size_hint: usize) -> String {
+    /// Size in bytes of the entry for `key`, without reading it.
+    pub fn size(&self, key: &ImageKey) -> CommandResult<Option<u64>> {
+        self.disk.size(key).map_err(CommandError::from)
+    }
+
+    /// Read only `range` of the entry for `key`; used to serve large entries in chunks.
+    pub fn fetch_range(
+        &self,
+        key: &ImageKey,
+        range: std::ops::Range<u64>,
+    ) -> CommandResult<Option<CachedImage>> {
+        match self.disk.read_range(key, range)? {
+            Some(bytes) => {
+                let size = self.disk.size(key).ok().flatten().unwrap_or(0) as usize;
+                let mime = self.mime_for(key, size);
+                self.record_lookup(key, true);
+                Ok(Some(CachedImage { bytes, mime }))
+            }
+            None => {
+                self.record_lookup(key, false);
+                Ok(None)
+            }
+        }
+    }
+
+    fn mime_for(&self, key: &ImageKey, size_hint: usize) -> String {
         if let Some(entry) = self.index.read().unwrap().get(key) {
             return entry.mime.clone();
         }
 
+        // Entries written before a restart are not indexed yet; their signature tells the type.
+        let head = self.disk.read_range(key, 0..SNIFF_LEN).ok().flatten().unwrap_or_default();
+        let mime = reader_core::codec::sniff_mime(&head).unwrap_or(FALLBACK_MIME);
         let mut index = self.index.write().unwrap();
         index
-            .entry(key.to_string())
+            .entry(key.clone())
             .or_insert_with(|| {
                 self.adjust_total_bytes(0, size_hint);
-                CachedEntry { mime: "image/png".to_string(), size: size_hint }
+                CachedEntry::new(mime, size_hint)
             })
             .mime
             .clone()
     }
 
-    fn disk_path_exists(&self, key: &str) -> bool {
-        let image_key = ImageKey::new(key.to_string());
-        self.disk.path_for(&image_key).exists()
+    /// Keep `bytes` of `key` in memory; entries larger than the memory budget are skipped.
+    fn remember(&self, key: &ImageKey, bytes: Vec<u8>) -> CommandResult<()> {
+        let entry = CacheEntry::new(key.page_id(), bytes);
+        Ok(self.memory.lock().unwrap().insert(key.clone(), entry)?)
+    }
+
+    fn disk_path_exists(&self, key: &ImageKey) -> bool {
+        self.disk.path_for(key).exists()
     }
 
-    fn record_existing_entry(&self, key: &str, mime: &str) {
+    fn record_existing_entry(&self, key: &ImageKey, mime: &str) {
         let mut index = self.index.write().unwrap();
         if let Some(entry) = index.get_mut(key) {
             entry.mime = mime.to_string();
             return;
         }
 
-        let image_key = ImageKey::new(key.to_string());
-        let path = self.disk.path_for(&image_key);
+        let path = self.disk.path_for(key);
         let size = std::fs::metadata(&path).map(|meta| meta.len() as usize).unwrap_or(0);
-        index.insert(key.to_string(), CachedEntry { mime: mime.to_string(), size });
+        index.insert(key.clone(), CachedEntry::new(mime, size));
         self.adjust_total_bytes(0, size);
         self.publish_usage();
     }
 
+    /// Remove the least recently used entries until the cache fits its budget.
+    fn trim_to_budget(&self) -> CommandResult<usize> {
+        let budget = self.budget();
+        if self.total_bytes.load(Ordering::Relaxed) <= budget {
+            return Ok(0);
+        }
+        let mut index = self.index.write().unwrap();
+        let mut coldest: Vec<(Option<u64>, ImageKey)> =
+            index.iter().map(|(key, entry)| (entry.last_access_ms, key.clone())).collect();
+        coldest.sort_by_cached_key(|(last_access_ms, key)| (*last_access_ms, key.to_string()));
+        let mut evicted = 0;
+        for (_, key) in coldest {
+            if self.total_bytes.load(Ordering::Relaxed) <= budget {
+                break;
+            }
+            self.disk.remove(&key)?;
+            self.memory.lock().unwrap().remove(&key);
+            if let Some(entry) = index.remove(&key) {
+                self.adjust_total_bytes(entry.size, 0);
+            }
+            evicted += 1;
+        }
+        drop(index);
+        tracing::debug!(target: "image_cache", evicted, budget, "trimmed cache to budget");
+        Ok(evicted)
+    }
+
+    /// Report of the largest keys, coldest namespaces and hit ratio per source.
+    pub fn efficiency_report(&self) -> CacheReport {
+        let mut builder = CacheReport::builder().with_budget(self.budget());
+        for (key, entry) in self.index.read().unwrap().iter() {
+            builder.record_entry(CacheEntryUsage {
+                key: key.to_string(),
+                namespace: key.namespace().to_string(),
+                source: key.source.as_str().to_string(),
+                bytes: entry.size as u64,
+                hits: entry.hits,
+                last_access_ms: entry.last_access_ms,
+            });
+        }
+        for (source, lookups) in self.lookups.lock().unwrap().iter() {
+            builder.record_lookups(source, lookups.hits, lookups.misses);
+        }
+        builder.build()
+    }
+
+    /// Count a lookup of `key` globally, per source and, for hits, on the index entry.
+    fn record_lookup(&self, key: &ImageKey, hit: bool) {
+        self.stats.record_cache_lookup(hit);
+        let mut lookups = self.lookups.lock().unwrap();
+        let counters = lookups.entry(key.source.as_str().to_string()).or_default();
+        if hit {
+            counters.hits += 1;
+        } else {
+            counters.misses += 1;
+        }
+        drop(lookups);
+        if hit && let Some(entry) = self.index.write().unwrap().get_mut(key) {
+            entry.hits += 1;
+            entry.last_access_ms = Some(now_ms());
+        }
+    }
+
     fn adjust_total_bytes(&self, previous: usize, current: usize) {
         let prev = previous as i64;
         let curr = current as i64;
@@ -138,35 +370,41 @@ impl ImageCache {
 
     fn publish_usage(&self) {
         let used = self.total_bytes.load(Ordering::Relaxed);
-        self.stats.update_cache_usage(used, self.budget_bytes);
+        self.stats.update_cache_usage(used, self.budget());
     }
 }
 
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
 fn default_cache_root() -> PathBuf {
-    if let Some(dirs) =
-        directories::ProjectDirs::from("com", "LocalComicReader", "local-comic-reader")
-    {
-        let mut path = dirs.data_dir().to_path_buf();
-        path.push("cache");
-        path
-    } else {
-        std::env::temp_dir().join("local-comic-reader-cache")
-    }
+    reader_core::store::cache_dir()
+        .unwrap_or_else(|_| std::env::temp_dir().join("local-comic-reader-cache"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn key(key: &str) -> ImageKey {
+        key.parse().unwrap()
+    }
+
     #[test]
     fn writes_and_reads_round_trip() {
         let temp = tempfile::tempdir().unwrap();
-        let stats = Arc::new(StatsCollector::new());
-        let cache = ImageCache::with_root(temp.path().join("cache"), Arc::clone(&stats)).unwrap();
-        let key = "demo-key";
-        cache.ensure_bytes(key, "image/png", || Ok(vec![1, 2, 3, 4])).expect("store bytes");
+        let stats = Arc::new(StatsCollector::default());
+        let cache = ImageCache::with_root(
+            temp.path().join("cache"),
+            CacheBudget::default(),
+            Arc::clone(&stats),
+        )
+        .unwrap();
+        let key = key("demo-page-0");
+        cache.ensure_bytes(&key, "image/png", || Ok(vec![1, 2, 3, 4])).expect("store bytes");
 
-        let fetched = cache.fetch(key).expect("fetch").expect("hit");
+        let fetched = cache.fetch(&key).expect("fetch").expect("hit");
         assert_eq!(fetched.bytes, vec![1, 2, 3, 4]);
         assert_eq!(fetched.mime, "image/png");
 
@@ -174,4 +412,101 @@ mod tests {
         assert_eq!(snapshot.cache_requests, 2);
         assert!(snapshot.cache_hit_ratio > 0.0);
     }
+
+    #[test]
+    fn serves_hot_entries_from_memory_within_its_budget() {
+        let temp = tempfile::tempdir().unwrap();
+        let stats = Arc::new(StatsCollector::default());
+        let budget = CacheBudget { memory_bytes_max: 8, ..CacheBudget::default() };
+        let cache = ImageCache::with_root(temp.path().join("cache"), budget, stats).unwrap();
+        cache.ensure_bytes(&key("vol-1-page-0"), "image/png", || Ok(vec![1; 4])).unwrap();
+        cache.ensure_bytes(&key("vol-1-page-1"), "image/png", || Ok(vec![2; 16])).unwrap();
+        std::fs::remove_file(cache.disk.path_for(&key("vol-1-page-0"))).unwrap();
+        std::fs::remove_file(cache.disk.path_for(&key("vol-1-page-1"))).unwrap();
+
+        let hot = cache.fetch(&key("vol-1-page-0")).unwrap().expect("kept in memory");
+        assert_eq!(hot.bytes, vec![1; 4]);
+        assert!(cache.fetch(&key("vol-1-page-1")).unwrap().is_none());
+    }
+
+    #[test]
+    fn sniffs_the_mime_of_entries_from_earlier_sessions() {
+        let temp = tempfile::tempdir().unwrap();
+        let open = || {
+            let stats = Arc::new(StatsCollector::default());
+            ImageCache::with_root(temp.path().join("cache"), CacheBudget::default(), stats).unwrap()
+        };
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 0, 0x10, b'J', b'F', b'I', b'F', 0];
+        open().ensure_bytes(&key("vol-1-page-0"), "image/jpeg", || Ok(jpeg.to_vec())).unwrap();
+
+        let reopened = open();
+        let fetched = reopened.fetch(&key("vol-1-page-0")).unwrap().expect("hit");
+        assert_eq!(fetched.mime, "image/jpeg");
+    }
+
+    #[test]
+    fn racing_misses_produce_once() {
+        let temp = tempfile::tempdir().unwrap();
+        let stats = Arc::new(StatsCollector::default());
+        let cache = ImageCache::with_root(temp.path().join("cache"), CacheBudget::default(), stats)
+            .unwrap();
+        let runs = AtomicU64::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    cache
+                        .ensure_bytes(&key("vol-1-page-0"), "image/png", || {
+                            runs.fetch_add(1, Ordering::SeqCst);
+                            std::thread::sleep(std::time::Duration::from_millis(50));
+                            Ok(vec![0; 16])
+                        })
+                        .unwrap();
+                });
+            }
+        });
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.size(&key("vol-1-page-0")).unwrap(), Some(16));
+    }
+
+    #[test]
+    fn efficiency_report_groups_by_namespace_and_source() {
+        let temp = tempfile::tempdir().unwrap();
+        let stats = Arc::new(StatsCollector::default());
+        let cache = ImageCache::with_root(temp.path().join("cache"), CacheBudget::default(), stats)
+            .unwrap();
+        cache.ensure_bytes(&key("vol-1-page-0"), "image/png", || Ok(vec![0; 64])).unwrap();
+        cache.ensure_bytes(&key("vol-1-thumb-0-256"), "image/png", || Ok(vec![0; 8])).unwrap();
+        cache.fetch(&key("vol-1-page-0")).unwrap();
+        cache.fetch(&key("vol-2-page-9")).unwrap();
+
+        let report = cache.efficiency_report();
+        assert_eq!(report.total_bytes, 72);
+        assert_eq!(report.largest[0].key, "vol-1-page-0");
+        assert_eq!(report.largest[0].hits, 1);
+        assert_eq!(report.namespaces[0].namespace, "thumb");
+        assert_eq!(report.sources[0].source, "vol-2");
+        assert_eq!((report.sources[1].hits, report.sources[1].misses), (1, 2));
+    }
+
+    #[test]
+    fn shrinking_the_budget_evicts_entries() {
+        let temp = tempfile::tempdir().unwrap();
+        let stats = Arc::new(StatsCollector::default());
+        let cache = ImageCache::with_root(
+            temp.path().join("cache"),
+            CacheBudget::default(),
+            Arc::clone(&stats),
+        )
+        .unwrap();
+        cache.ensure_bytes(&key("vol-1-page-0"), "image/png", || Ok(vec![0; 64])).unwrap();
+        cache.ensure_bytes(&key("vol-1-page-1"), "image/png", || Ok(vec![0; 64])).unwrap();
+
+        assert_eq!(cache.set_budget(100).unwrap(), 1);
+        assert_eq!(cache.efficiency_report().total_bytes, 64);
+        assert_eq!(stats.snapshot().cache_bytes_capacity, 100);
+
+        cache.ensure_bytes(&key("vol-1-page-2"), "image/png", || Ok(vec![0; 64])).unwrap();
+        assert_eq!(cache.efficiency_report().total_bytes, 64);
+        assert!(cache.contains(&key("vol-1-page-2")));
+    }
 }
@@ -0,0 +1,167 @@
+//! Opening sources from outside the app: `reader://open?path=…` deep links, file-association
+//! launches, and launches of a second instance, which are forwarded to the running one.
+//!
+//! Every path ends up in [`commands::open_source`] for the main window, which is then told
+//! about the new source with an [`OPENED_EVENT`]. Only local paths are opened this way: a deep
+//! link naming a URI would let any web page make the app contact hosts of its choosing, so remote
+//! sources are left to the explicit `open_path` command. A launch can finish before the window listens,
+//! so the window also asks for its source with `get_window_source` when it loads.
+
+use std::path::{Path, PathBuf};
+
+use reader_core::fs::registry as source_registry;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, Runtime, Url};
+use tauri_plugin_deep_link::DeepLinkExt;
+
+use crate::commands::{self, AppState, SourceId};
+use crate::error::CommandError;
+
+/// Scheme registered for deep links.
+pub const SCHEME: &str = "reader";
+/// Window that sources opened from outside the app are shown in.
+const MAIN_WINDOW: &str = "main";
+
+/// Sent to the main window after a launch opened `path`, or failed to.
+pub const OPENED_EVENT: &str = "app://opened";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Opened {
+    pub path: String,
+    pub source_id: Option<SourceId>,
+    pub error: Option<CommandError>,
+}
+
+/// Install the single-instance and deep-link plugins. Must run before other plugins so a
+/// second instance hands its arguments over and exits early.
+pub fn register<R: Runtime>(builder: tauri::Builder<R>) -> tauri::Builder<R> {
+    builder
+        .plugin(tauri_plugin_single_instance::init(|app, argv, cwd| {
+            tracing::info!(target: "launch", ?argv, "forwarded from a second instance");
+            focus_main(app);
+            route(app, paths_from_args(&argv, Path::new(&cwd)));
+        }))
+        .plugin(tauri_plugin_deep_link::init())
+}
+
+/// Listen for deep links and open whatever this process was launched with.
+pub fn start<R: Runtime>(app: &AppHandle<R>) {
+    let handle = app.clone();
+    app.deep_link().on_open_url(move |event| {
+        let urls = event.urls();
+        route(
+            &handle,
+            urls.iter().filter(|url| url.scheme() == SCHEME).filter_map(path_from_url).collect(),
+        );
+    });
+    let args: Vec<String> = std::env::args().collect();
+    let cwd = std::env::current_dir().unwrap_or_default();
+    route(app, paths_from_args(&args, &cwd));
+}
+
+/// Open the files of a macOS `Opened` run event; its deep links reach the deep-link plugin.
+pub fn open_files<R: Runtime>(app: &AppHandle<R>, urls: &[Url]) {
+    let files = urls.iter().filter(|url| url.scheme() == "file");
+    route(app, files.filter_map(path_from_url).collect());
+}
+
+/// Open each of `paths` in the main window, in the background.
+fn route<R: Runtime>(app: &AppHandle<R>, paths: Vec<PathBuf>) {
+    for path in paths {
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            let path = path.to_string_lossy().to_string();
+            let state = app.state::<AppState>();
+            let result = commands::open_source(&state, MAIN_WINDOW, path.clone()).await;
+            if let Err(err) = &result {
+                tracing::warn!(target: "launch", path = %path, "failed to open launch path: {err}");
+            }
+            let (source_id, error) = match result {
+                Ok(id) => (Some(id), None),
+                Err(err) => (None, Some(err)),
+            };
+            if let Err(err) =
+                app.emit_to(MAIN_WINDOW, OPENED_EVENT, Opened { path, source_id, error })
+            {
+                tracing::warn!(target: "launch", "failed to emit opened source: {err}");
+            }
+        });
+    }
+}
+
+fn focus_main<R: Runtime>(app: &AppHandle<R>) {
+    if let Some(window) = app.get_webview_window(MAIN_WINDOW) {
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+/// Paths named by a launch's arguments: deep links and plain paths, relative ones resolved
+/// against `cwd`, skipping the program name and flags.
+fn paths_from_args(args: &[String], cwd: &Path) -> Vec<PathBuf> {
+    args.iter()
+        .skip(1)
+        .filter(|arg| !arg.starts_with('-'))
+        .filter_map(|arg| match Url::parse(arg) {
+            Ok(url) if url.scheme().len() > 1 => path_from_url(&url),
+            // Not a URL, or a Windows drive letter parsed as a scheme.
+            _ => Some(cwd.join(arg)),
+        })
+        .collect()
+}
+
+/// Local path of a `reader://open?path=…` deep link or a `file://` URL.
+fn path_from_url(url: &Url) -> Option<PathBuf> {
+    match url.scheme() {
+        "file" => url.to_file_path().ok(),
+        SCHEME if url.host_str() == Some("open") => {
+            let (_, path) = url.query_pairs().find(|(name, _)| name == "path")?;
+            if !source_registry::is_local(&path) {
+                tracing::warn!(
+                    target: "launch",
+                    location = %source_registry::without_userinfo(&path),
+                    "ignoring deep link to a remote source"
+                );
+                return None;
+            }
+            Some(PathBuf::from(path.as_ref()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_paths_from_deep_links_and_file_urls() {
+        let url = Url::parse("reader://open?path=%2Fcomics%2FVol%201.cbz").unwrap();
+        assert_eq!(path_from_url(&url), Some(PathBuf::from("/comics/Vol 1.cbz")));
+        let url = Url::parse("reader://open?path=davs%3A%2F%2Fuser%3Apass%40nas.local%2F").unwrap();
+        assert_eq!(path_from_url(&url), None);
+        let url = Url::parse("reader://close?path=%2Fcomics").unwrap();
+        assert_eq!(path_from_url(&url), None);
+        let url = Url::parse("https://example.com/?path=%2Fcomics").unwrap();
+        assert_eq!(path_from_url(&url), None);
+        #[cfg(unix)]
+        {
+            let url = Url::parse("file:///comics/Vol%201.cbz").unwrap();
+            assert_eq!(path_from_url(&url), Some(PathBuf::from("/comics/Vol 1.cbz")));
+        }
+    }
+
+    #[test]
+    fn skips_the_program_name_and_flags() {
+        let cwd = Path::new("/home/reader");
+        let args = ["reader", "--minimized", "/comics/a.cbz", "reader://open?path=%2Fb.cbz"]
+            .map(String::from);
+        assert_eq!(
+            paths_from_args(&args, cwd),
+            vec![PathBuf::from("/comics/a.cbz"), PathBuf::from("/b.cbz")]
+        );
+        let args = ["reader", "c.cbz"].map(String::from);
+        assert_eq!(paths_from_args(&args, cwd), vec![cwd.join("c.cbz")]);
+    }
+}
@@ -1,33 +1,117 @@
 mod commands;
+mod error;
 mod image_cache;
+mod launch;
 mod protocol;
 
+/// Port on which to serve OpenMetrics at `http://127.0.0.1:<port>/metrics`; unset disables it.
+const METRICS_PORT_VAR: &str = "LOCAL_COMIC_READER_METRICS_PORT";
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     use std::sync::Arc;
 
+    let state_root =
+        reader_core::store::state_dir().expect("failed to resolve application state directory");
+
     let mut log_config = reader_core::log::LogConfig::default();
     if cfg!(debug_assertions) {
         log_config.console_level = reader_core::log::LogLevel::DEBUG;
     }
+    if !reader_core::log::filter_from_env() {
+        match reader_core::store::log_settings::load_filter(&state_root) {
+            Ok(filter) => log_config.env_filter = filter,
+            Err(err) => eprintln!("failed to load saved log filter: {err:#}"),
+        }
+    }
 
     if let Err(err) = reader_core::log::init(log_config) {
         eprintln!("failed to initialise logging: {err:#}");
     }
 
-    let stats = Arc::new(reader_core::stats::StatsCollector::new());
+    match reader_core::store::migrate::run_all(&state_root) {
+        Ok(reports) => {
+            for report in reports {
+                tracing::info!(
+                    path = %report.path.display(),
+                    from = report.from,
+                    to = report.to,
+                    backup = %report.backup.display(),
+                    "migrated store file"
+                );
+            }
+        }
+        Err(err) => tracing::error!("store migration failed, leaving data untouched: {err:#}"),
+    }
+
+    match reader_core::store::backup::ensure_daily(
+        &state_root,
+        reader_core::store::backup::DEFAULT_KEEP,
+    ) {
+        Ok(Some(backup)) => {
+            tracing::info!(dir = %backup.dir.display(), files = backup.files, "backed up stores")
+        }
+        Ok(None) => {}
+        Err(err) => tracing::warn!("daily store backup failed: {err:#}"),
+    }
+
+    let settings = reader_core::store::settings::load(&state_root).unwrap_or_else(|err| {
+        tracing::warn!("failed to load pipeline settings, using defaults: {err:#}");
+        Default::default()
+    });
+    commands::apply_decode_sandbox(settings.sandbox_decode);
+    let stats = Arc::new(reader_core::stats::StatsCollector::default());
     let cache = Arc::new(
-        image_cache::ImageCache::new(Arc::clone(&stats)).expect("failed to initialise image cache"),
+        image_cache::ImageCache::new(settings.cache_budget(), Arc::clone(&stats))
+            .expect("failed to initialise image cache"),
     );
 
+    // Opt-in OpenMetrics endpoint for charting the reader alongside other system metrics.
+    let _metrics_server = std::env::var(METRICS_PORT_VAR).ok().and_then(|port| {
+        let port = port
+            .parse()
+            .inspect_err(|err| tracing::warn!("ignoring invalid {METRICS_PORT_VAR}={port}: {err}"))
+            .ok()?;
+        reader_core::stats::openmetrics::MetricsServer::start(port, Arc::clone(&stats))
+            .inspect_err(|err| tracing::warn!("failed to start metrics server: {err}"))
+            .ok()
+    });
+
+    let stores = commands::Stores::open(&state_root).expect("failed to initialise stores");
+    let prefetcher =
+        reader_core::pipeline::pool::WorkerPool::new(settings.worker_threads(), Arc::clone(&stats))
+            .expect("failed to start prefetch workers");
+    commands::unlock_from_keychain(&state_root);
+
     if cfg!(debug_assertions) {
         tracing::info!(path = %cache.root().display(), "image cache ready");
     }
 
-    let builder = tauri::Builder::default();
+    let builder = launch::register(tauri::Builder::default());
     let builder = builder.plugin(tauri_plugin_dialog::init());
-    let builder = protocol::register(builder, Arc::clone(&cache));
-    let builder = commands::register(builder, Arc::clone(&cache), Arc::clone(&stats));
+    let builder = builder.plugin(tauri_plugin_clipboard_manager::init());
+    let assets = protocol::AccessToken::generate();
+    let builder = protocol::register(builder, Arc::clone(&cache), assets.clone());
+    let builder = commands::register(
+        builder,
+        Arc::clone(&cache),
+        Arc::clone(&stats),
+        stores,
+        prefetcher,
+        settings,
+        assets,
+    );
 
-    builder.run(tauri::generate_context!()).expect("error while running tauri application");
+    let app =
+        builder.build(tauri::generate_context!()).expect("error while building tauri application");
+    launch::start(app.handle());
+    app.run(|handle, event| match event {
+        tauri::RunEvent::Exit => {
+            use tauri::Manager;
+            handle.state::<commands::AppState>().shutdown();
+        }
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        tauri::RunEvent::Opened { urls } => launch::open_files(handle, &urls),
+        _ => {}
+    });
 }
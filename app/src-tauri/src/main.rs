@@ -1,6 +1,13 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-fn main() {
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    // Re-launched as a decode helper by the sandbox; see `apply_decode_sandbox`.
+    if let Some(code) = reader_core::codec::sandbox::helper_main() {
+        return code;
+    }
     app_lib::run();
+    ExitCode::SUCCESS
 }
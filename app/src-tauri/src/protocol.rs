@@ -1,24 +1,166 @@
+use std::fmt;
+use std::ops::Range;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use tauri::Runtime;
-use tauri::http::header::{ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE, HeaderValue};
+use tauri::http::header::{
+    ACCEPT_RANGES, ACCESS_CONTROL_ALLOW_ORIGIN, CACHE_CONTROL, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+    HeaderValue, IF_NONE_MATCH, LAST_MODIFIED, RANGE,
+};
 use tauri::http::{Request, Response, StatusCode};
 
+use reader_core::calendar;
+use reader_core::log::RequestId;
+use reader_core::types::{ImageKey, ImageVariant};
+
 use crate::image_cache::ImageCache;
 
 const SCHEME: &str = "asset";
 
+/// Query parameter carrying the [`RequestId`] of the page fetch that produced an image URL.
+pub const REQUEST_ID_PARAM: &str = "rid";
+/// Query parameter carrying the session's [`AccessToken`].
+pub const TOKEN_PARAM: &str = "t";
+
+/// Entries larger than this are served by range instead of being read whole.
+const STREAM_THRESHOLD: u64 = 16 * 1024 * 1024;
+/// Largest chunk returned for an open-ended range of a large entry.
+const STREAM_CHUNK: u64 = 4 * 1024 * 1024;
+
+/// Route of an image URL, selecting which cache entries it may serve and how long the webview
+/// may keep them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Namespace {
+    /// Pages and spreads rendered at display size, under `/img/`.
+    Page,
+    /// Thumbnail strip images, under `/thumb/`.
+    Thumb,
+    /// Slices of tall pages, under `/tile/`.
+    Tile,
+    /// Library cover thumbnails, under `/cover/`.
+    Cover,
+}
+
+impl Namespace {
+    fn from_segment(segment: &str) -> Option<Self> {
+        match segment {
+            "img" => Some(Self::Page),
+            "thumb" => Some(Self::Thumb),
+            "tile" => Some(Self::Tile),
+            "cover" => Some(Self::Cover),
+            _ => None,
+        }
+    }
+
+    fn segment(self) -> &'static str {
+        match self {
+            Self::Page => "img",
+            Self::Thumb => "thumb",
+            Self::Tile => "tile",
+            Self::Cover => "cover",
+        }
+    }
+
+    /// Whether the cache entry `key` may be served under this route.
+    fn serves(self, key: &ImageKey) -> bool {
+        matches!(
+            (self, key.variant),
+            (Self::Page, ImageVariant::Original | ImageVariant::Spread(_))
+                | (Self::Thumb, ImageVariant::Thumb(_))
+                | (Self::Tile, ImageVariant::Tile(_))
+                | (Self::Cover, ImageVariant::Cover(_))
+        )
+    }
+
+    /// Pages are revalidated every time, since keys are reused across sessions for different
+    /// content and 304s are cheap. Thumbnails, tiles and covers are small and many; their URLs
+    /// carry the session token, so a cached copy never outlives the content it was made from.
+    fn cache_control(self) -> &'static str {
+        match self {
+            Self::Page => "no-cache",
+            Self::Thumb | Self::Tile | Self::Cover => "private, max-age=3600",
+        }
+    }
+}
+
+/// Secret generated for each run of the app. Image URLs carry it and requests without it are
+/// refused, so other content loaded in the webview cannot read arbitrary cache keys.
+#[derive(Clone)]
+pub struct AccessToken(Arc<str>);
+
+impl AccessToken {
+    /// Random 128-bit token, hex encoded.
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; 16];
+        getrandom::fill(&mut bytes).expect("the OS random number generator is unavailable");
+        Self(bytes.iter().map(|byte| format!("{byte:02x}")).collect::<String>().into())
+    }
+
+    /// URL serving the page or spread cached under `key`, tagged with the page fetch that
+    /// produced it if any.
+    pub fn image_url(&self, key: &ImageKey, request_id: Option<RequestId>) -> String {
+        self.url(Namespace::Page, key, request_id)
+    }
+
+    /// URL serving the thumbnail cached under `key`.
+    pub fn thumb_url(&self, key: &ImageKey) -> String {
+        self.url(Namespace::Thumb, key, None)
+    }
+
+    /// URL serving the library cover cached under `key`.
+    pub fn cover_url(&self, key: &ImageKey) -> String {
+        self.url(Namespace::Cover, key, None)
+    }
+
+    /// URL serving the cache entry `key` under `namespace`.
+    pub fn url(
+        &self,
+        namespace: Namespace,
+        key: &ImageKey,
+        request_id: Option<RequestId>,
+    ) -> String {
+        let mut url =
+            format!("asset://localhost/{}/{key}?{TOKEN_PARAM}={}", namespace.segment(), self.0);
+        if let Some(request_id) = request_id {
+            url.push_str(&format!("&{REQUEST_ID_PARAM}={request_id}"));
+        }
+        url
+    }
+
+    /// Compare in constant time so the token cannot be guessed byte by byte.
+    fn verify(&self, presented: Option<&str>) -> bool {
+        let Some(presented) = presented else {
+            return false;
+        };
+        let (expected, presented) = (self.0.as_bytes(), presented.as_bytes());
+        expected.len() == presented.len()
+            && expected.iter().zip(presented).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+impl fmt::Debug for AccessToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AccessToken(..)")
+    }
+}
+
 pub fn register<R: Runtime>(
     builder: tauri::Builder<R>,
     cache: Arc<ImageCache>,
+    token: AccessToken,
 ) -> tauri::Builder<R> {
     builder.register_uri_scheme_protocol(SCHEME, move |_ctx, request| {
-        println!("[protocol] incoming request: {:?}", request.uri());
-        handle_request(request, Arc::clone(&cache))
+        tracing::trace!(target: "protocol", path = request.uri().path(), "incoming request");
+        handle_request(request, Arc::clone(&cache), &token)
     })
 }
 
-fn handle_request(request: Request<Vec<u8>>, cache: Arc<ImageCache>) -> Response<Vec<u8>> {
+fn handle_request(
+    request: Request<Vec<u8>>,
+    cache: Arc<ImageCache>,
+    token: &AccessToken,
+) -> Response<Vec<u8>> {
     let uri = request.uri().clone();
 
     let scheme = uri.scheme_str().unwrap_or_default().to_string();
@@ -42,25 +184,209 @@ fn handle_request(request: Request<Vec<u8>>, cache: Arc<ImageCache>) -> Response
         .unwrap_or_else(|_| raw_path.into())
         .to_string();
 
-    let Some(actual_key) = resolve_image_key(&decoded_path, &expected_host) else {
+    // `convertFileSrc` URLs carry the original query percent-encoded inside the path.
+    let (decoded_path, embedded_query) = match decoded_path.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (decoded_path, None),
+    };
+    let query = embedded_query.as_deref().or(uri.query()).unwrap_or_default();
+    let request_id = request_id_from_query(query);
+    if !token.verify(query_param(query, TOKEN_PARAM)) {
+        return forbidden();
+    }
+
+    let Some((namespace, actual_key)) = resolve_namespace_and_key(&decoded_path, &expected_host)
+    else {
         return not_found("Missing key");
     };
+    if !namespace.serves(&actual_key) {
+        return not_found("Missing resource");
+    }
+    let _span = tracing::info_span!(
+        "protocol_serve",
+        request_id = request_id.map(tracing::field::display),
+        namespace = namespace.segment(),
+        key = %actual_key
+    )
+    .entered();
 
     println!("[protocol] resolved key={}", actual_key);
 
+    match cache.size(&actual_key) {
+        Ok(Some(size)) if size > STREAM_THRESHOLD => {
+            return serve_large(&request, &cache, namespace, &actual_key, size);
+        }
+        Ok(_) => {}
+        Err(err) => return internal_error(&err.to_string()),
+    }
+
     let cached = match cache.fetch(&actual_key) {
         Ok(Some(image)) => image,
         Ok(None) => return not_found("Missing resource"),
-        Err(err) => return internal_error(&err),
+        Err(err) => return internal_error(&err.to_string()),
+    };
+    let validators = Validators {
+        cache_control: namespace.cache_control(),
+        etag: entity_tag(&cached.bytes),
+        last_modified: cache.modified(&actual_key).map(http_date),
     };
+    let if_none_match = request.headers().get(IF_NONE_MATCH).and_then(|value| value.to_str().ok());
+    if if_none_match.is_some_and(|tags| etag_matches(tags, &validators.etag)) {
+        tracing::trace!(target: "protocol", key = %actual_key, "not modified");
+        return not_modified(&validators);
+    }
     println!("[protocol] serving key={}, bytes={}", actual_key, cached.bytes.len());
-    success_response(cached.bytes, &cached.mime)
+    success_response(cached.bytes, &cached.mime, &validators)
+}
+
+/// Serve an entry above [`STREAM_THRESHOLD`] without passing it through the memory cache.
+///
+/// Protocol handlers hand the webview a complete body, so clients that stream large entries
+/// issue successive `Range` requests, and only the requested chunk is read; open-ended ranges
+/// return at most [`STREAM_CHUNK`] bytes. Requests without a range, such as `<img>` loads, get
+/// the whole entry, read a chunk at a time. The validators come from the file metadata, since
+/// hashing the content would mean reading it whole.
+fn serve_large(
+    request: &Request<Vec<u8>>,
+    cache: &ImageCache,
+    namespace: Namespace,
+    key: &ImageKey,
+    size: u64,
+) -> Response<Vec<u8>> {
+    let modified = cache.modified(key);
+    let modified_secs = modified
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let validators = Validators {
+        cache_control: namespace.cache_control(),
+        etag: format!("W/\"{size:x}-{modified_secs:x}\""),
+        last_modified: modified.map(http_date),
+    };
+    let if_none_match = request.headers().get(IF_NONE_MATCH).and_then(|value| value.to_str().ok());
+    if if_none_match.is_some_and(|tags| etag_matches(tags, &validators.etag)) {
+        return not_modified(&validators);
+    }
+
+    let Some(range) = request.headers().get(RANGE).and_then(|value| value.to_str().ok()) else {
+        return serve_whole(cache, key, size, &validators);
+    };
+    let range = match parse_range(range, size) {
+        Some(range) => range,
+        None => {
+            let builder = cors_builder(StatusCode::RANGE_NOT_SATISFIABLE, None)
+                .header(CONTENT_RANGE, format!("bytes */{size}"));
+            return finish(builder, Vec::new());
+        }
+    };
+
+    let cached = match cache.fetch_range(key, range.clone()) {
+        Ok(Some(image)) => image,
+        Ok(None) => return not_found("Missing resource"),
+        Err(err) => return internal_error(&err.to_string()),
+    };
+    tracing::trace!(target: "protocol", %key, ?range, size, "serving range");
+    let ct = HeaderValue::from_str(&cached.mime)
+        .unwrap_or_else(|_| HeaderValue::from_static("application/octet-stream"));
+    let builder = validators
+        .apply(cors_builder(StatusCode::PARTIAL_CONTENT, Some(ct)))
+        .header(ACCEPT_RANGES, "bytes")
+        .header(CONTENT_RANGE, format!("bytes {}-{}/{size}", range.start, range.end - 1));
+    finish(builder, cached.bytes)
 }
 
-fn success_response(body: Vec<u8>, mimetype: &str) -> Response<Vec<u8>> {
+/// Answer an unranged request for a large entry with all `size` bytes of it, read
+/// [`STREAM_CHUNK`] bytes at a time.
+fn serve_whole(
+    cache: &ImageCache,
+    key: &ImageKey,
+    size: u64,
+    validators: &Validators,
+) -> Response<Vec<u8>> {
+    let mut body = Vec::with_capacity(size as usize);
+    let mut mime = String::new();
+    while (body.len() as u64) < size {
+        let start = body.len() as u64;
+        let chunk = match cache.fetch_range(key, start..size.min(start + STREAM_CHUNK)) {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => return not_found("Missing resource"),
+            Err(err) => return internal_error(&err.to_string()),
+        };
+        if chunk.bytes.is_empty() {
+            return internal_error("cache entry shrank while it was read");
+        }
+        body.extend_from_slice(&chunk.bytes);
+        mime = chunk.mime;
+    }
+    tracing::trace!(target: "protocol", %key, size, "serving large entry whole");
+    let ct = HeaderValue::from_str(&mime)
+        .unwrap_or_else(|_| HeaderValue::from_static("application/octet-stream"));
+    let builder =
+        validators.apply(cors_builder(StatusCode::OK, Some(ct))).header(ACCEPT_RANGES, "bytes");
+    finish(builder, body)
+}
+
+/// Resolve a single-range `Range` header against an entry of `size` bytes. Returns `None` when
+/// the range cannot be satisfied; multi-range requests are answered with their first range.
+fn parse_range(header: &str, size: u64) -> Option<Range<u64>> {
+    let spec = header.trim().strip_prefix("bytes=")?.split(',').next()?.trim();
+    let (start, end) = spec.split_once('-')?;
+    let range = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            size.saturating_sub(suffix)..size
+        }
+        (start, "") => {
+            let start: u64 = start.parse().ok()?;
+            start..size.min(start.saturating_add(STREAM_CHUNK))
+        }
+        (start, end) => {
+            let (start, end): (u64, u64) = (start.parse().ok()?, end.parse().ok()?);
+            if end < start {
+                return None;
+            }
+            start..size.min(end.saturating_add(1))
+        }
+    };
+    (range.start < range.end).then_some(range)
+}
+
+/// Caching headers sent with every served image.
+struct Validators {
+    cache_control: &'static str,
+    etag: String,
+    last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Identical bytes are answered with an empty 304 whenever the webview revalidates.
+    fn apply(&self, builder: tauri::http::response::Builder) -> tauri::http::response::Builder {
+        let mut builder =
+            builder.header(CACHE_CONTROL, self.cache_control).header(ETAG, &self.etag);
+        if let Some(last_modified) = &self.last_modified {
+            builder = builder.header(LAST_MODIFIED, last_modified);
+        }
+        builder
+    }
+}
+
+fn success_response(body: Vec<u8>, mimetype: &str, validators: &Validators) -> Response<Vec<u8>> {
     let ct = HeaderValue::from_str(mimetype)
         .unwrap_or_else(|_| HeaderValue::from_static("application/octet-stream"));
-    cors_response(StatusCode::OK, body, Some(ct))
+    let builder = validators.apply(cors_builder(StatusCode::OK, Some(ct)));
+    finish(builder, body)
+}
+
+fn not_modified(validators: &Validators) -> Response<Vec<u8>> {
+    let builder = validators.apply(cors_builder(StatusCode::NOT_MODIFIED, None));
+    finish(builder, Vec::new())
+}
+
+fn forbidden() -> Response<Vec<u8>> {
+    cors_response(
+        StatusCode::FORBIDDEN,
+        b"Missing or invalid access token".to_vec(),
+        Some(HeaderValue::from_static("text/plain; charset=utf-8")),
+    )
 }
 
 fn not_found(message: &str) -> Response<Vec<u8>> {
@@ -84,6 +410,13 @@ fn cors_response(
     body: Vec<u8>,
     content_type: Option<HeaderValue>,
 ) -> Response<Vec<u8>> {
+    finish(cors_builder(status, content_type), body)
+}
+
+fn cors_builder(
+    status: StatusCode,
+    content_type: Option<HeaderValue>,
+) -> tauri::http::response::Builder {
     let mut builder = Response::builder();
     builder = builder.status(status);
 
@@ -97,33 +430,49 @@ fn cors_response(
         headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
     }
 
+    builder
+}
+
+fn finish(builder: tauri::http::response::Builder, body: Vec<u8>) -> Response<Vec<u8>> {
     builder.body(body).unwrap_or_else(|_| {
         Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Vec::new()).unwrap()
     })
 }
 
-fn resolve_image_key(decoded_path: &str, expected_host: &str) -> Option<String> {
-    let expected_host_with_slash = format!("{expected_host}/");
-    let mut remainder = decoded_path.trim_start_matches('/');
+/// Strong entity tag derived from the served bytes.
+fn entity_tag(bytes: &[u8]) -> String {
+    format!("\"{}\"", &blake3::hash(bytes).to_hex()[..32])
+}
 
-    remainder = strip_all_prefixes(remainder, "asset://");
-    remainder = strip_all_prefixes(remainder, "//");
-    remainder = strip_all_prefixes(remainder, expected_host_with_slash.as_str());
-    remainder = strip_all_prefixes(remainder, expected_host);
-    remainder = strip_all_prefixes(remainder, "asset://");
-    remainder = strip_all_prefixes(remainder, "//");
-    remainder = strip_all_prefixes(remainder, "localhost/");
-    remainder = remainder.trim_start_matches('/');
+/// Whether an `If-None-Match` header value names `etag`; weak tags compare equal too.
+fn etag_matches(header: &str, etag: &str) -> bool {
+    header.split(',').map(str::trim).any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
 
-    let mut had_img_prefix = false;
-    if let Some(stripped) = remainder.strip_prefix("img/") {
-        remainder = stripped;
-        had_img_prefix = true;
-    }
+/// Format `time` as an IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn http_date(time: SystemTime) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] =
+        ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let secs = time.duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0);
+    let days = (secs / 86_400) as i64;
+    let (hour, minute, second) = (secs % 86_400 / 3_600, secs % 3_600 / 60, secs % 60);
+    let (y, m, d) = calendar::civil_from_days(days);
+
+    format!(
+        "{}, {d:02} {} {y:04} {hour:02}:{minute:02}:{second:02} GMT",
+        WEEKDAYS[days.rem_euclid(7) as usize],
+        MONTHS[(m - 1) as usize]
+    )
+}
 
-    remainder = remainder.trim_start_matches('/');
+fn request_id_from_query(query: &str) -> Option<RequestId> {
+    query_param(query, REQUEST_ID_PARAM).and_then(RequestId::parse)
+}
 
-    if !had_img_prefix || remainder.is_empty() { None } else { Some(remainder.to_string()) }
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query.split('&').find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
 }
 
 fn strip_all_prefixes<'a>(mut value: &'a str, prefix: &str) -> &'a str {
@@ -142,41 +491,58 @@ fn strip_all_prefixes<'a>(mut value: &'a str, prefix: &str) -> &'a str {
 mod tests {
     use super::*;
     use reader_core::stats::StatsCollector;
+    use reader_core::types::CacheBudget;
     use std::sync::Arc;
 
+    fn token() -> AccessToken {
+        AccessToken("secret".into())
+    }
+
+    fn key(key: &str) -> ImageKey {
+        key.parse().unwrap()
+    }
+
     fn cache_with_entry(key: &str, bytes: &[u8], mime: &str) -> Arc<ImageCache> {
         let temp = tempfile::tempdir().unwrap();
-        let stats = Arc::new(StatsCollector::new());
-        let cache = ImageCache::with_root(temp.path().join("cache"), Arc::clone(&stats)).unwrap();
-        cache.ensure_bytes(key, mime, || Ok(bytes.to_vec())).unwrap();
+        let stats = Arc::new(StatsCollector::default());
+        let cache = ImageCache::with_root(
+            temp.path().join("cache"),
+            CacheBudget::default(),
+            Arc::clone(&stats),
+        )
+        .unwrap();
+        cache.ensure_bytes(&self::key(key), mime, || Ok(bytes.to_vec())).unwrap();
         Arc::new(cache)
     }
 
     #[test]
     fn resolve_key_from_convert_file_src_url() {
         let expected = "asset.localhost".to_string();
-        let key = resolve_image_key("asset://localhost/img/src-1-page-0", &expected).unwrap();
-        assert_eq!(key, "src-1-page-0");
+        let resolved = resolve_namespace_and_key("asset://localhost/img/src-1-page-0", &expected);
+        assert_eq!(resolved, Some((Namespace::Page, key("src-1-page-0"))));
+        assert_eq!(resolve_namespace_and_key("asset://localhost/img/", &expected), None);
+        assert_eq!(resolve_namespace_and_key("asset://localhost/raw/src-1", &expected), None);
     }
 
     #[test]
     fn resolve_key_from_nested_http_url() {
         let expected = "asset.localhost".to_string();
-        let key =
-            resolve_image_key("asset.localhost/asset://localhost/img/src-1-thumb-0-320", &expected)
-                .unwrap();
-        assert_eq!(key, "src-1-thumb-0-320");
+        let resolved = resolve_namespace_and_key(
+            "asset.localhost/asset://localhost/thumb/src-1-thumb-0-320",
+            &expected,
+        );
+        assert_eq!(resolved, Some((Namespace::Thumb, key("src-1-thumb-0-320"))));
     }
 
     #[test]
     fn serves_cached_bytes_for_http_requests() {
         let cache = cache_with_entry("src-1-page-0", b"hello", "image/png");
         let request = Request::builder()
-            .uri("http://asset.localhost/asset%3A%2F%2Flocalhost%2Fimg%2Fsrc-1-page-0")
+            .uri("http://asset.localhost/asset%3A%2F%2Flocalhost%2Fimg%2Fsrc-1-page-0%3Ft%3Dsecret")
             .body(Vec::new())
             .unwrap();
 
-        let response = handle_request(request, cache);
+        let response = handle_request(request, cache, &token());
 
         assert_eq!(response.status(), StatusCode::OK);
         assert_eq!(response.body(), &b"hello".to_vec());
@@ -186,54 +552,214 @@ mod tests {
     #[test]
     fn serves_cached_bytes_for_asset_scheme_requests() {
         let cache = cache_with_entry("src-1-page-1", b"world", "image/png");
-        let request =
-            Request::builder().uri("asset://localhost/img/src-1-page-1").body(Vec::new()).unwrap();
+        let request = Request::builder()
+            .uri("asset://localhost/img/src-1-page-1?t=secret")
+            .body(Vec::new())
+            .unwrap();
 
-        let response = handle_request(request, cache);
+        let response = handle_request(request, cache, &token());
 
         assert_eq!(response.status(), StatusCode::OK);
         assert_eq!(response.body(), &b"world".to_vec());
     }
 
     #[test]
-    fn missing_entries_return_not_found_with_cors() {
-        let temp = tempfile::tempdir().unwrap();
-        let stats = Arc::new(StatsCollector::new());
-        let cache =
-            Arc::new(ImageCache::with_root(temp.path().join("cache"), Arc::clone(&stats)).unwrap());
+    fn request_id_query_does_not_affect_key() {
+        let cache = cache_with_entry("src-1-page-2", b"page", "image/png");
         let request = Request::builder()
-            .uri("http://asset.localhost/asset%3A%2F%2Flocalhost%2Fimg%2Fmissing")
+            .uri("http://asset.localhost/asset%3A%2F%2Flocalhost%2Fimg%2Fsrc-1-page-2%3Ft%3Dsecret%26rid%3D0000002a")
             .body(Vec::new())
             .unwrap();
 
-        let response = handle_request(request, cache);
+        let response = handle_request(request, cache, &token());
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(request_id_from_query("x=1&rid=0000002a").map(RequestId::get), Some(42));
+        assert_eq!(request_id_from_query("rider=1"), None);
+    }
+
+    #[test]
+    fn requests_without_the_session_token_are_refused() {
+        let cache = cache_with_entry("src-1-page-5", b"private", "image/png");
+        for uri in [
+            "asset://localhost/img/src-1-page-5",
+            "asset://localhost/img/src-1-page-5?t=guess!",
+            "asset://localhost/img/src-1-page-5?rid=0000002a",
+        ] {
+            let request = Request::builder().uri(uri).body(Vec::new()).unwrap();
+            let response = handle_request(request, Arc::clone(&cache), &token());
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        }
+
+        let url = token().image_url(&key("src-1-page-5"), RequestId::parse("0000002a"));
+        assert_eq!(url, "asset://localhost/img/src-1-page-5?t=secret&rid=0000002a");
+        let request = Request::builder().uri(url.as_str()).body(Vec::new()).unwrap();
+        let response = handle_request(request, cache, &token());
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn routes_only_serve_their_own_namespace() {
+        let cache = cache_with_entry("src-1-thumb-0-320", b"thumb", "image/webp");
+        let serve = |uri: &str| {
+            let request = Request::builder().uri(uri).body(Vec::new()).unwrap();
+            handle_request(request, Arc::clone(&cache), &token())
+        };
+
+        let url = token().thumb_url(&key("src-1-thumb-0-320"));
+        assert_eq!(url, "asset://localhost/thumb/src-1-thumb-0-320?t=secret");
+        let response = serve(&url);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(CACHE_CONTROL).unwrap(), "private, max-age=3600");
 
+        let response = serve("asset://localhost/img/src-1-thumb-0-320?t=secret");
         assert_eq!(response.status(), StatusCode::NOT_FOUND);
-        assert_eq!(response.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "*");
+        let response = serve("asset://localhost/tile/src-1-thumb-0-320?t=secret");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = serve("asset://localhost/cover/src-1-thumb-0-320?t=secret");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let cache = cache_with_entry("lib-1-cover-0-256", b"cover", "image/webp");
+        let url = token().cover_url(&key("lib-1-cover-0-256"));
+        assert_eq!(url, "asset://localhost/cover/lib-1-cover-0-256?t=secret");
+        let request = Request::builder().uri(url.as_str()).body(Vec::new()).unwrap();
+        assert_eq!(handle_request(request, cache, &token()).status(), StatusCode::OK);
+
+        let cache = cache_with_entry("src-1-spread-0-1", b"spread", "image/png");
+        let request = Request::builder()
+            .uri("asset://localhost/img/src-1-spread-0-1?t=secret")
+            .body(Vec::new())
+            .unwrap();
+        let response = handle_request(request, cache, &token());
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(CACHE_CONTROL).unwrap(), "no-cache");
     }
-}
 
-fn resolve_namespace_and_key(decoded_path: &str, expected_host: &str) -> (String, String) {
-    let expected_host_with_slash = format!("{expected_host}/");
-    let mut remainder = decoded_path.trim_start_matches('/');
+    #[test]
+    fn matching_etag_returns_not_modified() {
+        let cache = cache_with_entry("src-1-page-3", b"same", "image/png");
+        let uri = "asset://localhost/img/src-1-page-3?t=secret";
+        let first = handle_request(
+            Request::builder().uri(uri).body(Vec::new()).unwrap(),
+            Arc::clone(&cache),
+            &token(),
+        );
+        let etag = first.headers().get(ETAG).unwrap().to_str().unwrap().to_string();
+        assert!(first.headers().get(LAST_MODIFIED).unwrap().to_str().unwrap().ends_with(" GMT"));
+
+        let revalidate = |tags: &str| {
+            let request =
+                Request::builder().uri(uri).header(IF_NONE_MATCH, tags).body(Vec::new()).unwrap();
+            handle_request(request, Arc::clone(&cache), &token())
+        };
+        let cached = revalidate(&format!("\"other\", W/{etag}"));
+        assert_eq!(cached.status(), StatusCode::NOT_MODIFIED);
+        assert!(cached.body().is_empty());
+        assert_eq!(cached.headers().get(ETAG).unwrap(), etag.as_str());
+        assert_eq!(revalidate("\"other\"").status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn parses_byte_ranges() {
+        assert_eq!(parse_range("bytes=0-99", 1_000), Some(0..100));
+        assert_eq!(parse_range("bytes=900-2000", 1_000), Some(900..1_000));
+        assert_eq!(parse_range("bytes=-100", 1_000), Some(900..1_000));
+        assert_eq!(parse_range("bytes=10-19, 30-39", 1_000), Some(10..20));
+        assert_eq!(parse_range("bytes=0-", u64::MAX), Some(0..STREAM_CHUNK));
+        assert_eq!(parse_range("bytes=1000-", 1_000), None);
+        assert_eq!(parse_range("bytes=5-1", 1_000), None);
+        assert_eq!(parse_range("items=0-1", 1_000), None);
+    }
+
+    #[test]
+    fn serves_large_entries_by_range() {
+        let size = STREAM_THRESHOLD as usize + 10;
+        let mut bytes = vec![0; size];
+        bytes[size - 2..].copy_from_slice(b"ok");
+        let cache = cache_with_entry("src-1-page-4", &bytes, "image/png");
+        let uri = "asset://localhost/img/src-1-page-4?t=secret";
+
+        let request =
+            Request::builder().uri(uri).header(RANGE, "bytes=-2").body(Vec::new()).unwrap();
+        let response = handle_request(request, Arc::clone(&cache), &token());
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.body(), &b"ok".to_vec());
+        let content_range = format!("bytes {}-{}/{size}", size - 2, size - 1);
+        assert_eq!(response.headers().get(CONTENT_RANGE).unwrap(), content_range.as_str());
+        assert_eq!(response.headers().get(ACCEPT_RANGES).unwrap(), "bytes");
 
-    if let Some(stripped) = remainder.strip_prefix("asset://") {
-        remainder = stripped;
+        let request =
+            Request::builder().uri(uri).header(RANGE, "bytes=0-").body(Vec::new()).unwrap();
+        let response = handle_request(request, Arc::clone(&cache), &token());
+        assert_eq!(response.body().len() as u64, STREAM_CHUNK);
+
+        let request = Request::builder().uri(uri).body(Vec::new()).unwrap();
+        let response = handle_request(request, Arc::clone(&cache), &token());
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), &bytes);
+        assert!(response.headers().get(CONTENT_RANGE).is_none());
+        assert_eq!(response.headers().get(ACCEPT_RANGES).unwrap(), "bytes");
+
+        let request = Request::builder()
+            .uri(uri)
+            .header(RANGE, format!("bytes={size}-"))
+            .body(Vec::new())
+            .unwrap();
+        let response = handle_request(request, cache, &token());
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
     }
 
-    if let Some(stripped) = remainder.strip_prefix("//") {
-        remainder = stripped;
+    #[test]
+    fn formats_http_dates() {
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(784_111_777);
+        assert_eq!(http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
     }
 
-    if let Some(stripped) = remainder.strip_prefix(&expected_host_with_slash) {
-        remainder = stripped;
-    } else if let Some(stripped) = remainder.strip_prefix("localhost/") {
-        remainder = stripped;
+    #[test]
+    fn missing_entries_return_not_found_with_cors() {
+        let temp = tempfile::tempdir().unwrap();
+        let stats = Arc::new(StatsCollector::default());
+        let cache = Arc::new(
+            ImageCache::with_root(
+                temp.path().join("cache"),
+                CacheBudget::default(),
+                Arc::clone(&stats),
+            )
+            .unwrap(),
+        );
+        let request = Request::builder()
+            .uri("http://asset.localhost/asset%3A%2F%2Flocalhost%2Fimg%2Fmissing%3Ft%3Dsecret")
+            .body(Vec::new())
+            .unwrap();
+
+        let response = handle_request(request, cache, &token());
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "*");
     }
+}
 
+/// Route and cache key of a request path such as `img/<key>`, tolerating the nested and
+/// repeated host prefixes that `convertFileSrc` produces.
+fn resolve_namespace_and_key(
+    decoded_path: &str,
+    expected_host: &str,
+) -> Option<(Namespace, ImageKey)> {
+    let expected_host_with_slash = format!("{expected_host}/");
+    let mut remainder = decoded_path.trim_start_matches('/');
+
+    remainder = strip_all_prefixes(remainder, "asset://");
+    remainder = strip_all_prefixes(remainder, "//");
+    remainder = strip_all_prefixes(remainder, expected_host_with_slash.as_str());
+    remainder = strip_all_prefixes(remainder, expected_host);
+    remainder = strip_all_prefixes(remainder, "asset://");
+    remainder = strip_all_prefixes(remainder, "//");
+    remainder = strip_all_prefixes(remainder, "localhost/");
     remainder = remainder.trim_start_matches('/');
-    let mut segments = remainder.splitn(2, '/');
-    let namespace = segments.next().unwrap_or_default().to_string();
-    let key = segments.next().unwrap_or_default().to_string();
-    (namespace, key)
+
+    let (segment, key) = remainder.split_once('/')?;
+    let namespace = Namespace::from_segment(segment)?;
+    let key = key.trim_start_matches('/').parse().ok()?;
+    Some((namespace, key))
 }
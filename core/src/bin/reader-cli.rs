@@ -0,0 +1,329 @@
+//! Headless companion to the reader for scripting: list pages, print metadata, extract or
+//! convert archives, warm the shared image cache (e.g. on a NAS before reading), and benchmark
+//! decoding and resizing.
+//!
+//! Cache entries are keyed by the canonical path of the source rather than a session id, so
+//! they stay valid across runs.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use reader_core::cache::disk::DiskCache;
+use reader_core::codec::DecodedImage;
+use reader_core::codec::encode::{EncodeFormat, encode};
+use reader_core::codec::sandbox::{self, DecodeSandbox};
+use reader_core::fs::{self, archive};
+use reader_core::pipeline::bench::{BenchConfig, run_benchmark};
+use reader_core::pipeline::mip::{MipChainConfig, build_chain};
+use reader_core::pipeline::render::render_thumbnail;
+use reader_core::pipeline::resize::ResizeFilter;
+use reader_core::{ImageKey, PageMeta, SourceId};
+
+const USAGE: &str = "\
+usage: reader-cli <command> <path> [options]
+
+  --sandbox                    decode pages in a helper process, for untrusted files
+
+commands:
+  pages <path>                 list the pages of a folder or archive
+  info <path>                  print metadata about a folder or archive
+  extract <path> <dir>         write every page into <dir>
+      --format png|jpeg|webp   convert pages instead of copying them as stored
+      --quality <1-100>        JPEG quality (default 90)
+  warm <path>                  pre-generate cache entries
+      --thumb <px>             thumbnail longest side (default 320)
+      --mips                   also generate mip chains of each page
+      --cache <dir>            cache directory (default: the app's cache)
+  bench <path>                 time reading, decoding and resizing every page
+      --threads <n>            worker threads (default: one per spare core, up to 4)
+      --longest <px>           longest side pages are resized to (default 1440)
+      --filter <name>          nearest|box|bilinear|hamming|catmull-rom|mitchell|lanczos3
+      --pages <n>              only the first <n> pages";
+
+/// JPEG quality used by `extract` when none is given.
+const DEFAULT_QUALITY: u8 = 90;
+/// Thumbnail size generated by `warm` when none is given.
+const DEFAULT_THUMB: u32 = 320;
+
+fn main() -> ExitCode {
+    if let Some(code) = sandbox::helper_main() {
+        return code;
+    }
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("reader-cli: {err:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<()> {
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) if command != "-h" && command != "--help" => (command, rest),
+        _ => {
+            println!("{USAGE}");
+            return Ok(());
+        }
+    };
+    let command: fn(&Book, &Options) -> Result<()> = match command.as_str() {
+        "pages" => |book, _| pages(book),
+        "info" => |book, _| info(book),
+        "extract" => extract,
+        "warm" => warm,
+        "bench" => bench,
+        other => bail!("unknown command `{other}`\n\n{USAGE}"),
+    };
+    let options = Options::parse(rest)?;
+    if options.has("sandbox") {
+        let exe = std::env::current_exe().context("locating reader-cli for the decode helper")?;
+        sandbox::install(Some(DecodeSandbox::new(exe, [sandbox::HELPER_ARG])));
+    }
+    let book = Book::open(Path::new(options.positional(0, "path")?))?;
+    command(&book, &options)
+}
+
+/// Positional arguments and `--flag [value]` options.
+#[derive(Debug, Default)]
+struct Options {
+    positional: Vec<String>,
+    flags: Vec<(String, Option<String>)>,
+}
+
+impl Options {
+    /// Flags that take no value.
+    const SWITCHES: [&str; 2] = ["mips", "sandbox"];
+
+    fn parse(args: &[String]) -> Result<Self> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if Self::SWITCHES.contains(&name) => {
+                    options.flags.push((name.to_string(), None));
+                }
+                Some(name) => {
+                    let value = args.next().ok_or_else(|| anyhow!("--{name} needs a value"))?;
+                    options.flags.push((name.to_string(), Some(value.clone())));
+                }
+                None => options.positional.push(arg.clone()),
+            }
+        }
+        Ok(options)
+    }
+
+    fn positional(&self, index: usize, name: &str) -> Result<&str> {
+        self.positional.get(index).map(String::as_str).ok_or_else(|| anyhow!("missing <{name}>"))
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.flags.iter().any(|(flag, _)| flag == name)
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.flags
+            .iter()
+            .rev()
+            .find(|(flag, _)| flag == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    fn number<T: std::str::FromStr>(&self, name: &str, default: T) -> Result<T> {
+        match self.value(name) {
+            Some(value) => value.parse().map_err(|_| anyhow!("invalid --{name} `{value}`")),
+            None => Ok(default),
+        }
+    }
+}
+
+/// A folder or archive opened for reading.
+struct Book {
+    path: PathBuf,
+    /// Stable cache namespace of the source; see [`fs::source_id_for`].
+    source: SourceId,
+    archive: bool,
+    pages: Vec<PageMeta>,
+}
+
+impl Book {
+    fn open(path: &Path) -> Result<Self> {
+        let path = path.canonicalize().with_context(|| format!("opening {}", path.display()))?;
+        let id = fs::source_id_for(&path)?;
+        let archive = !path.is_dir();
+        let pages = if archive {
+            let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+            if !archive::SUPPORTED_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()) {
+                bail!("{} is not a folder or supported archive", path.display());
+            }
+            fs::list_archive_pages(&path, &id)?
+        } else {
+            fs::list_folder_pages(&path, &id)?
+        };
+        Ok(Self { path, source: id, archive, pages })
+    }
+
+    fn read(&self, page: &PageMeta) -> Result<Vec<u8>> {
+        if self.archive {
+            Ok(fs::read_archive_entry(&self.path, &page.rel_path)?)
+        } else {
+            let full = self.path.join(&page.rel_path);
+            std::fs::read(&full).with_context(|| format!("reading {}", full.display()))
+        }
+    }
+
+    fn decode(&self, page: &PageMeta) -> Result<DecodedImage> {
+        sandbox::decode(page, &self.read(page)?)
+            .with_context(|| format!("decoding {}", page.rel_path.display()))
+    }
+}
+
+fn pages(book: &Book) -> Result<()> {
+    for page in &book.pages {
+        println!("{:>5}  {}", page.id.index, page.rel_path.display());
+    }
+    Ok(())
+}
+
+fn info(book: &Book) -> Result<()> {
+    println!("path:    {}", book.path.display());
+    println!("kind:    {}", if book.archive { "archive" } else { "folder" });
+    println!("pages:   {}", book.pages.len());
+    if let Some(first) = book.pages.first() {
+        let cover = book.decode(first)?;
+        println!("cover:   {} ({}x{})", first.rel_path.display(), cover.width(), cover.height());
+    }
+    println!("cache:   {}", book.source.as_str());
+    Ok(())
+}
+
+fn extract(book: &Book, options: &Options) -> Result<()> {
+    let dir = PathBuf::from(options.positional(1, "dir")?);
+    std::fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+    let quality = options.number("quality", DEFAULT_QUALITY)?;
+    let format = match options.value("format") {
+        None => None,
+        Some("png") => Some(EncodeFormat::Png),
+        Some("jpeg" | "jpg") => Some(EncodeFormat::Jpeg { quality: quality.clamp(1, 100) }),
+        Some("webp") => Some(EncodeFormat::WebP),
+        Some(other) => bail!("unsupported --format `{other}`"),
+    };
+
+    for page in &book.pages {
+        let stem = page.rel_path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("page");
+        let (bytes, ext) = match format {
+            Some(format) => (encode(&book.decode(page)?, format)?.bytes, format.extension()),
+            None => {
+                let ext = page.rel_path.extension().and_then(|ext| ext.to_str()).unwrap_or("bin");
+                (book.read(page)?, ext)
+            }
+        };
+        let out = dir.join(format!("{:04}-{stem}.{ext}", page.id.index + 1));
+        std::fs::write(&out, bytes).with_context(|| format!("writing {}", out.display()))?;
+        println!("{}", out.display());
+    }
+    Ok(())
+}
+
+fn warm(book: &Book, options: &Options) -> Result<()> {
+    let root = match options.value("cache") {
+        Some(dir) => PathBuf::from(dir),
+        None => reader_core::store::cache_dir()?,
+    };
+    let cache = DiskCache::new(&root)?;
+    let longest = options.number("thumb", DEFAULT_THUMB)?;
+    let mips = options.has("mips");
+    let (mut written, mut failed) = (0usize, 0usize);
+
+    for page in &book.pages {
+        let thumb_key = ImageKey::thumb(&page.id, longest);
+        let page_key = ImageKey::original(&page.id);
+        let result = (|| -> Result<usize> {
+            let thumb_cached = cache.size(&thumb_key)?.is_some();
+            if thumb_cached && !mips {
+                return Ok(0);
+            }
+            let decoded = book.decode(page)?;
+            let mut count = 0;
+            if !thumb_cached {
+                cache.write(&thumb_key, &render_thumbnail(&decoded, longest)?.bytes)?;
+                count += 1;
+            }
+            if mips {
+                for level in build_chain(&page_key, &decoded, MipChainConfig::default())?.levels() {
+                    let image = level.image.clone().into_decoded();
+                    cache.write(
+                        &level.key,
+                        &encode(&image, EncodeFormat::for_image(&image))?.bytes,
+                    )?;
+                    count += 1;
+                }
+            }
+            Ok(count)
+        })();
+        match result {
+            Ok(count) => written += count,
+            Err(err) => {
+                failed += 1;
+                eprintln!("{}: {err:#}", page.rel_path.display());
+            }
+        }
+    }
+    println!("wrote {written} cache entries to {} ({failed} pages failed)", root.display());
+    if failed > 0 {
+        bail!("{failed} of {} pages failed", book.pages.len());
+    }
+    Ok(())
+}
+
+fn bench(book: &Book, options: &Options) -> Result<()> {
+    let defaults = BenchConfig::default();
+    let filter = match options.value("filter") {
+        None => defaults.filter,
+        Some("nearest") => ResizeFilter::Nearest,
+        Some("box") => ResizeFilter::Box,
+        Some("bilinear") => ResizeFilter::Bilinear,
+        Some("hamming") => ResizeFilter::Hamming,
+        Some("catmull-rom") => ResizeFilter::CatmullRom,
+        Some("mitchell") => ResizeFilter::Mitchell,
+        Some("lanczos3") => ResizeFilter::Lanczos3,
+        Some(other) => bail!("unsupported --filter `{other}`"),
+    };
+    let config = BenchConfig {
+        threads: options.number("threads", defaults.threads)?,
+        longest: options.number("longest", defaults.longest)?,
+        filter,
+        max_pages: options.value("pages").map(|_| options.number("pages", 0)).transpose()?,
+    };
+    let report = run_benchmark(&book.pages, |page| book.read(page), config)?;
+
+    println!("pages:   {} ({} failed)", report.pages, report.failed);
+    println!(
+        "threads: {}  filter: {:?}  longest: {}px",
+        report.threads, report.filter, report.longest
+    );
+    println!(
+        "total:   {:.0} ms, {:.1} pages/s, {:.1} MP/s, {:.1} MB read",
+        report.wall_ms,
+        report.pages_per_sec,
+        report.megapixels_per_sec,
+        report.bytes_read as f64 / 1_000_000.0
+    );
+    for (stage, timing) in
+        [("read", report.read), ("decode", report.decode), ("resize", report.resize)]
+    {
+        println!(
+            "{:<8} {:>9.1} ms total {:>8.2} ms mean {:>8.2} ms max",
+            format!("{stage}:"),
+            timing.total_ms,
+            timing.mean_ms,
+            timing.max_ms
+        );
+    }
+    if report.failed > 0 {
+        bail!("{} of {} pages failed", report.failed, report.pages + report.failed);
+    }
+    Ok(())
+}
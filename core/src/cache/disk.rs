@@ -1,13 +1,15 @@
 //! Disk-backed cache for resized bitmaps and thumbnails.
 
-use std::fs;
-use std::io::Write;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Error, anyhow};
 use tempfile::NamedTempFile;
 
-use crate::types::ImageKey;
+use crate::error::CoreError;
+use crate::types::{CacheBudget, ImageKey};
 
 use super::Result;
 
@@ -17,15 +19,22 @@ const SHARD_LEN: usize = 2;
 #[derive(Debug, Clone)]
 pub struct DiskCache {
     root: PathBuf,
+    budget: u64,
 }
 
 impl DiskCache {
-    /// Create or reuse a disk cache rooted at the provided path.
+    /// Create or reuse a disk cache rooted at the provided path, with the default disk budget.
     pub fn new(root: impl Into<PathBuf>) -> Result<Self> {
         let root = root.into();
         fs::create_dir_all(&root)
             .with_context(|| format!("creating cache root directory at {}", root.display()))?;
-        Ok(Self { root })
+        Ok(Self { root, budget: CacheBudget::default().disk_bytes_max })
+    }
+
+    /// Use the disk half of `budget` for [`DiskCache::trim_to_budget`].
+    pub fn with_budget(mut self, budget: CacheBudget) -> Self {
+        self.budget = budget.disk_bytes_max;
+        self
     }
 
     /// Returns the root directory backing the cache.
@@ -33,9 +42,14 @@ impl DiskCache {
         &self.root
     }
 
+    /// Bytes the entries on disk may take up in total.
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
     /// Resolve the on-disk path associated with an image key.
     pub fn path_for(&self, key: &ImageKey) -> PathBuf {
-        let hash = blake3::hash(key.cache_key.as_bytes());
+        let hash = blake3::hash(key.to_string().as_bytes());
         let hex = hash.to_hex();
         let hex_str = hex.as_str();
 
@@ -56,9 +70,38 @@ impl DiskCache {
         }
     }
 
+    /// Size in bytes of the entry for the specified key, if present.
+    pub fn size(&self, key: &ImageKey) -> Result<Option<u64>> {
+        match fs::metadata(self.path_for(key)) {
+            Ok(meta) => Ok(Some(meta.len())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Read `range` of the cached bytes without loading the rest of the entry. The range is
+    /// clamped to the entry size.
+    pub fn read_range(&self, key: &ImageKey, range: Range<u64>) -> Result<Option<Vec<u8>>> {
+        let path = self.path_for(key);
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let len = file.metadata()?.len();
+        let (start, end) = (range.start.min(len), range.end.min(len));
+        let mut bytes = Vec::with_capacity(end.saturating_sub(start) as usize);
+        file.seek(SeekFrom::Start(start))?;
+        file.take(end.saturating_sub(start))
+            .read_to_end(&mut bytes)
+            .with_context(|| format!("reading {}", path.display()))?;
+        Ok(Some(bytes))
+    }
+
     /// Persist bytes to disk for the specified key, returning the final path.
     pub fn write(&self, key: &ImageKey, bytes: &[u8]) -> Result<PathBuf> {
         let path = self.path_for(key);
+        let _span = tracing::debug_span!("cache_write", bytes = bytes.len()).entered();
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent).with_context(|| {
                 format!("creating cache shard directory at {}", parent.display())
@@ -69,10 +112,10 @@ impl DiskCache {
             tmp.flush().with_context(|| format!("flushing {}", path.display()))?;
             tmp.persist(&path).map_err(|err| Error::from(err.error))?;
         } else {
-            return Err(anyhow!(
+            return Err(CoreError::Cache(anyhow!(
                 "derived cache path {} does not have a parent directory",
                 path.display()
-            ));
+            )));
         }
 
         Ok(path)
@@ -87,18 +130,68 @@ impl DiskCache {
             Err(err) => Err(err.into()),
         }
     }
+
+    /// Remove the least recently written entries until the cache fits its budget, returning
+    /// how many were removed.
+    ///
+    /// This scans the whole cache directory, so it suits startup and batch jobs; callers that
+    /// track their entries can evict more cheaply.
+    pub fn trim_to_budget(&self) -> Result<usize> {
+        let mut entries = Vec::new();
+        for shard_one in fs::read_dir(&self.root)? {
+            let shard_one = shard_one?.path();
+            if !shard_one.is_dir() {
+                continue;
+            }
+            for shard_two in fs::read_dir(&shard_one)? {
+                let shard_two = shard_two?.path();
+                if !shard_two.is_dir() {
+                    continue;
+                }
+                for entry in fs::read_dir(&shard_two)? {
+                    let entry = entry?;
+                    let path = entry.path();
+                    if path.extension().is_some_and(|ext| ext == "bin") {
+                        let meta = entry.metadata()?;
+                        entries.push((meta.modified().ok(), meta.len(), path));
+                    }
+                }
+            }
+        }
+
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort_unstable();
+        let mut removed = 0;
+        for (_, len, path) in entries {
+            if total <= self.budget {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+            total -= len;
+            removed += 1;
+        }
+        Ok(removed)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::ImageKey;
+    use crate::types::{PageId, SourceId};
+
+    fn page_key(index: u32) -> ImageKey {
+        ImageKey::original(&PageId { source_id: SourceId::new("src-test"), index })
+    }
 
     #[test]
     fn write_then_read_round_trip() -> Result<()> {
         let temp = tempfile::tempdir()?;
         let cache = DiskCache::new(temp.path())?;
-        let key = ImageKey::new("example::key");
+        let key = page_key(0);
         let bytes = vec![0xAA, 0xBB, 0xCC, 0xDD];
 
         cache.write(&key, &bytes)?;
@@ -111,17 +204,31 @@ mod tests {
     fn missing_entry_returns_none() -> Result<()> {
         let temp = tempfile::tempdir()?;
         let cache = DiskCache::new(temp.path())?;
-        let key = ImageKey::new("does::not::exist");
+        let key = page_key(1);
 
         assert!(cache.read(&key)?.is_none());
         Ok(())
     }
 
+    #[test]
+    fn reads_ranges_of_an_entry() -> Result<()> {
+        let temp = tempfile::tempdir()?;
+        let cache = DiskCache::new(temp.path())?;
+        let key = page_key(2);
+        cache.write(&key, &[0, 1, 2, 3, 4, 5])?;
+
+        assert_eq!(cache.size(&key)?, Some(6));
+        assert_eq!(cache.read_range(&key, 2..4)?, Some(vec![2, 3]));
+        assert_eq!(cache.read_range(&key, 4..100)?, Some(vec![4, 5]));
+        assert_eq!(cache.read_range(&page_key(3), 0..1)?, None);
+        Ok(())
+    }
+
     #[test]
     fn removal_is_idempotent() -> Result<()> {
         let temp = tempfile::tempdir()?;
         let cache = DiskCache::new(temp.path())?;
-        let key = ImageKey::new("to::remove");
+        let key = page_key(4);
         cache.write(&key, &[1, 2, 3, 4])?;
         cache.remove(&key)?;
         cache.remove(&key)?; // second deletion should be a no-op
@@ -129,11 +236,31 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn trims_the_oldest_entries_to_the_budget() -> Result<()> {
+        let temp = tempfile::tempdir()?;
+        let budget = CacheBudget { disk_bytes_max: 10, ..CacheBudget::default() };
+        let cache = DiskCache::new(temp.path())?.with_budget(budget);
+        let (old, new) = (page_key(5), page_key(6));
+        let old_path = cache.write(&old, &[0; 8])?;
+        cache.write(&new, &[0; 8])?;
+        File::options()
+            .write(true)
+            .open(&old_path)?
+            .set_modified(std::time::SystemTime::UNIX_EPOCH)?;
+
+        assert_eq!(cache.trim_to_budget()?, 1);
+        assert!(cache.read(&old)?.is_none());
+        assert!(cache.read(&new)?.is_some());
+        assert_eq!(cache.trim_to_budget()?, 0);
+        Ok(())
+    }
+
     #[test]
     fn writes_use_sharded_directories() -> Result<()> {
         let temp = tempfile::tempdir()?;
         let cache = DiskCache::new(temp.path())?;
-        let key = ImageKey::new("shard::me");
+        let key = page_key(7);
 
         let path = cache.write(&key, &[9, 9, 9, 9])?;
         assert!(path.exists());
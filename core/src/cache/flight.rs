@@ -0,0 +1,132 @@
+//! Per-key deduplication of concurrent cache fills.
+//!
+//! When several callers miss the cache for the same key at once, only the first produces the
+//! entry; the others wait for it to finish and then look the entry up again instead of decoding
+//! the same page twice.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use parking_lot::{Condvar, Mutex};
+
+/// In-flight work keyed by cache key.
+#[derive(Debug, Default)]
+pub struct SingleFlight {
+    flights: Mutex<HashMap<String, Arc<Flight>>>,
+}
+
+#[derive(Debug, Default)]
+struct Flight {
+    landed: Mutex<bool>,
+    wake: Condvar,
+}
+
+/// How a call to [`SingleFlight::run`] went.
+#[derive(Debug)]
+pub enum Outcome<T, F> {
+    /// No other caller was working on the key, so `work` ran here.
+    Ran(T),
+    /// Another caller was working on the key; it has finished, successfully or not, and `work`
+    /// is handed back unrun.
+    Joined(F),
+}
+
+impl SingleFlight {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `work` for `key` unless another caller already is, in which case wait for that
+    /// caller to finish instead.
+    pub fn run<T, F: FnOnce() -> T>(&self, key: &str, work: F) -> Outcome<T, F> {
+        let (flight, leader) = {
+            let mut flights = self.flights.lock();
+            match flights.get(key) {
+                Some(flight) => (Arc::clone(flight), false),
+                None => {
+                    let flight = Arc::new(Flight::default());
+                    flights.insert(key.to_string(), Arc::clone(&flight));
+                    (flight, true)
+                }
+            }
+        };
+
+        if !leader {
+            let mut landed = flight.landed.lock();
+            while !*landed {
+                flight.wake.wait(&mut landed);
+            }
+            return Outcome::Joined(work);
+        }
+
+        let _landing = Landing { flights: self, key, flight: &flight };
+        Outcome::Ran(work())
+    }
+
+    /// Number of keys being worked on.
+    pub fn in_flight(&self) -> usize {
+        self.flights.lock().len()
+    }
+}
+
+/// Releases the waiters of a flight when its work returns or panics.
+struct Landing<'a> {
+    flights: &'a SingleFlight,
+    key: &'a str,
+    flight: &'a Flight,
+}
+
+impl Drop for Landing<'_> {
+    fn drop(&mut self) {
+        self.flights.flights.lock().remove(self.key);
+        *self.flight.landed.lock() = true;
+        self.flight.wake.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn concurrent_callers_share_one_run() {
+        let flights = Arc::new(SingleFlight::new());
+        let runs = Arc::new(AtomicUsize::new(0));
+        let (started, wait_started) = mpsc::channel();
+        let (release, gate) = mpsc::channel::<()>();
+
+        let leader = {
+            let (flights, runs) = (Arc::clone(&flights), Arc::clone(&runs));
+            std::thread::spawn(move || {
+                let outcome = flights.run("page-1", || {
+                    started.send(()).unwrap();
+                    gate.recv_timeout(Duration::from_secs(5)).unwrap();
+                    runs.fetch_add(1, Ordering::SeqCst)
+                });
+                matches!(outcome, Outcome::Ran(0))
+            })
+        };
+        wait_started.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(flights.in_flight(), 1);
+
+        let follower = {
+            let (flights, runs) = (Arc::clone(&flights), Arc::clone(&runs));
+            std::thread::spawn(move || {
+                let outcome = flights.run("page-1", || runs.fetch_add(1, Ordering::SeqCst));
+                matches!(outcome, Outcome::Joined(_))
+            })
+        };
+        // Let the follower reach the wait before the leader lands.
+        std::thread::sleep(Duration::from_millis(50));
+        release.send(()).unwrap();
+
+        assert!(leader.join().unwrap());
+        assert!(follower.join().unwrap());
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(flights.in_flight(), 0);
+        assert!(matches!(flights.run("page-1", || ()), Outcome::Ran(())));
+    }
+}
@@ -0,0 +1,272 @@
+//! Structured cache keys and their canonical string form.
+//!
+//! Every cached image belongs to a page of a source and is one [`ImageVariant`] of it,
+//! optionally rendered for a set of [`RenderParams`]. Keys serialize to strings such as
+//! `src-1a2b-page-3`, `src-1a2b-thumb-3-320`, `src-1a2b-cover-0-256` or
+//! `src-1a2b-page-3-p00c0ffee00c0ffee`, which are what the disk cache hashes and what the asset
+//! protocol puts in URLs, and parse back into the same key.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+
+use crate::error::CoreError;
+use crate::pipeline::render::quarter_turns;
+use crate::types::{PageId, RenderParams, SourceId};
+
+/// Namespaces of the variants, as in `<source>-<namespace>-<page>`.
+const NAMESPACES: [&str; 6] = ["page", "mip", "thumb", "tile", "spread", "cover"];
+
+/// Which image of a page a key refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageVariant {
+    /// The page itself; with a params hash, the page rendered at display size.
+    Original,
+    /// Mip level `n`, each level halving the one before.
+    Mip(u32),
+    /// Thumbnail with the given longest side in pixels.
+    Thumb(u32),
+    /// Vertical tile `n` of a tall page.
+    Tile(u32),
+    /// Two-page spread of the page and the given second page.
+    Spread(u32),
+    /// Library cover of the source, with the given longest side in pixels.
+    Cover(u32),
+}
+
+impl ImageVariant {
+    /// Namespace the variant is stored under, as in `<source>-<namespace>-<page>`.
+    pub fn namespace(self) -> &'static str {
+        match self {
+            Self::Original => "page",
+            Self::Mip(_) => "mip",
+            Self::Thumb(_) => "thumb",
+            Self::Tile(_) => "tile",
+            Self::Spread(_) => "spread",
+            Self::Cover(_) => "cover",
+        }
+    }
+
+    fn argument(self) -> Option<u32> {
+        match self {
+            Self::Original => None,
+            Self::Mip(n) | Self::Thumb(n) | Self::Tile(n) | Self::Spread(n) | Self::Cover(n) => {
+                Some(n)
+            }
+        }
+    }
+
+    fn from_parts(namespace: &str, argument: Option<u32>) -> Option<Self> {
+        Some(match (namespace, argument) {
+            ("page", None) => Self::Original,
+            ("mip", Some(n)) => Self::Mip(n),
+            ("thumb", Some(n)) => Self::Thumb(n),
+            ("tile", Some(n)) => Self::Tile(n),
+            ("spread", Some(n)) => Self::Spread(n),
+            ("cover", Some(n)) => Self::Cover(n),
+            _ => return None,
+        })
+    }
+}
+
+/// Key of a cached image.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct ImageKey {
+    pub source: SourceId,
+    pub page: u32,
+    pub variant: ImageVariant,
+    /// [`params_hash`] of the parameters the image was rendered with, if any.
+    pub params: Option<u64>,
+}
+
+impl ImageKey {
+    pub fn new(source: SourceId, page: u32, variant: ImageVariant) -> Self {
+        Self { source, page, variant, params: None }
+    }
+
+    /// Key of `page` as stored in its source.
+    pub fn original(page: &PageId) -> Self {
+        Self::new(page.source_id.clone(), page.index, ImageVariant::Original)
+    }
+
+    /// Key of the thumbnail of `page` whose longest side is `longest`.
+    pub fn thumb(page: &PageId, longest: u32) -> Self {
+        Self::new(page.source_id.clone(), page.index, ImageVariant::Thumb(longest))
+    }
+
+    /// Key of the library cover of `source` whose longest side is `longest`. Covers are made
+    /// from the first page, so they are stored under page 0.
+    pub fn cover(source: &SourceId, longest: u32) -> Self {
+        Self::new(source.clone(), 0, ImageVariant::Cover(longest))
+    }
+
+    /// The same image rendered with parameters hashing to `params`.
+    pub fn with_params(self, params: u64) -> Self {
+        Self { params: Some(params), ..self }
+    }
+
+    /// Another variant of the same page and parameters.
+    pub fn with_variant(&self, variant: ImageVariant) -> Self {
+        Self { variant, ..self.clone() }
+    }
+
+    pub fn page_id(&self) -> PageId {
+        PageId { source_id: self.source.clone(), index: self.page }
+    }
+
+    pub fn namespace(&self) -> &'static str {
+        self.variant.namespace()
+    }
+}
+
+impl fmt::Display for ImageKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}-{}", self.source.as_str(), self.namespace(), self.page)?;
+        if let Some(argument) = self.variant.argument() {
+            write!(f, "-{argument}")?;
+        }
+        if let Some(params) = self.params {
+            write!(f, "-p{params:016x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ImageKey {
+    type Err = CoreError;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        let invalid = || CoreError::Cache(anyhow!("malformed image key {key:?}"));
+        // Source ids contain dashes themselves, so split at the last namespace in the key.
+        let (split, namespace) = NAMESPACES
+            .iter()
+            .filter_map(|namespace| key.rfind(&format!("-{namespace}-")).map(|at| (at, *namespace)))
+            .max_by_key(|(at, _)| *at)
+            .ok_or_else(invalid)?;
+        let source = &key[..split];
+        let mut rest = key[split + namespace.len() + 2..].split('-');
+        let page = rest.next().and_then(|page| page.parse().ok()).ok_or_else(invalid)?;
+        let (mut argument, mut params) = (None, None);
+        for part in rest {
+            if let Some(hash) = part.strip_prefix('p') {
+                let hash = u64::from_str_radix(hash, 16).map_err(|_| invalid())?;
+                if params.replace(hash).is_some() {
+                    return Err(invalid());
+                }
+            } else if params.is_none() && argument.is_none() {
+                argument = Some(part.parse().map_err(|_| invalid())?);
+            } else {
+                return Err(invalid());
+            }
+        }
+        let variant = ImageVariant::from_parts(namespace, argument).ok_or_else(invalid)?;
+        if source.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self { source: SourceId::new(source), page, variant, params })
+    }
+}
+
+impl From<ImageKey> for String {
+    fn from(key: ImageKey) -> Self {
+        key.to_string()
+    }
+}
+
+impl TryFrom<String> for ImageKey {
+    type Error = CoreError;
+
+    fn try_from(key: String) -> Result<Self, Self::Error> {
+        key.parse()
+    }
+}
+
+/// Stable hash of everything in `params` that changes rendered pixels, followed by `extra` for
+/// renders that also depend on settings outside the params (such as a spread's gutter).
+pub fn params_hash(params: &RenderParams, extra: &[u8]) -> u64 {
+    let mut hasher = blake3::Hasher::new();
+    let canonical = format!(
+        "{:?}-{}x{}-{}-r{}-{}dpi-{:?}-{:?}-{:?}",
+        params.fit,
+        params.viewport_w,
+        params.viewport_h,
+        params.scale,
+        u16::from(quarter_turns(params.rotation)) * 90,
+        params.dpi,
+        params.filter,
+        params.direction,
+        params.layout
+    );
+    hasher.update(canonical.as_bytes());
+    hasher.update(extra);
+    let hash = hasher.finalize();
+    u64::from_le_bytes(hash.as_bytes()[..8].try_into().expect("blake3 hashes are 32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(index: u32) -> PageId {
+        PageId { source_id: SourceId::new("src-1a2b"), index }
+    }
+
+    #[test]
+    fn round_trips_through_the_canonical_form() {
+        let params = params_hash(&RenderParams::default(), &[]);
+        let keys = [
+            (ImageKey::original(&page(3)), "src-1a2b-page-3".to_string()),
+            (ImageKey::thumb(&page(3), 320), "src-1a2b-thumb-3-320".to_string()),
+            (ImageKey::cover(&SourceId::new("lib-1a2b"), 256), "lib-1a2b-cover-0-256".to_string()),
+            (
+                ImageKey::original(&page(0)).with_variant(ImageVariant::Mip(2)),
+                "src-1a2b-mip-0-2".to_string(),
+            ),
+            (
+                ImageKey::original(&page(4)).with_params(params),
+                format!("src-1a2b-page-4-p{params:016x}"),
+            ),
+            (
+                ImageKey::new(SourceId::new("src-1a2b"), 4, ImageVariant::Spread(5))
+                    .with_params(params),
+                format!("src-1a2b-spread-4-5-p{params:016x}"),
+            ),
+        ];
+        for (key, canonical) in keys {
+            assert_eq!(key.to_string(), canonical);
+            assert_eq!(canonical.parse::<ImageKey>().unwrap(), key);
+        }
+
+        let tile: ImageKey = "src-demo-tile-7-1".parse().unwrap();
+        assert_eq!(tile.page_id(), PageId { source_id: SourceId::new("src-demo"), index: 7 });
+        assert_eq!(tile.variant, ImageVariant::Tile(1));
+    }
+
+    #[test]
+    fn rejects_malformed_keys() {
+        for key in [
+            "src-1",
+            "page-3",
+            "src-1-page-x",
+            "src-1-page-3-4",
+            "src-1-thumb-3",
+            "src-1-cover-0",
+            "src-1-page-3-pzz",
+            "src-1-page-3-p1-p2",
+        ] {
+            assert!(matches!(key.parse::<ImageKey>(), Err(CoreError::Cache(_))), "{key}");
+        }
+    }
+
+    #[test]
+    fn params_hash_ignores_equivalent_rotations() {
+        let base = RenderParams::default();
+        let turned = RenderParams { rotation: -270, ..base };
+        let quarter = RenderParams { rotation: 90, ..base };
+        assert_eq!(params_hash(&turned, &[]), params_hash(&quarter, &[]));
+        assert_ne!(params_hash(&base, &[]), params_hash(&quarter, &[]));
+        assert_ne!(params_hash(&base, &[]), params_hash(&base, &[1]));
+    }
+}
@@ -3,6 +3,7 @@
 use anyhow::anyhow;
 use hashlink::LruCache;
 
+use crate::error::CoreError;
 use crate::types::{CacheBudget, ImageKey, PageId};
 
 use super::Result;
@@ -56,7 +57,7 @@ impl MemoryCache {
     /// Insert or replace an entry. Entries larger than the cache budget are ignored.
     pub fn insert(&mut self, key: ImageKey, entry: CacheEntry) -> Result<()> {
         let cost = entry.cost();
-        if cost > self.budget.bytes_max {
+        if cost > self.budget.memory_bytes_max {
             // A single oversized entry should not wipe the cache; skip storing it.
             return Ok(());
         }
@@ -84,12 +85,11 @@ impl MemoryCache {
     pub fn retain(&mut self, key: &ImageKey, page: &PageId) -> Result<bool> {
         if let Some(entry) = self.entries.get(key) {
             if &entry.page != page {
-                return Err(anyhow!(
-                    "cache key {:?} mapped to page {:?} but was retained for {:?}",
-                    key.cache_key,
+                return Err(CoreError::Cache(anyhow!(
+                    "cache key {key} mapped to page {:?} but was retained for {:?}",
                     entry.page,
                     page
-                ));
+                )));
             }
             Ok(true)
         } else {
@@ -98,7 +98,7 @@ impl MemoryCache {
     }
 
     fn evict_if_needed(&mut self) {
-        while self.bytes_used > self.budget.bytes_max {
+        while self.bytes_used > self.budget.memory_bytes_max {
             if let Some((_key, oldest)) = self.entries.remove_lru() {
                 self.bytes_used = self.bytes_used.saturating_sub(oldest.cost());
             } else {
@@ -1,8 +1,11 @@
 //! In-memory and disk cache coordination.
 
 pub mod disk;
+pub mod flight;
+pub mod key;
 pub mod memory;
 
+pub use flight::SingleFlight;
 pub use memory::{CacheEntry, MemoryCache};
 
 pub type Result<T> = crate::Result<T>;
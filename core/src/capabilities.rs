@@ -0,0 +1,103 @@
+//! What this build can open and the limits it runs with, so callers can hide unsupported
+//! options instead of failing when a file is opened.
+
+use serde::Serialize;
+
+use crate::fs::{IMAGE_EXTENSIONS, archive};
+use crate::pipeline::pool::WorkerPool;
+use crate::store::settings::{CACHE_BUDGET_MB, MAX_DECODE_THREADS, MAX_PREFETCH_PAGES};
+
+/// Optional features compiled into this build.
+///
+/// Heavy formats sit behind the `avif`, `jxl`, `rar` and `sevenz` cargo features of
+/// `reader-core`, and `full` turns them all on. The `pdf`, `gpu` and `sqlite` features are
+/// reserved for backends still to come and only set their flags here.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    /// Page extensions that decode, lowercase without the dot.
+    pub image_formats: Vec<&'static str>,
+    /// Archive extensions that open as a source.
+    pub archive_formats: Vec<&'static str>,
+    /// AVIF pages decode.
+    pub avif: bool,
+    /// JPEG XL pages decode.
+    pub jxl: bool,
+    /// RAR and CBR archives open.
+    pub rar: bool,
+    /// 7z and CB7 archives open.
+    pub sevenz: bool,
+    /// PDF documents open.
+    pub pdf: bool,
+    /// Pages are scaled on the GPU instead of the CPU resizer.
+    pub gpu: bool,
+    /// Stores can be kept in SQLite.
+    pub sqlite: bool,
+    pub limits: Limits,
+}
+
+/// Bounds on the values accepted by the pipeline settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Limits {
+    /// Worker threads used when the settings leave the count at automatic.
+    pub default_decode_threads: usize,
+    pub max_decode_threads: usize,
+    pub min_cache_budget_mb: u32,
+    pub max_cache_budget_mb: u32,
+    pub max_prefetch_pages: u32,
+}
+
+impl Capabilities {
+    /// Capabilities of the running build.
+    pub fn detect() -> Self {
+        Self {
+            image_formats: IMAGE_EXTENSIONS.iter().copied().filter(|ext| decodes(ext)).collect(),
+            archive_formats: archive::SUPPORTED_EXTENSIONS.to_vec(),
+            avif: decodes("avif"),
+            jxl: decodes("jxl"),
+            rar: archive::SUPPORTED_EXTENSIONS.contains(&"rar"),
+            sevenz: archive::SUPPORTED_EXTENSIONS.contains(&"7z"),
+            pdf: cfg!(feature = "pdf"),
+            gpu: cfg!(feature = "gpu"),
+            sqlite: cfg!(feature = "sqlite"),
+            limits: Limits {
+                default_decode_threads: WorkerPool::default_threads(),
+                max_decode_threads: MAX_DECODE_THREADS,
+                min_cache_budget_mb: *CACHE_BUDGET_MB.start(),
+                max_cache_budget_mb: *CACHE_BUDGET_MB.end(),
+                max_prefetch_pages: MAX_PREFETCH_PAGES,
+            },
+        }
+    }
+}
+
+/// Whether the image decoders compiled in can read files with extension `ext`.
+fn decodes(ext: &str) -> bool {
+    if ext.eq_ignore_ascii_case("jxl") {
+        return cfg!(feature = "jxl");
+    }
+    image::ImageFormat::from_extension(ext).is_some_and(|format| format.reading_enabled())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_compiled_decoders() {
+        let capabilities = Capabilities::detect();
+        assert!(capabilities.image_formats.contains(&"png"));
+        assert!(capabilities.image_formats.contains(&"jpeg"));
+        assert_eq!(capabilities.image_formats.contains(&"avif"), cfg!(feature = "avif"));
+        assert_eq!(capabilities.avif, cfg!(feature = "avif"));
+        assert_eq!(capabilities.jxl, cfg!(feature = "jxl"));
+        assert_eq!(capabilities.rar, cfg!(feature = "rar"));
+        assert_eq!(capabilities.sevenz, cfg!(feature = "sevenz"));
+        assert_eq!(capabilities.pdf, cfg!(feature = "pdf"));
+        assert_eq!(capabilities.gpu, cfg!(feature = "gpu"));
+        assert_eq!(capabilities.sqlite, cfg!(feature = "sqlite"));
+        assert!(capabilities.archive_formats.starts_with(&["cbz", "zip"]));
+        assert!(capabilities.limits.default_decode_threads >= 1);
+    }
+}
@@ -0,0 +1,127 @@
+//! Encoding decoded frames back into compact image files for the cache.
+
+use std::io::Cursor;
+
+use anyhow::Context;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{ExtendedColorType, ImageEncoder};
+
+use crate::error::ensure;
+
+use super::{DecodedImage, Result};
+
+/// Output format of [`encode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeFormat {
+    /// Lossless, keeps alpha.
+    Png,
+    /// Lossy at `quality` (1–100); alpha is dropped.
+    Jpeg { quality: u8 },
+    /// Lossless, keeps alpha; smaller than PNG for most artwork.
+    WebP,
+}
+
+impl EncodeFormat {
+    /// JPEG for opaque images, PNG when any pixel is translucent.
+    pub fn for_image(image: &DecodedImage) -> Self {
+        let opaque = image.pixels().chunks_exact(4).all(|pixel| pixel[3] == u8::MAX);
+        if opaque { Self::Jpeg { quality: 90 } } else { Self::Png }
+    }
+
+    /// Lower-quality JPEG for opaque thumbnails, WebP when any pixel is translucent.
+    pub fn for_thumbnail(image: &DecodedImage) -> Self {
+        match Self::for_image(image) {
+            Self::Jpeg { .. } => Self::Jpeg { quality: 80 },
+            _ => Self::WebP,
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg { .. } => "image/jpeg",
+            Self::WebP => "image/webp",
+        }
+    }
+    /// File extension, without the dot, for files written in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg { .. } => "jpg",
+            Self::WebP => "webp",
+        }
+    }
+}
+
+/// Encoded image bytes with their content type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedImage {
+    pub bytes: Vec<u8>,
+    pub mime: &'static str,
+}
+
+/// Encode an RGBA frame as `format`.
+pub fn encode(image: &DecodedImage, format: EncodeFormat) -> Result<EncodedImage> {
+    let (width, height) = (image.width(), image.height());
+    let _span = tracing::debug_span!("encode", ?format, width, height).entered();
+    ensure!(
+        Decode,
+        image.pixels().len() == width as usize * height as usize * 4,
+        "pixel buffer does not match {width}x{height}"
+    );
+
+    let mut bytes = Cursor::new(Vec::new());
+    match format {
+        EncodeFormat::Png => PngEncoder::new(&mut bytes)
+            .write_image(image.pixels(), width, height, ExtendedColorType::Rgba8)
+            .context("encoding png")?,
+        EncodeFormat::Jpeg { quality } => {
+            let rgb: Vec<u8> = image
+                .pixels()
+                .chunks_exact(4)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                .collect();
+            JpegEncoder::new_with_quality(&mut bytes, quality.clamp(1, 100))
+                .write_image(&rgb, width, height, ExtendedColorType::Rgb8)
+                .context("encoding jpeg")?
+        }
+        EncodeFormat::WebP => WebPEncoder::new_lossless(&mut bytes)
+            .write_image(image.pixels(), width, height, ExtendedColorType::Rgba8)
+            .context("encoding webp")?,
+    }
+    Ok(EncodedImage { bytes: bytes.into_inner(), mime: format.mime() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ImageDimensions;
+
+    #[test]
+    fn encodes_png_and_jpeg_by_opacity() {
+        let mut image = DecodedImage {
+            dimensions: ImageDimensions { width: 2, height: 2 },
+            pixels: [[200, 10, 10, 255]; 4].concat(),
+        };
+        assert_eq!(EncodeFormat::for_image(&image), EncodeFormat::Jpeg { quality: 90 });
+        let jpeg = encode(&image, EncodeFormat::for_image(&image)).unwrap();
+        assert_eq!(jpeg.mime, "image/jpeg");
+        assert_eq!(&jpeg.bytes[..2], &[0xFF, 0xD8]);
+
+        image.pixels[3] = 0;
+        assert_eq!(EncodeFormat::for_image(&image), EncodeFormat::Png);
+        let png = encode(&image, EncodeFormat::Png).unwrap();
+        assert_eq!(&png.bytes[1..4], b"PNG");
+
+        assert_eq!(EncodeFormat::for_thumbnail(&image), EncodeFormat::WebP);
+        let webp = encode(&image, EncodeFormat::WebP).unwrap();
+        assert_eq!(webp.mime, "image/webp");
+        assert_eq!(EncodeFormat::WebP.extension(), "webp");
+        assert_eq!(&webp.bytes[8..12], b"WEBP");
+
+        image.pixels.pop();
+        assert!(encode(&image, EncodeFormat::Png).is_err());
+    }
+}
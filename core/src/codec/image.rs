@@ -5,10 +5,11 @@ use std::path::Path;
 
 use anyhow::{Context, anyhow};
 use image::metadata::Orientation;
-use image::{DynamicImage, ImageDecoder as _, ImageFormat, ImageReader, RgbaImage};
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, RgbaImage};
 use moxcms::{CmsError, ColorProfile, Layout, TransformOptions};
 use tracing::warn;
 
+use crate::error::CoreError;
 use crate::types::{ImageDimensions, PageMeta};
 
 use super::Result;
@@ -39,12 +40,22 @@ impl DecodedImage {
 
 /// Decode the primary frame of a comic page into an RGBA buffer.
 ///
-/// The decoder supports JPEG, PNG, WebP, and GIF (first frame). The input must be the raw
-/// image bytes sourced from disk or an archive. The returned pixels are straight-alpha RGBA8888
-/// data stored row-major from top-left to bottom-right.
+/// The decoder supports JPEG, PNG, WebP, and GIF (first frame), plus AVIF and JPEG XL with the
+/// `avif` and `jxl` features. The input must be the raw image bytes sourced from disk or an
+/// archive. The returned pixels are straight-alpha RGBA8888 data stored row-major from top-left
+/// to bottom-right.
 pub fn decode_primary(meta: &PageMeta, data: &[u8]) -> Result<DecodedImage> {
+    let _span = tracing::debug_span!("decode", path = ?meta.rel_path, bytes = data.len()).entered();
     if data.is_empty() {
-        return Err(anyhow!("empty image data for {:?}", meta.rel_path));
+        return Err(CoreError::Decode(anyhow!("empty image data for {:?}", meta.rel_path)));
+    }
+
+    #[cfg(feature = "jxl")]
+    if is_jxl(&meta.rel_path) {
+        let decoder = jxl_oxide::integration::JxlDecoder::new(Cursor::new(data))
+            .with_context(|| format!("constructing decoder for image {:?}", meta.rel_path))
+            .map_err(CoreError::Decode)?;
+        return decode_with(decoder, meta);
     }
 
     let reader = if let Some(format) = infer_format(&meta.rel_path) {
@@ -52,13 +63,18 @@ pub fn decode_primary(meta: &PageMeta, data: &[u8]) -> Result<DecodedImage> {
     } else {
         ImageReader::new(Cursor::new(data))
             .with_guessed_format()
-            .context("guessing image format")?
+            .context("guessing image format")
+            .map_err(CoreError::Decode)?
     };
 
-    let mut decoder = reader
+    let decoder = reader
         .into_decoder()
         .with_context(|| format!("constructing decoder for image {:?}", meta.rel_path))?;
+    decode_with(decoder, meta)
+}
 
+/// Decode the frame of `decoder`, upright and in sRGB.
+fn decode_with(mut decoder: impl ImageDecoder, meta: &PageMeta) -> Result<DecodedImage> {
     let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
     let icc_profile = decoder.icc_profile().unwrap_or(None);
 
@@ -85,6 +101,17 @@ pub fn decode_primary(meta: &PageMeta, data: &[u8]) -> Result<DecodedImage> {
     Ok(DecodedImage { dimensions, pixels })
 }
 
+#[cfg(feature = "jxl")]
+fn is_jxl(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("jxl"))
+}
+
+/// Content type of the image file starting with `head`, judged by its signature; the first 16
+/// bytes are enough for every format the reader handles.
+pub fn sniff_mime(head: &[u8]) -> Option<&'static str> {
+    image::guess_format(head).ok().map(|format| format.to_mime_type())
+}
+
 fn infer_format(path: &Path) -> Option<ImageFormat> {
     path.extension()
         .and_then(|ext| ext.to_str())
@@ -103,7 +130,7 @@ fn apply_orientation(image: &mut DynamicImage, orientation: Orientation) {
     }
 }
 
-fn convert_to_srgb_in_place(image: &mut RgbaImage, profile_bytes: &[u8]) -> Result<()> {
+fn convert_to_srgb_in_place(image: &mut RgbaImage, profile_bytes: &[u8]) -> anyhow::Result<()> {
     let src_profile = ColorProfile::new_from_slice(profile_bytes)
         .map_err(|err| anyhow!("invalid ICC profile: {err}"))?;
     let dest_profile = ColorProfile::new_srgb();
@@ -160,6 +187,9 @@ mod tests {
         PageMeta {
             id: PageId { source_id: SourceId::new("test"), index: 0 },
             rel_path: name.into(),
+            mime: String::new(),
+            size_bytes: 0,
+            modified: None,
             width: 0,
             height: 0,
             is_double_spread: false,
@@ -231,6 +261,19 @@ mod tests {
         assert_eq!(decoded.pixels.len(), 16);
     }
 
+    #[test]
+    fn sniffs_the_mime_of_encoded_files() {
+        let image = sample_image();
+        for (format, mime) in [
+            (ImageFormat::Png, "image/png"),
+            (ImageFormat::Jpeg, "image/jpeg"),
+            (ImageFormat::WebP, "image/webp"),
+        ] {
+            assert_eq!(sniff_mime(&encode(&image, format)[..16]), Some(mime));
+        }
+        assert_eq!(sniff_mime(b"not an image"), None);
+    }
+
     #[test]
     fn decodes_gif_first_frame() {
         let image = sample_image();
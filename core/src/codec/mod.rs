@@ -1,7 +1,9 @@
 //! Image decoding primitives and helpers.
 
+pub mod encode;
 pub mod image;
+pub mod sandbox;
 
-pub use image::{DecodedImage, decode_primary};
+pub use image::{DecodedImage, decode_primary, sniff_mime};
 
 pub type Result<T> = crate::Result<T>;
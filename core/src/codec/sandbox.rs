@@ -0,0 +1,441 @@
+//! Decoding in a separate helper process, so a decoder crash or exploit triggered by an
+//! untrusted file cannot take the reader down with it.
+//!
+//! The shell starts helpers by re-running an executable that calls [`helper_main`] first thing
+//! in `main`, passing [`HELPER_ARG`]. A helper drops what privileges it can and then serves
+//! decode requests on its stdin and stdout as length-prefixed frames: a little-endian `u32`
+//! byte count followed by the payload. A request carries the page's file name (for format
+//! detection) and its bytes; a response carries a status byte followed by the width, height
+//! and RGBA pixels, or by an error message.
+//!
+//! [`DecodeSandbox`] keeps a helper per concurrent caller. A helper that dies, breaks the
+//! protocol or does not answer within the sandbox's timeout is killed and discarded, and only
+//! that page fails; the next decode starts a fresh one. [`install`] routes [`decode`] through a
+//! sandbox for the whole process.
+//!
+//! Frames are limited to [`MAX_FRAME_BYTES`], so pages whose decoded pixels take more than that
+//! (about 268 megapixels) fail in the sandbox even though they decode in process. Helpers also
+//! limit their address space on Linux, which bounds the pages they can decode anyway.
+
+use std::ffi::OsString;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, ExitCode, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use parking_lot::{Mutex, RwLock};
+
+use crate::error::CoreError;
+use crate::types::{ImageDimensions, PageId, PageMeta, SourceId};
+
+use super::Result;
+use super::image::{DecodedImage, decode_primary};
+
+/// Argument that turns an executable calling [`helper_main`] into a decode helper.
+pub const HELPER_ARG: &str = "--decode-helper";
+
+/// Largest frame accepted in either direction.
+pub const MAX_FRAME_BYTES: usize = 1 << 30;
+/// How long a helper may take to answer a request unless the sandbox sets another timeout.
+pub const DEFAULT_DECODE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Address space a helper limits itself to on Linux.
+#[cfg(target_os = "linux")]
+const HELPER_MEMORY_BYTES: u64 = 4 << 30;
+
+const STATUS_OK: u8 = 0;
+const STATUS_ERROR: u8 = 1;
+
+static INSTALLED: RwLock<Option<Arc<DecodeSandbox>>> = RwLock::new(None);
+
+/// Route [`decode`] through `sandbox`, or back to in-process decoding with `None`. Helpers of
+/// a replaced sandbox exit once their running decodes finish.
+pub fn install(sandbox: Option<DecodeSandbox>) {
+    *INSTALLED.write() = sandbox.map(Arc::new);
+}
+
+/// Whether [`decode`] runs in a helper process.
+pub fn is_installed() -> bool {
+    INSTALLED.read().is_some()
+}
+
+/// [`decode_primary`] in the installed sandbox, or in this process when none is installed.
+pub fn decode(meta: &PageMeta, data: &[u8]) -> Result<DecodedImage> {
+    let sandbox = INSTALLED.read().clone();
+    match sandbox {
+        Some(sandbox) => sandbox.decode(meta, data),
+        None => decode_primary(meta, data),
+    }
+}
+
+/// Helper processes decoding pages on behalf of this one.
+#[derive(Debug)]
+pub struct DecodeSandbox {
+    program: PathBuf,
+    args: Vec<OsString>,
+    timeout: Duration,
+    idle: Mutex<Vec<Helper>>,
+    spawned: AtomicU64,
+}
+
+impl DecodeSandbox {
+    /// Sandbox whose helpers are started as `program args…`, typically the current executable
+    /// with [`HELPER_ARG`]. Helpers start on first use.
+    pub fn new<I>(program: impl Into<PathBuf>, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString>,
+    {
+        Self {
+            program: program.into(),
+            args: args.into_iter().map(Into::into).collect(),
+            timeout: DEFAULT_DECODE_TIMEOUT,
+            idle: Mutex::new(Vec::new()),
+            spawned: AtomicU64::new(0),
+        }
+    }
+
+    /// Give up on a decode, killing its helper, when the helper has not answered after
+    /// `timeout`. Guards against decoders stuck in a loop on hostile files.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Number of helpers started so far, counting replacements of crashed ones.
+    pub fn spawned(&self) -> u64 {
+        self.spawned.load(Ordering::Relaxed)
+    }
+
+    /// Decode `data`, the bytes of page `meta`, in a helper.
+    pub fn decode(&self, meta: &PageMeta, data: &[u8]) -> Result<DecodedImage> {
+        let _span = tracing::debug_span!("sandboxed_decode", path = ?meta.rel_path).entered();
+        if data.is_empty() {
+            return Err(CoreError::Decode(anyhow!("empty image data for {:?}", meta.rel_path)));
+        }
+        let mut helper = self.checkout()?;
+        let name = meta.rel_path.file_name().map_or_else(|| meta.rel_path.as_path(), Path::new);
+        match helper.decode(name, data, self.timeout) {
+            Ok(result) => {
+                self.idle.lock().push(helper);
+                result.map_err(|message| {
+                    CoreError::Decode(anyhow!("decoding {:?}: {message}", meta.rel_path))
+                })
+            }
+            Err(err) => {
+                let status = helper.stop();
+                tracing::warn!(
+                    target: "codec::sandbox",
+                    path = ?meta.rel_path,
+                    ?status,
+                    "decode helper failed: {err}"
+                );
+                Err(CoreError::Decode(
+                    anyhow::Error::new(err)
+                        .context(format!("decode helper failed on {:?}", meta.rel_path)),
+                ))
+            }
+        }
+    }
+
+    /// An idle helper that is still running, or a new one.
+    fn checkout(&self) -> Result<Helper> {
+        while let Some(mut helper) = self.idle.lock().pop() {
+            if matches!(helper.child.try_wait(), Ok(None)) {
+                return Ok(helper);
+            }
+        }
+        let helper = Helper::spawn(&self.program, &self.args).map_err(|err| {
+            CoreError::Decode(
+                anyhow::Error::new(err)
+                    .context(format!("starting decode helper {}", self.program.display())),
+            )
+        })?;
+        self.spawned.fetch_add(1, Ordering::Relaxed);
+        Ok(helper)
+    }
+}
+
+/// A decode result sent by a helper, or a failure to read one.
+type Response = io::Result<Option<std::result::Result<DecodedImage, String>>>;
+
+/// A running helper process, its stdin, and the responses a thread reads from its stdout.
+#[derive(Debug)]
+struct Helper {
+    child: Child,
+    input: BufWriter<ChildStdin>,
+    responses: Receiver<Response>,
+}
+
+impl Helper {
+    fn spawn(program: &Path, args: &[OsString]) -> io::Result<Self> {
+        let mut child = Command::new(program)
+            .args(args)
+            .env_clear()
+            .current_dir(std::env::temp_dir())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let input = BufWriter::new(child.stdin.take().expect("helper stdin is piped"));
+        let output = BufReader::new(child.stdout.take().expect("helper stdout is piped"));
+        // Reading on another thread lets `decode` stop waiting; killing the helper ends the
+        // thread with the end of its stdout.
+        let (sender, responses) = mpsc::channel();
+        let reader =
+            std::thread::Builder::new().name("decode-helper-output".into()).spawn(move || {
+                let mut output = output;
+                loop {
+                    let response = read_response(&mut output);
+                    let more = matches!(response, Ok(Some(_)));
+                    if sender.send(response).is_err() || !more {
+                        break;
+                    }
+                }
+            });
+        let mut helper = Self { child, input, responses };
+        if let Err(err) = reader {
+            helper.stop();
+            return Err(err);
+        }
+        Ok(helper)
+    }
+
+    /// Send one request and wait up to `timeout` for its response. The outer error means the
+    /// helper is gone, stuck or speaking nonsense; the inner one that it could not decode the
+    /// page.
+    fn decode(
+        &mut self,
+        name: &Path,
+        data: &[u8],
+        timeout: Duration,
+    ) -> io::Result<std::result::Result<DecodedImage, String>> {
+        write_request(&mut self.input, name, data)?;
+        self.input.flush()?;
+        let exited = || io::Error::new(io::ErrorKind::UnexpectedEof, "helper exited");
+        match self.responses.recv_timeout(timeout) {
+            Ok(response) => response?.ok_or_else(exited),
+            Err(RecvTimeoutError::Timeout) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("helper did not answer within {timeout:?}"),
+            )),
+            Err(RecvTimeoutError::Disconnected) => Err(exited()),
+        }
+    }
+
+    /// Kill the helper if it is still running and reap it.
+    fn stop(&mut self) -> Option<std::process::ExitStatus> {
+        let _ = self.child.kill();
+        self.child.wait().ok()
+    }
+}
+
+impl Drop for Helper {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Entry point for helper processes: when the first argument is [`HELPER_ARG`], serve decode
+/// requests on stdin and stdout until stdin closes and return the exit code to end `main`
+/// with. Returns `None` in every other process.
+pub fn helper_main() -> Option<ExitCode> {
+    if std::env::args_os().nth(1).is_none_or(|arg| arg != HELPER_ARG) {
+        return None;
+    }
+    restrict_process();
+    Some(match serve(io::stdin().lock(), io::stdout().lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(_) => ExitCode::FAILURE,
+    })
+}
+
+/// Answer decode requests from `input` on `output` until `input` ends.
+pub fn serve(input: impl Read, output: impl Write) -> io::Result<()> {
+    let (mut input, mut output) = (BufReader::new(input), BufWriter::new(output));
+    while let Some(request) = read_frame(&mut input)? {
+        match handle_request(&request) {
+            Ok(image) => write_frame(
+                &mut output,
+                &[
+                    &[STATUS_OK],
+                    &image.width().to_le_bytes(),
+                    &image.height().to_le_bytes(),
+                    image.pixels(),
+                ],
+            )?,
+            Err(message) => write_frame(&mut output, &[&[STATUS_ERROR], message.as_bytes()])?,
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+fn handle_request(request: &[u8]) -> std::result::Result<DecodedImage, String> {
+    let (name, data) = split_field(request).ok_or("malformed request")?;
+    let name = std::str::from_utf8(name).map_err(|_| "page name is not UTF-8")?;
+    let meta = PageMeta {
+        id: PageId { source_id: SourceId::new("sandbox"), index: 0 },
+        rel_path: PathBuf::from(name),
+        mime: String::new(),
+        size_bytes: data.len() as u64,
+        modified: None,
+        width: 0,
+        height: 0,
+        is_double_spread: false,
+    };
+    decode_primary(&meta, data).map_err(|err| format!("{err:#}"))
+}
+
+fn write_request(writer: &mut impl Write, name: &Path, data: &[u8]) -> io::Result<()> {
+    let name = name.to_string_lossy();
+    let name_len = u32::try_from(name.len()).map_err(|_| invalid("page name too long"))?;
+    write_frame(writer, &[&name_len.to_le_bytes(), name.as_bytes(), data])
+}
+
+fn read_response(
+    reader: &mut impl Read,
+) -> io::Result<Option<std::result::Result<DecodedImage, String>>> {
+    let Some(response) = read_frame(reader)? else { return Ok(None) };
+    let (&status, body) = response.split_first().ok_or_else(|| invalid("empty response"))?;
+    match status {
+        STATUS_OK if body.len() >= 8 => {
+            let width = u32::from_le_bytes(body[..4].try_into().expect("four bytes"));
+            let height = u32::from_le_bytes(body[4..8].try_into().expect("four bytes"));
+            let pixels = body[8..].to_vec();
+            if pixels.len() as u64 != u64::from(width) * u64::from(height) * 4 {
+                return Err(invalid("pixel data does not match the image size"));
+            }
+            Ok(Some(Ok(DecodedImage { dimensions: ImageDimensions { width, height }, pixels })))
+        }
+        STATUS_ERROR => Ok(Some(Err(String::from_utf8_lossy(body).into_owned()))),
+        _ => Err(invalid("malformed response")),
+    }
+}
+
+/// Write `parts` as one frame.
+fn write_frame(writer: &mut impl Write, parts: &[&[u8]]) -> io::Result<()> {
+    let len: usize = parts.iter().map(|part| part.len()).sum();
+    if len > MAX_FRAME_BYTES {
+        return Err(invalid("frame too large"));
+    }
+    writer.write_all(&(len as u32).to_le_bytes())?;
+    for part in parts {
+        writer.write_all(part)?;
+    }
+    Ok(())
+}
+
+/// The next frame, or `None` when the stream ends between frames.
+fn read_frame(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME_BYTES {
+        return Err(invalid("frame too large"));
+    }
+    let mut frame = vec![0; len];
+    reader.read_exact(&mut frame)?;
+    Ok(Some(frame))
+}
+
+/// Split a `u32`-length-prefixed field off the front of `bytes`.
+fn split_field(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (len, rest) = bytes.split_first_chunk::<4>()?;
+    let len = u32::from_le_bytes(*len) as usize;
+    (len <= rest.len()).then(|| rest.split_at(len))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Give up what the helper does not need: core dumps, new files, privilege escalation and,
+/// on Linux, an unbounded address space. Best effort; Windows helpers run unrestricted.
+fn restrict_process() {
+    #[cfg(unix)]
+    {
+        let limit = |resource, value: u64| {
+            let value = value as libc::rlim_t;
+            let limit = libc::rlimit { rlim_cur: value, rlim_max: value };
+            // SAFETY: `limit` is a valid rlimit for the duration of the call.
+            unsafe { libc::setrlimit(resource, &limit) };
+        };
+        limit(libc::RLIMIT_CORE, 0);
+        // The helper needs only stdin, stdout and stderr.
+        limit(libc::RLIMIT_NOFILE, 3);
+        #[cfg(target_os = "linux")]
+        {
+            limit(libc::RLIMIT_AS, HELPER_MEMORY_BYTES);
+            // SAFETY: PR_SET_NO_NEW_PRIVS takes no pointers.
+            unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, Rgba, RgbaImage};
+    use std::io::Cursor;
+
+    fn round_trip(name: &str, data: &[u8]) -> std::result::Result<DecodedImage, String> {
+        let mut request = Vec::new();
+        write_request(&mut request, Path::new(name), data).unwrap();
+        let mut response = Vec::new();
+        serve(Cursor::new(request), &mut response).unwrap();
+        let mut response = Cursor::new(response);
+        let result = read_response(&mut response).unwrap().unwrap();
+        assert!(read_response(&mut response).unwrap().is_none());
+        result
+    }
+
+    #[test]
+    fn decodes_over_the_frame_protocol() {
+        let mut png = Vec::new();
+        RgbaImage::from_pixel(3, 2, Rgba([1, 2, 3, 255]))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let image = round_trip("001.png", &png).unwrap();
+        assert_eq!((image.width(), image.height()), (3, 2));
+        assert_eq!(&image.pixels()[..4], &[1, 2, 3, 255]);
+
+        assert!(round_trip("002.png", b"not a png").is_err());
+        assert!(read_response(&mut Cursor::new([1, 0, 0, 0, 9])).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn crashed_helpers_fail_the_page_and_are_replaced() {
+        let sandbox = DecodeSandbox::new("/bin/sh", ["-c", "exit 3"]);
+        let meta = PageMeta {
+            id: PageId { source_id: SourceId::new("demo"), index: 0 },
+            rel_path: PathBuf::from("001.png"),
+            mime: "image/png".to_string(),
+            size_bytes: 4,
+            modified: None,
+            width: 0,
+            height: 0,
+            is_double_spread: false,
+        };
+        for spawned in 1..=2 {
+            assert!(matches!(sandbox.decode(&meta, b"page"), Err(CoreError::Decode(_))));
+            assert_eq!(sandbox.spawned(), spawned);
+        }
+
+        let stuck = DecodeSandbox::new("/bin/sh", ["-c", "while :; do :; done"])
+            .with_timeout(Duration::from_millis(200));
+        let started = std::time::Instant::now();
+        assert!(matches!(stuck.decode(&meta, b"page"), Err(CoreError::Decode(_))));
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(stuck.decode(&meta, b"page").is_err());
+        assert_eq!(stuck.spawned(), 2);
+    }
+}
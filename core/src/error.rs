@@ -0,0 +1,299 @@
+//! Errors returned by the core crate, and their classification for callers that need to react
+//! to the cause.
+
+use std::io;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::fs::registry::UnsupportedSource;
+use crate::keymap::KeymapError;
+use crate::pipeline::params::InvalidRenderParams;
+use crate::store::crypto::ProfileLocked;
+use crate::store::migrate::MigrationError;
+use crate::store::settings::InvalidSettings;
+
+/// Failure of a core operation, tagged with what went wrong.
+///
+/// Every variant keeps the full [`anyhow::Error`] chain, so the context added on the way up
+/// and the typed cause [`ErrorKind`] looks for are both preserved. Internal code may keep using
+/// `anyhow`: converting an [`anyhow::Error`] attributes it by the typed cause in its chain, and
+/// file system failures are [`CoreError::Io`] whichever subsystem ran into them.
+#[derive(Debug, Error)]
+pub enum CoreError {
+    /// Reading or writing a file failed.
+    #[error(transparent)]
+    Io(anyhow::Error),
+    /// An image could not be decoded, converted, resized or encoded.
+    #[error(transparent)]
+    Decode(anyhow::Error),
+    /// An archive could not be opened or read.
+    #[error(transparent)]
+    Archive(anyhow::Error),
+    /// The image cache refused an entry or holds an inconsistent one.
+    #[error(transparent)]
+    Cache(anyhow::Error),
+    /// A store file is damaged, locked or newer than supported, or a change to it was refused.
+    #[error(transparent)]
+    Store(anyhow::Error),
+    /// Anything not attributed to a subsystem.
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl CoreError {
+    /// The error chain this error wraps.
+    pub fn inner(&self) -> &anyhow::Error {
+        match self {
+            CoreError::Io(err)
+            | CoreError::Decode(err)
+            | CoreError::Archive(err)
+            | CoreError::Cache(err)
+            | CoreError::Store(err)
+            | CoreError::Other(err) => err,
+        }
+    }
+
+    /// Unwrap into the error chain, dropping the subsystem.
+    pub fn into_inner(self) -> anyhow::Error {
+        match self {
+            CoreError::Io(err)
+            | CoreError::Decode(err)
+            | CoreError::Archive(err)
+            | CoreError::Cache(err)
+            | CoreError::Store(err)
+            | CoreError::Other(err) => err,
+        }
+    }
+
+    /// Broad cause of the failure; see [`ErrorKind::of`].
+    pub fn kind(&self) -> ErrorKind {
+        ErrorKind::of(self.inner())
+    }
+
+    /// Constructor of this error's variant.
+    fn tag(&self) -> fn(anyhow::Error) -> CoreError {
+        match self {
+            CoreError::Io(_) => CoreError::Io,
+            CoreError::Decode(_) => CoreError::Decode,
+            CoreError::Archive(_) => CoreError::Archive,
+            CoreError::Cache(_) => CoreError::Cache,
+            CoreError::Store(_) => CoreError::Store,
+            CoreError::Other(_) => CoreError::Other,
+        }
+    }
+}
+
+impl From<anyhow::Error> for CoreError {
+    fn from(err: anyhow::Error) -> Self {
+        // The outermost attributable cause wins, so context added to an already attributed
+        // error keeps its subsystem.
+        match err.chain().find_map(attribution) {
+            Some(tag) => tag(err),
+            None => CoreError::Other(err),
+        }
+    }
+}
+
+fn attribution(
+    cause: &(dyn std::error::Error + 'static),
+) -> Option<fn(anyhow::Error) -> CoreError> {
+    if let Some(err) = cause.downcast_ref::<CoreError>() {
+        return Some(err.tag());
+    }
+    if cause.is::<image::ImageError>() {
+        return Some(CoreError::Decode);
+    }
+    // Refused params are the caller's mistake, not a page that failed to decode.
+    if cause.is::<InvalidRenderParams>() {
+        return Some(CoreError::Other);
+    }
+    if cause.is::<zip::result::ZipError>() {
+        return Some(CoreError::Archive);
+    }
+    if cause.is::<MigrationError>()
+        || cause.is::<ProfileLocked>()
+        || cause.is::<InvalidSettings>()
+        || cause.is::<KeymapError>()
+        || cause.is::<serde_json::Error>()
+    {
+        return Some(CoreError::Store);
+    }
+    if cause.is::<io::Error>() {
+        return Some(CoreError::Io);
+    }
+    None
+}
+
+/// Return early with `CoreError::$variant` unless `$condition` holds; the counterpart of
+/// [`anyhow::ensure!`] for functions returning [`crate::Result`].
+macro_rules! ensure {
+    ($variant:ident, $condition:expr, $($message:tt)+) => {
+        if !$condition {
+            return Err($crate::error::CoreError::$variant(::anyhow::anyhow!($($message)+)));
+        }
+    };
+}
+
+pub(crate) use ensure;
+
+/// Attribute typed causes to what went wrong when `?` converts them.
+macro_rules! attribute {
+    ($($cause:ty => $variant:ident),* $(,)?) => {$(
+        impl From<$cause> for CoreError {
+            fn from(err: $cause) -> Self {
+                CoreError::$variant(err.into())
+            }
+        }
+    )*};
+}
+
+attribute! {
+    io::Error => Io,
+    image::ImageError => Decode,
+    InvalidRenderParams => Other,
+    zip::result::ZipError => Archive,
+    serde_json::Error => Store,
+    MigrationError => Store,
+    ProfileLocked => Store,
+    InvalidSettings => Store,
+    KeymapError => Store,
+}
+
+/// Broad cause of a failure, derived from the typed errors in an [`anyhow::Error`] chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorKind {
+    /// A file, archive entry or other resource does not exist.
+    NotFound,
+    /// The operating system refused access.
+    PermissionDenied,
+    /// The file is of a format or variant that is not supported.
+    UnsupportedFormat,
+    /// The file is damaged or not what its name claims.
+    Corrupt,
+    /// An encrypted store was accessed while its profile is locked.
+    Locked,
+    /// A value passed in by the caller is out of range.
+    InvalidInput,
+    /// Any other I/O failure.
+    Io,
+    /// Anything else.
+    Internal,
+}
+
+impl ErrorKind {
+    /// Classify `err` by the first typed error in its chain that says something about the cause.
+    pub fn of(err: &anyhow::Error) -> Self {
+        err.chain().find_map(classify).unwrap_or(Self::Internal)
+    }
+}
+
+fn classify(cause: &(dyn std::error::Error + 'static)) -> Option<ErrorKind> {
+    // A wrapped core error forwards to the cause of its chain, skipping the chain's head.
+    if let Some(err) = cause.downcast_ref::<CoreError>() {
+        return Some(err.kind());
+    }
+    if let Some(err) = cause.downcast_ref::<io::Error>() {
+        return Some(match err.kind() {
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => ErrorKind::Corrupt,
+            _ => ErrorKind::Io,
+        });
+    }
+    if let Some(err) = cause.downcast_ref::<image::ImageError>() {
+        return match err {
+            image::ImageError::Unsupported(_) => Some(ErrorKind::UnsupportedFormat),
+            image::ImageError::Decoding(_) => Some(ErrorKind::Corrupt),
+            // The underlying io::Error is the next link of the chain.
+            image::ImageError::IoError(_) => None,
+            _ => Some(ErrorKind::Internal),
+        };
+    }
+    if let Some(err) = cause.downcast_ref::<zip::result::ZipError>() {
+        return match err {
+            zip::result::ZipError::InvalidArchive(_) => Some(ErrorKind::Corrupt),
+            zip::result::ZipError::UnsupportedArchive(_) => Some(ErrorKind::UnsupportedFormat),
+            zip::result::ZipError::FileNotFound => Some(ErrorKind::NotFound),
+            zip::result::ZipError::Io(_) => None,
+        };
+    }
+    if cause.is::<UnsupportedSource>() {
+        return Some(ErrorKind::UnsupportedFormat);
+    }
+    if cause.is::<ProfileLocked>() {
+        return Some(ErrorKind::Locked);
+    }
+    if cause.is::<InvalidSettings>()
+        || cause.is::<KeymapError>()
+        || cause.is::<InvalidRenderParams>()
+    {
+        return Some(ErrorKind::InvalidInput);
+    }
+    if let Some(err) = cause.downcast_ref::<MigrationError>() {
+        return match err {
+            MigrationError::TooNew { .. } => Some(ErrorKind::UnsupportedFormat),
+            MigrationError::Parse { .. } => Some(ErrorKind::Corrupt),
+            _ => None,
+        };
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn classifies_by_the_typed_cause() {
+        let missing = io::Error::new(io::ErrorKind::NotFound, "gone");
+        let err = anyhow::Error::new(missing).context("reading page").context("opening source");
+        assert_eq!(ErrorKind::of(&err), ErrorKind::NotFound);
+
+        let locked: anyhow::Error = ProfileLocked { path: "progress.json".into() }.into();
+        assert_eq!(ErrorKind::of(&locked), ErrorKind::Locked);
+
+        let invalid: anyhow::Error =
+            InvalidSettings { field: "decodeThreads", reason: "too many".into() }.into();
+        assert_eq!(ErrorKind::of(&invalid), ErrorKind::InvalidInput);
+
+        let invalid: anyhow::Error = InvalidRenderParams::Rotation(45).into();
+        assert_eq!(ErrorKind::of(&invalid), ErrorKind::InvalidInput);
+
+        let err = Err::<(), _>(zip::result::ZipError::InvalidArchive("bad")).context("listing");
+        assert_eq!(ErrorKind::of(&err.unwrap_err()), ErrorKind::Corrupt);
+
+        assert_eq!(ErrorKind::of(&anyhow::anyhow!("something else")), ErrorKind::Internal);
+    }
+
+    #[test]
+    fn attributes_errors_to_their_subsystem() {
+        let missing = io::Error::new(io::ErrorKind::NotFound, "gone");
+        let err = CoreError::from(anyhow::Error::new(missing).context("reading page"));
+        assert!(matches!(err, CoreError::Io(_)));
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert_eq!(err.to_string(), "reading page");
+
+        let err: CoreError = zip::result::ZipError::InvalidArchive("bad").into();
+        assert!(matches!(err, CoreError::Archive(_)));
+
+        let locked = CoreError::from(ProfileLocked { path: "progress.json".into() });
+        let err = CoreError::from(Err::<(), _>(locked).context("loading progress").unwrap_err());
+        assert!(matches!(err, CoreError::Store(_)));
+        assert_eq!(err.kind(), ErrorKind::Locked);
+        assert_eq!(ErrorKind::of(&err.into_inner()), ErrorKind::Locked);
+
+        assert!(matches!(CoreError::from(anyhow::anyhow!("other")), CoreError::Other(_)));
+        let err = CoreError::from(InvalidRenderParams::Rotation(45));
+        assert!(matches!(err, CoreError::Other(_)));
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn unsupported_images_are_reported_as_such() {
+        let err = image::load_from_memory(b"not an image").unwrap_err();
+        assert_eq!(ErrorKind::of(&err.into()), ErrorKind::UnsupportedFormat);
+    }
+}
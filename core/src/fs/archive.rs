@@ -1,12 +1,15 @@
-//! ZIP/CBZ archive handling.
+//! Archive handling: ZIP/CBZ always, RAR/CBR and 7z/CB7 with the `rar` and `sevenz` features.
 
+use std::collections::HashMap;
 use std::fs::File;
-use std::path::Path;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, anyhow};
 use zip::CompressionMethod;
 use zip::read::ZipArchive;
 
+use crate::error::CoreError;
 use crate::types::{ArchiveEntry, ArchiveKind, PageId, PageMeta, Source, SourceId};
 
 use super::{Result, util};
@@ -16,14 +19,22 @@ pub fn load_archive(path: &Path) -> Result<Source> {
     Ok(Source::Archive { path: path.to_path_buf(), kind: detect_kind(path), entries })
 }
 
+/// Enumerate the image entries of the archive at `path`, sorted using natural ordering.
+///
+/// Entry timestamps in ZIP files carry no time zone, so every page reports the modification
+/// time of the archive itself.
 pub fn list_archive_pages(path: &Path, source_id: &SourceId) -> Result<Vec<PageMeta>> {
     let entries = collect_entries(path)?;
+    let modified = std::fs::metadata(path)?.modified().ok();
     let pages = entries
         .into_iter()
         .enumerate()
         .map(|(index, entry)| PageMeta {
             id: PageId { source_id: source_id.clone(), index: index as u32 },
+            mime: util::mime_for(&entry.path).to_string(),
             rel_path: entry.path,
+            size_bytes: entry.size_bytes,
+            modified,
             width: 0,
             height: 0,
             is_double_spread: false,
@@ -32,39 +43,148 @@ pub fn list_archive_pages(path: &Path, source_id: &SourceId) -> Result<Vec<PageM
     Ok(pages)
 }
 
-fn collect_entries(path: &Path) -> Result<Vec<ArchiveEntry>> {
+/// Read the bytes of the page `entry`, as listed by [`list_archive_pages`], from the archive at
+/// `path`.
+pub fn read_archive_entry(path: &Path, entry: &Path) -> Result<Vec<u8>> {
+    let bytes = match detect_kind(path) {
+        #[cfg(feature = "rar")]
+        ArchiveKind::Rar => super::rar::read_entry(path, entry)?,
+        #[cfg(feature = "sevenz")]
+        ArchiveKind::SevenZip => super::sevenz::read_entry(path, entry)?,
+        _ => read_zip_entry(path, entry)?,
+    };
+    bytes.ok_or_else(|| CoreError::Archive(anyhow!("archive {:?} has no entry {:?}", path, entry)))
+}
+
+/// Walks an archive in order, passing the entries the filter picks to the visitor until it
+/// returns `false`.
+type Walker = fn(
+    &Path,
+    &dyn Fn(&Path) -> bool,
+    &mut dyn FnMut(PathBuf, Vec<u8>) -> Result<bool>,
+) -> Result<()>;
+
+/// How to walk the archive at `path` when its entries can only be read in order, as in RAR
+/// and 7z archives; `None` for ZIP, whose entries are read directly.
+fn sequential_walker(path: &Path) -> Option<Walker> {
+    match detect_kind(path) {
+        #[cfg(feature = "rar")]
+        ArchiveKind::Rar => Some(super::rar::for_each_entry),
+        #[cfg(feature = "sevenz")]
+        ArchiveKind::SevenZip => Some(super::sevenz::for_each_entry),
+        _ => None,
+    }
+}
+
+/// Whether reading one entry of the archive at `path` means walking the entries before it.
+pub(super) fn is_sequential(path: &Path) -> bool {
+    sequential_walker(path).is_some()
+}
+
+/// Write the visible images of a sequential archive at `path` into `dir` in one pass,
+/// returning the file each entry went to; `None` if the archive is not sequential.
+pub(super) fn extract_images(path: &Path, dir: &Path) -> Result<Option<HashMap<PathBuf, PathBuf>>> {
+    let Some(walk) = sequential_walker(path) else {
+        return Ok(None);
+    };
+    let mut extracted = HashMap::new();
+    let wanted = |name: &Path| !util::is_hidden(name) && util::is_supported_image(name);
+    walk(path, &wanted, &mut |name, bytes| {
+        let file = dir.join(extracted.len().to_string());
+        std::fs::write(&file, bytes)?;
+        extracted.insert(name, file);
+        Ok(true)
+    })?;
+    Ok(Some(extracted))
+}
+
+/// A file in an archive as the backend lists it, before names are sanitized and filtered.
+pub(super) struct StoredEntry {
+    pub name: PathBuf,
+    pub size_bytes: u64,
+    pub compressed: bool,
+}
+
+/// The visible image entries of the archive at `path`, in natural order.
+pub(super) fn collect_entries(path: &Path) -> Result<Vec<ArchiveEntry>> {
+    let stored = match detect_kind(path) {
+        #[cfg(feature = "rar")]
+        ArchiveKind::Rar => super::rar::list(path)?,
+        #[cfg(feature = "sevenz")]
+        ArchiveKind::SevenZip => super::sevenz::list(path)?,
+        _ => list_zip(path)?,
+    };
+    let mut entries: Vec<ArchiveEntry> = stored
+        .into_iter()
+        .filter_map(|entry| {
+            let sanitized = util::sanitize_zip_path(&entry.name)?;
+            if util::is_hidden(&sanitized) || !util::is_supported_image(&sanitized) {
+                return None;
+            }
+            Some(ArchiveEntry {
+                path: sanitized,
+                size_bytes: entry.size_bytes,
+                compressed: entry.compressed,
+            })
+        })
+        .collect();
+
+    entries.sort_by(|a, b| util::natural_cmp_path(&a.path, &b.path));
+    Ok(entries)
+}
+
+fn list_zip(path: &Path) -> Result<Vec<StoredEntry>> {
     let file = File::open(path).with_context(|| format!("opening archive {:?}", path))?;
-    let mut archive = ZipArchive::new(file).map_err(|err| anyhow!("{}", err))?;
-    let mut entries: Vec<ArchiveEntry> = Vec::new();
+    let mut archive = ZipArchive::new(file)?;
+    let mut entries = Vec::new();
 
     for idx in 0..archive.len() {
-        let file = archive.by_index(idx).map_err(|err| anyhow!("{}", err))?;
+        let file = archive.by_index(idx)?;
         if file.is_dir() {
             continue;
         }
-
         let Some(enclosed) = file.enclosed_name() else {
             continue;
         };
-        let Some(sanitized) = util::sanitize_zip_path(enclosed) else {
-            continue;
-        };
-        if util::is_hidden(&sanitized) || !util::is_supported_image(&sanitized) {
-            continue;
-        }
-
-        let compression = file.compression();
-        entries.push(ArchiveEntry {
-            path: sanitized,
+        entries.push(StoredEntry {
+            name: enclosed.to_path_buf(),
             size_bytes: file.size(),
-            compressed: compression != CompressionMethod::Stored,
+            compressed: file.compression() != CompressionMethod::Stored,
         });
     }
-
-    entries.sort_by(|a, b| util::natural_cmp_path(&a.path, &b.path));
     Ok(entries)
 }
 
+fn read_zip_entry(path: &Path, entry: &Path) -> Result<Option<Vec<u8>>> {
+    let file = File::open(path).with_context(|| format!("opening archive {:?}", path))?;
+    let mut archive = ZipArchive::new(file)?;
+    for idx in 0..archive.len() {
+        let mut file = archive.by_index(idx)?;
+        let sanitized = file.enclosed_name().and_then(util::sanitize_zip_path);
+        if sanitized.as_deref() == Some(entry) {
+            let mut bytes = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut bytes)
+                .with_context(|| format!("reading {:?} from {:?}", entry, path))?;
+            return Ok(Some(bytes));
+        }
+    }
+    Ok(None)
+}
+
+/// Archive extensions that can be opened as a source.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "cbz",
+    "zip",
+    #[cfg(feature = "rar")]
+    "cbr",
+    #[cfg(feature = "rar")]
+    "rar",
+    #[cfg(feature = "sevenz")]
+    "cb7",
+    #[cfg(feature = "sevenz")]
+    "7z",
+];
+
 fn detect_kind(path: &Path) -> ArchiveKind {
     match path.extension().and_then(|ext| ext.to_str()).map(|s| s.to_ascii_lowercase()) {
         Some(ref ext) if ext == "cbz" || ext == "zip" => ArchiveKind::Zip,
@@ -100,6 +220,8 @@ mod tests {
         let pages = list_archive_pages(&archive_path, &source_id).expect("list pages");
         assert_eq!(pages.len(), 3);
         assert!(pages.iter().all(|page| page.id.source_id == source_id));
+        assert_eq!(pages[1].mime, "image/png");
+        assert!(pages.iter().all(|page| page.size_bytes == 4 && page.modified.is_some()));
     }
 
     #[test]
@@ -119,6 +241,18 @@ mod tests {
         assert_eq!(names, vec!["pages/cover.png"]);
     }
 
+    #[test]
+    fn reads_listed_entries() {
+        let dir = tempdir().unwrap();
+        let archive_path = dir.path().join("demo.cbz");
+        create_zip(&archive_path, &["pages/", "pages/cover.png"]);
+
+        let pages = list_archive_pages(&archive_path, &SourceId::new("zip-1")).unwrap();
+        let bytes = read_archive_entry(&archive_path, &pages[0].rel_path).unwrap();
+        assert_eq!(bytes, b"demo");
+        assert!(read_archive_entry(&archive_path, Path::new("missing.png")).is_err());
+    }
+
     fn normalize_path(input: &str) -> String {
         input.replace('\\', "/")
     }
@@ -1,17 +1,17 @@
 //! Directory-based source handling and page enumeration.
 
 use std::fs;
-use std::path::{Path, PathBuf};
-
-use anyhow::anyhow;
+use std::io;
+use std::path::Path;
 
 use crate::types::{PageId, PageMeta, Source, SourceId};
 
+use super::source::SourceEntry;
 use super::{Result, util};
 
 /// Construct a [`Source::Folder`] description for the provided `root` directory.
 pub fn load_folder(root: &Path) -> Result<Source> {
-    let entries = collect_entries(root)?;
+    let entries = collect_entries(root)?.into_iter().map(|entry| entry.path).collect();
     Ok(Source::Folder { root: root.to_path_buf(), entries })
 }
 
@@ -22,9 +22,12 @@ pub fn list_folder_pages(root: &Path, source_id: &SourceId) -> Result<Vec<PageMe
     let pages = relative_entries
         .into_iter()
         .enumerate()
-        .map(|(index, rel_path)| PageMeta {
+        .map(|(index, entry)| PageMeta {
             id: PageId { source_id: source_id.clone(), index: index as u32 },
-            rel_path,
+            mime: util::mime_for(&entry.path).to_string(),
+            rel_path: entry.path,
+            size_bytes: entry.size_bytes,
+            modified: entry.modified,
             width: 0,
             height: 0,
             is_double_spread: false,
@@ -34,15 +37,18 @@ pub fn list_folder_pages(root: &Path, source_id: &SourceId) -> Result<Vec<PageMe
     Ok(pages)
 }
 
-fn collect_entries(root: &Path) -> Result<Vec<PathBuf>> {
+/// The visible images directly inside `root`, in natural order.
+pub(super) fn collect_entries(root: &Path) -> Result<Vec<SourceEntry>> {
     if !root.exists() {
-        return Err(anyhow!("folder {:?} does not exist", root));
+        let message = format!("folder {root:?} does not exist");
+        return Err(io::Error::new(io::ErrorKind::NotFound, message).into());
     }
     if !root.is_dir() {
-        return Err(anyhow!("folder {:?} is not a directory", root));
+        let message = format!("folder {root:?} is not a directory");
+        return Err(io::Error::new(io::ErrorKind::NotADirectory, message).into());
     }
 
-    let mut entries: Vec<PathBuf> = Vec::new();
+    let mut entries: Vec<SourceEntry> = Vec::new();
     for entry in fs::read_dir(root)? {
         let entry = entry?;
         let file_type = entry.file_type()?;
@@ -55,11 +61,16 @@ fn collect_entries(root: &Path) -> Result<Vec<PathBuf>> {
             continue;
         }
 
-        let rel = path.strip_prefix(root).unwrap_or_else(|_| path.as_path()).to_path_buf();
-        entries.push(rel);
+        let metadata = entry.metadata()?;
+        let rel_path = path.strip_prefix(root).unwrap_or_else(|_| path.as_path()).to_path_buf();
+        entries.push(SourceEntry {
+            path: rel_path,
+            size_bytes: metadata.len(),
+            modified: metadata.modified().ok(),
+        });
     }
 
-    entries.sort_by(|a, b| util::natural_cmp_path(a, b));
+    entries.sort_by(|a, b| util::natural_cmp_path(&a.path, &b.path));
     Ok(entries)
 }
 
@@ -87,6 +98,9 @@ mod tests {
             pages.iter().map(|meta| meta.rel_path.to_string_lossy().into_owned()).collect();
         assert_eq!(order, vec!["001.jpeg", "2.png", "10.jpg", "cover.bmp"]);
         assert!(pages.iter().all(|page| page.id.source_id == source_id));
+        assert_eq!(pages[0].mime, "image/jpeg");
+        assert_eq!(pages[3].mime, "image/bmp");
+        assert!(pages.iter().all(|page| page.size_bytes == 4 && page.modified.is_some()));
     }
 
     #[test]
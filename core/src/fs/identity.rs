@@ -0,0 +1,71 @@
+//! Stable source identifiers derived from where a source lives and what it contains.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::types::SourceId;
+
+use super::Result;
+
+/// Bytes read from the start of a file to fingerprint its content.
+const FINGERPRINT_LEN: u64 = 64 * 1024;
+
+/// Derive the id of the folder, archive or image at `path`.
+///
+/// The id hashes the canonicalized path together with a fingerprint of the content, so the
+/// same source gets the same id on every launch and progress, cache entries and annotations
+/// keyed by it survive restarts. Files are fingerprinted by their length and leading bytes, so
+/// replacing an archive with a different one at the same path yields a new id. Folders are
+/// identified by their path alone since pages are expected to be added to them over time.
+pub fn source_id_for(path: &Path) -> Result<SourceId> {
+    let path = path.canonicalize()?;
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(path.to_string_lossy().as_bytes());
+    if path.is_file() {
+        let file = File::open(&path)?;
+        hasher.update(&file.metadata()?.len().to_le_bytes());
+        let mut head = Vec::new();
+        file.take(FINGERPRINT_LEN).read_to_end(&mut head)?;
+        hasher.update(&head);
+    }
+    Ok(SourceId::new(format!("src-{}", &hasher.finalize().to_hex()[..16])))
+}
+
+/// Derive the id of the remote source at `uri`. Remote sources are identified by their URI
+/// alone, like folders, since fingerprinting their content would mean fetching it. Credentials
+/// in the URI are left out, so ids never depend on or reveal them.
+pub fn source_id_for_uri(uri: &str) -> SourceId {
+    let uri = super::registry::without_userinfo(uri);
+    SourceId::new(format!("src-{}", &blake3::hash(uri.as_bytes()).to_hex()[..16]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn ids_follow_path_and_content() {
+        let dir = tempdir().unwrap();
+        let archive = dir.path().join("vol1.cbz");
+        fs::write(&archive, b"first").unwrap();
+
+        let id = source_id_for(&archive).unwrap();
+        assert!(id.as_str().starts_with("src-"));
+        assert_eq!(source_id_for(&dir.path().join(".").join("vol1.cbz")).unwrap(), id);
+
+        fs::write(&archive, b"second").unwrap();
+        assert_ne!(source_id_for(&archive).unwrap(), id);
+
+        let folder = source_id_for(dir.path()).unwrap();
+        fs::write(dir.path().join("002.png"), b"page").unwrap();
+        assert_eq!(source_id_for(dir.path()).unwrap(), folder);
+        assert_ne!(folder, id);
+
+        let remote = source_id_for_uri("davs://nas.local/Comics/Vol%201/");
+        assert_eq!(source_id_for_uri("davs://nas.local/Comics/Vol%201/"), remote);
+        assert_ne!(source_id_for_uri("davs://nas.local/Comics/Vol%202/"), remote);
+    }
+}
@@ -2,11 +2,26 @@
 
 pub mod archive;
 pub mod folder;
+mod identity;
+#[cfg(feature = "tokio")]
+pub mod nonblocking;
+#[cfg(feature = "rar")]
+mod rar;
+pub mod registry;
+pub mod remote;
+#[cfg(feature = "sevenz")]
+mod sevenz;
+pub mod source;
 mod util;
 
-pub use archive::{list_archive_pages, load_archive};
+pub use archive::{list_archive_pages, load_archive, read_archive_entry};
 pub use folder::{list_folder_pages, load_folder};
-pub use util::{Token, is_hidden, is_supported_image, natural_cmp, natural_cmp_path, tokenize};
+pub use identity::{source_id_for, source_id_for_uri};
+pub use source::{ArchiveSource, FolderSource, ImageFileSource, PageByteSource, SourceEntry};
+pub use util::{
+    IMAGE_EXTENSIONS, Token, is_hidden, is_supported_image, mime_for, natural_cmp,
+    natural_cmp_path, tokenize,
+};
 
 /// Shared result type for fs operations.
 pub type Result<T> = crate::Result<T>;
@@ -0,0 +1,144 @@
+//! Async versions of the fs entry points, for callers running on a tokio runtime.
+//!
+//! Each function runs its sync counterpart on the runtime's blocking pool, so walking a large
+//! folder or inflating an archive entry never stalls the async worker threads. Arguments are
+//! taken by value because the work outlives the caller's borrows.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+
+use crate::error::CoreError;
+use crate::types::{PageMeta, Source, SourceId};
+
+use super::registry::SourceRegistry;
+use super::{PageByteSource, Result};
+
+/// Async [`super::source_id_for`].
+pub async fn source_id_for(path: impl Into<PathBuf>) -> Result<SourceId> {
+    let path = path.into();
+    run(move || super::source_id_for(&path)).await
+}
+
+/// Async [`super::load_folder`].
+pub async fn load_folder(root: impl Into<PathBuf>) -> Result<Source> {
+    let root = root.into();
+    run(move || super::load_folder(&root)).await
+}
+
+/// Async [`super::list_folder_pages`].
+pub async fn list_folder_pages(
+    root: impl Into<PathBuf>,
+    source_id: SourceId,
+) -> Result<Vec<PageMeta>> {
+    let root = root.into();
+    run(move || super::list_folder_pages(&root, &source_id)).await
+}
+
+/// Async [`super::load_archive`].
+pub async fn load_archive(path: impl Into<PathBuf>) -> Result<Source> {
+    let path = path.into();
+    run(move || super::load_archive(&path)).await
+}
+
+/// Async [`super::list_archive_pages`].
+pub async fn list_archive_pages(
+    path: impl Into<PathBuf>,
+    source_id: SourceId,
+) -> Result<Vec<PageMeta>> {
+    let path = path.into();
+    run(move || super::list_archive_pages(&path, &source_id)).await
+}
+
+/// Async [`super::read_archive_entry`].
+pub async fn read_archive_entry(
+    path: impl Into<PathBuf>,
+    entry: impl Into<PathBuf>,
+) -> Result<Vec<u8>> {
+    let (path, entry) = (path.into(), entry.into());
+    run(move || super::read_archive_entry(&path, &entry)).await
+}
+
+/// Async [`SourceRegistry::source_id`].
+pub async fn registry_source_id(
+    registry: Arc<SourceRegistry>,
+    location: impl Into<String>,
+) -> Result<SourceId> {
+    let location = location.into();
+    run(move || registry.source_id(&location)).await
+}
+
+/// Async [`SourceRegistry::open`].
+pub async fn open_source(
+    registry: Arc<SourceRegistry>,
+    location: impl Into<String>,
+) -> Result<Box<dyn PageByteSource>> {
+    let location = location.into();
+    run(move || registry.open(&location)).await
+}
+
+/// Async [`PageByteSource::list_pages`].
+pub async fn list_source_pages(
+    source: Arc<dyn PageByteSource>,
+    source_id: SourceId,
+) -> Result<Vec<PageMeta>> {
+    run(move || source.list_pages(&source_id)).await
+}
+
+/// Run `work` on the blocking pool, passing its panics on to the caller.
+async fn run<T, F>(work: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(work).await {
+        Ok(result) => result,
+        Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+        Err(err) => Err(CoreError::Other(anyhow!("blocking fs task did not run: {err}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn matches_the_sync_api() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("002.png"), b"two").unwrap();
+        fs::write(dir.path().join("001.png"), b"one").unwrap();
+
+        let id = source_id_for(dir.path()).await.unwrap();
+        assert_eq!(id, super::super::source_id_for(dir.path()).unwrap());
+        let pages = list_folder_pages(dir.path(), id.clone()).await.unwrap();
+        let names: Vec<_> = pages.iter().map(|page| page.rel_path.clone()).collect();
+        assert_eq!(names, [PathBuf::from("001.png"), PathBuf::from("002.png")]);
+
+        let registry = Arc::new(SourceRegistry::with_builtin());
+        let location = dir.path().to_string_lossy().into_owned();
+        assert_eq!(registry_source_id(Arc::clone(&registry), location.clone()).await.unwrap(), id);
+        let source: Arc<dyn PageByteSource> =
+            Arc::from(open_source(registry, location).await.unwrap());
+        assert_eq!(list_source_pages(source, id.clone()).await.unwrap().len(), 2);
+
+        let archive = dir.path().join("vol1.cbz");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+        writer.start_file("01.jpg", zip::write::FileOptions::default()).unwrap();
+        writer.write_all(b"page").unwrap();
+        writer.finish().unwrap();
+
+        let id = SourceId::new("vol1");
+        let pages = list_archive_pages(&archive, id).await.unwrap();
+        assert_eq!(pages.len(), 1);
+        let bytes = read_archive_entry(&archive, &pages[0].rel_path).await.unwrap();
+        assert_eq!(bytes, b"page");
+        assert!(matches!(
+            read_archive_entry(&archive, "missing.jpg").await,
+            Err(CoreError::Archive(_))
+        ));
+    }
+}
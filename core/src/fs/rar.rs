@@ -0,0 +1,76 @@
+//! RAR/CBR archives, read through the bundled unrar library.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::error::CoreError;
+
+use super::archive::StoredEntry;
+use super::{Result, util};
+
+/// Packing method of entries stored without compression.
+const METHOD_STORE: u32 = 0x30;
+
+pub(super) fn list(path: &Path) -> Result<Vec<StoredEntry>> {
+    let archive = unrar::Archive::new(path)
+        .open_for_listing()
+        .with_context(|| format!("opening archive {:?}", path))
+        .map_err(CoreError::Archive)?;
+    let mut entries = Vec::new();
+    for header in archive {
+        let header = header
+            .with_context(|| format!("listing archive {:?}", path))
+            .map_err(CoreError::Archive)?;
+        if header.is_directory() {
+            continue;
+        }
+        entries.push(StoredEntry {
+            name: header.filename.clone(),
+            size_bytes: header.unpacked_size,
+            compressed: header.method != METHOD_STORE,
+        });
+    }
+    Ok(entries)
+}
+
+/// Bytes of `entry`, walking the archive in order since solid archives cannot seek.
+pub(super) fn read_entry(path: &Path, entry: &Path) -> Result<Option<Vec<u8>>> {
+    let mut found = None;
+    for_each_entry(path, &|name| name == entry, &mut |_, bytes| {
+        found = Some(bytes);
+        Ok(false)
+    })?;
+    Ok(found)
+}
+
+/// Walk the archive in order, passing the sanitized name and bytes of every entry `wanted`
+/// picks to `visit` until it returns `false`.
+pub(super) fn for_each_entry(
+    path: &Path,
+    wanted: &dyn Fn(&Path) -> bool,
+    visit: &mut dyn FnMut(PathBuf, Vec<u8>) -> Result<bool>,
+) -> Result<()> {
+    let context = || format!("reading {:?}", path);
+    let mut archive = unrar::Archive::new(path)
+        .open_for_processing()
+        .with_context(context)
+        .map_err(CoreError::Archive)?;
+    while let Some(header) =
+        archive.read_header().with_context(context).map_err(CoreError::Archive)?
+    {
+        let name = util::sanitize_zip_path(&header.entry().filename).filter(|name| wanted(name));
+        archive = match name {
+            Some(name) => {
+                let (bytes, rest) =
+                    header.read().with_context(context).map_err(CoreError::Archive)?;
+                if !visit(name, bytes)? {
+                    return Ok(());
+                }
+                rest
+            }
+            None => header.skip().with_context(context).map_err(CoreError::Archive)?,
+        };
+    }
+    Ok(())
+}
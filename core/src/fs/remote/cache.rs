@@ -0,0 +1,250 @@
+//! Local copies of the files of slow sources.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use parking_lot::Mutex;
+
+use crate::error::CoreError;
+use crate::fs::Result;
+use crate::fs::source::{self, PageByteSource, SourceEntry};
+
+/// Wraps a source so every file is fetched once and read from a local copy afterwards.
+///
+/// Copies are keyed by the path, size and modification time the source lists, so a file
+/// changed on the server is fetched again. The listing itself is fetched once per wrapper;
+/// [`CachedSource::refresh`] fetches it again. With [`CachedSource::with_budget`] the least
+/// recently read copies are removed once they outgrow it.
+#[derive(Debug)]
+pub struct CachedSource<S> {
+    inner: S,
+    dir: PathBuf,
+    entries: Mutex<Option<Vec<SourceEntry>>>,
+    budget: Option<u64>,
+}
+
+impl<S: PageByteSource> CachedSource<S> {
+    /// Cache the files of `inner` in `dir`, typically a directory per source inside
+    /// [`crate::store::cache_dir`].
+    pub fn new(inner: S, dir: impl Into<PathBuf>) -> Self {
+        Self { inner, dir: dir.into(), entries: Mutex::new(None), budget: None }
+    }
+
+    /// Keep the copies of this source within `bytes`, removing the least recently read ones.
+    pub fn with_budget(mut self, bytes: u64) -> Self {
+        self.budget = Some(bytes);
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Forget the listing, so the next call fetches it from the source again.
+    pub fn refresh(&self) {
+        *self.entries.lock() = None;
+    }
+
+    /// Remove every local copy.
+    pub fn clear(&self) -> Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn entry(&self, path: &Path) -> Result<SourceEntry> {
+        let entries = self.entries()?;
+        entries.into_iter().find(|entry| entry.path == path).ok_or_else(|| source::missing(path))
+    }
+
+    fn copy_path(&self, entry: &SourceEntry) -> PathBuf {
+        let modified = entry
+            .modified
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_nanos());
+        let key = format!("{}\0{}\0{modified}", entry.path.display(), entry.size_bytes);
+        let hash = blake3::hash(key.as_bytes()).to_hex();
+        let ext = entry.path.extension().and_then(|ext| ext.to_str()).unwrap_or("bin");
+        self.dir.join(format!("{}.{ext}", &hash[..32]))
+    }
+}
+
+impl<S: PageByteSource> PageByteSource for CachedSource<S> {
+    fn entries(&self) -> Result<Vec<SourceEntry>> {
+        let mut entries = self.entries.lock();
+        if let Some(entries) = entries.as_ref() {
+            return Ok(entries.clone());
+        }
+        let listed = self.inner.entries()?;
+        *entries = Some(listed.clone());
+        Ok(listed)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let entry = self.entry(path)?;
+        let copy = self.copy_path(&entry);
+        match fs::read(&copy) {
+            Ok(bytes) => {
+                touch(&copy);
+                return Ok(bytes);
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        let bytes = self.inner.read(path)?;
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("creating source cache at {}", self.dir.display()))?;
+        let mut partial = tempfile::NamedTempFile::new_in(&self.dir)?;
+        partial.write_all(&bytes)?;
+        partial.persist(&copy).map_err(|err| CoreError::Io(err.error.into()))?;
+        tracing::debug!(target: "fs::remote::cache", path = ?path, copy = %copy.display(), "cached");
+        if let Some(budget) = self.budget {
+            let mut copies = Vec::new();
+            list_copies(&self.dir, &mut copies)?;
+            trim(copies, budget)?;
+        }
+        Ok(bytes)
+    }
+
+    fn modified(&self, path: &Path) -> Result<Option<SystemTime>> {
+        Ok(self.entry(path)?.modified)
+    }
+}
+
+/// Remove the least recently read copies under `root`, which holds one directory per source
+/// as laid out by [`CachedSource`], until they take at most `budget` bytes. Returns how many
+/// copies were removed.
+pub fn trim_copies(root: &Path, budget: u64) -> Result<usize> {
+    let sources = match fs::read_dir(root) {
+        Ok(sources) => sources,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+    let mut copies = Vec::new();
+    for source in sources {
+        let source = source?.path();
+        if source.is_dir() {
+            list_copies(&source, &mut copies)?;
+        }
+    }
+    trim(copies, budget)
+}
+
+/// Push the last read time, size and path of every copy in `dir` onto `copies`.
+fn list_copies(dir: &Path, copies: &mut Vec<(Option<SystemTime>, u64, PathBuf)>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_file() {
+            copies.push((meta.modified().ok(), meta.len(), entry.path()));
+        }
+    }
+    Ok(())
+}
+
+/// Remove the oldest of `copies` until the rest fit `budget`.
+fn trim(mut copies: Vec<(Option<SystemTime>, u64, PathBuf)>, budget: u64) -> Result<usize> {
+    let mut total: u64 = copies.iter().map(|(_, len, _)| len).sum();
+    copies.sort_unstable();
+    let mut removed = 0;
+    for (_, len, path) in copies {
+        if total <= budget {
+            break;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        total -= len;
+        removed += 1;
+    }
+    if removed > 0 {
+        tracing::debug!(target: "fs::remote::cache", removed, budget, "trimmed source copies");
+    }
+    Ok(removed)
+}
+
+/// Mark `copy` as just read, so trimming keeps it over older ones. Failing only makes it an
+/// earlier candidate.
+fn touch(copy: &Path) {
+    let touched = fs::File::options()
+        .write(true)
+        .open(copy)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+    if let Err(err) = touched {
+        tracing::trace!(target: "fs::remote::cache", copy = %copy.display(), "failed to touch copy: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Default)]
+    struct Counting {
+        version: AtomicUsize,
+        reads: AtomicUsize,
+    }
+
+    impl PageByteSource for Counting {
+        fn entries(&self) -> Result<Vec<SourceEntry>> {
+            let version = self.version.load(Ordering::SeqCst) as u64;
+            let modified = Some(UNIX_EPOCH + std::time::Duration::from_secs(version));
+            Ok(vec![SourceEntry { path: PathBuf::from("01.png"), size_bytes: 3, modified }])
+        }
+
+        fn read(&self, _path: &Path) -> Result<Vec<u8>> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            Ok(format!("v{}", self.version.load(Ordering::SeqCst)).into_bytes())
+        }
+    }
+
+    #[test]
+    fn reads_each_version_of_a_file_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let cached = CachedSource::new(Counting::default(), dir.path().join("source"));
+        let path = Path::new("01.png");
+        assert_eq!(cached.read(path).unwrap(), b"v0");
+        assert_eq!(cached.read(path).unwrap(), b"v0");
+        assert_eq!(cached.inner().reads.load(Ordering::SeqCst), 1);
+        assert!(cached.read(Path::new("02.png")).is_err());
+
+        cached.inner().version.store(1, Ordering::SeqCst);
+        assert_eq!(cached.read(path).unwrap(), b"v0", "the listing is kept until a refresh");
+        cached.refresh();
+        assert_eq!(cached.read(path).unwrap(), b"v1");
+        assert_eq!(cached.inner().reads.load(Ordering::SeqCst), 2);
+
+        cached.clear().unwrap();
+        assert!(!cached.dir().exists());
+        cached.clear().unwrap();
+    }
+
+    #[test]
+    fn trims_the_least_recently_read_copies() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = |name: &str| {
+            CachedSource::new(Counting::default(), dir.path().join(name)).with_budget(3)
+        };
+        let (first, second) = (source("first"), source("second"));
+        first.read(Path::new("01.png")).unwrap();
+        assert_eq!(trim_copies(dir.path(), 3).unwrap(), 0);
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        second.read(Path::new("01.png")).unwrap();
+        assert_eq!(trim_copies(dir.path(), 3).unwrap(), 1);
+        assert_eq!(fs::read_dir(first.dir()).unwrap().count(), 0);
+        assert_eq!(fs::read_dir(second.dir()).unwrap().count(), 1);
+        assert_eq!(trim_copies(&dir.path().join("missing"), 0).unwrap(), 0);
+    }
+}
@@ -0,0 +1,230 @@
+//! HTTP and XML plumbing shared by the remote backends.
+
+use std::fmt;
+use std::path::{Component, Path};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, anyhow};
+use base64::Engine as _;
+use roxmltree::Node;
+use url::{Origin, Url};
+
+use crate::calendar;
+use crate::error::CoreError;
+use crate::fs::Result;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest wait for the next bytes of a response. Bodies may take longer than this in total, so
+/// large archives download over slow links.
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Agent with the credentials of one server.
+#[derive(Clone)]
+pub(super) struct Http {
+    agent: ureq::Agent,
+    /// Origin the credentials belong to, and the `Authorization` header sent to it.
+    authorization: Option<(Origin, String)>,
+}
+
+impl fmt::Debug for Http {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Http").field("authenticated", &self.authorization.is_some()).finish()
+    }
+}
+
+impl Http {
+    pub fn new() -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(CONNECT_TIMEOUT)
+            .timeout_read(READ_TIMEOUT)
+            .build();
+        Self { agent, authorization: None }
+    }
+
+    /// Authenticate requests to the origin of `server` with HTTP basic auth. Requests to other
+    /// origins, such as links a feed names on a CDN, are sent without credentials.
+    pub fn with_credentials(mut self, server: &Url, user: &str, password: &str) -> Self {
+        let token = base64::engine::general_purpose::STANDARD.encode(format!("{user}:{password}"));
+        self.authorization = Some((server.origin(), format!("Basic {token}")));
+        self
+    }
+
+    /// A `method` request to `url`, authenticated if credentials were given for its origin.
+    pub fn request(&self, method: &str, url: &Url) -> ureq::Request {
+        let request = self.agent.request_url(method, url);
+        match &self.authorization {
+            Some((origin, authorization)) if url.origin() == *origin => {
+                request.set("Authorization", authorization)
+            }
+            _ => request,
+        }
+    }
+
+    pub fn get(&self, url: &Url, accept: &str) -> Result<ureq::Response> {
+        response(url, self.request("GET", url).set("Accept", accept).call())
+    }
+
+    pub fn get_text(&self, url: &Url, accept: &str) -> Result<String> {
+        self.get(url, accept)?
+            .into_string()
+            .with_context(|| format!("reading {url}"))
+            .map_err(CoreError::Io)
+    }
+
+    pub fn get_bytes(&self, url: &Url) -> Result<Vec<u8>> {
+        let response = self.get(url, "*/*")?;
+        let mut bytes = Vec::new();
+        std::io::Read::read_to_end(&mut response.into_reader(), &mut bytes)
+            .with_context(|| format!("reading {url}"))
+            .map_err(CoreError::Io)?;
+        Ok(bytes)
+    }
+}
+
+/// The outcome of a request to `url`, with transport and status failures as I/O errors.
+pub(super) fn response(
+    url: &Url,
+    result: std::result::Result<ureq::Response, ureq::Error>,
+) -> Result<ureq::Response> {
+    result.map_err(|err| CoreError::Io(anyhow::Error::new(err).context(format!("fetching {url}"))))
+}
+
+/// `root` with a trailing slash, so relative paths resolve inside it.
+pub(super) fn directory_url(root: &str) -> Result<Url> {
+    let mut url = Url::parse(root)
+        .with_context(|| format!("invalid URL {root:?}"))
+        .map_err(CoreError::Other)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(CoreError::Other(anyhow!("{url} is not an HTTP URL")));
+    }
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    Ok(url)
+}
+
+/// URL of the file at relative `path` below the directory `root`, percent-encoding each
+/// component.
+pub(super) fn entry_url(root: &Url, path: &Path) -> Result<Url> {
+    let path = crate::fs::source::relative(path)?;
+    let mut url = root.clone();
+    {
+        let mut segments = url
+            .path_segments_mut()
+            .map_err(|()| CoreError::Other(anyhow!("{root} has no path")))?;
+        segments.pop_if_empty();
+        for component in path.components() {
+            if let Component::Normal(part) = component {
+                segments.push(&part.to_string_lossy());
+            }
+        }
+    }
+    Ok(url)
+}
+
+/// Path of `url` relative to the directory `root`, decoded, or `None` outside of it.
+pub(super) fn relative_path(root: &Url, url: &Url) -> Option<String> {
+    if url.origin() != root.origin() {
+        return None;
+    }
+    let rest = url.path().strip_prefix(root.path())?;
+    let decoded = percent_decode(rest.trim_end_matches('/'));
+    (!decoded.is_empty()).then_some(decoded)
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes.get(index + 1..index + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+        match (bytes[index], hex.and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Child elements of `node` named `name`, in any namespace.
+pub(super) fn elements<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+/// Trimmed text of the first `name` child of `node`, if not blank.
+pub(super) fn child_text(node: Node<'_, '_>, name: &'static str) -> Option<String> {
+    let child = elements(node, name).next()?;
+    let text: String = child.descendants().filter_map(|text| text.text()).collect();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Parse an HTTP date such as `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub(super) fn parse_http_date(date: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] =
+        ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let mut parts = date.split_whitespace().skip(1);
+    let day: u32 = parts.next()?.parse().ok()?;
+    let month = MONTHS.iter().position(|month| Some(*month) == parts.next())? as u32 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':').map(|part| part.parse::<u64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if parts.next()? != "GMT" || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+    let days = u64::try_from(calendar::days_from_civil(year, month, day)).ok()?;
+    let seconds = days * 86_400 + hour * 3600 + minute * 60 + second.min(60);
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_paths_to_urls_and_back() {
+        let root = directory_url("https://dav.example/remote.php/Comics").unwrap();
+        assert_eq!(root.as_str(), "https://dav.example/remote.php/Comics/");
+        let url = entry_url(&root, Path::new("Vol 1/01 #1.jpg")).unwrap();
+        assert_eq!(url.as_str(), "https://dav.example/remote.php/Comics/Vol%201/01%20%231.jpg");
+        assert_eq!(relative_path(&root, &url).as_deref(), Some("Vol 1/01 #1.jpg"));
+        assert_eq!(relative_path(&root, &root), None);
+        assert_eq!(relative_path(&root, &Url::parse("https://other.example/x").unwrap()), None);
+        assert!(entry_url(&root, Path::new("../secret")).is_err());
+        assert!(directory_url("ftp://dav.example/").is_err());
+    }
+
+    #[test]
+    fn sends_credentials_to_their_origin_only() {
+        let server = Url::parse("https://books.example/opds").unwrap();
+        let http = Http::new().with_credentials(&server, "reader", "secret");
+        let authorization = |url: &str| {
+            let request = http.request("GET", &Url::parse(url).unwrap());
+            request.header("Authorization").map(str::to_string)
+        };
+        assert_eq!(
+            authorization("https://books.example/books/42").as_deref(),
+            Some("Basic cmVhZGVyOnNlY3JldA==")
+        );
+        assert_eq!(authorization("https://cdn.example/books/42"), None);
+        assert_eq!(authorization("http://books.example/books/42"), None);
+        assert_eq!(Http::new().request("GET", &server).header("Authorization"), None);
+    }
+
+    #[test]
+    fn parses_http_dates() {
+        let date = parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+        assert_eq!(date.duration_since(UNIX_EPOCH).unwrap().as_secs(), 784_111_777);
+        assert!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT").is_none());
+        assert!(parse_http_date("").is_none());
+    }
+}
@@ -0,0 +1,126 @@
+//! Plain HTTP directory listings as page sources, such as nginx `autoindex` or
+//! `python -m http.server`.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use url::Url;
+
+use crate::fs::Result;
+use crate::fs::source::{PageByteSource, SourceEntry};
+
+use super::client::{self, Http};
+
+/// The files linked from an HTTP directory listing.
+///
+/// Listings carry no sizes or dates, so entries report neither; [`PageByteSource::modified`]
+/// asks the server with a `HEAD` request instead.
+#[derive(Debug, Clone)]
+pub struct HttpIndexSource {
+    root: Url,
+    http: Http,
+}
+
+impl HttpIndexSource {
+    /// Source for the listing at `url`.
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self { root: client::directory_url(url)?, http: Http::new() })
+    }
+
+    /// Authenticate requests to the server with HTTP basic auth.
+    pub fn with_credentials(mut self, user: &str, password: &str) -> Self {
+        self.http = self.http.with_credentials(&self.root, user, password);
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        self.root.as_str()
+    }
+
+    /// Names of the directories linked from the listing, such as the books of a library.
+    pub fn directories(&self) -> Result<Vec<String>> {
+        Ok(self.links()?.into_iter().filter(|(_, dir)| *dir).map(|(name, _)| name).collect())
+    }
+
+    fn links(&self) -> Result<Vec<(String, bool)>> {
+        Ok(parse_listing(&self.http.get_text(&self.root, "text/html")?, &self.root))
+    }
+}
+
+impl PageByteSource for HttpIndexSource {
+    fn entries(&self) -> Result<Vec<SourceEntry>> {
+        Ok(self
+            .links()?
+            .into_iter()
+            .filter(|(_, dir)| !dir)
+            .map(|(name, _)| SourceEntry {
+                path: PathBuf::from(name),
+                size_bytes: 0,
+                modified: None,
+            })
+            .collect())
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.http.get_bytes(&client::entry_url(&self.root, path)?)
+    }
+
+    fn modified(&self, path: &Path) -> Result<Option<SystemTime>> {
+        let url = client::entry_url(&self.root, path)?;
+        let response = client::response(&url, self.http.request("HEAD", &url).call())?;
+        Ok(response.header("Last-Modified").and_then(client::parse_http_date))
+    }
+}
+
+/// Names of the files and directories directly inside `root` linked from the page `html`,
+/// in order and without duplicates, each with whether it is a directory.
+fn parse_listing(html: &str, root: &Url) -> Vec<(String, bool)> {
+    let mut links: Vec<(String, bool)> = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find("href=") {
+        rest = &rest[start + "href=".len()..];
+        let Some(quote) = rest.chars().next().filter(|ch| matches!(ch, '"' | '\'')) else {
+            continue;
+        };
+        let Some(end) = rest[1..].find(quote) else { break };
+        let href = rest[1..=end].replace("&amp;", "&");
+        rest = &rest[end + 2..];
+
+        let Ok(url) = root.join(&href) else { continue };
+        // Sort links such as `?C=M;O=A` and anchors lead back to the listing itself.
+        if url.query().is_some() || url.fragment().is_some() {
+            continue;
+        }
+        let Some(name) = client::relative_path(root, &url) else { continue };
+        if name.contains('/') || links.iter().any(|(known, _)| *known == name) {
+            continue;
+        }
+        links.push((name, url.path().ends_with('/')));
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_autoindex_pages() {
+        let html = r#"<html><body><h1>Index of /comics/Vol 1/</h1><pre>
+<a href="../">../</a>
+<a href="?C=N;O=D">Name</a>
+<a href="Extras/">Extras/</a>
+<a href="01%20cover.jpg">01 cover.jpg</a>     06-Nov-1994 08:49    2048
+<a href='02.png'>02.png</a>
+<a href="/comics/Vol%201/03.webp">03.webp</a>
+<a href="https://elsewhere.example/04.jpg">04.jpg</a>
+<a href="01%20cover.jpg#top">again</a>
+<a href=broken>
+</pre></body></html>"#;
+        let source = HttpIndexSource::new("http://nas.local/comics/Vol%201/").unwrap();
+        let links = parse_listing(html, &source.root);
+        let expected =
+            [("Extras", true), ("01 cover.jpg", false), ("02.png", false), ("03.webp", false)];
+        assert_eq!(links, expected.map(|(name, dir)| (name.to_string(), dir)));
+    }
+}
@@ -0,0 +1,21 @@
+//! Sources fetched from other machines and read from local copies.
+//!
+//! SMB and NFS shares have no backend here: mounted by the operating system, they are local
+//! folders to [`super::FolderSource`]. Wrap slow backends in [`CachedSource`] so each file
+//! crosses the network once.
+
+mod cache;
+#[cfg(feature = "remote")]
+mod client;
+#[cfg(feature = "remote")]
+mod index;
+#[cfg(feature = "opds")]
+pub mod opds;
+#[cfg(feature = "remote")]
+mod webdav;
+
+pub use cache::{CachedSource, trim_copies};
+#[cfg(feature = "remote")]
+pub use index::HttpIndexSource;
+#[cfg(feature = "remote")]
+pub use webdav::WebDavSource;
@@ -0,0 +1,433 @@
+//! OPDS catalog client, for self-hosted servers such as Komga, Kavita and Calibre.
+//!
+//! [`OpdsClient`] fetches the Atom feeds of a catalog, downloads the comic archives its
+//! entries offer into a managed directory, and opens them as ordinary archive sources. Feeds
+//! are parsed leniently: unknown elements are skipped and relative links are resolved against
+//! the feed they appear in.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, anyhow};
+use roxmltree::{Document, Node};
+use url::Url;
+
+use crate::error::CoreError;
+use crate::fs::{Result, archive, util};
+use crate::types::Source;
+
+use super::client::{Http, child_text, elements};
+
+/// Link relation of acquisitions; `/open-access`, `/borrow` and friends extend it.
+const ACQUISITION_REL: &str = "http://opds-spec.org/acquisition";
+/// Link relation of cover thumbnails.
+const THUMBNAIL_REL: &str = "http://opds-spec.org/image/thumbnail";
+/// Media types of comic archives, with the extension they are saved under.
+const ARCHIVE_TYPES: &[(&str, &str)] = &[
+    ("application/vnd.comicbook+zip", "cbz"),
+    ("application/x-cbz", "cbz"),
+    ("application/zip", "zip"),
+    ("application/vnd.comicbook-rar", "cbr"),
+    ("application/x-cbr", "cbr"),
+    ("application/vnd.rar", "rar"),
+    ("application/x-rar-compressed", "rar"),
+    ("application/x-cb7", "cb7"),
+    ("application/x-7z-compressed", "7z"),
+];
+const FEED_ACCEPT: &str = "application/atom+xml, application/xml;q=0.9, */*;q=0.1";
+/// Longest title kept in the file name of a download.
+const MAX_FILE_STEM: usize = 80;
+
+/// One page of an OPDS catalog.
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Feed {
+    /// Absolute URL the feed was fetched from.
+    pub url: String,
+    pub id: String,
+    pub title: String,
+    pub entries: Vec<FeedEntry>,
+    /// Next page of a paginated feed.
+    pub next: Option<String>,
+    /// OpenSearch description or `{searchTerms}` template of the catalog's search.
+    pub search: Option<String>,
+}
+
+/// A book or a navigation link in a [`Feed`].
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeedEntry {
+    pub id: String,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub summary: Option<String>,
+    pub updated: Option<String>,
+    pub links: Vec<FeedLink>,
+}
+
+/// A link of an entry or feed, with `href` resolved to an absolute URL.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeedLink {
+    pub href: String,
+    pub rel: Option<String>,
+    pub mime: Option<String>,
+    pub title: Option<String>,
+}
+
+impl FeedLink {
+    fn media_type(&self) -> Option<&str> {
+        self.mime.as_deref().map(|mime| mime.split(';').next().unwrap_or_default().trim())
+    }
+
+    fn is_feed(&self) -> bool {
+        self.media_type().is_some_and(|mime| mime == "application/atom+xml")
+    }
+}
+
+impl FeedEntry {
+    /// The feed a navigation entry leads to.
+    pub fn subsection(&self) -> Option<&FeedLink> {
+        self.links.iter().find(|link| {
+            link.is_feed()
+                && !link.rel.as_deref().is_some_and(|rel| rel.starts_with(ACQUISITION_REL))
+        })
+    }
+
+    /// The download of the entry in an archive format this build opens, with the extension to
+    /// save it under.
+    pub fn acquisition(&self) -> Option<(&FeedLink, &'static str)> {
+        self.links
+            .iter()
+            .filter(|link| link.rel.as_deref().is_some_and(|rel| rel.starts_with(ACQUISITION_REL)))
+            .find_map(|link| Some((link, archive_extension(link)?)))
+    }
+
+    pub fn thumbnail(&self) -> Option<&FeedLink> {
+        self.links.iter().find(|link| link.rel.as_deref() == Some(THUMBNAIL_REL))
+    }
+}
+
+/// Extension of the supported archive `link` downloads, from its media type or else its URL.
+fn archive_extension(link: &FeedLink) -> Option<&'static str> {
+    let from_type = link
+        .media_type()
+        .and_then(|mime| ARCHIVE_TYPES.iter().find(|(archive, _)| *archive == mime))
+        .map(|(_, ext)| *ext);
+    let from_url = || {
+        let url = Url::parse(&link.href).ok()?;
+        let ext = Path::new(url.path()).extension()?.to_str()?.to_ascii_lowercase();
+        archive::SUPPORTED_EXTENSIONS.iter().copied().find(|supported| *supported == ext)
+    };
+    from_type.or_else(from_url).filter(|ext| archive::SUPPORTED_EXTENSIONS.contains(ext))
+}
+
+/// Client for one OPDS catalog, keeping its downloads under a library directory.
+#[derive(Clone)]
+pub struct OpdsClient {
+    catalog: Url,
+    http: Http,
+    library: PathBuf,
+}
+
+impl fmt::Debug for OpdsClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OpdsClient")
+            .field("catalog", &self.catalog.as_str())
+            .field("library", &self.library)
+            .field("http", &self.http)
+            .finish_non_exhaustive()
+    }
+}
+
+impl OpdsClient {
+    /// Client for the catalog whose root feed is at `catalog`. Downloads go to a directory per
+    /// catalog host inside `library`, usually [`crate::store::downloads_dir`].
+    pub fn new(catalog: &str, library: impl Into<PathBuf>) -> Result<Self> {
+        let catalog = Url::parse(catalog)
+            .with_context(|| format!("invalid catalog URL {catalog:?}"))
+            .map_err(CoreError::Other)?;
+        if !matches!(catalog.scheme(), "http" | "https") {
+            return Err(CoreError::Other(anyhow!("catalog URL {catalog} is not HTTP")));
+        }
+        Ok(Self { catalog, http: Http::new(), library: library.into() })
+    }
+
+    /// Authenticate requests to the catalog's server with HTTP basic auth. Links to other
+    /// servers, such as covers or downloads on a CDN, are fetched without credentials.
+    pub fn with_credentials(mut self, user: &str, password: &str) -> Self {
+        self.http = self.http.with_credentials(&self.catalog, user, password);
+        self
+    }
+
+    pub fn catalog_url(&self) -> &str {
+        self.catalog.as_str()
+    }
+
+    /// The root feed of the catalog.
+    pub fn root(&self) -> Result<Feed> {
+        self.fetch_feed(self.catalog.clone())
+    }
+
+    /// The feed at `href`, resolved against the catalog URL.
+    pub fn feed(&self, href: &str) -> Result<Feed> {
+        self.fetch_feed(self.resolve(href)?)
+    }
+
+    /// Results of searching `feed`'s catalog for `terms`.
+    pub fn search(&self, feed: &Feed, terms: &str) -> Result<Feed> {
+        let search = feed
+            .search
+            .as_deref()
+            .ok_or_else(|| CoreError::Other(anyhow!("feed {} offers no search", feed.url)))?;
+        let (base, template) = if search.contains("{searchTerms}") {
+            (self.catalog.clone(), search.to_string())
+        } else {
+            let url = self.resolve(search)?;
+            let description = self.http.get_text(&url, "application/opensearchdescription+xml")?;
+            let template = search_template(&description)
+                .with_context(|| format!("parsing search description {url}"))
+                .map_err(CoreError::Other)?;
+            (url, template)
+        };
+        let terms: String = url::form_urlencoded::byte_serialize(terms.as_bytes()).collect();
+        let href = template.replace("{searchTerms}", &terms);
+        let url = base
+            .join(&href)
+            .with_context(|| format!("invalid search link {href:?}"))
+            .map_err(CoreError::Other)?;
+        self.fetch_feed(url)
+    }
+
+    /// Directory the downloads of this catalog are kept in.
+    pub fn download_dir(&self) -> PathBuf {
+        let host = self.catalog.host_str().unwrap_or("catalog");
+        let dir = match self.catalog.port() {
+            Some(port) => format!("{host}_{port}"),
+            None => host.to_string(),
+        };
+        self.library.join(file_stem(&dir))
+    }
+
+    /// Where `entry` is saved once downloaded, or `None` if it offers no supported archive.
+    pub fn download_path(&self, entry: &FeedEntry) -> Option<PathBuf> {
+        let (_, ext) = entry.acquisition()?;
+        let tag = &blake3::hash(entry.id.as_bytes()).to_hex()[..8];
+        Some(self.download_dir().join(format!("{} [{tag}].{ext}", file_stem(&entry.title))))
+    }
+
+    /// Download `entry` unless it already was, returning the local archive.
+    pub fn download(&self, entry: &FeedEntry) -> Result<PathBuf> {
+        let (link, _) = entry.acquisition().ok_or_else(|| {
+            CoreError::Other(anyhow!("{:?} offers no supported archive", entry.title))
+        })?;
+        let path = self.download_path(entry).expect("entries with an acquisition have a path");
+        if path.is_file() {
+            return Ok(path);
+        }
+        let dir = path.parent().expect("downloads are inside the download directory");
+        fs::create_dir_all(dir)?;
+        let url = self.resolve(&link.href)?;
+        let response = self.http.get(&url, link.mime.as_deref().unwrap_or("*/*"))?;
+        let mut partial = tempfile::NamedTempFile::new_in(dir)?;
+        io::copy(&mut response.into_reader(), partial.as_file_mut())
+            .with_context(|| format!("downloading {url}"))?;
+        partial.persist(&path).map_err(|err| CoreError::Io(err.error.into()))?;
+        tracing::info!(target: "fs::remote::opds", %url, path = %path.display(), "downloaded");
+        Ok(path)
+    }
+
+    /// Download `entry` if needed and open it as an archive source.
+    pub fn open(&self, entry: &FeedEntry) -> Result<Source> {
+        archive::load_archive(&self.download(entry)?)
+    }
+
+    /// Archives downloaded from this catalog, in natural order.
+    pub fn downloaded(&self) -> Result<Vec<PathBuf>> {
+        let dir = self.download_dir();
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            let ext = path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase);
+            if ext.is_some_and(|ext| archive::SUPPORTED_EXTENSIONS.contains(&ext.as_str())) {
+                paths.push(path);
+            }
+        }
+        paths.sort_by(|a, b| util::natural_cmp_path(a, b));
+        Ok(paths)
+    }
+
+    fn resolve(&self, href: &str) -> Result<Url> {
+        self.catalog
+            .join(href)
+            .with_context(|| format!("invalid link {href:?}"))
+            .map_err(CoreError::Other)
+    }
+
+    fn fetch_feed(&self, url: Url) -> Result<Feed> {
+        let text = self.http.get_text(&url, FEED_ACCEPT)?;
+        parse_feed(&text, &url)
+            .with_context(|| format!("parsing OPDS feed {url}"))
+            .map_err(CoreError::Other)
+    }
+}
+
+/// Parse the Atom document `xml` fetched from `url`.
+fn parse_feed(xml: &str, url: &Url) -> anyhow::Result<Feed> {
+    let doc = Document::parse(xml)?;
+    let root = doc.root_element();
+    if root.tag_name().name() != "feed" {
+        return Err(anyhow!("expected an Atom feed, found <{}>", root.tag_name().name()));
+    }
+    let mut feed = Feed {
+        url: url.to_string(),
+        id: child_text(root, "id").unwrap_or_default(),
+        title: child_text(root, "title").unwrap_or_default(),
+        ..Feed::default()
+    };
+    for link in links(root, url) {
+        match link.rel.as_deref() {
+            Some("next") => feed.next = Some(link.href),
+            Some("search") if feed.search.is_none() || link.is_feed() => {
+                feed.search = Some(link.href);
+            }
+            _ => {}
+        }
+    }
+    feed.entries = elements(root, "entry")
+        .map(|entry| FeedEntry {
+            id: child_text(entry, "id").unwrap_or_default(),
+            title: child_text(entry, "title").unwrap_or_default(),
+            authors: elements(entry, "author")
+                .filter_map(|author| child_text(author, "name"))
+                .collect(),
+            summary: child_text(entry, "summary").or_else(|| child_text(entry, "content")),
+            updated: child_text(entry, "updated"),
+            links: links(entry, url),
+        })
+        .collect();
+    Ok(feed)
+}
+
+/// The Atom search template in the OpenSearch description `xml`.
+fn search_template(xml: &str) -> anyhow::Result<String> {
+    let doc = Document::parse(xml)?;
+    elements(doc.root_element(), "Url")
+        .filter(|url| url.attribute("template").is_some_and(|t| t.contains("{searchTerms}")))
+        .max_by_key(|url| url.attribute("type").is_some_and(|t| t.contains("atom")))
+        .and_then(|url| url.attribute("template"))
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("no search template"))
+}
+
+fn links(node: Node<'_, '_>, base: &Url) -> Vec<FeedLink> {
+    elements(node, "link")
+        .filter_map(|link| {
+            Some(FeedLink {
+                href: base.join(link.attribute("href")?).ok()?.to_string(),
+                rel: link.attribute("rel").map(str::to_string),
+                mime: link.attribute("type").map(str::to_string),
+                title: link.attribute("title").map(str::to_string),
+            })
+        })
+        .collect()
+}
+
+/// `name` made safe to use as a file name on every platform.
+fn file_stem(name: &str) -> String {
+    let stem: String = name
+        .chars()
+        .map(|ch| if ch.is_control() || r#"/\:*?"<>|"#.contains(ch) { '_' } else { ch })
+        .take(MAX_FILE_STEM)
+        .collect();
+    let stem = stem.trim().trim_matches('.');
+    if stem.is_empty() { "book".to_string() } else { stem.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xmlns:opds="http://opds-spec.org/2010/catalog">
+  <id>urn:catalog:series</id>
+  <title>Series &amp; more</title>
+  <link rel="next" href="?page=2" type="application/atom+xml;profile=opds-catalog"/>
+  <link rel="search" href="/opds/search.xml" type="application/opensearchdescription+xml"/>
+  <entry>
+    <id>urn:series:1</id>
+    <title>Latest</title>
+    <link rel="subsection" href="latest" type="application/atom+xml;profile=opds-catalog;kind=acquisition"/>
+  </entry>
+  <entry>
+    <id>urn:book:42</id>
+    <title>Volume 1: The Start</title>
+    <author><name>Some Author</name></author>
+    <summary type="text">  First volume.  </summary>
+    <link rel="http://opds-spec.org/image/thumbnail" href="/covers/42.jpg" type="image/jpeg"/>
+    <link rel="http://opds-spec.org/acquisition" href="/books/42.pdf" type="application/pdf"/>
+    <link rel="http://opds-spec.org/acquisition/open-access" href="/books/42/file" type="application/vnd.comicbook+zip"/>
+  </entry>
+</feed>"#;
+
+    #[test]
+    fn parses_navigation_and_acquisition_entries() {
+        let url = Url::parse("https://books.example/opds/series").unwrap();
+        let feed = parse_feed(FEED, &url).unwrap();
+        assert_eq!(feed.title, "Series & more");
+        assert_eq!(feed.next.as_deref(), Some("https://books.example/opds/series?page=2"));
+        assert_eq!(feed.search.as_deref(), Some("https://books.example/opds/search.xml"));
+
+        let [section, book] = feed.entries.as_slice() else { panic!("expected two entries") };
+        assert_eq!(
+            section.subsection().map(|link| link.href.as_str()),
+            Some("https://books.example/opds/latest")
+        );
+        assert!(section.acquisition().is_none());
+
+        assert_eq!(book.authors, ["Some Author"]);
+        assert_eq!(book.summary.as_deref(), Some("First volume."));
+        assert!(book.subsection().is_none());
+        let (link, ext) = book.acquisition().unwrap();
+        assert_eq!((link.href.as_str(), ext), ("https://books.example/books/42/file", "cbz"));
+        assert_eq!(book.thumbnail().unwrap().href, "https://books.example/covers/42.jpg");
+
+        assert!(parse_feed("<html/>", &url).is_err());
+    }
+
+    #[test]
+    fn downloads_get_stable_safe_names() {
+        let library = tempfile::tempdir().unwrap();
+        let client = OpdsClient::new("http://nas.local:8080/opds", library.path()).unwrap();
+        let url = Url::parse(client.catalog_url()).unwrap();
+        let feed = parse_feed(FEED, &url).unwrap();
+
+        let path = client.download_path(&feed.entries[1]).unwrap();
+        assert_eq!(path.parent().unwrap(), library.path().join("nas.local_8080"));
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("Volume 1_ The Start ["), "{name}");
+        assert!(name.ends_with("].cbz"), "{name}");
+        assert_eq!(client.download_path(&feed.entries[1]).unwrap(), path);
+        assert!(client.download_path(&feed.entries[0]).is_none());
+        assert!(client.downloaded().unwrap().is_empty());
+
+        assert!(OpdsClient::new("file:///srv/books", library.path()).is_err());
+    }
+
+    #[test]
+    fn finds_the_opensearch_template() {
+        let description = r#"<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">
+  <Url type="text/html" template="/search?q={searchTerms}"/>
+  <Url type="application/atom+xml" template="/opds/search?q={searchTerms}"/>
+</OpenSearchDescription>"#;
+        assert_eq!(search_template(description).unwrap(), "/opds/search?q={searchTerms}");
+        assert!(search_template("<OpenSearchDescription/>").is_err());
+    }
+}
@@ -0,0 +1,196 @@
+//! WebDAV collections as page sources, for Nextcloud, ownCloud and NAS shares.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, anyhow};
+use roxmltree::Document;
+use url::Url;
+
+use crate::error::CoreError;
+use crate::fs::Result;
+use crate::fs::source::{PageByteSource, SourceEntry};
+
+use super::client::{self, Http, child_text, elements};
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop><d:resourcetype/><d:getcontentlength/><d:getlastmodified/></d:prop>
+</d:propfind>"#;
+
+/// The files directly inside a WebDAV collection.
+#[derive(Debug, Clone)]
+pub struct WebDavSource {
+    root: Url,
+    http: Http,
+}
+
+impl WebDavSource {
+    /// Source for the collection at `url`.
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self { root: client::directory_url(url)?, http: Http::new() })
+    }
+
+    /// Authenticate requests to the server with HTTP basic auth.
+    pub fn with_credentials(mut self, user: &str, password: &str) -> Self {
+        self.http = self.http.with_credentials(&self.root, user, password);
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        self.root.as_str()
+    }
+
+    /// Names of the collections directly inside this one, such as the books of a library.
+    pub fn collections(&self) -> Result<Vec<String>> {
+        Ok(self
+            .propfind()?
+            .into_iter()
+            .filter(|member| member.collection)
+            .map(|m| m.name)
+            .collect())
+    }
+
+    fn propfind(&self) -> Result<Vec<Member>> {
+        let request = self
+            .http
+            .request("PROPFIND", &self.root)
+            .set("Depth", "1")
+            .set("Content-Type", "application/xml; charset=utf-8");
+        let text = client::response(&self.root, request.send_string(PROPFIND_BODY))?
+            .into_string()
+            .with_context(|| format!("reading {}", self.root))
+            .map_err(CoreError::Io)?;
+        parse_multistatus(&text, &self.root)
+            .with_context(|| format!("parsing WebDAV listing of {}", self.root))
+            .map_err(CoreError::Other)
+    }
+}
+
+impl PageByteSource for WebDavSource {
+    fn entries(&self) -> Result<Vec<SourceEntry>> {
+        Ok(self
+            .propfind()?
+            .into_iter()
+            .filter(|member| !member.collection)
+            .map(|member| SourceEntry {
+                path: PathBuf::from(member.name),
+                size_bytes: member.size_bytes,
+                modified: member.modified,
+            })
+            .collect())
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.http.get_bytes(&client::entry_url(&self.root, path)?)
+    }
+}
+
+/// A resource of a PROPFIND response.
+#[derive(Debug)]
+struct Member {
+    name: String,
+    collection: bool,
+    size_bytes: u64,
+    modified: Option<SystemTime>,
+}
+
+/// The direct members of the collection `root` in the multistatus document `xml`.
+fn parse_multistatus(xml: &str, root: &Url) -> anyhow::Result<Vec<Member>> {
+    let doc = Document::parse(xml)?;
+    let multistatus = doc.root_element();
+    if multistatus.tag_name().name() != "multistatus" {
+        return Err(anyhow!("expected a multistatus, found <{}>", multistatus.tag_name().name()));
+    }
+    let mut members = Vec::new();
+    for response in elements(multistatus, "response") {
+        let Some(href) = child_text(response, "href") else { continue };
+        let Some(name) = root.join(&href).ok().and_then(|url| client::relative_path(root, &url))
+        else {
+            continue;
+        };
+        // Depth 1 answers with direct members only, but proxies have been known to flatten.
+        if name.contains('/') {
+            continue;
+        }
+        let Some(prop) = elements(response, "propstat")
+            .filter(|propstat| {
+                child_text(*propstat, "status").is_none_or(|status| status.contains(" 200 "))
+            })
+            .find_map(|propstat| elements(propstat, "prop").next())
+        else {
+            continue;
+        };
+        members.push(Member {
+            name,
+            collection: elements(prop, "resourcetype")
+                .any(|kind| elements(kind, "collection").next().is_some()),
+            size_bytes: child_text(prop, "getcontentlength")
+                .and_then(|len| len.parse().ok())
+                .unwrap_or(0),
+            modified: child_text(prop, "getlastmodified")
+                .and_then(|date| client::parse_http_date(&date)),
+        });
+    }
+    Ok(members)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTING: &str = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:">
+  <d:response>
+    <d:href>/dav/Comics/Vol%201/</d:href>
+    <d:propstat>
+      <d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/dav/Comics/Vol%201/01.jpg</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:resourcetype/>
+        <d:getcontentlength>2048</d:getcontentlength>
+        <d:getlastmodified>Sun, 06 Nov 1994 08:49:37 GMT</d:getlastmodified>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>https://dav.example/dav/Comics/Vol%201/Extras/</d:href>
+    <d:propstat>
+      <d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/dav/Comics/Vol%201/02.png</d:href>
+    <d:propstat>
+      <d:prop><d:getcontentlength/></d:prop>
+      <d:status>HTTP/1.1 404 Not Found</d:status>
+    </d:propstat>
+    <d:propstat>
+      <d:prop><d:resourcetype/></d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+</d:multistatus>"#;
+
+    #[test]
+    fn parses_the_members_of_a_collection() {
+        let source = WebDavSource::new("https://dav.example/dav/Comics/Vol 1").unwrap();
+        assert_eq!(source.url(), "https://dav.example/dav/Comics/Vol%201/");
+        let members = parse_multistatus(LISTING, &source.root).unwrap();
+        let names: Vec<_> = members.iter().map(|member| member.name.as_str()).collect();
+        assert_eq!(names, ["01.jpg", "Extras", "02.png"]);
+        assert_eq!(members[0].size_bytes, 2048);
+        assert!(members[0].modified.is_some());
+        assert!(members[1].collection);
+        assert_eq!((members[2].collection, members[2].size_bytes), (false, 0));
+
+        assert!(parse_multistatus("<html/>", &source.root).is_err());
+    }
+}
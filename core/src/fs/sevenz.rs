@@ -0,0 +1,71 @@
+//! 7z/CB7 archives.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use sevenz_rust::{Password, SevenZReader};
+
+use crate::error::CoreError;
+
+use super::archive::StoredEntry;
+use super::{Result, util};
+
+pub(super) fn list(path: &Path) -> Result<Vec<StoredEntry>> {
+    let reader = SevenZReader::open(path, Password::empty())
+        .with_context(|| format!("opening archive {:?}", path))
+        .map_err(CoreError::Archive)?;
+    let entries = reader
+        .archive()
+        .files
+        .iter()
+        .filter(|file| !file.is_directory())
+        .map(|file| StoredEntry {
+            name: PathBuf::from(file.name()),
+            size_bytes: file.size(),
+            // 7z has no stored mode worth telling apart; every entry goes through a coder.
+            compressed: true,
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// Bytes of `entry`, decompressing the blocks before it since 7z archives are usually solid.
+pub(super) fn read_entry(path: &Path, entry: &Path) -> Result<Option<Vec<u8>>> {
+    let mut found = None;
+    for_each_entry(path, &|name| name == entry, &mut |_, bytes| {
+        found = Some(bytes);
+        Ok(false)
+    })?;
+    Ok(found)
+}
+
+/// Walk the archive in order, passing the sanitized name and bytes of every entry `wanted`
+/// picks to `visit` until it returns `false`.
+pub(super) fn for_each_entry(
+    path: &Path,
+    wanted: &dyn Fn(&Path) -> bool,
+    visit: &mut dyn FnMut(PathBuf, Vec<u8>) -> Result<bool>,
+) -> Result<()> {
+    let context = || format!("reading {:?}", path);
+    let mut reader = SevenZReader::open(path, Password::empty())
+        .with_context(context)
+        .map_err(CoreError::Archive)?;
+    let mut failed = None;
+    reader
+        .for_each_entries(|file, data| {
+            let name = util::sanitize_zip_path(Path::new(file.name()));
+            let Some(name) = name.filter(|name| wanted(name)) else {
+                return Ok(true);
+            };
+            let mut bytes = Vec::with_capacity(file.size() as usize);
+            data.read_to_end(&mut bytes)?;
+            visit(name, bytes).or_else(|err| {
+                failed = Some(err);
+                Ok(false)
+            })
+        })
+        .with_context(context)
+        .map_err(CoreError::Archive)?;
+    failed.map_or(Ok(()), Err)
+}
@@ -0,0 +1,282 @@
+//! Uniform access to the files of a source, wherever it lives.
+//!
+//! [`PageByteSource`] is what the pipeline needs from a source: its entries, their bytes and
+//! their modification times. Local folders and archives implement it here; remote libraries
+//! implement it in [`super::remote`], so pages list and decode the same way for both.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::anyhow;
+use parking_lot::Mutex;
+
+use crate::error::CoreError;
+use crate::types::{PageId, PageMeta, SourceId};
+
+use super::{Result, archive, folder, util};
+
+/// A file of a source, as its backend lists it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceEntry {
+    /// Path relative to the root of the source.
+    pub path: PathBuf,
+    /// Size in bytes, or `0` when the backend does not know it.
+    pub size_bytes: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// Where the bytes of a source's pages come from.
+pub trait PageByteSource: fmt::Debug + Send + Sync {
+    /// The files of the source, in any order. Backends may leave out files that are not
+    /// images.
+    fn entries(&self) -> Result<Vec<SourceEntry>>;
+
+    /// Bytes of the file at `path`, relative to the root as listed by [`Self::entries`].
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+
+    /// Modification time of the file at `path`, if the backend knows it.
+    fn modified(&self, path: &Path) -> Result<Option<SystemTime>> {
+        let entries = self.entries()?;
+        let entry = entries.into_iter().find(|entry| entry.path == path);
+        entry.map(|entry| entry.modified).ok_or_else(|| missing(path))
+    }
+
+    /// The pages of the source as source `source_id`: its visible, supported images in natural
+    /// order.
+    fn list_pages(&self, source_id: &SourceId) -> Result<Vec<PageMeta>> {
+        let mut entries: Vec<SourceEntry> = self
+            .entries()?
+            .into_iter()
+            .filter(|entry| !util::is_hidden(&entry.path) && util::is_supported_image(&entry.path))
+            .collect();
+        entries.sort_by(|a, b| util::natural_cmp_path(&a.path, &b.path));
+        Ok(entries
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry)| PageMeta {
+                id: PageId { source_id: source_id.clone(), index: index as u32 },
+                mime: util::mime_for(&entry.path).to_string(),
+                rel_path: entry.path,
+                size_bytes: entry.size_bytes,
+                modified: entry.modified,
+                width: 0,
+                height: 0,
+                is_double_spread: false,
+            })
+            .collect())
+    }
+}
+
+impl<S: PageByteSource + ?Sized> PageByteSource for Box<S> {
+    fn entries(&self) -> Result<Vec<SourceEntry>> {
+        (**self).entries()
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        (**self).read(path)
+    }
+
+    fn modified(&self, path: &Path) -> Result<Option<SystemTime>> {
+        (**self).modified(path)
+    }
+
+    fn list_pages(&self, source_id: &SourceId) -> Result<Vec<PageMeta>> {
+        (**self).list_pages(source_id)
+    }
+}
+
+/// Error for a `path` the source has no file at.
+pub(crate) fn missing(path: &Path) -> CoreError {
+    CoreError::Io(anyhow!("source has no entry {path:?}"))
+}
+
+/// `path` if it stays inside the root of a source, normalized.
+pub(crate) fn relative(path: &Path) -> Result<PathBuf> {
+    util::sanitize_zip_path(path)
+        .ok_or_else(|| CoreError::Io(anyhow!("entry path {path:?} leaves the source")))
+}
+
+/// The images directly inside a local folder.
+#[derive(Debug, Clone)]
+pub struct FolderSource {
+    root: PathBuf,
+}
+
+impl FolderSource {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl PageByteSource for FolderSource {
+    fn entries(&self) -> Result<Vec<SourceEntry>> {
+        folder::collect_entries(&self.root)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        Ok(std::fs::read(self.root.join(relative(path)?))?)
+    }
+
+    fn modified(&self, path: &Path) -> Result<Option<SystemTime>> {
+        Ok(std::fs::metadata(self.root.join(relative(path)?))?.modified().ok())
+    }
+}
+
+/// The images inside a local archive.
+///
+/// RAR and 7z archives can only be read in order, so the first page read extracts every image
+/// into a temporary directory in one pass and later pages are read from there. The directory
+/// goes away with the source, and a changed archive is extracted again.
+#[derive(Debug, Clone)]
+pub struct ArchiveSource {
+    path: PathBuf,
+    extracted: Arc<Mutex<Option<Extracted>>>,
+}
+
+/// Images of a sequential archive extracted by [`ArchiveSource`].
+#[derive(Debug)]
+struct Extracted {
+    /// Modification time of the archive when it was extracted.
+    modified: Option<SystemTime>,
+    _dir: tempfile::TempDir,
+    files: HashMap<PathBuf, PathBuf>,
+}
+
+impl ArchiveSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), extracted: Arc::new(Mutex::new(None)) }
+    }
+
+    /// Read `entry` from the extracted images, extracting them first if that has not happened
+    /// since the archive last changed.
+    fn read_extracted(&self, entry: &Path) -> Result<Vec<u8>> {
+        let modified = std::fs::metadata(&self.path)?.modified().ok();
+        let mut extracted = self.extracted.lock();
+        let current = match extracted.take() {
+            Some(current) if current.modified == modified => current,
+            _ => {
+                let dir = tempfile::tempdir()?;
+                let files = archive::extract_images(&self.path, dir.path())?.unwrap_or_default();
+                tracing::debug!(
+                    target: "fs::source",
+                    archive = %self.path.display(),
+                    entries = files.len(),
+                    "extracted archive"
+                );
+                Extracted { modified, _dir: dir, files }
+            }
+        };
+        let file = current.files.get(entry).cloned();
+        *extracted = Some(current);
+        drop(extracted);
+        match file {
+            Some(file) => Ok(std::fs::read(file)?),
+            None => {
+                Err(CoreError::Archive(anyhow!("archive {:?} has no entry {:?}", self.path, entry)))
+            }
+        }
+    }
+}
+
+impl PageByteSource for ArchiveSource {
+    fn entries(&self) -> Result<Vec<SourceEntry>> {
+        // Entry timestamps carry no time zone; see `list_archive_pages`.
+        let modified = std::fs::metadata(&self.path)?.modified().ok();
+        Ok(archive::collect_entries(&self.path)?
+            .into_iter()
+            .map(|entry| SourceEntry { path: entry.path, size_bytes: entry.size_bytes, modified })
+            .collect())
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        if archive::is_sequential(&self.path) {
+            return self.read_extracted(path);
+        }
+        archive::read_archive_entry(&self.path, path)
+    }
+
+    fn modified(&self, _path: &Path) -> Result<Option<SystemTime>> {
+        Ok(std::fs::metadata(&self.path)?.modified().ok())
+    }
+}
+
+/// A lone image file, read as a one-page source.
+#[derive(Debug, Clone)]
+pub struct ImageFileSource {
+    path: PathBuf,
+}
+
+impl ImageFileSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn name(&self) -> &Path {
+        Path::new(self.path.file_name().unwrap_or("image".as_ref()))
+    }
+}
+
+impl PageByteSource for ImageFileSource {
+    fn entries(&self) -> Result<Vec<SourceEntry>> {
+        let metadata = std::fs::metadata(&self.path)?;
+        Ok(vec![SourceEntry {
+            path: self.name().to_path_buf(),
+            size_bytes: metadata.len(),
+            modified: metadata.modified().ok(),
+        }])
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        if path != self.name() {
+            return Err(missing(path));
+        }
+        Ok(std::fs::read(&self.path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn local_sources_match_the_fs_listings() {
+        let dir = tempdir().unwrap();
+        for name in ["10.png", "2.png", ".hidden.png", "notes.txt"] {
+            fs::write(dir.path().join(name), name).unwrap();
+        }
+        let id = SourceId::new("local");
+        let folder = FolderSource::new(dir.path());
+        assert_eq!(
+            folder.list_pages(&id).unwrap(),
+            folder::list_folder_pages(dir.path(), &id).unwrap()
+        );
+        assert_eq!(folder.read(Path::new("2.png")).unwrap(), b"2.png");
+        assert!(folder.modified(Path::new("10.png")).unwrap().is_some());
+        assert!(folder.read(Path::new("../outside.png")).is_err());
+
+        let path = dir.path().join("vol.cbz");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        for name in ["b/2.jpg", "a/1.jpg"] {
+            zip.start_file(name, zip::write::FileOptions::default()).unwrap();
+            zip.write_all(name.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        let archive = ArchiveSource::new(&path);
+        let pages = archive.list_pages(&id).unwrap();
+        assert_eq!(pages, archive::list_archive_pages(&path, &id).unwrap());
+        assert_eq!(archive.read(&pages[1].rel_path).unwrap(), b"b/2.jpg");
+        assert!(matches!(archive.read(Path::new("c/3.jpg")), Err(CoreError::Archive(_))));
+
+        let image = ImageFileSource::new(dir.path().join("2.png"));
+        let pages = image.list_pages(&id).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(image.read(&pages[0].rel_path).unwrap(), b"2.png");
+        assert!(image.read(Path::new("10.png")).is_err());
+    }
+}
@@ -3,7 +3,17 @@ use std::ffi::OsStr;
 use std::path::{Component, Path, PathBuf};
 
 /// Supported image file extensions (lowercase, without the dot).
-pub const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp", "avif", "gif", "bmp"];
+pub const IMAGE_EXTENSIONS: &[&str] = &[
+    "jpg",
+    "jpeg",
+    "png",
+    "webp",
+    "avif",
+    "gif",
+    "bmp",
+    #[cfg(feature = "jxl")]
+    "jxl",
+];
 
 pub fn is_hidden(path: &Path) -> bool {
     path.file_name().and_then(OsStr::to_str).map(|name| name.starts_with('.')).unwrap_or(false)
@@ -19,6 +29,20 @@ pub fn is_supported_image(path: &Path) -> bool {
         .unwrap_or(false)
 }
 
+/// Content type of the image at `path`, judged by its extension.
+pub fn mime_for(path: &Path) -> &'static str {
+    match path.extension().and_then(OsStr::to_str).map(str::to_ascii_lowercase).as_deref() {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("jxl") => "image/jxl",
+        Some("gif") => "image/gif",
+        Some("bmp") => "image/bmp",
+        _ => "application/octet-stream",
+    }
+}
+
 pub fn natural_cmp_path(a: &Path, b: &Path) -> Ordering {
     natural_cmp(&to_cmp_key(a), &to_cmp_key(b))
 }
@@ -0,0 +1,76 @@
+//! Canonical form of the gestures bindings are keyed by.
+//!
+//! Gestures are written `keyboard:<modifiers+>Code`, with `Code` a DOM `KeyboardEvent.code`, or
+//! `mouse:<modifiers+>Button<n>`. Modifiers may come in any order and under common aliases
+//! (`Control`, `Option`, `Cmd`, `Command`); the canonical form lists them as
+//! `Ctrl+Alt+Shift+Meta`, so the same chord always maps to the same gesture.
+
+use crate::types::InputGesture;
+
+/// Canonical modifier names, in canonical order, with the lowercase aliases accepted for each.
+const MODIFIERS: [(&str, &[&str]); 4] = [
+    ("Ctrl", &["ctrl", "control"]),
+    ("Alt", &["alt", "option"]),
+    ("Shift", &["shift"]),
+    ("Meta", &["meta", "cmd", "command"]),
+];
+
+/// Parse `raw` into its canonical gesture, or `None` when it is not a valid gesture.
+pub fn parse_gesture(raw: &str) -> Option<InputGesture> {
+    let (kind, payload) = raw.trim().split_once(':')?;
+    let mut segments: Vec<&str> =
+        payload.split('+').map(str::trim).filter(|segment| !segment.is_empty()).collect();
+    let key = segments.pop()?;
+
+    let mut held = [false; MODIFIERS.len()];
+    for segment in segments {
+        let segment = segment.to_ascii_lowercase();
+        let index =
+            MODIFIERS.iter().position(|(_, aliases)| aliases.contains(&segment.as_str()))?;
+        held[index] = true;
+    }
+
+    let key = match kind {
+        "keyboard" if key.chars().all(|c| c.is_ascii_alphanumeric()) => key.to_string(),
+        "mouse" => format!("Button{}", key.strip_prefix("Button")?.parse::<u8>().ok()?),
+        _ => return None,
+    };
+    let mut parts: Vec<&str> =
+        MODIFIERS.iter().zip(held).filter(|(_, held)| *held).map(|((name, _), _)| *name).collect();
+    parts.push(&key);
+    Some(InputGesture(format!("{kind}:{}", parts.join("+"))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canonical(raw: &str) -> Option<String> {
+        parse_gesture(raw).map(|gesture| gesture.0)
+    }
+
+    #[test]
+    fn orders_and_renames_modifiers() {
+        assert_eq!(
+            canonical("keyboard:Cmd+Shift+KeyK").as_deref(),
+            Some("keyboard:Shift+Meta+KeyK")
+        );
+        assert_eq!(canonical(" keyboard:control+KeyG ").as_deref(), Some("keyboard:Ctrl+KeyG"));
+        assert_eq!(canonical("mouse:Option+Button4").as_deref(), Some("mouse:Alt+Button4"));
+        assert_eq!(canonical("keyboard:ArrowRight").as_deref(), Some("keyboard:ArrowRight"));
+    }
+
+    #[test]
+    fn rejects_malformed_gestures() {
+        for raw in [
+            "ArrowRight",
+            "keyboard:",
+            "keyboard:Hyper+KeyA",
+            "mouse:Left",
+            "pen:Tip",
+            "keyboard:Key A",
+        ] {
+            assert_eq!(canonical(raw), None, "{raw}");
+        }
+    }
+}
@@ -1,9 +1,74 @@
-//! Placeholder shortcut mapping definitions.
+//! Default shortcut layout: every action the reader offers and the gestures bound to it out of
+//! the box.
 
 use crate::types::{ActionId, InputGesture};
 
 use super::Result;
+use super::gesture::parse_gesture;
+
+/// Actions with their default gestures, in the order the UI lists them.
+pub const DEFAULT_BINDINGS: &[(&str, &[&str])] = &[
+    ("reader.page.next", &["keyboard:ArrowRight", "keyboard:PageDown", "mouse:Button4"]),
+    ("reader.page.previous", &["keyboard:ArrowLeft", "keyboard:PageUp", "mouse:Button3"]),
+    ("reader.page.first", &["keyboard:Home"]),
+    ("reader.page.last", &["keyboard:End"]),
+    ("reader.page.jump", &["keyboard:Ctrl+KeyG", "keyboard:Meta+KeyG"]),
+    ("reader.layout.single", &["keyboard:KeyS"]),
+    ("reader.layout.double", &["keyboard:KeyD"]),
+    ("reader.layout.vertical", &["keyboard:KeyC"]),
+    ("reader.layout.toggle-direction", &["keyboard:KeyL"]),
+    ("reader.fit.original", &["keyboard:Digit1"]),
+    ("reader.fit.width", &["keyboard:Digit2"]),
+    ("reader.fit.height", &["keyboard:Digit3"]),
+    ("reader.fit.contain", &["keyboard:Digit0"]),
+    ("reader.zoom.in", &["keyboard:Equal", "keyboard:NumpadAdd"]),
+    ("reader.zoom.out", &["keyboard:Minus", "keyboard:NumpadSubtract"]),
+    ("reader.zoom.reset", &["keyboard:Digit9"]),
+    ("reader.rotate.cw", &["keyboard:KeyR"]),
+    ("reader.rotate.ccw", &["keyboard:Shift+KeyR"]),
+    ("reader.rotate.reset", &["keyboard:Ctrl+KeyR"]),
+    ("reader.command.palette", &["keyboard:Ctrl+KeyK", "keyboard:Meta+KeyK"]),
+    ("reader.settings.open", &["keyboard:Ctrl+Comma", "keyboard:Meta+Comma"]),
+    ("reader.library.open", &["keyboard:Ctrl+KeyO", "keyboard:Meta+KeyO"]),
+    ("reader.fullscreen.toggle", &["keyboard:KeyF"]),
+    ("reader.fullscreen.immersive", &["keyboard:Shift+KeyF"]),
+    ("reader.bookmark.toggle", &["keyboard:KeyB"]),
+];
+
+/// Default gestures for `action`, or `None` for an unknown action.
+pub fn defaults_for(action: &str) -> Option<Vec<InputGesture>> {
+    DEFAULT_BINDINGS
+        .iter()
+        .find(|(id, _)| *id == action)
+        .map(|(_, gestures)| gestures.iter().filter_map(|gesture| parse_gesture(gesture)).collect())
+}
 
 pub fn default_layout() -> Result<Vec<(InputGesture, ActionId)>> {
-    Ok(Vec::new())
+    Ok(DEFAULT_BINDINGS
+        .iter()
+        .flat_map(|(action, gestures)| {
+            gestures
+                .iter()
+                .filter_map(|gesture| parse_gesture(gesture))
+                .map(|gesture| (gesture, ActionId(action.to_string())))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn defaults_are_canonical_and_unique() {
+        let layout = default_layout().unwrap();
+        let gestures: usize = DEFAULT_BINDINGS.iter().map(|(_, gestures)| gestures.len()).sum();
+        assert_eq!(layout.len(), gestures);
+        for (gesture, _) in &layout {
+            assert_eq!(parse_gesture(&gesture.0).as_ref(), Some(gesture));
+        }
+        let unique: HashSet<_> = layout.iter().map(|(gesture, _)| gesture).collect();
+        assert_eq!(unique.len(), layout.len());
+    }
 }
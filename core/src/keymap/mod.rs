@@ -1,5 +1,197 @@
 //! User-customisable keyboard and pointer shortcut mapping.
 
+pub mod gesture;
 pub mod layout;
 
+use thiserror::Error;
+
+use crate::types::{ActionId, InputGesture};
+
+pub use gesture::parse_gesture;
+
 pub type Result<T> = crate::Result<T>;
+
+/// A binding change that was refused.
+#[derive(Debug, Error)]
+pub enum KeymapError {
+    #[error("unknown action `{0}`")]
+    UnknownAction(String),
+    #[error("invalid gesture `{0}`")]
+    InvalidGesture(String),
+    #[error("`{gesture}` is already bound to `{action}`")]
+    Conflict { gesture: String, action: String },
+}
+
+/// Gestures bound to every action of [`layout::DEFAULT_BINDINGS`], in its order. A gesture is
+/// bound to at most one action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: Vec<(ActionId, Vec<InputGesture>)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = layout::DEFAULT_BINDINGS
+            .iter()
+            .map(|(action, _)| {
+                let defaults = layout::defaults_for(action).unwrap_or_default();
+                (ActionId(action.to_string()), defaults)
+            })
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    /// The defaults with `overrides` applied on top, each taking its gestures from whichever
+    /// action held them. Unknown actions and invalid gestures are skipped.
+    pub fn with_overrides<'a>(
+        overrides: impl IntoIterator<Item = (&'a str, &'a [String])>,
+    ) -> Self {
+        let mut keymap = Self::default();
+        for (action, gestures) in overrides {
+            let Some(index) = keymap.index_of(action) else {
+                continue;
+            };
+            let gestures: Vec<InputGesture> =
+                gestures.iter().filter_map(|gesture| parse_gesture(gesture)).collect();
+            for gesture in &gestures {
+                keymap.unbind(gesture);
+            }
+            keymap.bindings[index].1 = gestures;
+        }
+        keymap
+    }
+
+    /// Every action with its gestures.
+    pub fn bindings(&self) -> &[(ActionId, Vec<InputGesture>)] {
+        &self.bindings
+    }
+
+    /// Actions whose gestures differ from the defaults.
+    pub fn overrides(&self) -> impl Iterator<Item = (&ActionId, &[InputGesture])> {
+        self.bindings
+            .iter()
+            .filter(|(action, gestures)| layout::defaults_for(&action.0).as_ref() != Some(gestures))
+            .map(|(action, gestures)| (action, gestures.as_slice()))
+    }
+
+    /// Action bound to `gesture`, in any spelling [`parse_gesture`] accepts.
+    pub fn resolve(&self, gesture: &str) -> Option<&ActionId> {
+        let gesture = parse_gesture(gesture)?;
+        self.bindings
+            .iter()
+            .find(|(_, gestures)| gestures.contains(&gesture))
+            .map(|(action, _)| action)
+    }
+
+    /// Replace the gestures of `action`; an empty list leaves it unbound. Fails without
+    /// changing anything if a gesture is invalid or bound to another action.
+    pub fn set_binding(
+        &mut self,
+        action: &str,
+        gestures: &[String],
+    ) -> std::result::Result<(), KeymapError> {
+        let index =
+            self.index_of(action).ok_or_else(|| KeymapError::UnknownAction(action.to_string()))?;
+        let mut parsed: Vec<InputGesture> = Vec::with_capacity(gestures.len());
+        for raw in gestures {
+            let gesture =
+                parse_gesture(raw).ok_or_else(|| KeymapError::InvalidGesture(raw.clone()))?;
+            if let Some(owner) = self.resolve(&gesture.0).filter(|owner| owner.0 != action) {
+                return Err(KeymapError::Conflict { gesture: gesture.0, action: owner.0.clone() });
+            }
+            if !parsed.contains(&gesture) {
+                parsed.push(gesture);
+            }
+        }
+        self.bindings[index].1 = parsed;
+        Ok(())
+    }
+
+    /// Restore the defaults of `action`, or of every action when `None`. Gestures another
+    /// action was given in the meantime are taken back.
+    pub fn reset(&mut self, action: Option<&str>) -> std::result::Result<(), KeymapError> {
+        let Some(action) = action else {
+            *self = Self::default();
+            return Ok(());
+        };
+        let index =
+            self.index_of(action).ok_or_else(|| KeymapError::UnknownAction(action.to_string()))?;
+        let defaults = layout::defaults_for(action).unwrap_or_default();
+        for gesture in &defaults {
+            self.unbind(gesture);
+        }
+        self.bindings[index].1 = defaults;
+        Ok(())
+    }
+
+    fn index_of(&self, action: &str) -> Option<usize> {
+        self.bindings.iter().position(|(id, _)| id.0 == action)
+    }
+
+    fn unbind(&mut self, gesture: &InputGesture) {
+        for (_, gestures) in &mut self.bindings {
+            gestures.retain(|bound| bound != gesture);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_gestures_in_any_spelling() {
+        let keymap = Keymap::default();
+        assert_eq!(
+            keymap.resolve("keyboard:Cmd+KeyK").map(|a| a.0.as_str()),
+            Some("reader.command.palette")
+        );
+        assert_eq!(keymap.resolve("mouse:Button4").map(|a| a.0.as_str()), Some("reader.page.next"));
+        assert_eq!(keymap.resolve("keyboard:KeyZ"), None);
+        assert_eq!(keymap.overrides().count(), 0);
+    }
+
+    #[test]
+    fn refuses_conflicts_and_resets_defaults() {
+        let mut keymap = Keymap::default();
+        let err = keymap.set_binding("reader.page.next", &["keyboard:KeyB".into()]).unwrap_err();
+        assert!(
+            matches!(err, KeymapError::Conflict { ref action, .. } if action == "reader.bookmark.toggle")
+        );
+        assert!(matches!(
+            keymap.set_binding("reader.page.nope", &[]),
+            Err(KeymapError::UnknownAction(_))
+        ));
+
+        keymap.set_binding("reader.bookmark.toggle", &[]).unwrap();
+        keymap
+            .set_binding("reader.page.next", &["keyboard:KeyB".into(), " keyboard: KeyB ".into()])
+            .unwrap();
+        assert_eq!(keymap.resolve("keyboard:KeyB").map(|a| a.0.as_str()), Some("reader.page.next"));
+        assert_eq!(keymap.overrides().count(), 2);
+
+        // The bookmark default is taken back from the next-page action.
+        keymap.reset(Some("reader.bookmark.toggle")).unwrap();
+        assert_eq!(
+            keymap.resolve("keyboard:KeyB").map(|a| a.0.as_str()),
+            Some("reader.bookmark.toggle")
+        );
+        keymap.reset(None).unwrap();
+        assert_eq!(keymap, Keymap::default());
+    }
+
+    #[test]
+    fn applies_stored_overrides_leniently() {
+        let next = vec!["keyboard:KeyN".to_string(), "keyboard:???".to_string()];
+        let stale = vec!["keyboard:KeyX".to_string()];
+        let keymap = Keymap::with_overrides([
+            ("reader.page.next", next.as_slice()),
+            ("reader.removed", stale.as_slice()),
+        ]);
+        let (_, gestures) = &keymap.bindings()[0];
+        assert_eq!(gestures, &[InputGesture("keyboard:KeyN".into())]);
+        assert_eq!(keymap.resolve("keyboard:KeyX"), None);
+    }
+}
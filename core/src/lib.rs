@@ -3,23 +3,32 @@
 #![deny(missing_debug_implementations)]
 
 pub mod cache;
+pub mod calendar;
+pub mod capabilities;
 pub mod codec;
+pub mod error;
 pub mod fs;
 pub mod keymap;
 pub mod log;
 pub mod meta;
 pub mod pipeline;
+pub mod shutdown;
 pub mod stats;
 pub mod store;
 pub mod types;
 
-pub type Result<T> = std::result::Result<T, anyhow::Error>;
+pub type Result<T> = std::result::Result<T, CoreError>;
+
+pub use error::CoreError;
 
 pub use types::{
     ActionId, AppState, ArchiveEntry, ArchiveKind, CacheBudget, FitMode, ImageDimensions, ImageKey,
-    InputGesture, PageId, PageMeta, PrefetchPolicy, RenderParams, SeriesMeta, Source, SourceId,
+    ImageVariant, InputGesture, PageId, PageLayout, PageMeta, PrefetchPolicy, ReadingDirection,
+    RenderParams, SeriesMeta, Source, SourceId,
 };
 
+pub use shutdown::shutdown;
+
 /// Returns the version of the core crate for telemetry and debugging.
 pub fn version() -> &'static str {
     env!("CARGO_PKG_VERSION")
@@ -41,6 +50,9 @@ mod tests {
         let meta = PageMeta {
             id: page,
             rel_path: std::path::PathBuf::from("0001.png"),
+            mime: "image/png".to_string(),
+            size_bytes: 0,
+            modified: None,
             width: 0,
             height: 0,
             is_double_spread: false,
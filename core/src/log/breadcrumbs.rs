@@ -0,0 +1,149 @@
+//! Bounded in-memory trail of recent events for bug reports.
+//!
+//! The breadcrumb layer has its own level, so recent activity is available even when the file
+//! sink is configured to drop it. Only the last [`BREADCRUMB_CAPACITY`] events are kept.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+
+use super::redact::{is_path_field, redact};
+
+/// Number of events kept in the breadcrumb ring.
+pub const BREADCRUMB_CAPACITY: usize = 500;
+
+/// One recorded event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Breadcrumb {
+    pub timestamp_ms: u64,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+    pub fields: BTreeMap<String, String>,
+}
+
+#[derive(Debug)]
+pub(super) struct BreadcrumbRing {
+    entries: Mutex<VecDeque<Breadcrumb>>,
+    capacity: usize,
+}
+
+impl BreadcrumbRing {
+    pub(super) fn new(capacity: usize) -> Self {
+        Self { entries: Mutex::new(VecDeque::with_capacity(capacity)), capacity }
+    }
+
+    fn push(&self, breadcrumb: Breadcrumb) {
+        let mut entries = self.entries.lock().expect("breadcrumb mutex poisoned");
+        while entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(breadcrumb);
+    }
+
+    /// Recorded events, oldest first.
+    pub(super) fn snapshot(&self) -> Vec<Breadcrumb> {
+        self.entries.lock().expect("breadcrumb mutex poisoned").iter().cloned().collect()
+    }
+}
+
+/// Layer that appends every event it sees to a [`BreadcrumbRing`].
+pub(super) struct BreadcrumbLayer {
+    ring: Arc<BreadcrumbRing>,
+    redact_paths: bool,
+}
+
+impl BreadcrumbLayer {
+    pub(super) fn new(ring: Arc<BreadcrumbRing>, redact_paths: bool) -> Self {
+        Self { ring, redact_paths }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for BreadcrumbLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor {
+            message: String::new(),
+            fields: BTreeMap::new(),
+            redact_paths: self.redact_paths,
+        };
+        event.record(&mut visitor);
+        let metadata = event.metadata();
+        self.ring.push(Breadcrumb {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            level: metadata.level().to_string(),
+            target: metadata.target().to_string(),
+            message: visitor.message,
+            fields: visitor.fields,
+        });
+    }
+}
+
+struct FieldVisitor {
+    message: String,
+    fields: BTreeMap<String, String>,
+    redact_paths: bool,
+}
+
+impl FieldVisitor {
+    fn insert(&mut self, field: &Field, value: String) {
+        let value =
+            if self.redact_paths && is_path_field(field.name()) { redact(&value) } else { value };
+        self.fields.insert(field.name().to_string(), value);
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.insert(field, value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.insert(field, format!("{value:?}"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn keeps_the_latest_events_with_fields() {
+        let ring = Arc::new(BreadcrumbRing::new(3));
+        let subscriber =
+            tracing_subscriber::registry().with(BreadcrumbLayer::new(Arc::clone(&ring), true));
+
+        tracing::subscriber::with_default(subscriber, || {
+            for page_index in 0..5 {
+                tracing::info!(target: "commands::progress", page_index, "progress saved");
+            }
+            tracing::error!(path = "/comics/private.cbz", "failed to open");
+        });
+
+        let breadcrumbs = ring.snapshot();
+        assert_eq!(breadcrumbs.len(), 3);
+        assert_eq!(breadcrumbs[0].fields["page_index"], "3");
+        assert_eq!(breadcrumbs[1].target, "commands::progress");
+        assert_eq!(breadcrumbs[2].level, "ERROR");
+        assert_eq!(breadcrumbs[2].message, "failed to open");
+        assert!(breadcrumbs[2].fields["path"].starts_with("<redacted:"));
+    }
+}
@@ -0,0 +1,36 @@
+//! One-shot bundle of the state worth attaching to a bug report.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use super::Breadcrumb;
+use crate::stats::{PerfSnapshot, StatsCollector};
+
+/// Breadcrumbs, performance snapshot and build information collected at one point in time.
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostics {
+    /// Version of the core crate.
+    pub version: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub collected_at_ms: u64,
+    pub perf: PerfSnapshot,
+    /// Recent events, oldest first; empty if logging was not initialised.
+    pub breadcrumbs: Vec<Breadcrumb>,
+}
+
+/// Collect a [`Diagnostics`] bundle from the installed logger and `stats`.
+pub fn collect_diagnostics(stats: &StatsCollector) -> Diagnostics {
+    Diagnostics {
+        version: crate::version(),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        collected_at_ms: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64,
+        perf: stats.snapshot(),
+        breadcrumbs: super::handle().map(super::LogHandle::breadcrumbs).unwrap_or_default(),
+    }
+}
@@ -4,19 +4,33 @@
 //! application keeps a persistent, low-noise diagnostic trail. The public `init` function is
 //! intended to be called once on startup (typically from the Tauri shell) and is safe to call
 //! multiple times—subsequent calls simply return the already-installed logger handle.
+//!
+//! Events and spans use the same field names for the same concepts so either output format can
+//! be filtered consistently: `source_id`, `page_index` and `request_id`.
 
 use std::cmp::Ordering;
 use std::ffi::OsStr;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
 use std::time::SystemTime;
 
 use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use tracing::Subscriber;
+use tracing_subscriber::Layer;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::fmt::format::{DefaultFields, FmtSpan, Format, Json, JsonFields};
 use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::{EnvFilter, filter::LevelFilter, util::SubscriberInitExt};
+use tracing_subscriber::{Registry, reload};
 
 const DEFAULT_ENV_FILTER_VARS: [&str; 2] = ["LOCAL_COMIC_READER_LOG", "RUST_LOG"];
+/// Selects the default file record format: `json` for [`LogFormat::JsonLines`], otherwise text.
+const LOG_FORMAT_VAR: &str = "LOCAL_COMIC_READER_LOG_FORMAT";
+/// Enables [`LogConfig::redact_paths`] when set to `1` or `true`.
+const LOG_REDACT_VAR: &str = "LOCAL_COMIC_READER_LOG_REDACT";
 
 /// Global log handle stored after the first successful initialisation.
 static LOG_HANDLE: OnceLock<LogHandle> = OnceLock::new();
@@ -24,6 +38,19 @@ static LOG_HANDLE: OnceLock<LogHandle> = OnceLock::new();
 /// Re-export of the level filter type to avoid leaking `tracing-subscriber` to callers.
 pub use tracing_subscriber::filter::LevelFilter as LogLevel;
 
+mod breadcrumbs;
+mod diagnostics;
+mod redact;
+mod request;
+mod tail;
+
+pub use breadcrumbs::{BREADCRUMB_CAPACITY, Breadcrumb};
+use breadcrumbs::{BreadcrumbLayer, BreadcrumbRing};
+pub use diagnostics::{Diagnostics, collect_diagnostics};
+use redact::{RedactJsonWriter, RedactPaths};
+pub use request::RequestId;
+pub use tail::{active_log_file, tail};
+
 /// Controls how the log file rolling behaviour works.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum LogRolling {
@@ -45,6 +72,17 @@ impl LogRolling {
     }
 }
 
+/// Record format of the file sink.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line with event fields at the top level, plus the current span and
+    /// the span stack, for ingestion by external tools.
+    JsonLines,
+}
+
 /// Configuration for the logging system.
 #[derive(Debug, Clone)]
 pub struct LogConfig {
@@ -64,6 +102,13 @@ pub struct LogConfig {
     pub env_filter: Option<String>,
     /// Rolling strategy used for the file sink.
     pub rolling: LogRolling,
+    /// Record format of the file sink.
+    pub file_format: LogFormat,
+    /// Replace path fields in the log file with a hash, depth and extension so logs can be
+    /// shared without revealing library contents.
+    pub redact_paths: bool,
+    /// Minimum level of events kept in the in-memory breadcrumb ring, independent of the sinks.
+    pub breadcrumb_level: LevelFilter,
 }
 
 impl Default for LogConfig {
@@ -90,6 +135,12 @@ impl Default for LogConfig {
             capture_log: true,
             env_filter,
             rolling: LogRolling::Daily,
+            file_format: match std::env::var(LOG_FORMAT_VAR).as_deref() {
+                Ok("json" | "jsonl" | "json-lines") => LogFormat::JsonLines,
+                _ => LogFormat::Text,
+            },
+            breadcrumb_level: LevelFilter::INFO,
+            redact_paths: matches!(std::env::var(LOG_REDACT_VAR).as_deref(), Ok("1" | "true")),
         }
     }
 }
@@ -106,14 +157,28 @@ impl LogConfig {
         self.file_prefix = prefix.into();
         self
     }
+
+    /// Convenience helper for choosing the file record format.
+    pub fn with_file_format(mut self, format: LogFormat) -> Self {
+        self.file_format = format;
+        self
+    }
+
+    /// Convenience helper for enabling path redaction in the log file.
+    pub fn with_path_redaction(mut self, redact: bool) -> Self {
+        self.redact_paths = redact;
+        self
+    }
 }
 
 /// Handle returned from [`init`] that owns the background logging worker.
 #[derive(Debug)]
 pub struct LogHandle {
-    _guard: tracing_appender::non_blocking::WorkerGuard,
+    guard: Mutex<Option<tracing_appender::non_blocking::WorkerGuard>>,
     directory: PathBuf,
     file_prefix: String,
+    filter: reload::Handle<EnvFilter, Registry>,
+    breadcrumbs: Arc<BreadcrumbRing>,
 }
 
 impl LogHandle {
@@ -126,6 +191,57 @@ impl LogHandle {
     pub fn file_prefix(&self) -> &str {
         &self.file_prefix
     }
+
+    /// Replace the filter directive of the running logger; `None` restores the default.
+    pub fn set_filter(&self, directive: Option<&str>) -> Result<()> {
+        let directive = directive.map_or_else(default_directive, str::to_string);
+        let filter = EnvFilter::try_new(&directive).context("parsing env filter directive")?;
+        self.filter.reload(filter).context("replacing log filter")?;
+        tracing::info!(target: "log", %directive, "log filter changed");
+        Ok(())
+    }
+
+    /// Write out buffered file records and stop the background writer. The handle lives in a
+    /// static that is never dropped, so call this before the process exits; later events only
+    /// reach the console.
+    pub fn flush(&self) {
+        drop(self.guard.lock().take());
+    }
+
+    /// The most recent events at or above [`LogConfig::breadcrumb_level`], oldest first.
+    pub fn breadcrumbs(&self) -> Vec<Breadcrumb> {
+        self.breadcrumbs.snapshot()
+    }
+
+    /// The last `count` lines of the active log file, oldest first; see [`tail`].
+    pub fn tail(&self, count: usize, max_level: Option<LevelFilter>) -> Result<Vec<String>> {
+        match active_log_file(&self.directory, &self.file_prefix)? {
+            Some(path) => tail(&path, count, max_level),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+/// Check that `directive` is a valid filter (e.g. `info,reader_core::pipeline=trace`).
+pub fn validate_filter(directive: &str) -> Result<()> {
+    EnvFilter::try_new(directive).map(drop).context("parsing env filter directive")
+}
+
+/// Whether a filter directive was given through the environment, which takes precedence over
+/// [`LogConfig::env_filter`] values from other sources.
+pub fn filter_from_env() -> bool {
+    DEFAULT_ENV_FILTER_VARS
+        .iter()
+        .any(|var| std::env::var(var).is_ok_and(|directive| !directive.trim().is_empty()))
+}
+
+fn default_directive() -> String {
+    if cfg!(debug_assertions) { "debug" } else { "info" }.to_string()
+}
+
+/// The handle installed by [`init`], if logging has been initialised.
+pub fn handle() -> Option<&'static LogHandle> {
+    LOG_HANDLE.get()
 }
 
 /// Initialise the global logging subscriber.
@@ -169,17 +285,18 @@ fn setup(config: LogConfig) -> Result<LogHandle> {
         .env_filter
         .or_else(|| DEFAULT_ENV_FILTER_VARS.iter().find_map(|var| std::env::var(var).ok()))
         .filter(|directive| !directive.trim().is_empty())
-        .unwrap_or_else(|| if cfg!(debug_assertions) { "debug" } else { "info" }.to_string());
+        .unwrap_or_else(default_directive);
 
     let env_filter = EnvFilter::try_new(directive).context("parsing env filter directive")?;
+    let (env_filter, filter) = reload::Layer::new(env_filter);
 
-    let file_layer = tracing_subscriber::fmt::layer()
-        .with_ansi(false)
-        .with_writer(file_writer)
-        .with_file(true)
-        .with_line_number(true)
+    let file_layer = file_layer(file_writer, config.file_format, config.redact_paths)
         .with_filter(config.file_level);
 
+    let breadcrumbs = Arc::new(BreadcrumbRing::new(BREADCRUMB_CAPACITY));
+    let breadcrumb_layer = BreadcrumbLayer::new(Arc::clone(&breadcrumbs), config.redact_paths)
+        .with_filter(config.breadcrumb_level);
+
     let console_layer = tracing_subscriber::fmt::layer()
         .with_writer(std::io::stderr)
         .with_filter(config.console_level);
@@ -188,10 +305,52 @@ fn setup(config: LogConfig) -> Result<LogHandle> {
         .with(env_filter)
         .with(file_layer)
         .with(console_layer)
+        .with(breadcrumb_layer)
         .try_init()
         .map_err(|err| anyhow::anyhow!(err))?;
 
-    Ok(LogHandle { _guard: guard, directory: config.directory, file_prefix: config.file_prefix })
+    Ok(LogHandle {
+        guard: Mutex::new(Some(guard)),
+        directory: config.directory,
+        file_prefix: config.file_prefix,
+        filter,
+        breadcrumbs,
+    })
+}
+
+fn file_layer<S, W>(writer: W, format: LogFormat, redact: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    match (format, redact) {
+        (LogFormat::Text, false) => text_layer(writer).boxed(),
+        (LogFormat::Text, true) => text_layer(writer).fmt_fields(RedactPaths).boxed(),
+        (LogFormat::JsonLines, false) => json_layer(writer).boxed(),
+        (LogFormat::JsonLines, true) => json_layer(RedactJsonWriter(writer)).boxed(),
+    }
+}
+
+fn text_layer<S, W>(writer: W) -> tracing_subscriber::fmt::Layer<S, DefaultFields, Format, W>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> MakeWriter<'writer> + 'static,
+{
+    tracing_subscriber::fmt::layer()
+        .with_ansi(false)
+        .with_writer(writer)
+        .with_file(true)
+        .with_line_number(true)
+        // Log each span's busy/idle time when it closes so slow requests can be reconstructed.
+        .with_span_events(FmtSpan::CLOSE)
+}
+
+fn json_layer<S, W>(writer: W) -> tracing_subscriber::fmt::Layer<S, JsonFields, Format<Json>, W>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    W: for<'writer> MakeWriter<'writer> + 'static,
+{
+    text_layer(writer).json().flatten_event(true).with_current_span(true).with_span_list(true)
 }
 
 fn install_log_tracer(file_level: LevelFilter, console_level: LevelFilter) -> Result<()> {
@@ -272,4 +431,68 @@ mod tests {
         let second = init(config).expect("init twice");
         assert!(std::ptr::eq(first, second));
     }
+
+    #[test]
+    fn json_lines_carry_event_and_span_fields() {
+        use std::sync::{Arc, Mutex};
+
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&buffer);
+        let writer = move || SharedWriter(Arc::clone(&sink));
+        let subscriber =
+            tracing_subscriber::registry().with(file_layer(writer, LogFormat::JsonLines, false));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("page_fetch", request_id = "0000002a").entered();
+            tracing::info!(source_id = "src-1", page_index = 3, "served page");
+        });
+
+        let output = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("one object per line"))
+            .collect();
+        let event = lines.iter().find(|line| line["message"] == "served page").unwrap();
+        assert_eq!(event["source_id"], "src-1");
+        assert_eq!(event["page_index"], 3);
+        assert_eq!(event["span"]["request_id"], "0000002a");
+        assert!(lines.iter().any(|line| line["message"] == "close"), "span timings are logged");
+    }
+
+    #[test]
+    fn redaction_hides_path_fields_in_both_formats() {
+        use std::sync::{Arc, Mutex};
+
+        for format in [LogFormat::Text, LogFormat::JsonLines] {
+            let buffer = Arc::new(Mutex::new(Vec::new()));
+            let sink = Arc::clone(&buffer);
+            let writer = move || SharedWriter(Arc::clone(&sink));
+            let subscriber = tracing_subscriber::registry().with(file_layer(writer, format, true));
+
+            tracing::subscriber::with_default(subscriber, || {
+                let path = Path::new("/home/reader/Comics/Secret Series/vol 01.CBZ");
+                let _span = tracing::info_span!("open", dir = ?path.parent().unwrap()).entered();
+                tracing::info!(path = %path.display(), source_id = "src-1", "opened");
+            });
+
+            let output = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+            assert!(!output.contains("Secret Series"), "{output}");
+            assert!(output.contains("depth=5 ext=cbz>"), "{output}");
+            assert!(output.contains("depth=4>"), "{output}");
+            assert!(output.contains("src-1"), "{output}");
+        }
+    }
+
+    struct SharedWriter(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedWriter {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
 }
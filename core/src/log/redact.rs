@@ -0,0 +1,164 @@
+//! Optional redaction of file paths before records reach the log file.
+//!
+//! Path fields (`path`, `dir` and names ending in `_path` or `_dir`) are replaced with a short
+//! hash of the full path plus its depth and extension, e.g. `<redacted:1f0c9a3e depth=4 ext=cbz>`.
+//! The same path always maps to the same hash, so one item can still be followed through a log
+//! without revealing library contents. Free-form message text is left untouched.
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+use std::path::{Component, Path};
+
+use serde_json::Value;
+use tracing::field::{Field, Visit};
+use tracing_subscriber::field::{MakeVisitor, VisitFmt, VisitOutput};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::fmt::format::{DefaultVisitor, Writer};
+
+/// Whether a field with this name carries a file system path.
+pub(super) fn is_path_field(name: &str) -> bool {
+    matches!(name, "path" | "dir") || name.ends_with("_path") || name.ends_with("_dir")
+}
+
+/// Replacement for `path` that keeps its hash, depth and extension.
+///
+/// Paths recorded with `?path` arrive as escaped, quoted strings and are unquoted first so both
+/// spellings of a path hash the same.
+pub(super) fn redact(path: &str) -> String {
+    if path.is_empty() {
+        return String::new();
+    }
+    let unquoted = path.strip_prefix('"').and_then(|path| path.strip_suffix('"'));
+    let path = match unquoted {
+        Some(quoted) => quoted.replace("\\\\", "\\"),
+        None => path.to_string(),
+    };
+    let hash = blake3::hash(path.as_bytes()).to_hex();
+    let path = Path::new(&path);
+    let depth =
+        path.components().filter(|component| matches!(component, Component::Normal(_))).count();
+    let mut redacted = format!("<redacted:{} depth={depth}", &hash[..8]);
+    if let Some(ext) = path.extension().and_then(OsStr::to_str) {
+        redacted.push_str(" ext=");
+        redacted.push_str(&ext.to_ascii_lowercase());
+    }
+    redacted.push('>');
+    redacted
+}
+
+/// Field formatter for the text format that redacts path fields of events and spans.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct RedactPaths;
+
+impl<'writer> MakeVisitor<Writer<'writer>> for RedactPaths {
+    type Visitor = RedactVisitor<'writer>;
+
+    fn make_visitor(&self, target: Writer<'writer>) -> Self::Visitor {
+        RedactVisitor(DefaultVisitor::new(target, true))
+    }
+}
+
+pub(super) struct RedactVisitor<'writer>(DefaultVisitor<'writer>);
+
+impl Visit for RedactVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if is_path_field(field.name()) {
+            self.0.record_str(field, &redact(value));
+        } else {
+            self.0.record_str(field, value);
+        }
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.0.record_error(field, value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !is_path_field(field.name()) {
+            self.0.record_debug(field, value);
+            return;
+        }
+        self.0.record_str(field, &redact(&format!("{value:?}")));
+    }
+}
+
+impl VisitOutput<fmt::Result> for RedactVisitor<'_> {
+    fn finish(self) -> fmt::Result {
+        self.0.finish()
+    }
+}
+
+impl VisitFmt for RedactVisitor<'_> {
+    fn writer(&mut self) -> &mut dyn fmt::Write {
+        self.0.writer()
+    }
+}
+
+/// Writer for the JSON-lines format that redacts path fields of each record, including those of
+/// the spans the record carries.
+#[derive(Debug)]
+pub(super) struct RedactJsonWriter<W>(pub(super) W);
+
+impl<'a, W: MakeWriter<'a>> MakeWriter<'a> for RedactJsonWriter<W> {
+    type Writer = RedactJsonWrite<W::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactJsonWrite(self.0.make_writer())
+    }
+}
+
+pub(super) struct RedactJsonWrite<W>(W);
+
+impl<W: io::Write> io::Write for RedactJsonWrite<W> {
+    // The formatter hands over one complete record per write.
+    fn write(&mut self, record: &[u8]) -> io::Result<usize> {
+        match redact_json_record(record) {
+            Some(redacted) => self.0.write_all(&redacted)?,
+            None => self.0.write_all(record)?,
+        }
+        Ok(record.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// The record with path fields redacted, or `None` if it has none (or is not JSON).
+fn redact_json_record(record: &[u8]) -> Option<Vec<u8>> {
+    let mut value: Value = serde_json::from_slice(record).ok()?;
+    if !redact_value(&mut value) {
+        return None;
+    }
+    let mut redacted = serde_json::to_vec(&value).ok()?;
+    redacted.push(b'\n');
+    Some(redacted)
+}
+
+fn redact_value(value: &mut Value) -> bool {
+    match value {
+        Value::Object(fields) => {
+            let mut changed = false;
+            for (name, value) in fields.iter_mut() {
+                if is_path_field(name)
+                    && let Value::String(path) = value
+                {
+                    *path = redact(path);
+                    changed = true;
+                } else {
+                    changed |= redact_value(value);
+                }
+            }
+            changed
+        }
+        Value::Array(values) => {
+            let mut changed = false;
+            for value in values {
+                changed |= redact_value(value);
+            }
+            changed
+        }
+        _ => false,
+    }
+}
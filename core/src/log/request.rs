@@ -0,0 +1,52 @@
+//! Identifiers correlating the log output of one request across components.
+//!
+//! A [`RequestId`] is attached as a field to the root span of a request (e.g. a page fetch).
+//! Work done on its behalf runs in child spans, and components that handle a later leg of the
+//! same request in another context (such as the protocol handler serving the fetched page) are
+//! handed the id explicitly and open their own span with it.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Process-unique identifier of a request, rendered as lowercase hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestId(u64);
+
+impl RequestId {
+    /// Allocate a new id.
+    pub fn next() -> Self {
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Parse an id previously rendered with `Display`.
+    pub fn parse(value: &str) -> Option<Self> {
+        u64::from_str_radix(value, 16).ok().map(Self)
+    }
+
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_unique_and_round_trip() {
+        let first = RequestId::next();
+        let second = RequestId::next();
+        assert_ne!(first, second);
+        assert_eq!(RequestId::parse(&first.to_string()), Some(first));
+        assert_eq!(first.to_string().len(), 8);
+        assert_eq!(RequestId::parse("not-hex"), None);
+    }
+}
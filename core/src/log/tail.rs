@@ -0,0 +1,142 @@
+//! Reading the end of the log files for the in-app log viewer.
+
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use tracing::Level;
+use tracing_subscriber::filter::LevelFilter;
+
+use super::matches_prefix;
+
+const CHUNK_SIZE: u64 = 64 * 1024;
+const LEVELS: [(&str, Level); 5] = [
+    ("ERROR", Level::ERROR),
+    ("WARN", Level::WARN),
+    ("INFO", Level::INFO),
+    ("DEBUG", Level::DEBUG),
+    ("TRACE", Level::TRACE),
+];
+
+/// The most recently modified log file in `dir` whose name starts with `prefix`.
+pub fn active_log_file(dir: &Path, prefix: &str) -> Result<Option<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).with_context(|| format!("reading log directory at {}", dir.display()));
+        }
+    };
+    Ok(entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.metadata().map(|meta| meta.is_file()).unwrap_or(false))
+        .filter(|entry| matches_prefix(&entry.path(), prefix))
+        .max_by_key(|entry| {
+            entry.metadata().and_then(|meta| meta.modified()).unwrap_or(SystemTime::UNIX_EPOCH)
+        })
+        .map(|entry| entry.path()))
+}
+
+/// The last `count` lines of `path`, oldest first.
+///
+/// With `max_level`, only lines of that level or more severe are returned; lines without a
+/// recognisable level (such as wrapped continuation lines) are then skipped too. The file is
+/// read backwards in chunks, so the cost depends on how far back the lines are, not on the size
+/// of the file.
+pub fn tail(path: &Path, count: usize, max_level: Option<LevelFilter>) -> Result<Vec<String>> {
+    let mut file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut position = file.metadata()?.len();
+    let mut lines = Vec::new();
+    // Bytes of the line that straddles the previously read chunk boundary.
+    let mut carry = Vec::new();
+
+    while lines.len() < count && position > 0 {
+        let read = CHUNK_SIZE.min(position);
+        position -= read;
+        file.seek(SeekFrom::Start(position))?;
+        let mut chunk = vec![0; read as usize];
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&carry);
+
+        let mut segments: Vec<&[u8]> = chunk.split(|&byte| byte == b'\n').collect();
+        // Unless at the start of the file, the first segment may be a partial line.
+        carry = if position > 0 { segments.remove(0).to_vec() } else { Vec::new() };
+        for segment in segments.into_iter().rev() {
+            push_line(&mut lines, segment, max_level);
+            if lines.len() == count {
+                break;
+            }
+        }
+    }
+    if lines.len() < count && !carry.is_empty() {
+        push_line(&mut lines, &carry, max_level);
+    }
+
+    lines.reverse();
+    Ok(lines)
+}
+
+fn push_line(lines: &mut Vec<String>, segment: &[u8], max_level: Option<LevelFilter>) {
+    let line = String::from_utf8_lossy(segment);
+    let line = line.trim_end_matches('\r');
+    if line.is_empty() {
+        return;
+    }
+    if let Some(max_level) = max_level
+        && line_level(line).is_none_or(|level| level > max_level)
+    {
+        return;
+    }
+    lines.push(line.to_string());
+}
+
+/// Level of a text (`<timestamp>  INFO target: ...`) or JSON-lines (`"level":"INFO"`) record.
+fn line_level(line: &str) -> Option<Level> {
+    if line.starts_with('{') {
+        let value = line.split_once("\"level\":\"")?.1;
+        let name = value.split('"').next()?;
+        return LEVELS.iter().find(|(label, _)| *label == name).map(|(_, level)| *level);
+    }
+    line.split_whitespace()
+        .take(3)
+        .find_map(|token| LEVELS.iter().find(|(label, _)| *label == token).map(|(_, level)| *level))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Write as _;
+
+    #[test]
+    fn reads_last_lines_across_chunks_with_level_filter() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("reader.2024-03-01.log");
+        let mut text = String::new();
+        for index in 0..5_000 {
+            let level = if index % 10 == 0 { "WARN" } else { "DEBUG" };
+            let _ = writeln!(
+                text,
+                "2024-03-01T00:00:00Z {level:>5} reader: line {index:05} {}",
+                "x".repeat(20)
+            );
+        }
+        text.push_str("{\"timestamp\":\"t\",\"level\":\"ERROR\",\"message\":\"json\"}\n");
+        fs::write(&path, &text).unwrap();
+
+        let last = tail(&path, 3, None).unwrap();
+        assert_eq!(last.len(), 3);
+        assert!(last[0].contains("line 04998"));
+        assert!(last[2].contains("\"json\""));
+
+        let warnings = tail(&path, 400, Some(LevelFilter::WARN)).unwrap();
+        assert_eq!(warnings.len(), 400);
+        assert!(warnings[0].contains("line 01010"), "{}", warnings[0]);
+        assert!(warnings.iter().all(|line| !line.contains("DEBUG")));
+
+        assert_eq!(tail(&path, 10_000, None).unwrap().len(), 5_001);
+        assert_eq!(active_log_file(temp.path(), "reader").unwrap(), Some(path));
+        assert_eq!(active_log_file(temp.path(), "other").unwrap(), None);
+    }
+}
@@ -0,0 +1,439 @@
+//! Structured page descriptions for screen readers.
+//!
+//! [`Describers`] runs a list of [`PageDescriber`]s over a decoded page, each filling in what it
+//! knows: the built-in [`PanelCounter`] counts panels from the gutters between them, and an OCR
+//! engine can plug in to add the text of the page. The resulting [`PageDescription`] gives
+//! assistive technology something better to announce than "image".
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::codec::DecodedImage;
+use crate::types::PageMeta;
+
+use super::Result;
+
+/// Longest text snippet kept in a description, in characters.
+pub const MAX_TEXT_SNIPPET: usize = 280;
+
+/// What is known about a page, for assistive technology.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageDescription {
+    /// Position of the page in its source, from 1.
+    pub page_number: u32,
+    pub page_count: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Whether the page is a two-page spread, as listed or because it is wider than tall.
+    pub is_double_spread: bool,
+    /// Panels detected on the page, if a describer counted them.
+    pub panel_count: Option<u32>,
+    /// Text on the page, shortened to [`MAX_TEXT_SNIPPET`] characters, if a describer read it.
+    pub text: Option<String>,
+}
+
+impl fmt::Display for PageDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Page {} of {}", self.page_number, self.page_count)?;
+        if self.is_double_spread {
+            f.write_str(", double-page spread")?;
+        }
+        match self.panel_count {
+            Some(1) => f.write_str(", 1 panel")?,
+            Some(panels) => write!(f, ", {panels} panels")?,
+            None => {}
+        }
+        if let Some(text) = &self.text {
+            write!(f, ". Text: {text}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds what it can tell about a page to its description.
+pub trait PageDescriber: fmt::Debug + Send + Sync {
+    /// Short name, for logs.
+    fn name(&self) -> &str;
+
+    /// Fill in `description` of `page` from its decoded `image`. Fields set by describers that
+    /// ran before may be overwritten.
+    fn describe(
+        &self,
+        page: &PageMeta,
+        image: &DecodedImage,
+        description: &mut PageDescription,
+    ) -> Result<()>;
+}
+
+/// The describers pages are described with, run in the order they were registered.
+#[derive(Debug, Clone, Default)]
+pub struct Describers {
+    describers: Vec<Arc<dyn PageDescriber>>,
+}
+
+impl Describers {
+    /// No describers; descriptions only carry what the page listing knows.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The describers of this build: the [`PanelCounter`].
+    pub fn with_builtin() -> Self {
+        let mut describers = Self::new();
+        describers.register(PanelCounter);
+        describers
+    }
+
+    /// Add `describer`, running after the ones registered before it.
+    pub fn register(&mut self, describer: impl PageDescriber + 'static) {
+        self.describers.push(Arc::new(describer));
+    }
+
+    pub fn len(&self) -> usize {
+        self.describers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.describers.is_empty()
+    }
+
+    /// Describe `page`, one of `page_count` pages of its source, from its decoded `image`.
+    ///
+    /// Descriptions are best effort: a failing describer is logged and leaves the fields it
+    /// would have set to the others.
+    pub fn describe(
+        &self,
+        page: &PageMeta,
+        page_count: u32,
+        image: &DecodedImage,
+    ) -> PageDescription {
+        let mut description = PageDescription {
+            page_number: page.id.index + 1,
+            page_count,
+            width: image.width(),
+            height: image.height(),
+            is_double_spread: page.is_double_spread || image.width() > image.height(),
+            panel_count: None,
+            text: None,
+        };
+        for describer in &self.describers {
+            if let Err(err) = describer.describe(page, image, &mut description) {
+                tracing::warn!(
+                    target: "meta::describe",
+                    describer = describer.name(),
+                    path = ?page.rel_path,
+                    "page description failed: {err:#}"
+                );
+            }
+        }
+        description.text = description.text.as_deref().and_then(snippet);
+        description
+    }
+}
+
+/// `text` with its whitespace collapsed, shortened to [`MAX_TEXT_SNIPPET`] characters, or
+/// `None` if blank.
+fn snippet(text: &str) -> Option<String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let text = words.join(" ");
+    if text.is_empty() {
+        return None;
+    }
+    if text.chars().count() <= MAX_TEXT_SNIPPET {
+        return Some(text);
+    }
+    let mut short: String = text.chars().take(MAX_TEXT_SNIPPET - 1).collect();
+    short.truncate(short.trim_end().len());
+    short.push('…');
+    Some(short)
+}
+
+/// Counts the panels of a page by cutting it along its gutters.
+///
+/// The page is split recursively at rows and columns that are entirely background, the color
+/// of most of its border. Pieces too small to be panels are ignored. Borderless art or panels
+/// that overlap count as one, so the count is a lower bound on irregular layouts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PanelCounter;
+
+impl PageDescriber for PanelCounter {
+    fn name(&self) -> &str {
+        "panels"
+    }
+
+    fn describe(
+        &self,
+        _page: &PageMeta,
+        image: &DecodedImage,
+        description: &mut PageDescription,
+    ) -> Result<()> {
+        description.panel_count = Some(count_panels(image));
+        Ok(())
+    }
+}
+
+/// Longest side of the grid pages are analysed at.
+const GRID_LONGEST: usize = 400;
+/// Largest luma difference from the background still counted as background.
+const BACKGROUND_TOLERANCE: u8 = 24;
+/// Deepest nesting of cuts followed; real layouts rarely need more than three or four.
+const MAX_CUT_DEPTH: u32 = 8;
+
+/// Panels on `image`; see [`PanelCounter`].
+pub fn count_panels(image: &DecodedImage) -> u32 {
+    let Some(grid) = Grid::of(image) else { return 0 };
+    let whole = Rect { x0: 0, y0: 0, x1: grid.width, y1: grid.height };
+    grid.count(whole, 0)
+}
+
+/// A downsampled page marking which cells are background.
+struct Grid {
+    width: usize,
+    height: usize,
+    background: Vec<bool>,
+    /// Shortest run of background lines that separates panels.
+    min_gutter: usize,
+    min_width: usize,
+    min_height: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+}
+
+impl Grid {
+    fn of(image: &DecodedImage) -> Option<Self> {
+        let (image_width, image_height) = (image.width() as usize, image.height() as usize);
+        if image_width == 0
+            || image_height == 0
+            || image.pixels().len() < image_width * image_height * 4
+        {
+            return None;
+        }
+        let step = image_width.max(image_height).div_ceil(GRID_LONGEST);
+        let (width, height) = (image_width.div_ceil(step), image_height.div_ceil(step));
+        let mut luma = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let at = ((y * step) * image_width + x * step) * 4;
+                let pixel = &image.pixels()[at..at + 4];
+                // Transparent areas show the reader's backdrop, so treat them as paper.
+                let value = if pixel[3] < 128 {
+                    255
+                } else {
+                    let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(u32::from);
+                    ((r * 299 + g * 587 + b * 114) / 1000) as u8
+                };
+                luma.push(value);
+            }
+        }
+
+        let mut border: Vec<u8> = Vec::with_capacity(2 * (width + height));
+        for x in 0..width {
+            border.push(luma[x]);
+            border.push(luma[(height - 1) * width + x]);
+        }
+        for y in 0..height {
+            border.push(luma[y * width]);
+            border.push(luma[y * width + width - 1]);
+        }
+        border.sort_unstable();
+        let paper = border[border.len() / 2];
+
+        Some(Self {
+            width,
+            height,
+            background: luma
+                .iter()
+                .map(|value| value.abs_diff(paper) <= BACKGROUND_TOLERANCE)
+                .collect(),
+            min_gutter: (width.max(height) / 100).max(2),
+            min_width: (width / 20).max(2),
+            min_height: (height / 20).max(2),
+        })
+    }
+
+    /// Whether row `y` is background from `x0` to `x1`, allowing a stray cell on wide rows.
+    fn row_is_gutter(&self, y: usize, x0: usize, x1: usize) -> bool {
+        let row = &self.background[y * self.width + x0..y * self.width + x1];
+        row.iter().filter(|background| !**background).count() <= row.len() / GRID_LONGEST
+    }
+
+    fn column_is_gutter(&self, x: usize, y0: usize, y1: usize) -> bool {
+        let ink = (y0..y1).filter(|y| !self.background[y * self.width + x]).count();
+        ink <= (y1 - y0) / GRID_LONGEST
+    }
+
+    fn is_gutter(&self, rect: Rect, line: usize, horizontal: bool) -> bool {
+        if horizontal {
+            self.row_is_gutter(line, rect.x0, rect.x1)
+        } else {
+            self.column_is_gutter(line, rect.y0, rect.y1)
+        }
+    }
+
+    /// `rect` without the background lines along its edges, or `None` if it is all background.
+    fn trim(&self, mut rect: Rect) -> Option<Rect> {
+        while rect.y0 < rect.y1 && self.row_is_gutter(rect.y0, rect.x0, rect.x1) {
+            rect.y0 += 1;
+        }
+        while rect.y0 < rect.y1 && self.row_is_gutter(rect.y1 - 1, rect.x0, rect.x1) {
+            rect.y1 -= 1;
+        }
+        if rect.y0 == rect.y1 {
+            return None;
+        }
+        while rect.x0 < rect.x1 && self.column_is_gutter(rect.x0, rect.y0, rect.y1) {
+            rect.x0 += 1;
+        }
+        while rect.x0 < rect.x1 && self.column_is_gutter(rect.x1 - 1, rect.y0, rect.y1) {
+            rect.x1 -= 1;
+        }
+        (rect.x0 < rect.x1).then_some(rect)
+    }
+
+    /// The pieces of the trimmed `rect` between gutters running across it: rows when
+    /// `horizontal`, columns otherwise.
+    fn split(&self, rect: Rect, horizontal: bool) -> Vec<Rect> {
+        let (start, end) = if horizontal { (rect.y0, rect.y1) } else { (rect.x0, rect.x1) };
+        let piece = |from: usize, to: usize| {
+            if horizontal {
+                Rect { y0: from, y1: to, ..rect }
+            } else {
+                Rect { x0: from, x1: to, ..rect }
+            }
+        };
+        let mut pieces = Vec::new();
+        let (mut piece_start, mut gutter) = (start, 0);
+        for line in start..end {
+            if self.is_gutter(rect, line, horizontal) {
+                gutter += 1;
+                continue;
+            }
+            if gutter >= self.min_gutter {
+                pieces.push(piece(piece_start, line - gutter));
+                piece_start = line;
+            }
+            gutter = 0;
+        }
+        pieces.push(piece(piece_start, end));
+        pieces
+    }
+
+    fn count(&self, rect: Rect, depth: u32) -> u32 {
+        let Some(rect) = self.trim(rect) else { return 0 };
+        if rect.x1 - rect.x0 < self.min_width || rect.y1 - rect.y0 < self.min_height {
+            return 0;
+        }
+        if depth < MAX_CUT_DEPTH {
+            for horizontal in [true, false] {
+                let pieces = self.split(rect, horizontal);
+                if pieces.len() > 1 {
+                    return pieces.into_iter().map(|piece| self.count(piece, depth + 1)).sum();
+                }
+            }
+        }
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ImageDimensions, PageId, SourceId};
+    use std::path::PathBuf;
+
+    /// A white page with black-bordered white panels at the given `(x, y, width, height)`.
+    fn page_with_panels(width: u32, height: u32, panels: &[(u32, u32, u32, u32)]) -> DecodedImage {
+        let mut pixels = vec![255; (width * height * 4) as usize];
+        for &(px, py, pw, ph) in panels {
+            for y in py..py + ph {
+                for x in px..px + pw {
+                    let edge = x < px + 3 || x >= px + pw - 3 || y < py + 3 || y >= py + ph - 3;
+                    if edge {
+                        let at = ((y * width + x) * 4) as usize;
+                        pixels[at..at + 3].copy_from_slice(&[0, 0, 0]);
+                    }
+                }
+            }
+        }
+        DecodedImage { dimensions: ImageDimensions { width, height }, pixels }
+    }
+
+    fn meta(index: u32) -> PageMeta {
+        PageMeta {
+            id: PageId { source_id: SourceId::new("describe"), index },
+            rel_path: PathBuf::from(format!("{index}.png")),
+            mime: "image/png".to_string(),
+            size_bytes: 0,
+            modified: None,
+            width: 0,
+            height: 0,
+            is_double_spread: false,
+        }
+    }
+
+    #[test]
+    fn counts_panels_between_gutters() {
+        // A wide panel on top, two below it, and the right one split in two again.
+        let layout =
+            [(20, 20, 560, 300), (20, 340, 270, 440), (310, 340, 270, 200), (310, 560, 270, 220)];
+        assert_eq!(count_panels(&page_with_panels(600, 800, &layout)), 4);
+        assert_eq!(count_panels(&page_with_panels(600, 800, &layout[..1])), 1);
+        assert_eq!(count_panels(&page_with_panels(600, 800, &[])), 0);
+
+        // Full-bleed art: no row or column is a single color.
+        let mut pixels = Vec::new();
+        for y in 0..400u32 {
+            for x in 0..300u32 {
+                let value = ((x * 7 + y * 13) % 256) as u8;
+                pixels.extend_from_slice(&[value, value, value, 255]);
+            }
+        }
+        let splash =
+            DecodedImage { dimensions: ImageDimensions { width: 300, height: 400 }, pixels };
+        assert_eq!(count_panels(&splash), 1);
+    }
+
+    #[test]
+    fn describers_fill_in_and_shorten_descriptions() {
+        #[derive(Debug)]
+        struct Ocr(String);
+
+        impl PageDescriber for Ocr {
+            fn name(&self) -> &str {
+                "ocr"
+            }
+
+            fn describe(
+                &self,
+                _page: &PageMeta,
+                _image: &DecodedImage,
+                description: &mut PageDescription,
+            ) -> Result<()> {
+                description.text = Some(self.0.clone());
+                Ok(())
+            }
+        }
+
+        let image = page_with_panels(600, 800, &[(20, 20, 560, 760)]);
+        let mut describers = Describers::with_builtin();
+        describers.register(Ocr("  WHERE   is\neveryone?  ".to_string()));
+        let description = describers.describe(&meta(2), 24, &image);
+        assert_eq!((description.page_number, description.page_count), (3, 24));
+        assert_eq!(description.panel_count, Some(1));
+        assert_eq!(description.to_string(), "Page 3 of 24, 1 panel. Text: WHERE is everyone?");
+
+        let mut describers = Describers::new();
+        describers.register(Ocr("word ".repeat(100)));
+        let text = describers.describe(&meta(0), 1, &image).text.unwrap();
+        assert_eq!(text.chars().count(), MAX_TEXT_SNIPPET);
+        assert!(text.ends_with("word…"), "{text}");
+        assert_eq!(Describers::new().describe(&meta(0), 1, &image).panel_count, None);
+    }
+}
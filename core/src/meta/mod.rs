@@ -1,5 +1,6 @@
-//! Metadata parsing (ComicInfo.xml, directory hints, etc.).
+//! Metadata parsing (ComicInfo.xml, directory hints, etc.) and page descriptions.
 
 pub mod comicinfo;
+pub mod describe;
 
 pub type Result<T> = crate::Result<T>;
@@ -0,0 +1,251 @@
+//! Decode and resize benchmark over the pages of a source.
+//!
+//! [`run_benchmark`] reads, decodes and resizes pages on a configurable number of threads and
+//! reports throughput and per-stage timings, so codecs, filters and thread counts can be
+//! compared on the hardware the reader actually runs on.
+
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::codec::decode_primary;
+use crate::error::CoreError;
+use crate::types::PageMeta;
+
+use super::Result;
+use super::pool::WorkerPool;
+use super::render::thumbnail_dimensions;
+use super::resize::{ResizeFilter, ResizeSettings, resize_rgba};
+
+/// Longest side pages are resized to by default, about a full-screen page on a 1440p display.
+pub const DEFAULT_BENCH_LONGEST: u32 = 1440;
+
+/// What [`run_benchmark`] measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchConfig {
+    /// Threads working through the pages in parallel (at least one).
+    pub threads: usize,
+    /// Longest side of the resized pages; pages already smaller are resized to their own size.
+    pub longest: u32,
+    pub filter: ResizeFilter,
+    /// Benchmark only the first pages of the source.
+    pub max_pages: Option<usize>,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            threads: WorkerPool::default_threads(),
+            longest: DEFAULT_BENCH_LONGEST,
+            filter: ResizeFilter::default(),
+            max_pages: None,
+        }
+    }
+}
+
+/// Time spent in one stage over every benchmarked page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct StageTiming {
+    pub total_ms: f32,
+    pub mean_ms: f32,
+    pub max_ms: f32,
+}
+
+/// Outcome of [`run_benchmark`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BenchReport {
+    /// Pages read, decoded and resized.
+    pub pages: usize,
+    /// Pages that failed in any stage; they count towards no timing.
+    pub failed: usize,
+    pub threads: usize,
+    pub longest: u32,
+    pub filter: ResizeFilter,
+    pub bytes_read: u64,
+    /// Decoded pixels, in megapixels.
+    pub megapixels: f64,
+    pub wall_ms: f32,
+    pub pages_per_sec: f32,
+    pub megapixels_per_sec: f32,
+    pub read: StageTiming,
+    pub decode: StageTiming,
+    pub resize: StageTiming,
+}
+
+/// Per-stage durations accumulated by the threads.
+#[derive(Debug, Default)]
+struct Totals {
+    pages: usize,
+    failed: usize,
+    bytes_read: u64,
+    pixels: u64,
+    read: Vec<Duration>,
+    decode: Vec<Duration>,
+    resize: Vec<Duration>,
+}
+
+impl Totals {
+    fn merge(&mut self, other: Totals) {
+        self.pages += other.pages;
+        self.failed += other.failed;
+        self.bytes_read += other.bytes_read;
+        self.pixels += other.pixels;
+        self.read.extend(other.read);
+        self.decode.extend(other.decode);
+        self.resize.extend(other.resize);
+    }
+}
+
+/// Read `pages` with `read`, then decode and resize them as `config` says, timing every stage.
+///
+/// Pages failing any stage are logged and counted in [`BenchReport::failed`]; the benchmark
+/// itself only fails when its threads cannot be started.
+pub fn run_benchmark<E, F>(pages: &[PageMeta], read: F, config: BenchConfig) -> Result<BenchReport>
+where
+    E: fmt::Display,
+    F: Fn(&PageMeta) -> std::result::Result<Vec<u8>, E> + Sync,
+{
+    let pages = &pages[..config.max_pages.map_or(pages.len(), |max| max.min(pages.len()))];
+    let threads = config.threads.max(1);
+    let next = AtomicUsize::new(0);
+    let totals = Mutex::new(Totals::default());
+    let started = Instant::now();
+    std::thread::scope(|scope| -> Result<()> {
+        for index in 0..threads {
+            std::thread::Builder::new()
+                .name(format!("bench-{index}"))
+                .spawn_scoped(scope, || {
+                    let mut local = Totals::default();
+                    while let Some(page) = pages.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        if let Err(err) = bench_page(page, &read, &config, &mut local) {
+                            local.failed += 1;
+                            tracing::debug!(
+                                target: "pipeline::bench",
+                                path = ?page.rel_path,
+                                "benchmark page failed: {err}"
+                            );
+                        }
+                    }
+                    totals.lock().merge(local);
+                })
+                .map_err(CoreError::from)?;
+        }
+        Ok(())
+    })?;
+    let wall = started.elapsed();
+
+    let totals = totals.into_inner();
+    let megapixels = totals.pixels as f64 / 1_000_000.0;
+    let per_sec = |amount: f64| {
+        if wall.is_zero() { 0.0 } else { (amount / wall.as_secs_f64()) as f32 }
+    };
+    Ok(BenchReport {
+        pages: totals.pages,
+        failed: totals.failed,
+        threads,
+        longest: config.longest,
+        filter: config.filter,
+        bytes_read: totals.bytes_read,
+        megapixels,
+        wall_ms: millis(wall),
+        pages_per_sec: per_sec(totals.pages as f64),
+        megapixels_per_sec: per_sec(megapixels),
+        read: timing(&totals.read),
+        decode: timing(&totals.decode),
+        resize: timing(&totals.resize),
+    })
+}
+
+/// Run one page through every stage, recording the timings into `totals` once all succeed.
+fn bench_page<E: fmt::Display>(
+    page: &PageMeta,
+    read: &impl Fn(&PageMeta) -> std::result::Result<Vec<u8>, E>,
+    config: &BenchConfig,
+    totals: &mut Totals,
+) -> std::result::Result<(), String> {
+    let started = Instant::now();
+    let bytes = read(page).map_err(|err| format!("reading: {err}"))?;
+    let read_time = started.elapsed();
+
+    let started = Instant::now();
+    let decoded = decode_primary(page, &bytes).map_err(|err| format!("{err:#}"))?;
+    let decode_time = started.elapsed();
+
+    let started = Instant::now();
+    let target = thumbnail_dimensions(decoded.dimensions, config.longest);
+    resize_rgba(&decoded, ResizeSettings::new(target).filter(config.filter))
+        .map_err(|err| format!("{err:#}"))?;
+    let resize_time = started.elapsed();
+
+    totals.pages += 1;
+    totals.bytes_read += bytes.len() as u64;
+    totals.pixels += u64::from(decoded.width()) * u64::from(decoded.height());
+    totals.read.push(read_time);
+    totals.decode.push(decode_time);
+    totals.resize.push(resize_time);
+    Ok(())
+}
+
+fn timing(samples: &[Duration]) -> StageTiming {
+    if samples.is_empty() {
+        return StageTiming::default();
+    }
+    let total: Duration = samples.iter().sum();
+    StageTiming {
+        total_ms: millis(total),
+        mean_ms: millis(total) / samples.len() as f32,
+        max_ms: samples.iter().copied().max().map_or(0.0, millis),
+    }
+}
+
+fn millis(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{PageId, SourceId};
+    use image::{ImageFormat, Rgba, RgbaImage};
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    fn page(index: u32, name: &str) -> PageMeta {
+        PageMeta {
+            id: PageId { source_id: SourceId::new("bench"), index },
+            rel_path: PathBuf::from(name),
+            mime: "image/png".to_string(),
+            size_bytes: 0,
+            modified: None,
+            width: 0,
+            height: 0,
+            is_double_spread: false,
+        }
+    }
+
+    #[test]
+    fn times_every_stage_and_counts_failures() {
+        let mut png = Vec::new();
+        RgbaImage::from_pixel(64, 32, Rgba([200, 10, 10, 255]))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let pages: Vec<_> = (0..5).map(|index| page(index, &format!("{index}.png"))).collect();
+        let read = |meta: &PageMeta| {
+            if meta.id.index == 4 { Err("missing") } else { Ok(png.clone()) }
+        };
+
+        let config = BenchConfig { threads: 2, longest: 16, ..BenchConfig::default() };
+        let report = run_benchmark(&pages, read, config).unwrap();
+        assert_eq!((report.pages, report.failed, report.threads), (4, 1, 2));
+        assert_eq!(report.bytes_read, 4 * png.len() as u64);
+        assert!((report.megapixels - 4.0 * 64.0 * 32.0 / 1_000_000.0).abs() < 1e-9);
+        assert!(report.decode.total_ms >= report.decode.max_ms);
+        assert!(report.decode.max_ms >= report.decode.mean_ms);
+
+        let first = BenchConfig { max_pages: Some(2), ..config };
+        assert_eq!(run_benchmark(&pages, read, first).unwrap().pages, 2);
+    }
+}
@@ -0,0 +1,124 @@
+//! Sizing pages to the viewport for each [`FitMode`].
+
+use crate::types::{FitMode, ImageDimensions, RenderParams};
+
+use super::render::{quarter_turns, rotated};
+
+/// Device-independent DPI that `RenderParams::dpi` is measured against.
+const BASE_DPI: f32 = 96.0;
+
+/// Height-to-width ratio from which [`FitMode::FitSmart`] treats a page as a tall strip.
+///
+/// Regular comic pages stay well below it (about 1.5), while webtoon strips are several times
+/// taller than wide.
+pub const TALL_PAGE_ASPECT: f64 = 2.0;
+
+/// The concrete fit `mode` resolves to for a page of `source` dimensions, after rotation.
+///
+/// [`FitMode::FitSmart`] fits tall strips to the width so they can be scrolled, and everything
+/// else into the viewport; the other modes resolve to themselves.
+pub fn resolve(mode: FitMode, source: ImageDimensions) -> FitMode {
+    match mode {
+        FitMode::FitSmart if is_tall(source) => FitMode::FitWidth,
+        FitMode::FitSmart => FitMode::FitContain,
+        other => other,
+    }
+}
+
+/// Whether `source` is at least [`TALL_PAGE_ASPECT`] times taller than wide.
+pub fn is_tall(source: ImageDimensions) -> bool {
+    source.width > 0 && f64::from(source.height) / f64::from(source.width) >= TALL_PAGE_ASPECT
+}
+
+/// Size at which an image of `source` dimensions is displayed with `params`, in device pixels
+/// and after rotation.
+///
+/// Images are only ever scaled down, since the viewer can upscale as well as the resizer, and
+/// the aspect ratio is preserved.
+pub fn compute_target(source: ImageDimensions, params: &RenderParams) -> ImageDimensions {
+    let source = rotated(source, quarter_turns(params.rotation));
+    if source.width == 0 || source.height == 0 {
+        return source;
+    }
+    let width_ratio = f64::from(params.viewport_w) / f64::from(source.width);
+    let height_ratio = f64::from(params.viewport_h) / f64::from(source.height);
+    let fit = match resolve(params.fit, source) {
+        FitMode::FitWidth => width_ratio,
+        FitMode::FitHeight => height_ratio,
+        FitMode::FitContain | FitMode::FitSmart => width_ratio.min(height_ratio),
+        FitMode::Fill => width_ratio.max(height_ratio),
+        FitMode::Original => 1.0,
+    };
+    let scale =
+        if params.scale.is_finite() && params.scale > 0.0 { f64::from(params.scale) } else { 1.0 };
+    let density = if params.dpi.is_finite() && params.dpi > 0.0 {
+        f64::from(params.dpi / BASE_DPI)
+    } else {
+        1.0
+    };
+    let ratio = (fit * scale * density).min(1.0);
+    if ratio <= 0.0 || !ratio.is_finite() {
+        return source;
+    }
+    ImageDimensions {
+        width: ((f64::from(source.width) * ratio).round() as u32).max(1),
+        height: ((f64::from(source.height) * ratio).round() as u32).max(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(fit: FitMode, scale: f32) -> RenderParams {
+        RenderParams { fit, viewport_w: 1000, viewport_h: 800, scale, ..RenderParams::default() }
+    }
+
+    #[test]
+    fn fits_into_the_viewport_without_upscaling() {
+        let page = ImageDimensions { width: 2000, height: 3000 };
+        let dims = |fit, scale| {
+            let target = compute_target(page, &params(fit, scale));
+            (target.width, target.height)
+        };
+        assert_eq!(dims(FitMode::FitContain, 1.0), (533, 800));
+        assert_eq!(dims(FitMode::FitWidth, 1.0), (1000, 1500));
+        assert_eq!(dims(FitMode::FitHeight, 2.0), (1067, 1600));
+        assert_eq!(dims(FitMode::Fill, 1.0), (1000, 1500));
+        assert_eq!(dims(FitMode::Original, 1.0), (2000, 3000));
+        assert_eq!(dims(FitMode::FitWidth, 4.0), (2000, 3000));
+
+        let small = ImageDimensions { width: 100, height: 100 };
+        assert_eq!(compute_target(small, &params(FitMode::FitContain, 1.0)), small);
+    }
+
+    #[test]
+    fn smart_fit_follows_the_page_shape() {
+        let page = ImageDimensions { width: 2000, height: 3000 };
+        let strip = ImageDimensions { width: 800, height: 12000 };
+        assert_eq!(resolve(FitMode::FitSmart, page), FitMode::FitContain);
+        assert_eq!(resolve(FitMode::FitSmart, strip), FitMode::FitWidth);
+        assert_eq!(resolve(FitMode::Fill, strip), FitMode::Fill);
+
+        let smart = params(FitMode::FitSmart, 1.0);
+        assert_eq!(compute_target(page, &smart), ImageDimensions { width: 533, height: 800 });
+        let wide = RenderParams { viewport_w: 400, ..smart };
+        assert_eq!(compute_target(strip, &wide), ImageDimensions { width: 400, height: 6000 });
+
+        // A strip turned on its side is wide, so it is contained again.
+        let turned = RenderParams { rotation: 90, ..wide };
+        assert_eq!(compute_target(strip, &turned), ImageDimensions { width: 400, height: 27 });
+    }
+
+    #[test]
+    fn accounts_for_rotation_and_density() {
+        let page = ImageDimensions { width: 2000, height: 3000 };
+        let rotated = RenderParams { rotation: 90, ..params(FitMode::FitWidth, 1.0) };
+        let target = compute_target(page, &rotated);
+        assert_eq!((target.width, target.height), (1000, 667));
+
+        let retina = RenderParams { dpi: 192.0, ..params(FitMode::FitContain, 1.0) };
+        let target = compute_target(page, &retina);
+        assert_eq!((target.width, target.height), (1067, 1600));
+    }
+}
@@ -4,7 +4,7 @@ use crate::codec::DecodedImage;
 use crate::pipeline::resize::{
     AlphaBehavior, ResizeFilter, ResizeSettings, ResizedImage, resize_rgba,
 };
-use crate::types::{ImageDimensions, ImageKey};
+use crate::types::{ImageDimensions, ImageKey, ImageVariant};
 
 use super::Result;
 
@@ -91,7 +91,7 @@ pub fn build_chain(
             ResizeSettings::new(target).filter(config.filter).alpha_behavior(config.alpha);
 
         let resized = resize_rgba(&current, settings)?;
-        let key = base_key.derive(format!("mip{level_index}"));
+        let key = base_key.with_variant(ImageVariant::Mip(level_index));
 
         levels.push(MipLevel {
             level: level_index,
@@ -124,6 +124,11 @@ fn next_dimension(current: u32, min_dimension: u32) -> u32 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::{PageId, SourceId};
+
+    fn page_key(index: u32) -> ImageKey {
+        ImageKey::original(&PageId { source_id: SourceId::new("src-test"), index })
+    }
 
     fn source_image(width: u32, height: u32) -> DecodedImage {
         let mut pixels = Vec::with_capacity((width * height * 4) as usize);
@@ -135,7 +140,7 @@ mod tests {
 
     #[test]
     fn generates_expected_number_of_levels() {
-        let base_key = ImageKey::new("source::base");
+        let base_key = page_key(0);
         let source = source_image(16, 8);
         let chain = build_chain(&base_key, &source, MipChainConfig::default()).expect("chain");
         let dims: Vec<(u32, u32)> = chain
@@ -149,7 +154,7 @@ mod tests {
 
     #[test]
     fn respects_custom_min_dimension() {
-        let base_key = ImageKey::new("source::base");
+        let base_key = page_key(0);
         let source = source_image(40, 20);
         let config = MipChainConfig { min_dimension: 8, ..Default::default() };
         let chain = build_chain(&base_key, &source, config).expect("chain");
@@ -166,10 +171,10 @@ mod tests {
 
     #[test]
     fn derives_stable_keys_per_level() {
-        let base_key = ImageKey::new("page::123");
+        let base_key = page_key(123);
         let source = source_image(8, 8);
         let chain = build_chain(&base_key, &source, MipChainConfig::default()).expect("chain");
-        let keys: Vec<_> = chain.levels().iter().map(|lvl| lvl.key.cache_key.clone()).collect();
-        assert_eq!(keys, vec!["page::123::mip1", "page::123::mip2", "page::123::mip3",]);
+        let keys: Vec<_> = chain.levels().iter().map(|lvl| lvl.key.to_string()).collect();
+        assert_eq!(keys, vec!["src-test-mip-123-1", "src-test-mip-123-2", "src-test-mip-123-3"]);
     }
 }
@@ -1,8 +1,16 @@
 //! Decode, scale, and prefetch pipeline coordination.
 
+pub mod bench;
+pub mod fit;
 pub mod mip;
+#[cfg(feature = "tokio")]
+pub mod nonblocking;
+pub mod params;
+pub mod pool;
 pub mod queue;
+pub mod render;
 pub mod resize;
+pub mod spread;
 pub mod tile;
 
 pub type Result<T> = crate::Result<T>;
@@ -0,0 +1,360 @@
+//! Prefetch executor running its jobs as tasks on a tokio runtime.
+//!
+//! [`AsyncWorkerPool`] drains a [`PrefetchQueue`] like [`WorkerPool`](super::pool::WorkerPool),
+//! but instead of owning threads it spawns up to `concurrency` tasks at a time on a runtime, so
+//! a shell that is already async can await I/O inside its jobs and hand only the CPU-bound parts
+//! to the blocking pool. Stats are reported the same way, with each concurrency slot counted as
+//! a worker.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+
+use parking_lot::Mutex;
+use tokio::runtime::Handle;
+use tokio::sync::Notify;
+
+use crate::stats::StatsCollector;
+use crate::types::{PageId, PageLayout, PrefetchPolicy, RequestToken};
+
+use super::Result;
+use super::queue::{PrefetchQueue, PrefetchTask};
+
+/// Future performing the work for one prefetched page.
+pub type PrefetchFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+/// Work performed for one prefetched page, as a future.
+pub type AsyncPrefetchJob = Arc<dyn Fn(PrefetchTask) -> PrefetchFuture + Send + Sync>;
+
+struct PoolState {
+    queue: PrefetchQueue,
+    job: Option<AsyncPrefetchJob>,
+    concurrency: usize,
+    /// Which slots run a task; slots at or above `concurrency` are not refilled.
+    busy: Vec<bool>,
+    shutdown: bool,
+}
+
+impl PoolState {
+    fn free_slot(&self) -> Option<usize> {
+        (0..self.concurrency).find(|&slot| !self.busy.get(slot).copied().unwrap_or(false))
+    }
+
+    fn running(&self) -> usize {
+        self.busy.iter().filter(|&&busy| busy).count()
+    }
+}
+
+struct Shared {
+    state: Mutex<PoolState>,
+    /// Notified whenever the last running task finishes.
+    idle: Notify,
+    stats: Arc<StatsCollector>,
+    runtime: Handle,
+}
+
+/// Prefetch executor spawning tasks in priority order on a tokio runtime.
+pub struct AsyncWorkerPool {
+    shared: Arc<Shared>,
+}
+
+impl fmt::Debug for AsyncWorkerPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncWorkerPool")
+            .field("concurrency", &self.concurrency())
+            .field("running", &self.running())
+            .field("pending", &self.pending())
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncWorkerPool {
+    /// Run up to `concurrency` tasks (at least one) at a time on `runtime`.
+    pub fn new(runtime: Handle, concurrency: usize, stats: Arc<StatsCollector>) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(PoolState {
+                queue: PrefetchQueue::new(),
+                job: None,
+                concurrency: concurrency.max(1),
+                busy: Vec::new(),
+                shutdown: false,
+            }),
+            idle: Notify::new(),
+            stats,
+            runtime,
+        });
+        Self { shared }
+    }
+
+    /// Change how many tasks run at once (at least one). Running tasks above the new limit
+    /// finish first.
+    pub fn set_concurrency(&self, concurrency: usize) {
+        self.shared.state.lock().concurrency = concurrency.max(1);
+        spawn_ready(&self.shared);
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.shared.state.lock().concurrency
+    }
+
+    /// Number of tasks running.
+    pub fn running(&self) -> usize {
+        self.shared.state.lock().running()
+    }
+
+    /// Number of queued tasks not yet started.
+    pub fn pending(&self) -> usize {
+        self.shared.state.lock().queue.len()
+    }
+
+    /// Replace the queued window with the pages around `center` and run `job` for each of them.
+    /// Returns the number of queued tasks.
+    pub fn plan(
+        &self,
+        center: &PageId,
+        total_pages: u32,
+        policy: PrefetchPolicy,
+        velocity: f32,
+        layout: PageLayout,
+        job: AsyncPrefetchJob,
+    ) -> Result<usize> {
+        let mut state = self.shared.state.lock();
+        state.queue.plan_window(center, total_pages, policy, velocity, layout)?;
+        state.job = Some(job);
+        let pending = state.queue.len();
+        drop(state);
+        self.shared.stats.update_prefetch_pending(pending);
+        spawn_ready(&self.shared);
+        Ok(pending)
+    }
+
+    /// Drop every queued task. Returns how many were dropped.
+    pub fn cancel_pending(&self) -> usize {
+        let mut state = self.shared.state.lock();
+        let dropped = state.queue.len();
+        state.queue.clear_pending();
+        drop(state);
+        for _ in 0..dropped {
+            self.shared.stats.record_task_cancelled();
+        }
+        self.shared.stats.update_prefetch_pending(0);
+        dropped
+    }
+
+    /// Drop every queued task and cancel the running tasks whose page matches `stale`, as
+    /// [`WorkerPool::cancel_where`](super::pool::WorkerPool::cancel_where) does.
+    pub fn cancel_where(&self, stale: impl Fn(&PageId) -> bool) -> usize {
+        let dropped = self.cancel_pending();
+        let tokens = self.shared.state.lock().queue.active_tokens(stale);
+        dropped + tokens.iter().filter(|token| self.cancel(token)).count()
+    }
+
+    /// Cancel the running task issued `token`. Its job runs to the end, but the task is counted
+    /// as cancelled and its page can be planned again right away.
+    pub fn cancel(&self, token: &RequestToken) -> bool {
+        let cancelled = self.shared.state.lock().queue.cancel(token);
+        if cancelled {
+            self.shared.stats.record_task_cancelled();
+        }
+        cancelled
+    }
+
+    /// Drop the queued tasks, stop starting new ones and wait for the running tasks to finish.
+    /// Later plans are queued but never run. Returns how many queued tasks were dropped.
+    pub async fn shutdown(&self) -> usize {
+        self.shared.state.lock().shutdown = true;
+        let dropped = self.cancel_pending();
+        loop {
+            // Registered before checking, so a task finishing in between still wakes us.
+            let idle = self.shared.idle.notified();
+            if self.running() == 0 {
+                return dropped;
+            }
+            idle.await;
+        }
+    }
+}
+
+impl Drop for AsyncWorkerPool {
+    /// Stops starting tasks; the ones running finish on the runtime.
+    fn drop(&mut self) {
+        self.shared.state.lock().shutdown = true;
+        self.cancel_pending();
+    }
+}
+
+/// Spawn the next tasks in priority order until every slot is busy or the queue is empty.
+fn spawn_ready(shared: &Arc<Shared>) {
+    let mut state = shared.state.lock();
+    while !state.shutdown {
+        let Some(job) = state.job.clone() else { break };
+        let Some(slot) = state.free_slot() else { break };
+        let Some((token, task)) = state.queue.next_task() else { break };
+        if state.busy.len() <= slot {
+            state.busy.resize(slot + 1, false);
+        }
+        state.busy[slot] = true;
+        shared.stats.update_prefetch_pending(state.queue.len());
+        let shared_task = Arc::clone(shared);
+        shared.runtime.spawn(run_task(shared_task, slot, token, task, job));
+    }
+}
+
+async fn run_task(
+    shared: Arc<Shared>,
+    slot: usize,
+    token: RequestToken,
+    task: PrefetchTask,
+    job: AsyncPrefetchJob,
+) {
+    let page = task.page.clone();
+    shared.stats.record_task_started();
+    // Frees the slot even when the job panics, so the pool neither shrinks nor hangs `shutdown`.
+    let _slot = SlotGuard { shared: Arc::clone(&shared), slot, token };
+    let started = Instant::now();
+    let result = job(task).await;
+    shared.stats.record_worker_busy(slot, started.elapsed());
+    if let Err(err) = result {
+        tracing::debug!(
+            target: "pipeline::nonblocking",
+            source_id = page.source_id.as_str(),
+            page_index = page.index,
+            "prefetch failed: {err:#}"
+        );
+    }
+}
+
+/// Completes the task running in `slot` and starts the next ones when dropped.
+struct SlotGuard {
+    shared: Arc<Shared>,
+    slot: usize,
+    token: RequestToken,
+}
+
+impl Drop for SlotGuard {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            tracing::warn!(target: "pipeline::nonblocking", slot = self.slot, "prefetch job panicked");
+        }
+        let mut state = self.shared.state.lock();
+        if state.queue.complete(&self.token) {
+            self.shared.stats.record_task_completed();
+        }
+        state.busy[self.slot] = false;
+        let idle = state.running() == 0;
+        drop(state);
+        if idle {
+            self.shared.idle.notify_waiters();
+        }
+        spawn_ready(&self.shared);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SourceId;
+    use std::time::Duration;
+    use tokio::sync::{Semaphore, mpsc};
+
+    fn center(index: u32) -> PageId {
+        PageId { source_id: SourceId::new("demo"), index }
+    }
+
+    #[tokio::test]
+    async fn runs_planned_pages_and_reports_stats() {
+        let stats = Arc::new(StatsCollector::default());
+        let pool = AsyncWorkerPool::new(Handle::current(), 2, Arc::clone(&stats));
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let job: AsyncPrefetchJob = Arc::new(move |task| {
+            let sender = sender.clone();
+            Box::pin(async move {
+                sender.send(task.page.index).unwrap();
+                Ok(())
+            })
+        });
+
+        let policy = PrefetchPolicy { ahead: 2, behind: 1 };
+        assert_eq!(pool.plan(&center(4), 10, policy, 0.0, PageLayout::Single, job).unwrap(), 3);
+
+        let mut pages = Vec::new();
+        for _ in 0..3 {
+            let page = tokio::time::timeout(Duration::from_secs(5), receiver.recv()).await;
+            pages.push(page.unwrap().unwrap());
+        }
+        pages.sort_unstable();
+        assert_eq!(pages, [3, 5, 6]);
+
+        assert_eq!(pool.shutdown().await, 0);
+        let snap = stats.snapshot();
+        assert_eq!((snap.tasks_started, snap.tasks_completed), (3, 3));
+        assert_eq!(snap.prefetch_pending, 0);
+    }
+
+    #[tokio::test]
+    async fn limits_concurrency_and_waits_on_shutdown() {
+        let stats = Arc::new(StatsCollector::default());
+        let pool = AsyncWorkerPool::new(Handle::current(), 1, Arc::clone(&stats));
+        let gate = Arc::new(Semaphore::new(0));
+        let job: AsyncPrefetchJob = {
+            let gate = Arc::clone(&gate);
+            Arc::new(move |_| {
+                let gate = Arc::clone(&gate);
+                Box::pin(async move {
+                    gate.acquire().await.unwrap().forget();
+                    Ok(())
+                })
+            })
+        };
+
+        let policy = PrefetchPolicy { ahead: 3, behind: 0 };
+        pool.plan(&center(0), 10, policy, 0.0, PageLayout::Single, job).unwrap();
+        assert_eq!((pool.running(), pool.pending()), (1, 2));
+
+        // Release the running task only once shutdown is waiting on it.
+        let release = tokio::spawn({
+            let gate = Arc::clone(&gate);
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                gate.add_permits(1);
+            }
+        });
+        assert_eq!(pool.shutdown().await, 2);
+        release.await.unwrap();
+        assert_eq!(pool.running(), 0);
+        let snap = stats.snapshot();
+        assert_eq!((snap.tasks_completed, snap.tasks_cancelled), (1, 2));
+    }
+
+    #[tokio::test]
+    async fn frees_the_slot_of_a_panicking_job() {
+        let stats = Arc::new(StatsCollector::default());
+        let pool = AsyncWorkerPool::new(Handle::current(), 1, Arc::clone(&stats));
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let job: AsyncPrefetchJob = Arc::new(move |task| {
+            let sender = sender.clone();
+            Box::pin(async move {
+                assert_ne!(task.page.index, 1, "page 1 is broken");
+                sender.send(task.page.index).unwrap();
+                Ok(())
+            })
+        });
+
+        let policy = PrefetchPolicy { ahead: 3, behind: 0 };
+        pool.plan(&center(0), 10, policy, 0.0, PageLayout::Single, job).unwrap();
+        let mut pages = Vec::new();
+        for _ in 0..2 {
+            let page = tokio::time::timeout(Duration::from_secs(5), receiver.recv()).await;
+            pages.push(page.unwrap().unwrap());
+        }
+        pages.sort_unstable();
+        assert_eq!(pages, [2, 3]);
+
+        let drained = tokio::time::timeout(Duration::from_secs(5), pool.shutdown()).await;
+        assert_eq!(drained.unwrap(), 0);
+        assert_eq!(pool.running(), 0);
+        assert_eq!(stats.snapshot().tasks_started, 3);
+    }
+}
@@ -0,0 +1,146 @@
+//! Building and validating [`RenderParams`] before they reach the pipeline.
+
+use std::ops::RangeInclusive;
+
+use thiserror::Error;
+
+use crate::pipeline::resize::ResizeFilter;
+use crate::types::{FitMode, PageLayout, ReadingDirection, RenderParams};
+
+/// Zoom factors accepted on top of the fit.
+pub const SCALE_RANGE: RangeInclusive<f32> = 0.1..=8.0;
+
+/// Render parameters the pipeline cannot honour.
+#[derive(Debug, Clone, Copy, PartialEq, Error)]
+pub enum InvalidRenderParams {
+    #[error("viewport {width}x{height} has no area")]
+    EmptyViewport { width: u32, height: u32 },
+    #[error("rotation {0} is not a multiple of 90 degrees")]
+    Rotation(i16),
+    #[error("scale {0} is outside {range:?}", range = SCALE_RANGE)]
+    Scale(f32),
+    #[error("dpi {0} is not a positive number")]
+    Dpi(f32),
+}
+
+impl RenderParams {
+    /// Builder starting from the defaults.
+    pub fn builder() -> RenderParamsBuilder {
+        RenderParamsBuilder::default()
+    }
+
+    /// These params checked and normalized like [`RenderParamsBuilder::build`] does, for params
+    /// that arrive whole, such as over IPC.
+    pub fn validated(self) -> Result<Self, InvalidRenderParams> {
+        RenderParamsBuilder { params: self }.build()
+    }
+}
+
+/// Builds [`RenderParams`], refusing combinations the pipeline cannot render.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderParamsBuilder {
+    params: RenderParams,
+}
+
+impl RenderParamsBuilder {
+    pub fn fit(mut self, fit: FitMode) -> Self {
+        self.params.fit = fit;
+        self
+    }
+
+    /// Size of the area the page is displayed in, in CSS pixels.
+    pub fn viewport(mut self, width: u32, height: u32) -> Self {
+        self.params.viewport_w = width;
+        self.params.viewport_h = height;
+        self
+    }
+
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.params.scale = scale;
+        self
+    }
+
+    /// Clockwise rotation in degrees; any multiple of 90 is accepted and normalized.
+    pub fn rotation(mut self, degrees: i16) -> Self {
+        self.params.rotation = degrees;
+        self
+    }
+
+    pub fn dpi(mut self, dpi: f32) -> Self {
+        self.params.dpi = dpi;
+        self
+    }
+
+    pub fn filter(mut self, filter: ResizeFilter) -> Self {
+        self.params.filter = filter;
+        self
+    }
+
+    pub fn direction(mut self, direction: ReadingDirection) -> Self {
+        self.params.direction = direction;
+        self
+    }
+
+    pub fn layout(mut self, layout: PageLayout) -> Self {
+        self.params.layout = layout;
+        self
+    }
+
+    /// Check the params, normalizing the rotation into `0..360`.
+    pub fn build(self) -> Result<RenderParams, InvalidRenderParams> {
+        let params = self.params;
+        if params.viewport_w == 0 || params.viewport_h == 0 {
+            return Err(InvalidRenderParams::EmptyViewport {
+                width: params.viewport_w,
+                height: params.viewport_h,
+            });
+        }
+        if params.rotation % 90 != 0 {
+            return Err(InvalidRenderParams::Rotation(params.rotation));
+        }
+        if !SCALE_RANGE.contains(&params.scale) {
+            return Err(InvalidRenderParams::Scale(params.scale));
+        }
+        if !(params.dpi.is_finite() && params.dpi > 0.0) {
+            return Err(InvalidRenderParams::Dpi(params.dpi));
+        }
+        Ok(RenderParams { rotation: params.rotation.rem_euclid(360), ..params })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_normalized_params() {
+        let params = RenderParams::builder()
+            .fit(FitMode::FitWidth)
+            .viewport(800, 600)
+            .rotation(-90)
+            .scale(2.0)
+            .build()
+            .unwrap();
+        assert_eq!(params.rotation, 270);
+        assert_eq!((params.viewport_w, params.viewport_h), (800, 600));
+        assert_eq!(params.fit, FitMode::FitWidth);
+        assert_eq!(RenderParams::builder().rotation(450).build().unwrap().rotation, 90);
+        assert_eq!(RenderParams::default().validated(), Ok(RenderParams::default()));
+    }
+
+    #[test]
+    fn refuses_impossible_params() {
+        let build = |builder: RenderParamsBuilder| builder.build().unwrap_err();
+        assert_eq!(
+            build(RenderParams::builder().viewport(0, 600)),
+            InvalidRenderParams::EmptyViewport { width: 0, height: 600 }
+        );
+        assert_eq!(build(RenderParams::builder().rotation(45)), InvalidRenderParams::Rotation(45));
+        assert_eq!(build(RenderParams::builder().scale(0.0)), InvalidRenderParams::Scale(0.0));
+        assert!(matches!(
+            build(RenderParams::builder().scale(f32::NAN)),
+            InvalidRenderParams::Scale(_)
+        ));
+        assert_eq!(build(RenderParams::builder().dpi(-1.0)), InvalidRenderParams::Dpi(-1.0));
+    }
+}
@@ -0,0 +1,543 @@
+//! Worker threads draining a [`PrefetchQueue`].
+//!
+//! Each call to [`WorkerPool::plan`] replaces the queued window and the job that runs for each
+//! of its pages; tasks already running finish with the job they started with, unless they are
+//! cancelled through their [`CancelFlag`]. Plans made through [`WorkerPool::owned_by`] tag
+//! their tasks with an owner, e.g. an app window, and that owner's cancellations leave the tasks
+//! of other owners alone. Task starts, completions, cancellations and per-worker busy time are
+//! reported to the [`StatsCollector`].
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+use parking_lot::{Condvar, Mutex};
+
+use crate::stats::StatsCollector;
+use crate::types::{PageId, PageLayout, PrefetchPolicy, RequestToken};
+
+use super::Result;
+use super::queue::{PrefetchQueue, PrefetchTask};
+
+/// Work performed for one prefetched page. Long jobs should check the flag between steps and
+/// give up once it is cancelled.
+pub type PrefetchJob = Arc<dyn Fn(&PrefetchTask, &CancelFlag) -> Result<()> + Send + Sync>;
+
+/// Set when the task a job runs for is cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Upper bound of [`WorkerPool::default_threads`]; prefetching is I/O and decode bound and
+/// should not starve the UI of cores.
+const MAX_DEFAULT_THREADS: usize = 4;
+
+struct PoolState {
+    queue: PrefetchQueue,
+    job: Option<PrefetchJob>,
+    /// Owner of the queued tasks, given by the last plan.
+    owner: Option<Arc<str>>,
+    /// Tasks being run, by token.
+    running: HashMap<RequestToken, RunningTask>,
+    /// Workers with an index at or above this exit once idle.
+    workers: usize,
+    shutdown: bool,
+}
+
+/// A task picked up by a worker.
+struct RunningTask {
+    flag: CancelFlag,
+    owner: Option<Arc<str>>,
+}
+
+struct Shared {
+    state: Mutex<PoolState>,
+    wake: Condvar,
+    stats: Arc<StatsCollector>,
+}
+
+/// Set of threads executing prefetch tasks in priority order.
+pub struct WorkerPool {
+    shared: Arc<Shared>,
+    threads: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl fmt::Debug for WorkerPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WorkerPool")
+            .field("threads", &self.threads())
+            .field("pending", &self.pending())
+            .finish_non_exhaustive()
+    }
+}
+
+impl WorkerPool {
+    /// Start `threads` workers (at least one).
+    pub fn new(threads: usize, stats: Arc<StatsCollector>) -> io::Result<Self> {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(PoolState {
+                queue: PrefetchQueue::new(),
+                job: None,
+                owner: None,
+                running: HashMap::new(),
+                workers: 0,
+                shutdown: false,
+            }),
+            wake: Condvar::new(),
+            stats,
+        });
+        let pool = Self { shared, threads: Mutex::new(Vec::new()) };
+        pool.set_threads(threads)?;
+        Ok(pool)
+    }
+
+    /// Grow or shrink the pool to `threads` workers (at least one). Removed workers finish
+    /// the task they are running first; this waits for them to exit.
+    pub fn set_threads(&self, threads: usize) -> io::Result<()> {
+        let target = threads.max(1);
+        let mut handles = self.threads.lock();
+        let mut state = self.shared.state.lock();
+        if state.shutdown {
+            return Ok(());
+        }
+        state.workers = target;
+        drop(state);
+        if target < handles.len() {
+            self.shared.wake.notify_all();
+            for thread in handles.drain(target..) {
+                let _ = thread.join();
+            }
+        }
+        for index in handles.len()..target {
+            let shared = Arc::clone(&self.shared);
+            let thread = std::thread::Builder::new()
+                .name(format!("prefetch-{index}"))
+                .spawn(move || run_worker(index, &shared));
+            match thread {
+                Ok(thread) => handles.push(thread),
+                Err(err) => {
+                    self.shared.state.lock().workers = handles.len();
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// One worker per spare core, up to four.
+    pub fn default_threads() -> usize {
+        std::thread::available_parallelism()
+            .map_or(1, |cores| cores.get().saturating_sub(1))
+            .clamp(1, MAX_DEFAULT_THREADS)
+    }
+
+    /// Number of worker threads.
+    pub fn threads(&self) -> usize {
+        self.threads.lock().len()
+    }
+
+    /// Number of queued tasks not yet picked up by a worker.
+    pub fn pending(&self) -> usize {
+        self.shared.state.lock().queue.len()
+    }
+
+    /// Replace the queued window with the pages around `center` and run `job` for each of them.
+    /// Returns the number of queued tasks.
+    pub fn plan(
+        &self,
+        center: &PageId,
+        total_pages: u32,
+        policy: PrefetchPolicy,
+        velocity: f32,
+        layout: PageLayout,
+        job: PrefetchJob,
+    ) -> Result<usize> {
+        self.unowned().plan(center, total_pages, policy, velocity, layout, job)
+    }
+
+    /// Plan and cancel tasks on behalf of `owner`, e.g. the window that reads the pages.
+    pub fn owned_by<'a>(&'a self, owner: &'a str) -> OwnedTasks<'a> {
+        OwnedTasks { pool: self, owner: Some(owner) }
+    }
+
+    /// Plans without an owner, and cancellations that reach the tasks of every owner.
+    fn unowned(&self) -> OwnedTasks<'_> {
+        OwnedTasks { pool: self, owner: None }
+    }
+
+    /// Drop every queued task. Returns how many were dropped.
+    pub fn cancel_pending(&self) -> usize {
+        let mut state = self.shared.state.lock();
+        let dropped = state.queue.len();
+        state.queue.clear_pending();
+        drop(state);
+        for _ in 0..dropped {
+            self.shared.stats.record_task_cancelled();
+        }
+        self.shared.stats.update_prefetch_pending(0);
+        dropped
+    }
+
+    /// Drop every queued task and cancel, through their tokens, the running tasks whose page
+    /// matches `stale`, e.g. pages left behind when navigation reverses. Returns how many tasks
+    /// were cancelled in total.
+    pub fn cancel_where(&self, stale: impl Fn(&PageId) -> bool) -> usize {
+        self.unowned().cancel_where(stale)
+    }
+
+    /// Drop the queued tasks and cancel the running tasks whose page matches `filter`, leaving
+    /// the others, e.g. those of another source, to run. Returns how many were cancelled.
+    pub fn cancel_matching(&self, filter: impl Fn(&PageId) -> bool) -> usize {
+        self.unowned().cancel_matching(filter)
+    }
+
+    /// Cancel the running task issued `token`: its job sees its [`CancelFlag`] set, the task is
+    /// counted as cancelled and its page can be planned again right away.
+    pub fn cancel(&self, token: &RequestToken) -> bool {
+        let mut state = self.shared.state.lock();
+        let cancelled = state.queue.cancel(token);
+        if let Some(task) = state.running.get(token) {
+            task.flag.cancel();
+        }
+        drop(state);
+        if cancelled {
+            self.shared.stats.record_task_cancelled();
+        }
+        cancelled
+    }
+}
+
+impl WorkerPool {
+    /// Drop the queued tasks and stop every worker, waiting for running tasks to finish. Later
+    /// plans are queued but never run. Returns how many queued tasks were dropped.
+    pub fn shutdown(&self) -> usize {
+        let dropped = self.cancel_pending();
+        let mut handles = self.threads.lock();
+        self.shared.state.lock().shutdown = true;
+        self.shared.wake.notify_all();
+        for thread in handles.drain(..) {
+            let _ = thread.join();
+        }
+        dropped
+    }
+}
+
+/// Tasks of one owner of a [`WorkerPool`]; see [`WorkerPool::owned_by`].
+#[derive(Debug, Clone, Copy)]
+pub struct OwnedTasks<'a> {
+    pool: &'a WorkerPool,
+    /// `None` plans tasks without an owner and cancels those of every owner.
+    owner: Option<&'a str>,
+}
+
+impl OwnedTasks<'_> {
+    /// Replace the queued window as [`WorkerPool::plan`] does, with the new tasks belonging to
+    /// this owner.
+    pub fn plan(
+        self,
+        center: &PageId,
+        total_pages: u32,
+        policy: PrefetchPolicy,
+        velocity: f32,
+        layout: PageLayout,
+        job: PrefetchJob,
+    ) -> Result<usize> {
+        let shared = &self.pool.shared;
+        let mut state = shared.state.lock();
+        state.queue.plan_window(center, total_pages, policy, velocity, layout)?;
+        state.job = Some(job);
+        state.owner = self.owner.map(Arc::from);
+        let pending = state.queue.len();
+        drop(state);
+        shared.stats.update_prefetch_pending(pending);
+        shared.wake.notify_all();
+        Ok(pending)
+    }
+
+    /// Drop the queued tasks and cancel the running tasks whose page matches `stale`, as
+    /// [`WorkerPool::cancel_where`] does, for this owner's tasks only.
+    pub fn cancel_where(self, stale: impl Fn(&PageId) -> bool) -> usize {
+        self.cancel(|_| true, stale)
+    }
+
+    /// Drop the queued tasks and cancel the running tasks whose page matches `filter`, as
+    /// [`WorkerPool::cancel_matching`] does, for this owner's tasks only.
+    pub fn cancel_matching(self, filter: impl Fn(&PageId) -> bool) -> usize {
+        self.cancel(&filter, &filter)
+    }
+
+    fn cancel(self, queued: impl Fn(&PageId) -> bool, running: impl Fn(&PageId) -> bool) -> usize {
+        let shared = &self.pool.shared;
+        let mut state = shared.state.lock();
+        let dropped = if self.owns(state.owner.as_deref()) {
+            state.queue.clear_pending_where(queued)
+        } else {
+            0
+        };
+        let pending = state.queue.len();
+        let tokens: Vec<RequestToken> = state
+            .queue
+            .active_tokens(running)
+            .into_iter()
+            .filter(|token| {
+                state.running.get(token).is_some_and(|task| self.owns(task.owner.as_deref()))
+            })
+            .collect();
+        drop(state);
+        for _ in 0..dropped {
+            shared.stats.record_task_cancelled();
+        }
+        shared.stats.update_prefetch_pending(pending);
+        dropped + tokens.iter().filter(|token| self.pool.cancel(token)).count()
+    }
+
+    /// Whether tasks tagged with `owner` are this owner's to cancel.
+    fn owns(&self, owner: Option<&str>) -> bool {
+        self.owner.is_none_or(|mine| owner == Some(mine))
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn run_worker(index: usize, shared: &Shared) {
+    loop {
+        let (token, task, job, flag) = {
+            let mut state = shared.state.lock();
+            loop {
+                if state.shutdown || index >= state.workers {
+                    return;
+                }
+                if let Some(job) = state.job.clone()
+                    && let Some((token, task)) = state.queue.next_task()
+                {
+                    shared.stats.update_prefetch_pending(state.queue.len());
+                    let flag = CancelFlag::new();
+                    let owner = state.owner.clone();
+                    state.running.insert(token, RunningTask { flag: flag.clone(), owner });
+                    break (token, task, job, flag);
+                }
+                shared.wake.wait(&mut state);
+            }
+        };
+
+        shared.stats.record_task_started();
+        let started = Instant::now();
+        let result = job(&task, &flag);
+        shared.stats.record_worker_busy(index, started.elapsed());
+        if let Err(err) = result
+            && !flag.is_cancelled()
+        {
+            tracing::debug!(
+                target: "pipeline::pool",
+                source_id = task.page.source_id.as_str(),
+                page_index = task.page.index,
+                "prefetch failed: {err:#}"
+            );
+        }
+        let mut state = shared.state.lock();
+        state.running.remove(&token);
+        if state.queue.complete(&token) {
+            shared.stats.record_task_completed();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SourceId;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn runs_planned_pages_and_reports_stats() {
+        let stats = Arc::new(StatsCollector::default());
+        let pool = WorkerPool::new(2, Arc::clone(&stats)).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        let job: PrefetchJob = Arc::new(move |task, _| {
+            sender.lock().send(task.page.index).unwrap();
+            Ok(())
+        });
+
+        let center = PageId { source_id: SourceId::new("demo"), index: 4 };
+        let policy = PrefetchPolicy { ahead: 2, behind: 1 };
+        assert_eq!(pool.plan(&center, 10, policy, 0.0, PageLayout::Single, job).unwrap(), 3);
+
+        let mut pages: Vec<u32> =
+            (0..3).map(|_| receiver.recv_timeout(Duration::from_secs(5)).unwrap()).collect();
+        pages.sort_unstable();
+        assert_eq!(pages, [3, 5, 6]);
+
+        drop(pool);
+        let snap = stats.snapshot();
+        assert_eq!((snap.tasks_started, snap.tasks_completed), (3, 3));
+        assert_eq!(snap.prefetch_pending, 0);
+    }
+
+    #[test]
+    fn shutdown_drops_queued_tasks_and_stops_workers() {
+        let stats = Arc::new(StatsCollector::default());
+        let pool = WorkerPool::new(1, Arc::clone(&stats)).unwrap();
+        let (release, gate) = mpsc::channel::<()>();
+        let gate = Mutex::new(gate);
+        let job: PrefetchJob = Arc::new(move |_, _| {
+            let _ = gate.lock().recv_timeout(Duration::from_secs(5));
+            Ok(())
+        });
+        let center = PageId { source_id: SourceId::new("demo"), index: 0 };
+        let policy = PrefetchPolicy { ahead: 3, behind: 0 };
+        pool.plan(&center, 10, policy, 0.0, PageLayout::Single, job).unwrap();
+        while stats.snapshot().tasks_started == 0 {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        // Let the running task finish only once shutdown is waiting on it.
+        let releaser = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            release.send(()).unwrap();
+        });
+        assert_eq!(pool.shutdown(), 2);
+        releaser.join().unwrap();
+        assert_eq!(pool.threads(), 0);
+        assert_eq!(stats.snapshot().tasks_completed, 1);
+        pool.set_threads(2).unwrap();
+        assert_eq!(pool.threads(), 0);
+    }
+
+    #[test]
+    fn cancelling_pending_tasks_skips_them() {
+        let stats = Arc::new(StatsCollector::default());
+        let pool = WorkerPool::new(1, Arc::clone(&stats)).unwrap();
+        let (release, gate) = mpsc::channel::<()>();
+        let gate = Mutex::new(gate);
+        let job: PrefetchJob = Arc::new(move |_, _| {
+            let _ = gate.lock().recv_timeout(Duration::from_secs(5));
+            Ok(())
+        });
+
+        let center = PageId { source_id: SourceId::new("demo"), index: 0 };
+        let policy = PrefetchPolicy { ahead: 5, behind: 0 };
+        pool.plan(&center, 10, policy, 0.0, PageLayout::Single, job).unwrap();
+        // Wait for the worker to hold one task, then drop the rest.
+        while pool.pending() == 5 {
+            std::thread::yield_now();
+        }
+        assert_eq!(pool.cancel_pending(), 4);
+        release.send(()).unwrap();
+
+        drop(pool);
+        let snap = stats.snapshot();
+        assert_eq!((snap.tasks_started, snap.tasks_completed), (1, 1));
+        assert_eq!(snap.tasks_cancelled, 4);
+    }
+
+    #[test]
+    fn cancels_running_tasks_matching_a_filter() {
+        let stats = Arc::new(StatsCollector::default());
+        let pool = WorkerPool::new(2, Arc::clone(&stats)).unwrap();
+        let (release, gate) = mpsc::channel::<()>();
+        let gate = Mutex::new(gate);
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        let job: PrefetchJob = Arc::new(move |task, flag| {
+            let _ = gate.lock().recv_timeout(Duration::from_secs(5));
+            sender.lock().send((task.page.index, flag.is_cancelled())).unwrap();
+            Ok(())
+        });
+
+        let center = PageId { source_id: SourceId::new("demo"), index: 5 };
+        let policy = PrefetchPolicy { ahead: 1, behind: 1 };
+        pool.plan(&center, 10, policy, 0.0, PageLayout::Single, job).unwrap();
+        while stats.snapshot().tasks_started < 2 {
+            std::thread::yield_now();
+        }
+        // Pages 4 and 6 are running; only the one ahead is stale.
+        assert_eq!(pool.cancel_where(|page| page.index > center.index), 1);
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        let mut seen: Vec<(u32, bool)> =
+            (0..2).map(|_| receiver.recv_timeout(Duration::from_secs(5)).unwrap()).collect();
+        seen.sort_unstable();
+        assert_eq!(seen, [(4, false), (6, true)]);
+
+        drop(pool);
+        let snap = stats.snapshot();
+        assert_eq!((snap.tasks_completed, snap.tasks_cancelled), (1, 1));
+    }
+
+    #[test]
+    fn owners_cancel_only_their_own_tasks() {
+        let stats = Arc::new(StatsCollector::default());
+        let pool = WorkerPool::new(2, Arc::clone(&stats)).unwrap();
+        let (release, gate) = mpsc::channel::<()>();
+        let gate = Mutex::new(gate);
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        let job: PrefetchJob = Arc::new(move |task, flag| {
+            let _ = gate.lock().recv_timeout(Duration::from_secs(5));
+            sender.lock().send((task.page.index, flag.is_cancelled())).unwrap();
+            Ok(())
+        });
+
+        let center = PageId { source_id: SourceId::new("demo"), index: 5 };
+        let policy = PrefetchPolicy { ahead: 1, behind: 1 };
+        pool.owned_by("left").plan(&center, 10, policy, 0.0, PageLayout::Single, job).unwrap();
+        while stats.snapshot().tasks_started < 2 {
+            std::thread::yield_now();
+        }
+        // Another window reading the same source leaves pages 4 and 6 running.
+        assert_eq!(pool.owned_by("right").cancel_matching(|_| true), 0);
+        assert_eq!(pool.owned_by("left").cancel_where(|page| page.index > center.index), 1);
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        let mut seen: Vec<(u32, bool)> =
+            (0..2).map(|_| receiver.recv_timeout(Duration::from_secs(5)).unwrap()).collect();
+        seen.sort_unstable();
+        assert_eq!(seen, [(4, false), (6, true)]);
+    }
+
+    #[test]
+    fn resizes_while_running() {
+        let stats = Arc::new(StatsCollector::default());
+        let pool = WorkerPool::new(1, Arc::clone(&stats)).unwrap();
+        pool.set_threads(3).unwrap();
+        assert_eq!(pool.threads(), 3);
+        pool.set_threads(0).unwrap();
+        assert_eq!(pool.threads(), 1);
+
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        let job: PrefetchJob = Arc::new(move |task, _| {
+            sender.lock().send(task.page.index).unwrap();
+            Ok(())
+        });
+        let center = PageId { source_id: SourceId::new("demo"), index: 0 };
+        let policy = PrefetchPolicy { ahead: 2, behind: 0 };
+        assert_eq!(pool.plan(&center, 10, policy, 0.0, PageLayout::Single, job).unwrap(), 2);
+        for _ in 0..2 {
+            receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+    }
+}
@@ -3,7 +3,7 @@
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap, HashSet};
 
-use crate::types::{PageId, PrefetchPolicy, RequestToken};
+use crate::types::{PageId, PageLayout, PrefetchPolicy, RequestToken};
 
 use super::Result;
 
@@ -107,25 +107,46 @@ impl PrefetchQueue {
         self.active_pages.clear();
     }
 
+    /// Drop every queued task, leaving issued tokens valid.
+    pub fn clear_pending(&mut self) {
+        self.pending.clear();
+        self.queued.clear();
+    }
+
+    /// Drop the queued tasks whose page matches `filter`. Returns how many were dropped.
+    pub fn clear_pending_where(&mut self, filter: impl Fn(&PageId) -> bool) -> usize {
+        let before = self.queued.len();
+        self.pending.retain(|entry| !filter(&entry.task.page));
+        self.queued.retain(|page| !filter(page));
+        before - self.queued.len()
+    }
+
     /// Rebuild the queue around a new center page, applying the given policy and viewport velocity.
+    ///
+    /// `policy` counts views of `layout`: in [`PageLayout::Dual`] the window spans that many
+    /// spreads on each side, the page facing `center` ranks as on screen, and both pages of a
+    /// spread share a priority. Task distances stay in pages.
     pub fn plan_window(
         &mut self,
         center: &PageId,
         total_pages: u32,
         policy: PrefetchPolicy,
         velocity: f32,
+        layout: PageLayout,
     ) -> Result<()> {
-        self.pending.clear();
-        self.queued.clear();
+        self.clear_pending();
 
         if total_pages == 0 {
             return Ok(());
         }
 
         let center_index = center.index;
+        let per_view = layout.pages_per_view();
 
-        let start = center_index.saturating_sub(policy.behind.min(center_index));
-        let end = (center_index + policy.ahead).min(total_pages.saturating_sub(1));
+        let start = center_index.saturating_sub(policy.behind.saturating_mul(per_view));
+        let end = center_index
+            .saturating_add(policy.ahead.saturating_add(1).saturating_mul(per_view) - 1)
+            .min(total_pages.saturating_sub(1));
 
         for index in start..=end {
             if index == center_index {
@@ -133,7 +154,7 @@ impl PrefetchQueue {
             }
 
             let distance = index as i32 - center_index as i32;
-            let priority = compute_priority(distance, velocity);
+            let priority = compute_priority(view_distance(distance, per_view), velocity);
             if priority <= 0.0 {
                 continue;
             }
@@ -176,6 +197,11 @@ impl PrefetchQueue {
         self.complete(token)
     }
 
+    /// Tokens of the in-flight tasks whose page matches `filter`.
+    pub fn active_tokens(&self, filter: impl Fn(&PageId) -> bool) -> Vec<RequestToken> {
+        self.active.iter().filter(|(_, page)| filter(page)).map(|(token, _)| *token).collect()
+    }
+
     fn push_task(&mut self, page: PageId, distance: i32, priority: f64) {
         if !self.queued.insert(page.clone()) {
             return;
@@ -196,6 +222,13 @@ impl PrefetchQueue {
     }
 }
 
+/// Views between the one starting at the center page and the one holding the page `distance`
+/// pages away, when each view shows `per_view` pages.
+fn view_distance(distance: i32, per_view: u32) -> i32 {
+    let per_view = per_view.max(1) as i32;
+    if distance >= 0 { distance / per_view } else { -((-distance + per_view - 1) / per_view) }
+}
+
 fn compute_priority(distance: i32, velocity: f32) -> f64 {
     let abs_distance = distance.abs() as f64;
     let distance_weight = 1.0 / (abs_distance + 1.0);
@@ -225,7 +258,15 @@ mod tests {
     fn prioritizes_closer_pages() {
         let center = page("demo", 10);
         let mut queue = PrefetchQueue::new();
-        queue.plan_window(&center, 30, PrefetchPolicy { ahead: 3, behind: 2 }, 0.0).unwrap();
+        queue
+            .plan_window(
+                &center,
+                30,
+                PrefetchPolicy { ahead: 3, behind: 2 },
+                0.0,
+                PageLayout::Single,
+            )
+            .unwrap();
 
         let priorities: Vec<_> = (0..queue.len()).filter_map(|_| queue.next_task()).collect();
         let distances: Vec<i32> = priorities.iter().map(|(_, task)| task.distance).collect();
@@ -236,7 +277,15 @@ mod tests {
     fn forward_velocity_biases_future_pages() {
         let center = page("demo", 5);
         let mut queue = PrefetchQueue::new();
-        queue.plan_window(&center, 20, PrefetchPolicy { ahead: 3, behind: 3 }, 2.5).unwrap();
+        queue
+            .plan_window(
+                &center,
+                20,
+                PrefetchPolicy { ahead: 3, behind: 3 },
+                2.5,
+                PageLayout::Single,
+            )
+            .unwrap();
 
         let distances: Vec<i32> =
             (0..queue.len()).filter_map(|_| queue.next_task()).map(|(_, t)| t.distance).collect();
@@ -252,7 +301,15 @@ mod tests {
     fn backward_velocity_prioritizes_previous_pages() {
         let center = page("demo", 8);
         let mut queue = PrefetchQueue::new();
-        queue.plan_window(&center, 50, PrefetchPolicy { ahead: 3, behind: 3 }, -3.0).unwrap();
+        queue
+            .plan_window(
+                &center,
+                50,
+                PrefetchPolicy { ahead: 3, behind: 3 },
+                -3.0,
+                PageLayout::Single,
+            )
+            .unwrap();
 
         let first = queue.next_task().unwrap();
         assert!(first.1.distance < 0);
@@ -262,27 +319,113 @@ mod tests {
     fn deduplicates_pages_and_handles_cancellation() {
         let center = page("demo", 2);
         let mut queue = PrefetchQueue::new();
-        queue.plan_window(&center, 10, PrefetchPolicy { ahead: 2, behind: 2 }, 1.0).unwrap();
+        queue
+            .plan_window(
+                &center,
+                10,
+                PrefetchPolicy { ahead: 2, behind: 2 },
+                1.0,
+                PageLayout::Single,
+            )
+            .unwrap();
         let len_first = queue.len();
-        queue.plan_window(&center, 10, PrefetchPolicy { ahead: 2, behind: 2 }, 1.0).unwrap();
+        queue
+            .plan_window(
+                &center,
+                10,
+                PrefetchPolicy { ahead: 2, behind: 2 },
+                1.0,
+                PageLayout::Single,
+            )
+            .unwrap();
         assert_eq!(queue.len(), len_first);
 
         let (token, _) = queue.next_task().unwrap();
         assert!(queue.cancel(&token));
         assert!(!queue.cancel(&token));
+
+        let ahead = queue.clear_pending_where(|page| page.index > 2);
+        assert!(ahead > 0);
+        assert_eq!(queue.len(), len_first - 1 - ahead);
+        while let Some((_, task)) = queue.next_task() {
+            assert!(task.page.index < 2);
+        }
+    }
+
+    #[test]
+    fn dual_layout_plans_whole_spreads() {
+        let center = page("demo", 10);
+        let mut queue = PrefetchQueue::new();
+        let policy = PrefetchPolicy { ahead: 2, behind: 1 };
+        queue.plan_window(&center, 30, policy, 0.0, PageLayout::Dual).unwrap();
+
+        let tasks: Vec<_> =
+            (0..queue.len()).filter_map(|_| queue.next_task()).map(|(_, task)| task).collect();
+        let mut distances: Vec<i32> = tasks.iter().map(|task| task.distance).collect();
+        distances.sort();
+        assert_eq!(distances, vec![-2, -1, 1, 2, 3, 4, 5]);
+
+        // The facing page ranks as on screen; both pages of a spread rank together.
+        let priority =
+            |distance| tasks.iter().find(|task| task.distance == distance).unwrap().priority;
+        assert_eq!(priority(1), 1.0);
+        assert_eq!(priority(2), priority(3));
+        assert_eq!(priority(-2), priority(-1));
+        assert_eq!(priority(2), priority(-1));
+        assert!(priority(3) > priority(4));
+        assert_eq!(priority(4), priority(5));
+    }
+
+    #[test]
+    fn lists_tokens_of_matching_in_flight_pages() {
+        let center = page("demo", 4);
+        let mut queue = PrefetchQueue::new();
+        queue
+            .plan_window(
+                &center,
+                10,
+                PrefetchPolicy { ahead: 2, behind: 2 },
+                0.0,
+                PageLayout::Single,
+            )
+            .unwrap();
+        let issued: Vec<_> = (0..3).filter_map(|_| queue.next_task()).collect();
+
+        let ahead = queue.active_tokens(|page| page.index > 4);
+        let expected: Vec<_> =
+            issued.iter().filter(|(_, task)| task.distance > 0).map(|(token, _)| *token).collect();
+        assert_eq!(ahead.len(), expected.len());
+        assert!(expected.iter().all(|token| ahead.contains(token)));
+        assert!(queue.active_tokens(|page| page.source_id.as_str() != "demo").is_empty());
     }
 
     #[test]
     fn complete_releases_page_for_future_scheduling() {
         let center = page("demo", 1);
         let mut queue = PrefetchQueue::new();
-        queue.plan_window(&center, 5, PrefetchPolicy { ahead: 2, behind: 0 }, 0.0).unwrap();
+        queue
+            .plan_window(
+                &center,
+                5,
+                PrefetchPolicy { ahead: 2, behind: 0 },
+                0.0,
+                PageLayout::Single,
+            )
+            .unwrap();
 
         let (token, task) = queue.next_task().unwrap();
         assert!(queue.complete(&token));
         assert!(!queue.complete(&token));
 
-        queue.plan_window(&center, 5, PrefetchPolicy { ahead: 2, behind: 0 }, 0.0).unwrap();
+        queue
+            .plan_window(
+                &center,
+                5,
+                PrefetchPolicy { ahead: 2, behind: 0 },
+                0.0,
+                PageLayout::Single,
+            )
+            .unwrap();
         let distances: Vec<i32> =
             (0..queue.len()).filter_map(|_| queue.next_task()).map(|(_, t)| t.distance).collect();
         assert!(distances.contains(&task.distance));
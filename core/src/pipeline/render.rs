@@ -0,0 +1,165 @@
+//! Rendering decoded pages at the size they are displayed.
+
+use std::borrow::Cow;
+
+use crate::codec::DecodedImage;
+use crate::codec::encode::{EncodeFormat, EncodedImage, encode};
+use crate::types::{ImageDimensions, RenderParams};
+
+use super::Result;
+use super::fit::compute_target;
+use super::resize::{ResizeSettings, resize_rgba};
+
+/// Clockwise quarter turns for `rotation` degrees, snapped to the nearest multiple of 90.
+pub fn quarter_turns(rotation: i16) -> u8 {
+    (f32::from(rotation) / 90.0).round().rem_euclid(4.0) as u8
+}
+
+pub(super) fn rotated(dimensions: ImageDimensions, turns: u8) -> ImageDimensions {
+    if turns.is_multiple_of(2) {
+        return dimensions;
+    }
+    ImageDimensions { width: dimensions.height, height: dimensions.width }
+}
+
+/// Size of a thumbnail of `source` whose longest edge is at most `longest`.
+pub fn thumbnail_dimensions(source: ImageDimensions, longest: u32) -> ImageDimensions {
+    let edge = source.width.max(source.height);
+    if edge <= longest || longest == 0 {
+        return source;
+    }
+    let ratio = f64::from(longest) / f64::from(edge);
+    ImageDimensions {
+        width: ((f64::from(source.width) * ratio).round() as u32).max(1),
+        height: ((f64::from(source.height) * ratio).round() as u32).max(1),
+    }
+}
+
+/// Rotate RGBA `image` clockwise by `turns` quarter turns.
+pub fn rotate(image: &DecodedImage, turns: u8) -> DecodedImage {
+    let turns = turns % 4;
+    let ImageDimensions { width, height } = image.dimensions;
+    let (w, h) = (width as usize, height as usize);
+    if turns == 0 || w == 0 || h == 0 {
+        return image.clone();
+    }
+    let dimensions = rotated(image.dimensions, turns);
+    let out_w = dimensions.width as usize;
+    let mut pixels = vec![0; image.pixels.len()];
+    for (index, pixel) in image.pixels.chunks_exact(4).enumerate() {
+        let (x, y) = (index % w, index / w);
+        let (out_x, out_y) = match turns {
+            1 => (h - 1 - y, x),
+            2 => (w - 1 - x, h - 1 - y),
+            _ => (y, w - 1 - x),
+        };
+        let offset = (out_y * out_w + out_x) * 4;
+        pixels[offset..offset + 4].copy_from_slice(pixel);
+    }
+    DecodedImage { dimensions, pixels }
+}
+
+/// Scale and rotate `image` to its display size for `params`, borrowing it when neither is
+/// needed. Params that fail [`RenderParams::validated`] are refused.
+///
+/// Scaling happens before rotation so the rotation only touches the smaller image.
+pub fn scale_to_display<'a>(
+    image: &'a DecodedImage,
+    params: &RenderParams,
+) -> Result<Cow<'a, DecodedImage>> {
+    let params = &params.validated()?;
+    let turns = quarter_turns(params.rotation);
+    let target = rotated(compute_target(image.dimensions, params), turns);
+    let scaled = if target == image.dimensions {
+        Cow::Borrowed(image)
+    } else {
+        let settings = ResizeSettings::new(target).filter(params.filter);
+        Cow::Owned(resize_rgba(image, settings)?.into_decoded())
+    };
+    if turns == 0 {
+        return Ok(scaled);
+    }
+    Ok(Cow::Owned(rotate(&scaled, turns)))
+}
+
+/// Scale `image` to its display size for `params` and encode it for caching.
+pub fn render(image: &DecodedImage, params: &RenderParams) -> Result<EncodedImage> {
+    let format = EncodeFormat::for_image(image);
+    let scaled = scale_to_display(image, params)?;
+    encode(&scaled, format)
+}
+
+/// Downscale `image` to fit `longest` and encode it compactly for the thumbnail strip.
+pub fn render_thumbnail(image: &DecodedImage, longest: u32) -> Result<EncodedImage> {
+    let target = thumbnail_dimensions(image.dimensions, longest);
+    let format = EncodeFormat::for_thumbnail(image);
+    if target == image.dimensions {
+        return encode(image, format);
+    }
+    let resized = resize_rgba(image, ResizeSettings::new(target))?;
+    encode(&resized.into_decoded(), format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snaps_rotation_to_quarter_turns() {
+        assert_eq!(quarter_turns(0), 0);
+        assert_eq!(quarter_turns(-90), 3);
+        assert_eq!(quarter_turns(450), 1);
+        assert_eq!(quarter_turns(175), 2);
+    }
+
+    #[test]
+    fn rotates_pixels_clockwise() {
+        // 2x1: red, green.
+        let image = DecodedImage {
+            dimensions: ImageDimensions { width: 2, height: 1 },
+            pixels: vec![255, 0, 0, 255, 0, 255, 0, 255],
+        };
+        let quarter = rotate(&image, 1);
+        assert_eq!(quarter.dimensions, ImageDimensions { width: 1, height: 2 });
+        assert_eq!(quarter.pixels, image.pixels);
+        let half = rotate(&image, 2);
+        assert_eq!(half.pixels, vec![0, 255, 0, 255, 255, 0, 0, 255]);
+        let three = rotate(&image, 3);
+        assert_eq!(three.dimensions, ImageDimensions { width: 1, height: 2 });
+        assert_eq!(three.pixels, half.pixels);
+
+        let params = RenderParams { rotation: 270, viewport_w: 10, ..RenderParams::default() };
+        let rendered = scale_to_display(&image, &params).unwrap();
+        assert_eq!(rendered.pixels, half.pixels);
+    }
+
+    #[test]
+    fn renders_scaled_frames() {
+        let image = DecodedImage {
+            dimensions: ImageDimensions { width: 40, height: 20 },
+            pixels: vec![255; 40 * 20 * 4],
+        };
+        let params = RenderParams { viewport_w: 10, viewport_h: 10, ..RenderParams::default() };
+        let rendered = render(&image, &params).unwrap();
+        assert_eq!(rendered.mime, "image/jpeg");
+        let decoded = ::image::load_from_memory(&rendered.bytes).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (10, 5));
+    }
+
+    #[test]
+    fn renders_thumbnails_by_longest_edge() {
+        let page = ImageDimensions { width: 1600, height: 2400 };
+        let thumb = thumbnail_dimensions(page, 256);
+        assert_eq!((thumb.width, thumb.height), (171, 256));
+        assert_eq!(thumbnail_dimensions(page, 4000), page);
+
+        let image = DecodedImage {
+            dimensions: ImageDimensions { width: 60, height: 30 },
+            pixels: vec![255; 60 * 30 * 4],
+        };
+        let rendered = render_thumbnail(&image, 12).unwrap();
+        assert_eq!(rendered.mime, "image/jpeg");
+        let decoded = ::image::load_from_memory(&rendered.bytes).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (12, 6));
+    }
+}
@@ -1,15 +1,18 @@
 //! High-quality image resizing utilities built on top of `fast_image_resize`.
 
-use anyhow::{anyhow, ensure};
+use anyhow::anyhow;
 use fast_image_resize as fir;
+use serde::{Deserialize, Serialize};
 
 use crate::codec::DecodedImage;
+use crate::error::{CoreError, ensure};
 use crate::types::ImageDimensions;
 
 use super::Result;
 
 /// Filtering kernels supported by the resizer.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub enum ResizeFilter {
     /// Fastest option, mostly useful for tests or diagnostic paths.
     Nearest,
@@ -132,11 +135,17 @@ impl ResizedImage {
 pub fn resize_rgba(source: &DecodedImage, settings: ResizeSettings) -> Result<ResizedImage> {
     let src_width = source.width();
     let src_height = source.height();
-    ensure!(src_width > 0 && src_height > 0, "source image has zero dimensions");
+    let _span = tracing::debug_span!(
+        "resize",
+        from = %format_args!("{src_width}x{src_height}"),
+        to = %format_args!("{}x{}", settings.target.width, settings.target.height)
+    )
+    .entered();
+    ensure!(Decode, src_width > 0 && src_height > 0, "source image has zero dimensions");
 
     let dst_width = settings.target.width;
     let dst_height = settings.target.height;
-    ensure!(dst_width > 0 && dst_height > 0, "target dimensions must be non-zero");
+    ensure!(Decode, dst_width > 0 && dst_height > 0, "target dimensions must be non-zero");
 
     if src_width == dst_width && src_height == dst_height {
         return Ok(ResizedImage { dimensions: settings.target, pixels: source.pixels().to_vec() });
@@ -144,13 +153,14 @@ pub fn resize_rgba(source: &DecodedImage, settings: ResizeSettings) -> Result<Re
 
     let src_pixels = source.pixels();
     ensure!(
+        Decode,
         src_pixels.len() >= (src_width as usize * src_height as usize * 4),
         "source buffer is smaller than expected"
     );
 
     let src_view =
         fir::images::ImageRef::new(src_width, src_height, src_pixels, fir::PixelType::U8x4)
-            .map_err(|err| anyhow!("failed to prepare source image: {err}"))?;
+            .map_err(|err| CoreError::Decode(anyhow!("failed to prepare source image: {err}")))?;
 
     let mut dst_image = fir::images::Image::new(dst_width, dst_height, fir::PixelType::U8x4);
 
@@ -161,7 +171,7 @@ pub fn resize_rgba(source: &DecodedImage, settings: ResizeSettings) -> Result<Re
     let mut resizer = fir::Resizer::new();
     resizer
         .resize(&src_view, &mut dst_image, Some(&options))
-        .map_err(|err| anyhow!("fast image resize failed: {err}"))?;
+        .map_err(|err| CoreError::Decode(anyhow!("fast image resize failed: {err}")))?;
 
     let pixels = dst_image.into_vec();
 
@@ -0,0 +1,121 @@
+//! Compose two facing pages into a single spread image.
+
+use crate::codec::DecodedImage;
+use crate::types::{ImageDimensions, ReadingDirection};
+
+use super::Result;
+use super::resize::{ResizeSettings, resize_rgba};
+
+/// Place `first` and `second` next to each other in `direction` with `gutter` transparent
+/// pixels between them: side by side for horizontal directions, `first` above `second` for
+/// [`ReadingDirection::Vertical`].
+///
+/// Side by side, the taller page is scaled down to the height of the shorter one so both share
+/// a baseline; stacked, the wider page is scaled down to the narrower one. Nothing is upscaled.
+pub fn compose_spread(
+    first: &DecodedImage,
+    second: &DecodedImage,
+    direction: ReadingDirection,
+    gutter: u32,
+) -> Result<DecodedImage> {
+    let (left, right) = match direction {
+        ReadingDirection::LeftToRight => (first, second),
+        ReadingDirection::RightToLeft => (second, first),
+        ReadingDirection::Vertical => return stack(first, second, gutter),
+    };
+    let height = left.height().min(right.height());
+    let left = fit_height(left, height)?;
+    let right = fit_height(right, height)?;
+
+    let width = left.width() + gutter + right.width();
+    let stride = width as usize * 4;
+    let mut pixels = vec![0; stride * height as usize];
+    let right_offset = (left.width() + gutter) as usize * 4;
+    for (row, out) in pixels.chunks_exact_mut(stride).enumerate() {
+        for (image, offset) in [(&left, 0), (&right, right_offset)] {
+            let line = image.width() as usize * 4;
+            let start = row * line;
+            out[offset..offset + line].copy_from_slice(&image.pixels[start..start + line]);
+        }
+    }
+    Ok(DecodedImage { dimensions: ImageDimensions { width, height }, pixels })
+}
+
+/// `top` above `bottom`, sharing the narrower width, with `gutter` transparent rows between.
+fn stack(top: &DecodedImage, bottom: &DecodedImage, gutter: u32) -> Result<DecodedImage> {
+    let width = top.width().min(bottom.width());
+    let top = fit_width(top, width)?;
+    let bottom = fit_width(bottom, width)?;
+
+    let stride = width as usize * 4;
+    let height = top.height() + gutter + bottom.height();
+    let mut pixels = Vec::with_capacity(stride * height as usize);
+    pixels.extend_from_slice(&top.pixels);
+    pixels.resize(stride * (top.height() + gutter) as usize, 0);
+    pixels.extend_from_slice(&bottom.pixels);
+    Ok(DecodedImage { dimensions: ImageDimensions { width, height }, pixels })
+}
+
+fn fit_height(image: &DecodedImage, height: u32) -> Result<DecodedImage> {
+    if image.height() == height || image.height() == 0 {
+        return Ok(image.clone());
+    }
+    let ratio = f64::from(height) / f64::from(image.height());
+    let width = ((f64::from(image.width()) * ratio).round() as u32).max(1);
+    scale_to(image, ImageDimensions { width, height })
+}
+
+fn fit_width(image: &DecodedImage, width: u32) -> Result<DecodedImage> {
+    if image.width() == width || image.width() == 0 {
+        return Ok(image.clone());
+    }
+    let ratio = f64::from(width) / f64::from(image.width());
+    let height = ((f64::from(image.height()) * ratio).round() as u32).max(1);
+    scale_to(image, ImageDimensions { width, height })
+}
+
+fn scale_to(image: &DecodedImage, target: ImageDimensions) -> Result<DecodedImage> {
+    Ok(resize_rgba(image, ResizeSettings::new(target))?.into_decoded())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, value: u8) -> DecodedImage {
+        DecodedImage {
+            dimensions: ImageDimensions { width, height },
+            pixels: vec![value; (width * height * 4) as usize],
+        }
+    }
+
+    #[test]
+    fn places_pages_in_reading_order_with_a_gutter() {
+        let (first, second) = (solid(2, 2, 10), solid(3, 2, 20));
+        let ltr = compose_spread(&first, &second, ReadingDirection::LeftToRight, 1).unwrap();
+        assert_eq!(ltr.dimensions, ImageDimensions { width: 6, height: 2 });
+        let row: Vec<u8> = ltr.pixels[..24].chunks_exact(4).map(|px| px[0]).collect();
+        assert_eq!(row, vec![10, 10, 0, 20, 20, 20]);
+
+        let rtl = compose_spread(&first, &second, ReadingDirection::RightToLeft, 1).unwrap();
+        let row: Vec<u8> = rtl.pixels[..24].chunks_exact(4).map(|px| px[0]).collect();
+        assert_eq!(row, vec![20, 20, 20, 0, 10, 10]);
+    }
+
+    #[test]
+    fn stacks_vertical_pages_at_the_narrower_width() {
+        let (first, second) = (solid(2, 1, 10), solid(4, 2, 255));
+        let stacked = compose_spread(&first, &second, ReadingDirection::Vertical, 1).unwrap();
+        assert_eq!(stacked.dimensions, ImageDimensions { width: 2, height: 3 });
+        let column: Vec<u8> = stacked.pixels.chunks_exact(8).map(|row| row[0]).collect();
+        assert_eq!(column, vec![10, 0, 255]);
+    }
+
+    #[test]
+    fn scales_the_taller_page_to_the_shorter_one() {
+        let spread =
+            compose_spread(&solid(4, 8, 255), &solid(4, 4, 255), ReadingDirection::default(), 0)
+                .unwrap();
+        assert_eq!(spread.dimensions, ImageDimensions { width: 6, height: 4 });
+    }
+}
@@ -1,7 +1,7 @@
 //! Slice extremely tall pages into smaller vertical tiles for efficient rendering.
 
 use crate::codec::DecodedImage;
-use crate::types::{ImageDimensions, ImageKey};
+use crate::types::{ImageDimensions, ImageKey, ImageVariant};
 
 use super::Result;
 
@@ -66,7 +66,7 @@ pub fn slice_vertical(
         let end_byte = (end_row as usize) * stride;
         pixels.extend_from_slice(&source.pixels()[start_byte..end_byte]);
 
-        let key = base_key.derive(format!("tile{index}"));
+        let key = base_key.with_variant(ImageVariant::Tile(index));
         let image = DecodedImage {
             dimensions: ImageDimensions { width: source.width(), height: tile_height },
             pixels,
@@ -86,6 +86,11 @@ pub fn slice_vertical(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::{PageId, SourceId};
+
+    fn page_key(index: u32) -> ImageKey {
+        ImageKey::original(&PageId { source_id: SourceId::new("src-test"), index })
+    }
 
     fn tall_image(width: u32, height: u32, value: u8) -> DecodedImage {
         let pixels = vec![value; (width * height * 4) as usize];
@@ -95,7 +100,7 @@ mod tests {
     #[test]
     fn returns_empty_when_not_tall_enough() {
         let image = tall_image(1024, 2048, 10);
-        let key = ImageKey::new("page::1");
+        let key = page_key(1);
         let tiles = slice_vertical(&image, &key, TileConfig::default()).unwrap();
         assert!(tiles.is_empty());
     }
@@ -103,7 +108,7 @@ mod tests {
     #[test]
     fn slices_long_image_into_overlapping_tiles() {
         let image = tall_image(512, 4096, 42);
-        let key = ImageKey::new("page::webtoon");
+        let key = page_key(2);
         let config = TileConfig::default();
         let tiles = slice_vertical(&image, &key, config).unwrap();
 
@@ -121,7 +126,7 @@ mod tests {
     #[test]
     fn ensures_last_tile_reaches_bottom() {
         let image = tall_image(400, 5000, 99);
-        let key = ImageKey::new("page::long");
+        let key = page_key(3);
         let tiles = slice_vertical(&image, &key, TileConfig::default()).unwrap();
         let last = tiles.last().unwrap();
         assert_eq!(last.offset_y + last.image.dimensions.height, image.height());
@@ -130,11 +135,12 @@ mod tests {
     #[test]
     fn derives_unique_keys_per_tile() {
         let image = tall_image(300, 3000, 55);
-        let key = ImageKey::new("page::unique");
+        let key = page_key(4);
         let tiles = slice_vertical(&image, &key, TileConfig::default()).unwrap();
         let mut unique = std::collections::HashSet::new();
         for tile in tiles {
-            assert!(unique.insert(tile.key.cache_key));
+            assert_eq!(tile.key.variant, ImageVariant::Tile(tile.index));
+            assert!(unique.insert(tile.key));
         }
     }
 }
@@ -0,0 +1,59 @@
+//! Orderly shutdown of the background work and buffers the core keeps for the process.
+//!
+//! The shell calls [`shutdown`] once when the app exits so queued work stops cleanly and
+//! nothing buffered in memory is lost to a quick quit.
+
+use std::time::{Duration, Instant};
+
+use crate::pipeline::pool::WorkerPool;
+use crate::store::progress;
+
+/// What [`shutdown`] did, for the shell to log.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Queued prefetch tasks dropped before they started.
+    pub cancelled_tasks: usize,
+    /// Progress entries written by the default progress store.
+    pub progress_written: usize,
+    pub elapsed: Duration,
+}
+
+/// Stop `pools`, waiting for their running tasks, write the progress buffered by the default
+/// store, and flush the log file. Stores owned by the shell are flushed by the shell.
+pub fn shutdown(pools: &[&WorkerPool]) -> ShutdownReport {
+    let started = Instant::now();
+    let cancelled_tasks = pools.iter().map(|pool| pool.shutdown()).sum();
+    let progress_written = progress::flush_default().unwrap_or_else(|err| {
+        tracing::warn!(target: "shutdown", "failed to flush progress: {err:#}");
+        0
+    });
+    let report = ShutdownReport { cancelled_tasks, progress_written, elapsed: started.elapsed() };
+    tracing::info!(
+        target: "shutdown",
+        cancelled_tasks,
+        progress_written,
+        elapsed_ms = report.elapsed.as_millis() as u64,
+        "core shut down"
+    );
+    if let Some(handle) = crate::log::handle() {
+        handle.flush();
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::StatsCollector;
+    use std::sync::Arc;
+
+    #[test]
+    fn stops_every_pool() {
+        let pools: Vec<WorkerPool> = (0..2)
+            .map(|_| WorkerPool::new(1, Arc::new(StatsCollector::default())).unwrap())
+            .collect();
+        let report = shutdown(&pools.iter().collect::<Vec<_>>());
+        assert_eq!(report.cancelled_tasks, 0);
+        assert!(pools.iter().all(|pool| pool.threads() == 0));
+    }
+}
@@ -0,0 +1,142 @@
+//! Decode latency broken down by source, image format and image size.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::histogram::WindowedHistogram;
+
+/// Decodes kept per label.
+const LABEL_SAMPLE_CAPACITY: usize = 240;
+/// Labels tracked per kind; the least recently used one is dropped to make room.
+const MAX_LABELS_PER_KIND: usize = 64;
+
+/// Optional context of a decode measurement. Unset fields are not aggregated.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DecodeLabels<'a> {
+    /// Image key of the decoded page; only reported with slow decodes, never aggregated.
+    pub key: Option<&'a str>,
+    /// Identifier of the source (archive or folder) the page came from.
+    pub source: Option<&'a str>,
+    /// Image format, e.g. `"jpeg"` or `"avif"`.
+    pub format: Option<&'a str>,
+    /// Decoded width times height.
+    pub pixels: Option<u64>,
+}
+
+/// Dimension a [`DecodeLabelStats`] entry is aggregated over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LabelKind {
+    Source,
+    Format,
+    /// Source and format together, labelled `"<source> <format>"`.
+    SourceFormat,
+    /// Size class of the decoded image, e.g. `"4-16MP"`.
+    Size,
+}
+
+/// Decode latency of one label.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecodeLabelStats {
+    pub kind: LabelKind,
+    pub label: String,
+    /// Decodes in the window.
+    pub count: u64,
+    pub mean_ms: f32,
+    pub p50_ms: f32,
+    pub p95_ms: f32,
+    pub p99_ms: f32,
+    pub max_ms: f32,
+}
+
+#[derive(Debug)]
+struct LabelWindow {
+    window: WindowedHistogram,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct LabeledDecodes {
+    labels: HashMap<(LabelKind, String), LabelWindow>,
+    clock: u64,
+}
+
+impl LabeledDecodes {
+    pub(super) fn record(&mut self, duration: Duration, labels: DecodeLabels<'_>) {
+        if let Some(source) = labels.source {
+            self.record_label(LabelKind::Source, source, duration);
+        }
+        if let Some(format) = labels.format {
+            self.record_label(LabelKind::Format, format, duration);
+        }
+        if let (Some(source), Some(format)) = (labels.source, labels.format) {
+            self.record_label(LabelKind::SourceFormat, &format!("{source} {format}"), duration);
+        }
+        if let Some(pixels) = labels.pixels {
+            self.record_label(LabelKind::Size, size_class(pixels), duration);
+        }
+    }
+
+    /// Per-label statistics, slowest (by p95) first.
+    pub(super) fn breakdown(&self) -> Vec<DecodeLabelStats> {
+        let mut stats: Vec<_> = self
+            .labels
+            .iter()
+            .map(|((kind, label), entry)| {
+                let histogram = entry.window.histogram();
+                DecodeLabelStats {
+                    kind: *kind,
+                    label: label.clone(),
+                    count: histogram.len(),
+                    mean_ms: histogram.mean_ms(),
+                    p50_ms: histogram.percentile_ms(0.50),
+                    p95_ms: histogram.percentile_ms(0.95),
+                    p99_ms: histogram.percentile_ms(0.99),
+                    max_ms: histogram.max_ms(),
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| {
+            b.p95_ms.total_cmp(&a.p95_ms).then_with(|| (a.kind, &a.label).cmp(&(b.kind, &b.label)))
+        });
+        stats
+    }
+
+    fn record_label(&mut self, kind: LabelKind, label: &str, duration: Duration) {
+        self.clock += 1;
+        let key = (kind, label.to_string());
+        if !self.labels.contains_key(&key) {
+            self.evict_if_full(kind);
+        }
+        let entry = self.labels.entry(key).or_insert_with(|| LabelWindow {
+            window: WindowedHistogram::new(LABEL_SAMPLE_CAPACITY),
+            last_used: 0,
+        });
+        entry.window.record(duration);
+        entry.last_used = self.clock;
+    }
+
+    fn evict_if_full(&mut self, kind: LabelKind) {
+        let same_kind: Vec<_> =
+            self.labels.iter().filter(|((other, _), _)| *other == kind).collect();
+        if same_kind.len() < MAX_LABELS_PER_KIND {
+            return;
+        }
+        if let Some((oldest, _)) = same_kind.into_iter().min_by_key(|(_, entry)| entry.last_used) {
+            let oldest = oldest.clone();
+            self.labels.remove(&oldest);
+        }
+    }
+}
+
+fn size_class(pixels: u64) -> &'static str {
+    const MEGAPIXEL: u64 = 1_000_000;
+    match pixels {
+        p if p < MEGAPIXEL => "<1MP",
+        p if p < 4 * MEGAPIXEL => "1-4MP",
+        p if p < 16 * MEGAPIXEL => "4-16MP",
+        _ => "16MP+",
+    }
+}
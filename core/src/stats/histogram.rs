@@ -0,0 +1,325 @@
+//! Fixed-bucket latency histograms.
+//!
+//! Values are recorded in microseconds into log-linear buckets (the HDR histogram layout): values
+//! below [`SUB_BUCKETS`] get a bucket each, larger values share buckets whose width doubles with
+//! every power of two, keeping the relative error of any reported value below 1/64. Queries walk
+//! the fixed bucket array, so their cost does not depend on how many samples were recorded.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Number of buckets per power of two (and of exact buckets for small values).
+const SUB_BUCKETS: u64 = 128;
+const HALF_SUB_BUCKETS: u64 = SUB_BUCKETS / 2;
+const SUB_BUCKET_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+
+/// Largest value tracked exactly enough to be useful (a bit over an hour); larger values are
+/// clamped.
+pub const MAX_TRACKABLE_US: u64 = u32::MAX as u64;
+
+/// Latency histogram over every recorded value.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    sum_us: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self { counts: vec![0; bucket_index(MAX_TRACKABLE_US) + 1], total: 0, sum_us: 0 }
+    }
+
+    /// Record one duration.
+    pub fn record(&mut self, duration: Duration) {
+        self.record_us(to_us(duration));
+    }
+
+    /// Number of recorded values.
+    pub fn len(&self) -> u64 {
+        self.total
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// Forget every recorded value.
+    pub fn clear(&mut self) {
+        self.counts.fill(0);
+        self.total = 0;
+        self.sum_us = 0;
+    }
+
+    /// Mean of the recorded values in milliseconds, `0.0` when empty.
+    pub fn mean_ms(&self) -> f32 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.sum_us as f32 / self.total as f32 / 1_000.0
+    }
+
+    /// Value at `quantile` (`0.0..=1.0`) in milliseconds, `0.0` when empty.
+    ///
+    /// Reports the highest value sharing a bucket with the sample of that rank.
+    pub fn percentile_ms(&self, quantile: f64) -> f32 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        let rank = ((quantile.clamp(0.0, 1.0) * self.total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return to_ms(highest_equivalent(index));
+            }
+        }
+        self.max_ms()
+    }
+
+    /// Smallest recorded value in milliseconds, `0.0` when empty.
+    pub fn min_ms(&self) -> f32 {
+        self.counts.iter().position(|&count| count > 0).map_or(0.0, |i| to_ms(lowest_equivalent(i)))
+    }
+
+    /// Largest recorded value in milliseconds, `0.0` when empty.
+    pub fn max_ms(&self) -> f32 {
+        self.counts
+            .iter()
+            .rposition(|&count| count > 0)
+            .map_or(0.0, |i| to_ms(highest_equivalent(i)))
+    }
+
+    /// Halve every bucket `times` times, scaling the sum so the mean is preserved.
+    pub fn halve(&mut self, times: u32) {
+        if times == 0 || self.total == 0 {
+            return;
+        }
+        let before = self.total;
+        for count in &mut self.counts {
+            *count = count.checked_shr(times).unwrap_or(0);
+        }
+        self.total = self.counts.iter().sum();
+        self.sum_us = (self.sum_us as f64 * self.total as f64 / before as f64) as u64;
+    }
+
+    fn record_us(&mut self, value_us: u64) {
+        self.counts[bucket_index(value_us)] += 1;
+        self.total += 1;
+        self.sum_us = self.sum_us.saturating_add(value_us);
+    }
+
+    fn remove_us(&mut self, value_us: u64) {
+        let count = &mut self.counts[bucket_index(value_us)];
+        if *count > 0 {
+            *count -= 1;
+            self.total -= 1;
+            self.sum_us = self.sum_us.saturating_sub(value_us);
+        }
+    }
+}
+
+/// Histogram over the most recent `capacity` values.
+///
+/// Only the raw values are queued (four bytes each) so the oldest one can be taken out of its
+/// bucket when it falls out of the window.
+#[derive(Debug, Clone)]
+pub struct WindowedHistogram {
+    histogram: Histogram,
+    samples: VecDeque<u32>,
+    capacity: usize,
+}
+
+impl WindowedHistogram {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self { histogram: Histogram::new(), samples: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    /// Record one duration, evicting the oldest value once the window is full.
+    pub fn record(&mut self, duration: Duration) {
+        if self.samples.len() == self.capacity
+            && let Some(oldest) = self.samples.pop_front()
+        {
+            self.histogram.remove_us(u64::from(oldest));
+        }
+        let value_us = to_us(duration);
+        self.samples.push_back(value_us as u32);
+        self.histogram.record_us(value_us);
+    }
+
+    /// Maximum number of values in the window.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Histogram of the values currently in the window.
+    pub fn histogram(&self) -> &Histogram {
+        &self.histogram
+    }
+}
+
+/// Histogram over every recorded value in which older values lose half their weight every
+/// `half_life`.
+///
+/// Decay is applied in whole half-lives when the histogram is next recorded into or read, so
+/// long sessions are dominated by recent behaviour without a fixed sample count.
+#[derive(Debug, Clone)]
+pub struct DecayingHistogram {
+    histogram: Histogram,
+    half_life: Duration,
+    last_decay: Instant,
+}
+
+impl DecayingHistogram {
+    pub fn new(half_life: Duration) -> Self {
+        Self {
+            histogram: Histogram::new(),
+            half_life: half_life.max(Duration::from_millis(1)),
+            last_decay: Instant::now(),
+        }
+    }
+
+    /// Interval after which a value counts half as much.
+    pub fn half_life(&self) -> Duration {
+        self.half_life
+    }
+
+    /// Record one duration.
+    pub fn record(&mut self, duration: Duration) {
+        self.record_at(duration, Instant::now());
+    }
+
+    /// Histogram of the decayed values.
+    pub fn histogram(&mut self) -> &Histogram {
+        self.decay(Instant::now());
+        &self.histogram
+    }
+
+    fn record_at(&mut self, duration: Duration, now: Instant) {
+        self.decay(now);
+        self.histogram.record(duration);
+    }
+
+    fn decay(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_decay);
+        let halvings = elapsed.as_nanos() / self.half_life.as_nanos();
+        if halvings == 0 {
+            return;
+        }
+        self.histogram.halve(u32::try_from(halvings).unwrap_or(u32::MAX));
+        self.last_decay +=
+            self.half_life.saturating_mul(u32::try_from(halvings).unwrap_or(u32::MAX));
+    }
+}
+
+fn to_us(duration: Duration) -> u64 {
+    (duration.as_micros() as u64).min(MAX_TRACKABLE_US)
+}
+
+fn to_ms(value_us: u64) -> f32 {
+    value_us as f32 / 1_000.0
+}
+
+fn bucket_index(value_us: u64) -> usize {
+    if value_us < SUB_BUCKETS {
+        return value_us as usize;
+    }
+    // Keep the top `SUB_BUCKET_BITS` bits: the shift selects the power of two, the remaining
+    // mantissa (always in `HALF_SUB_BUCKETS..SUB_BUCKETS`) the bucket within it.
+    let shift = u64::BITS - value_us.leading_zeros() - SUB_BUCKET_BITS;
+    (u64::from(shift) * HALF_SUB_BUCKETS + (value_us >> shift)) as usize
+}
+
+fn bucket_bounds(index: usize) -> (u64, u64) {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return (index, index);
+    }
+    let shift = index / HALF_SUB_BUCKETS - 1;
+    let mantissa = index - shift * HALF_SUB_BUCKETS;
+    (mantissa << shift, ((mantissa + 1) << shift) - 1)
+}
+
+fn lowest_equivalent(index: usize) -> u64 {
+    bucket_bounds(index).0
+}
+
+fn highest_equivalent(index: usize) -> u64 {
+    bucket_bounds(index).1.min(MAX_TRACKABLE_US)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_cover_values_with_bounded_error() {
+        for value in [0, 1, 127, 128, 129, 255, 256, 1_000, 16_667, 123_456_789, MAX_TRACKABLE_US] {
+            let index = bucket_index(value);
+            let (low, high) = bucket_bounds(index);
+            assert!(
+                low <= value && value <= high,
+                "{value} outside bucket {index}: {low}..={high}"
+            );
+            assert!((high - low) as f64 <= value as f64 / HALF_SUB_BUCKETS as f64);
+        }
+        assert_eq!(bucket_index(127) + 1, bucket_index(128));
+    }
+
+    #[test]
+    fn reports_percentiles_min_and_max() {
+        let mut histogram = Histogram::new();
+        assert_eq!(histogram.percentile_ms(0.5), 0.0);
+        for ms in 1..=100 {
+            histogram.record(Duration::from_millis(ms));
+        }
+
+        assert_eq!(histogram.len(), 100);
+        let close = |actual: f32, expected: f32| (actual - expected).abs() <= expected / 64.0;
+        assert!(close(histogram.percentile_ms(0.5), 50.0));
+        assert!(close(histogram.percentile_ms(0.99), 99.0));
+        assert!(close(histogram.min_ms(), 1.0));
+        assert!(close(histogram.max_ms(), 100.0));
+        assert!(close(histogram.mean_ms(), 50.5));
+    }
+
+    #[test]
+    fn window_forgets_old_values() {
+        let mut window = WindowedHistogram::new(3);
+        for ms in [500, 10, 20, 30] {
+            window.record(Duration::from_millis(ms));
+        }
+
+        let histogram = window.histogram();
+        assert_eq!(histogram.len(), 3);
+        assert!(histogram.max_ms() < 31.0);
+        assert!((histogram.mean_ms() - 20.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn decay_halves_older_values() {
+        let mut decaying = DecayingHistogram::new(Duration::from_secs(60));
+        let start = decaying.last_decay;
+        for _ in 0..8 {
+            decaying.record_at(Duration::from_millis(100), start);
+        }
+        // Two half-lives later the old values weigh a quarter: 2 against 6 new ones.
+        let later = start + Duration::from_secs(150);
+        for _ in 0..6 {
+            decaying.record_at(Duration::from_millis(10), later);
+        }
+
+        assert_eq!(decaying.histogram.len(), 8);
+        assert!(decaying.histogram.percentile_ms(0.75) < 11.0);
+        assert!(decaying.histogram.percentile_ms(0.9) >= 100.0);
+        assert!((decaying.histogram.mean_ms() - 32.5).abs() < 0.01);
+        assert_eq!(decaying.last_decay, start + Duration::from_secs(120));
+    }
+}
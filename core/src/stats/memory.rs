@@ -0,0 +1,116 @@
+//! Memory usage of the current process, read from platform APIs.
+
+/// Memory figures of the current process; fields are `None` where the platform does not expose
+/// them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProcessMemory {
+    /// Physical memory currently mapped into the process (resident set / working set).
+    pub resident_bytes: Option<u64>,
+    /// Private anonymous memory, which is mostly heap: `RssAnon` on Linux, the private commit
+    /// charge on Windows.
+    pub private_bytes: Option<u64>,
+}
+
+/// Read the current process's memory usage.
+pub fn process_memory() -> ProcessMemory {
+    imp::process_memory()
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod imp {
+    use super::ProcessMemory;
+
+    pub(super) fn process_memory() -> ProcessMemory {
+        match std::fs::read_to_string("/proc/self/status") {
+            Ok(status) => parse_status(&status),
+            Err(err) => {
+                tracing::debug!(target: "stats::memory", "failed to read process status: {err}");
+                ProcessMemory::default()
+            }
+        }
+    }
+
+    pub(super) fn parse_status(status: &str) -> ProcessMemory {
+        let field = |name: &str| {
+            status.lines().find_map(|line| {
+                let value = line.strip_prefix(name)?.strip_prefix(':')?;
+                let kib: u64 = value.trim().strip_suffix("kB")?.trim().parse().ok()?;
+                Some(kib * 1024)
+            })
+        };
+        ProcessMemory { resident_bytes: field("VmRSS"), private_bytes: field("RssAnon") }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use super::ProcessMemory;
+
+    pub(super) fn process_memory() -> ProcessMemory {
+        let mut info = std::mem::MaybeUninit::<libc::proc_taskinfo>::zeroed();
+        let size = std::mem::size_of::<libc::proc_taskinfo>() as libc::c_int;
+        // SAFETY: the buffer is a properly sized `proc_taskinfo`, which `PROC_PIDTASKINFO` fills.
+        let written = unsafe {
+            libc::proc_pidinfo(
+                libc::getpid(),
+                libc::PROC_PIDTASKINFO,
+                0,
+                info.as_mut_ptr().cast(),
+                size,
+            )
+        };
+        if written != size {
+            return ProcessMemory::default();
+        }
+        // SAFETY: fully written by the successful call above.
+        let info = unsafe { info.assume_init() };
+        ProcessMemory { resident_bytes: Some(info.pti_resident_size), private_bytes: None }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::ProcessMemory;
+    use windows_sys::Win32::System::ProcessStatus::{
+        GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS,
+    };
+    use windows_sys::Win32::System::Threading::GetCurrentProcess;
+
+    pub(super) fn process_memory() -> ProcessMemory {
+        let mut counters = PROCESS_MEMORY_COUNTERS::default();
+        let size = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+        counters.cb = size;
+        // SAFETY: the pseudo handle needs no cleanup and `counters` is sized as declared in `cb`.
+        let ok = unsafe { GetProcessMemoryInfo(GetCurrentProcess(), &mut counters, size) };
+        if ok == 0 {
+            return ProcessMemory::default();
+        }
+        ProcessMemory {
+            resident_bytes: Some(counters.WorkingSetSize as u64),
+            private_bytes: Some(counters.PagefileUsage as u64),
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", windows)))]
+mod imp {
+    use super::ProcessMemory;
+
+    pub(super) fn process_memory() -> ProcessMemory {
+        ProcessMemory::default()
+    }
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "android")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_proc_status() {
+        let status = "Name:\treader\nVmRSS:\t  20480 kB\nRssAnon:\t   8192 kB\nThreads:\t4\n";
+        let memory = imp::parse_status(status);
+        assert_eq!(memory.resident_bytes, Some(20 * 1024 * 1024));
+        assert_eq!(memory.private_bytes, Some(8 * 1024 * 1024));
+        assert!(process_memory().resident_bytes.is_some_and(|bytes| bytes > 0));
+    }
+}
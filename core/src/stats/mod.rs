@@ -2,105 +2,236 @@
 //!
 //! The reader exposes lightweight hooks for recording frame cadence, decode latency, and cache
 //! effectiveness. The collected data powers the `stats` IPC command used by the developer HUD.
+//! Snapshots taken with [`StatsCollector::sample`] are also kept in a ring buffer so the HUD can
+//! plot the recent past.
+
+mod decode;
+mod histogram;
+mod memory;
+pub mod openmetrics;
+pub mod report;
+mod slow;
+mod workers;
 
-use std::cmp::Ordering;
 use std::collections::VecDeque;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use serde::Serialize;
 use tracing::warn;
 
+pub use decode::{DecodeLabelStats, DecodeLabels, LabelKind};
+pub use histogram::{DecayingHistogram, Histogram, MAX_TRACKABLE_US, WindowedHistogram};
+pub use memory::{ProcessMemory, process_memory};
+pub use slow::{SlowOpKind, SlowOpThresholds, SlowOperation};
+pub use workers::WORKER_WINDOW;
+
+use decode::LabeledDecodes;
+use slow::SlowOpMonitor;
+use workers::WorkerActivity;
+
 const DEFAULT_SAMPLE_CAPACITY: usize = 240;
 
-#[derive(Debug, Default)]
-struct SampleWindow {
-    samples: VecDeque<f32>,
-    capacity: usize,
+/// Number of most recent samples the latency percentiles are computed over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleWindows {
+    pub frames: usize,
+    pub decodes: usize,
 }
 
-impl SampleWindow {
-    fn new(capacity: usize) -> Self {
-        Self { samples: VecDeque::with_capacity(capacity), capacity }
+impl Default for SampleWindows {
+    fn default() -> Self {
+        Self { frames: DEFAULT_SAMPLE_CAPACITY, decodes: DEFAULT_SAMPLE_CAPACITY }
     }
+}
+
+/// Sampling configuration of a [`StatsCollector`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatsConfig {
+    /// Number of most recent samples the latency percentiles cover.
+    pub windows: SampleWindows,
+    /// When set, latency percentiles cover every sample instead of a window, with older
+    /// samples losing half their weight each `decay` interval.
+    pub decay: Option<Duration>,
+}
 
-    fn push(&mut self, value: f32) {
-        if self.samples.len() == self.capacity {
-            self.samples.pop_front();
+/// Latency samples, either a fixed window or an exponentially decaying histogram.
+#[derive(Debug)]
+enum LatencySamples {
+    Window(WindowedHistogram),
+    Decaying(DecayingHistogram),
+}
+
+impl LatencySamples {
+    fn new(window: usize, decay: Option<Duration>) -> Self {
+        match decay {
+            Some(half_life) => Self::Decaying(DecayingHistogram::new(half_life)),
+            None => Self::Window(WindowedHistogram::new(window)),
         }
-        self.samples.push_back(value);
     }
 
-    fn percentile(&self, percentile: f32) -> f32 {
-        if self.samples.is_empty() {
-            return 0.0;
+    fn record(&mut self, duration: Duration) {
+        match self {
+            Self::Window(window) => window.record(duration),
+            Self::Decaying(decaying) => decaying.record(duration),
         }
-
-        let mut sorted: Vec<f32> = self.samples.iter().copied().collect();
-        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
-
-        let rank = percentile.clamp(0.0, 1.0) * (sorted.len() - 1) as f32;
-        let index = rank.round() as usize;
-        sorted.get(index).copied().unwrap_or(0.0)
     }
 
-    fn mean(&self) -> f32 {
-        if self.samples.is_empty() {
-            return 0.0;
+    fn histogram(&mut self) -> &Histogram {
+        match self {
+            Self::Window(window) => window.histogram(),
+            Self::Decaying(decaying) => decaying.histogram(),
         }
-        let sum: f32 = self.samples.iter().copied().sum();
-        sum / self.samples.len() as f32
     }
 }
 
+/// Longest interval between reported frame timestamps still counted as a frame; longer gaps
+/// (e.g. while the window is hidden and the UI stops rendering) are skipped.
+pub const MAX_FRAME_GAP: Duration = Duration::from_secs(1);
+
+/// Number of snapshots kept by [`StatsCollector::sample`]: five minutes at one sample a second.
+pub const HISTORY_CAPACITY: usize = 300;
+/// Cadence at which the application is expected to call [`StatsCollector::sample`].
+pub const HISTORY_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Debug)]
 struct StatsInner {
     started_at: Instant,
-    frame_times_ms: SampleWindow,
-    decode_times_ms: SampleWindow,
+    config: StatsConfig,
+    frame_times: LatencySamples,
+    /// Last timestamp passed to `record_frame_timestamps`, in milliseconds.
+    last_frame_ms: Option<f64>,
+    decode_times: LatencySamples,
+    decode_labels: LabeledDecodes,
     cache_requests: u64,
     cache_hits: u64,
     cache_bytes_used: u64,
     cache_bytes_capacity: u64,
+    decoded_bytes: u64,
     prefetch_pending: usize,
+    tasks_started: u64,
+    tasks_completed: u64,
+    tasks_cancelled: u64,
+    workers: WorkerActivity,
+    slow_ops: SlowOpMonitor,
 }
 
-impl Default for StatsInner {
-    fn default() -> Self {
+impl StatsInner {
+    fn new(config: StatsConfig) -> Self {
         Self {
             started_at: Instant::now(),
-            frame_times_ms: SampleWindow::new(DEFAULT_SAMPLE_CAPACITY),
-            decode_times_ms: SampleWindow::new(DEFAULT_SAMPLE_CAPACITY),
+            config,
+            frame_times: LatencySamples::new(config.windows.frames, config.decay),
+            last_frame_ms: None,
+            decode_times: LatencySamples::new(config.windows.decodes, config.decay),
+            decode_labels: LabeledDecodes::default(),
             cache_requests: 0,
             cache_hits: 0,
             cache_bytes_used: 0,
             cache_bytes_capacity: 0,
+            decoded_bytes: 0,
             prefetch_pending: 0,
+            tasks_started: 0,
+            tasks_completed: 0,
+            tasks_cancelled: 0,
+            workers: WorkerActivity::default(),
+            slow_ops: SlowOpMonitor::default(),
         }
     }
 }
 
 /// Thread-safe counter collection consumed by the developer instrumentation.
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct StatsCollector {
     inner: parking_lot::Mutex<StatsInner>,
+    history: parking_lot::Mutex<VecDeque<PerfSnapshot>>,
+}
+
+impl Default for StatsCollector {
+    fn default() -> Self {
+        Self::new(StatsConfig::default())
+    }
 }
 
 impl StatsCollector {
-    /// Create a new collector with default sampling capacity.
-    pub fn new() -> Self {
-        Self::default()
+    /// Create a new collector sampling latencies as described by `config`.
+    pub fn new(config: StatsConfig) -> Self {
+        Self {
+            inner: parking_lot::Mutex::new(StatsInner::new(config)),
+            history: parking_lot::Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Sampling configuration, including window changes made since construction.
+    pub fn config(&self) -> StatsConfig {
+        self.inner.lock().config
     }
 
     /// Record the time taken to present a frame.
     pub fn record_frame(&self, duration: Duration) {
         let mut guard = self.inner.lock();
-        guard.frame_times_ms.push(duration.as_secs_f64() as f32 * 1_000.0);
+        guard.frame_times.record(duration);
+    }
+
+    /// Record the intervals between consecutive frame timestamps (in milliseconds on any
+    /// monotonic clock, such as the UI's `requestAnimationFrame` time).
+    ///
+    /// Batches continue from the last timestamp of the previous batch. Timestamps that do not
+    /// advance are ignored, and gaps above [`MAX_FRAME_GAP`] are not counted as frames.
+    pub fn record_frame_timestamps(&self, timestamps_ms: &[f64]) {
+        let mut guard = self.inner.lock();
+        for &timestamp in timestamps_ms.iter().filter(|timestamp| timestamp.is_finite()) {
+            let previous = guard.last_frame_ms.replace(timestamp);
+            let Some(previous) = previous else {
+                continue;
+            };
+            if timestamp <= previous {
+                guard.last_frame_ms = Some(previous);
+                continue;
+            }
+            let interval = Duration::from_secs_f64((timestamp - previous) / 1_000.0);
+            if interval <= MAX_FRAME_GAP {
+                guard.frame_times.record(interval);
+            }
+        }
     }
 
     /// Record the time spent decoding or preparing an image for display.
-    pub fn record_decode(&self, duration: Duration) {
+    ///
+    /// Besides the overall decode latency, the measurement is aggregated under each label that
+    /// is set; see [`decode_breakdown`](Self::decode_breakdown). Decodes slower than the
+    /// configured threshold are reported as a [`SlowOperation`].
+    pub fn record_decode(&self, duration: Duration, labels: DecodeLabels<'_>) {
         let mut guard = self.inner.lock();
-        guard.decode_times_ms.push(duration.as_secs_f64() as f32 * 1_000.0);
+        guard.decode_times.record(duration);
+        guard.decode_labels.record(duration, labels);
+        guard.slow_ops.check(SlowOpKind::Decode, labels.key, duration, now_ms());
+    }
+
+    /// Record the time taken to write `key` to the cache, reporting it if slow.
+    pub fn record_cache_write(&self, key: &str, duration: Duration) {
+        let mut guard = self.inner.lock();
+        guard.slow_ops.check(SlowOpKind::CacheWrite, Some(key), duration, now_ms());
+    }
+
+    /// Thresholds above which operations are reported as slow.
+    pub fn slow_thresholds(&self) -> SlowOpThresholds {
+        self.inner.lock().slow_ops.thresholds()
+    }
+
+    /// Replace the slow-operation thresholds.
+    pub fn set_slow_thresholds(&self, thresholds: SlowOpThresholds) {
+        self.inner.lock().slow_ops.set_thresholds(thresholds);
+    }
+
+    /// Receive every slow operation detected from now on. Each one is also logged as a warning.
+    pub fn subscribe_slow_ops(&self) -> std::sync::mpsc::Receiver<SlowOperation> {
+        self.inner.lock().slow_ops.subscribe()
+    }
+
+    /// Decode latency per source, format, source and format pair, and size class, slowest
+    /// first.
+    pub fn decode_breakdown(&self) -> Vec<DecodeLabelStats> {
+        self.inner.lock().decode_labels.breakdown()
     }
 
     /// Record whether a cache lookup produced a hit.
@@ -119,18 +250,100 @@ impl StatsCollector {
         guard.cache_bytes_capacity = capacity_bytes;
     }
 
+    /// Update the bytes of decoded pixel buffers currently held by the pipeline.
+    pub fn update_decoded_bytes(&self, bytes: u64) {
+        let mut guard = self.inner.lock();
+        guard.decoded_bytes = bytes;
+    }
+
     /// Update the number of pending prefetch operations.
     pub fn update_prefetch_pending(&self, pending: usize) {
         let mut guard = self.inner.lock();
         guard.prefetch_pending = pending;
     }
 
+    /// Count a prefetch or decode task picked up by a worker.
+    pub fn record_task_started(&self) {
+        let mut guard = self.inner.lock();
+        guard.tasks_started = guard.tasks_started.saturating_add(1);
+    }
+
+    /// Count a task that ran to completion (successfully or not).
+    pub fn record_task_completed(&self) {
+        let mut guard = self.inner.lock();
+        guard.tasks_completed = guard.tasks_completed.saturating_add(1);
+    }
+
+    /// Count a task cancelled before it completed.
+    pub fn record_task_cancelled(&self) {
+        let mut guard = self.inner.lock();
+        guard.tasks_cancelled = guard.tasks_cancelled.saturating_add(1);
+    }
+
+    /// Record that `worker` just finished `busy` worth of work. Workers are identified by their
+    /// index in the pool.
+    pub fn record_worker_busy(&self, worker: usize, busy: Duration) {
+        let mut guard = self.inner.lock();
+        guard.workers.record_busy(worker, busy, Instant::now());
+    }
+
+    /// Current sample window sizes.
+    pub fn sample_windows(&self) -> SampleWindows {
+        self.inner.lock().config.windows
+    }
+
+    /// Resize the sample windows. Samples recorded so far are discarded. In decay mode the
+    /// sizes are stored but latencies keep decaying instead of using a window.
+    pub fn set_sample_windows(&self, windows: SampleWindows) {
+        let mut guard = self.inner.lock();
+        guard.config.windows = windows;
+        guard.frame_times = LatencySamples::new(windows.frames, guard.config.decay);
+        guard.decode_times = LatencySamples::new(windows.decodes, guard.config.decay);
+    }
+
+    /// Discard all samples, counters and history, e.g. to measure a specific scenario without
+    /// startup noise.
+    ///
+    /// Gauges describing current state (cache usage, queue depth, decoded bytes), the
+    /// slow-operation thresholds and subscribers, and the sampling configuration are kept.
+    pub fn reset(&self) {
+        let mut guard = self.inner.lock();
+        let config = guard.config;
+        guard.started_at = Instant::now();
+        guard.frame_times = LatencySamples::new(config.windows.frames, config.decay);
+        guard.last_frame_ms = None;
+        guard.decode_times = LatencySamples::new(config.windows.decodes, config.decay);
+        guard.decode_labels = LabeledDecodes::default();
+        guard.cache_requests = 0;
+        guard.cache_hits = 0;
+        guard.tasks_started = 0;
+        guard.tasks_completed = 0;
+        guard.tasks_cancelled = 0;
+        guard.workers = WorkerActivity::default();
+        drop(guard);
+        self.history.lock().clear();
+    }
+
+    /// Frame time at `quantile` (`0.0..=1.0`) over the sample window, in milliseconds.
+    pub fn frame_time_percentile(&self, quantile: f64) -> f32 {
+        self.inner.lock().frame_times.histogram().percentile_ms(quantile)
+    }
+
+    /// Decode time at `quantile` (`0.0..=1.0`) over the sample window, in milliseconds.
+    pub fn decode_time_percentile(&self, quantile: f64) -> f32 {
+        self.inner.lock().decode_times.histogram().percentile_ms(quantile)
+    }
+
     /// Generate a snapshot of the current metrics for presentation to the UI.
     pub fn snapshot(&self) -> PerfSnapshot {
-        let guard = self.inner.lock();
+        let memory = process_memory();
+        let mut guard = self.inner.lock();
+        let guard = &mut *guard;
 
         let uptime = guard.started_at.elapsed();
-        let frame_mean = guard.frame_times_ms.mean();
+        let frames = guard.frame_times.histogram();
+        let decodes = guard.decode_times.histogram();
+        let frame_mean = frames.mean_ms();
         let fps = if frame_mean > f32::EPSILON { 1_000.0 / frame_mean } else { 0.0 };
 
         let cache_requests = guard.cache_requests.max(1);
@@ -140,16 +353,45 @@ impl StatsCollector {
             timestamp_ms: now_ms(),
             uptime_ms: uptime.as_millis() as u64,
             fps,
-            frame_time_ms_p50: guard.frame_times_ms.percentile(0.50),
-            frame_time_ms_p95: guard.frame_times_ms.percentile(0.95),
-            decode_time_ms_p50: guard.decode_times_ms.percentile(0.50),
-            decode_time_ms_p95: guard.decode_times_ms.percentile(0.95),
+            frame_time_ms_p50: frames.percentile_ms(0.50),
+            frame_time_ms_p95: frames.percentile_ms(0.95),
+            frame_time_ms_p99: frames.percentile_ms(0.99),
+            frame_time_ms_max: frames.max_ms(),
+            decode_time_ms_p50: decodes.percentile_ms(0.50),
+            decode_time_ms_p95: decodes.percentile_ms(0.95),
+            decode_time_ms_p99: decodes.percentile_ms(0.99),
+            decode_time_ms_min: decodes.min_ms(),
+            decode_time_ms_max: decodes.max_ms(),
             cache_hit_ratio,
             cache_requests: guard.cache_requests,
             cache_bytes_used: guard.cache_bytes_used,
             cache_bytes_capacity: guard.cache_bytes_capacity,
+            decoded_bytes: guard.decoded_bytes,
+            process_resident_bytes: memory.resident_bytes,
+            process_private_bytes: memory.private_bytes,
             prefetch_pending: guard.prefetch_pending,
+            tasks_started: guard.tasks_started,
+            tasks_completed: guard.tasks_completed,
+            tasks_cancelled: guard.tasks_cancelled,
+            worker_busy_ratio: guard.workers.busy_ratios(Instant::now()),
+        }
+    }
+
+    /// Take a snapshot and append it to the history, dropping the oldest beyond
+    /// [`HISTORY_CAPACITY`].
+    pub fn sample(&self) -> PerfSnapshot {
+        let snapshot = self.snapshot();
+        let mut history = self.history.lock();
+        if history.len() == HISTORY_CAPACITY {
+            history.pop_front();
         }
+        history.push_back(snapshot.clone());
+        snapshot
+    }
+
+    /// Snapshots recorded by [`sample`](Self::sample), oldest first.
+    pub fn history(&self) -> Vec<PerfSnapshot> {
+        self.history.lock().iter().cloned().collect()
     }
 }
 
@@ -171,13 +413,30 @@ pub struct PerfSnapshot {
     pub fps: f32,
     pub frame_time_ms_p50: f32,
     pub frame_time_ms_p95: f32,
+    pub frame_time_ms_p99: f32,
+    pub frame_time_ms_max: f32,
     pub decode_time_ms_p50: f32,
     pub decode_time_ms_p95: f32,
+    pub decode_time_ms_p99: f32,
+    pub decode_time_ms_min: f32,
+    pub decode_time_ms_max: f32,
     pub cache_hit_ratio: f32,
     pub cache_requests: u64,
     pub cache_bytes_used: u64,
     pub cache_bytes_capacity: u64,
+    /// Decoded pixel buffers held by the pipeline, outside the cache.
+    pub decoded_bytes: u64,
+    /// Resident memory of the whole process, when the platform reports it.
+    pub process_resident_bytes: Option<u64>,
+    /// Private (mostly heap) memory of the process, when the platform reports it.
+    pub process_private_bytes: Option<u64>,
+    /// Depth of the prefetch queue.
     pub prefetch_pending: usize,
+    pub tasks_started: u64,
+    pub tasks_completed: u64,
+    pub tasks_cancelled: u64,
+    /// Busy fraction of each worker over the last [`WORKER_WINDOW`], indexed by worker.
+    pub worker_busy_ratio: Vec<f32>,
 }
 
 #[cfg(test)]
@@ -187,7 +446,7 @@ mod tests {
 
     #[test]
     fn percentile_and_mean_are_computed() {
-        let collector = StatsCollector::new();
+        let collector = StatsCollector::default();
         collector.record_frame(Duration::from_millis(10));
         collector.record_frame(Duration::from_millis(20));
         collector.record_frame(Duration::from_millis(30));
@@ -195,20 +454,160 @@ mod tests {
         let snap = collector.snapshot();
         assert!(snap.fps > 40.0 && snap.fps < 120.0);
         assert!(snap.frame_time_ms_p50 >= 10.0);
+        assert!(snap.frame_time_ms_max >= 30.0 && snap.frame_time_ms_max < 31.0);
+        assert!(collector.frame_time_percentile(0.0) < 11.0);
+    }
+
+    #[test]
+    fn frame_timestamps_are_recorded_as_intervals() {
+        let collector = StatsCollector::default();
+        collector.record_frame_timestamps(&[1_000.0, 1_016.0, 1_033.0]);
+        collector.record_frame_timestamps(&[1_033.0, 1_050.0, f64::NAN, 5_000.0, 5_016.0]);
+
+        let snap = collector.snapshot();
+        assert!(snap.fps > 55.0 && snap.fps < 65.0, "fps {}", snap.fps);
+        assert!(snap.frame_time_ms_max < 18.0, "the pause is not a frame");
+        assert_eq!(collector.inner.lock().frame_times.histogram().len(), 4);
     }
 
     #[test]
     fn cache_metrics_are_tracked() {
-        let collector = StatsCollector::new();
+        let collector = StatsCollector::default();
         collector.record_cache_lookup(true);
         collector.record_cache_lookup(false);
         collector.update_cache_usage(128 * 1024 * 1024, 512 * 1024 * 1024);
         collector.update_prefetch_pending(3);
+        collector.update_decoded_bytes(4096);
 
         let snap = collector.snapshot();
         assert_eq!(snap.cache_requests, 2);
         assert!(snap.cache_hit_ratio > 0.0 && snap.cache_hit_ratio < 1.0);
         assert_eq!(snap.cache_bytes_used, 128 * 1024 * 1024);
         assert_eq!(snap.prefetch_pending, 3);
+        assert_eq!(snap.decoded_bytes, 4096);
+    }
+
+    #[test]
+    fn task_counters_and_workers_are_reported() {
+        let collector = StatsCollector::default();
+        collector.record_task_started();
+        collector.record_task_started();
+        collector.record_task_completed();
+        collector.record_task_cancelled();
+        collector.record_worker_busy(1, Duration::from_millis(5));
+
+        let snap = collector.snapshot();
+        assert_eq!((snap.tasks_started, snap.tasks_completed, snap.tasks_cancelled), (2, 1, 1));
+        assert_eq!(snap.worker_busy_ratio.len(), 2);
+        assert!(snap.worker_busy_ratio[1] > 0.0);
+    }
+
+    #[test]
+    fn decode_latency_is_broken_down_by_label() {
+        let collector = StatsCollector::default();
+        let labels = |source, format| DecodeLabels {
+            key: None,
+            source: Some(source),
+            format: Some(format),
+            pixels: Some(2_000_000),
+        };
+        for _ in 0..10 {
+            collector.record_decode(Duration::from_millis(5), labels("vol1", "jpeg"));
+            collector.record_decode(Duration::from_millis(8), labels("vol2", "jpeg"));
+        }
+        collector.record_decode(Duration::from_millis(90), labels("vol2", "avif"));
+        collector.record_decode(Duration::from_millis(1), DecodeLabels::default());
+
+        let breakdown = collector.decode_breakdown();
+        let find = |kind, label: &str| {
+            breakdown.iter().find(|stats| stats.kind == kind && stats.label == label).unwrap()
+        };
+        assert!(breakdown[0].p95_ms >= 90.0);
+        assert!(find(LabelKind::Format, "avif").p50_ms >= 90.0);
+        assert!(find(LabelKind::Format, "jpeg").p95_ms < 9.0);
+        assert_eq!(find(LabelKind::SourceFormat, "vol2 avif").count, 1);
+        assert_eq!(find(LabelKind::Source, "vol2").count, 11);
+        assert_eq!(find(LabelKind::Size, "1-4MP").count, 21);
+        assert_eq!(breakdown.len(), 8, "unlabelled decodes only count overall");
+        assert_eq!(collector.snapshot().decode_time_ms_min, 1.0);
+    }
+
+    #[test]
+    fn slow_operations_are_reported_to_subscribers() {
+        let collector = StatsCollector::default();
+        let slow = collector.subscribe_slow_ops();
+        collector.set_slow_thresholds(SlowOpThresholds {
+            decode: Duration::from_millis(100),
+            ..SlowOpThresholds::default()
+        });
+
+        let labels = DecodeLabels { key: Some("src-1-page-3"), ..DecodeLabels::default() };
+        collector.record_decode(Duration::from_millis(90), labels);
+        collector.record_decode(Duration::from_millis(150), labels);
+        collector.record_cache_write("src-1-page-3", Duration::from_millis(150));
+        collector.record_cache_write("src-1-page-4", Duration::from_millis(250));
+
+        let reported: Vec<_> = slow.try_iter().collect();
+        assert_eq!(reported.len(), 2);
+        assert_eq!(reported[0].kind, SlowOpKind::Decode);
+        assert_eq!(reported[0].key.as_deref(), Some("src-1-page-3"));
+        assert_eq!(reported[0].threshold_ms, 100.0);
+        assert_eq!(reported[1].kind, SlowOpKind::CacheWrite);
+        assert_eq!(reported[1].key.as_deref(), Some("src-1-page-4"));
+    }
+
+    #[test]
+    fn reset_discards_samples_but_keeps_configuration() {
+        let collector = StatsCollector::default();
+        collector.set_sample_windows(SampleWindows { frames: 2, decodes: 500 });
+        for ms in [100, 10, 20] {
+            collector.record_frame(Duration::from_millis(ms));
+        }
+        assert!(collector.snapshot().frame_time_ms_max < 21.0, "window holds two frames");
+
+        collector.record_cache_lookup(true);
+        collector.record_task_started();
+        collector.update_prefetch_pending(4);
+        collector.sample();
+        collector.reset();
+
+        let snap = collector.snapshot();
+        assert_eq!(snap.frame_time_ms_max, 0.0);
+        assert_eq!((snap.cache_requests, snap.tasks_started), (0, 0));
+        assert_eq!(snap.prefetch_pending, 4);
+        assert!(collector.history().is_empty());
+        assert_eq!(collector.sample_windows(), SampleWindows { frames: 2, decodes: 500 });
+    }
+
+    #[test]
+    fn decay_mode_weights_recent_latencies() {
+        let config = StatsConfig { decay: Some(Duration::from_secs(60)), ..StatsConfig::default() };
+        let collector = StatsCollector::new(config);
+        for _ in 0..DEFAULT_SAMPLE_CAPACITY * 2 {
+            collector.record_frame(Duration::from_millis(16));
+        }
+        collector.record_frame(Duration::from_millis(100));
+
+        assert_eq!(collector.config(), config);
+        let snap = collector.snapshot();
+        assert!(snap.frame_time_ms_max >= 100.0);
+        assert!(snap.frame_time_ms_p99 < 17.0, "samples beyond the window size still count");
+    }
+
+    #[test]
+    fn history_keeps_the_latest_samples() {
+        let collector = StatsCollector::default();
+        assert!(collector.history().is_empty());
+
+        for pending in 0..HISTORY_CAPACITY + 5 {
+            collector.update_prefetch_pending(pending);
+            collector.sample();
+        }
+        collector.snapshot();
+
+        let history = collector.history();
+        assert_eq!(history.len(), HISTORY_CAPACITY);
+        assert_eq!(history[0].prefetch_pending, 5);
+        assert_eq!(history.last().unwrap().prefetch_pending, HISTORY_CAPACITY + 4);
     }
 }
@@ -0,0 +1,286 @@
+//! OpenMetrics text exposition of the collected statistics.
+//!
+//! [`render`] produces the text for one scrape; [`MetricsServer`] optionally serves it at
+//! `http://127.0.0.1:<port>/metrics` so the reader can be charted next to other system metrics.
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write as _};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use super::{LabelKind, StatsCollector};
+
+/// Content type of the exposition format.
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+const QUANTILES: [f64; 3] = [0.5, 0.95, 0.99];
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Render the current metrics of `collector` in OpenMetrics text format.
+pub fn render(collector: &StatsCollector) -> String {
+    let snapshot = collector.snapshot();
+    let mut out = String::new();
+
+    gauge(&mut out, "reader_uptime_seconds", "Time since the collector was created.", |out| {
+        sample(out, "reader_uptime_seconds", &[], snapshot.uptime_ms as f64 / 1_000.0);
+    });
+    gauge(&mut out, "reader_fps", "Frames per second over the frame window.", |out| {
+        sample(out, "reader_fps", &[], f64::from(snapshot.fps));
+    });
+
+    family(&mut out, "reader_frame_time_seconds", "summary", "Frame presentation time.");
+    for quantile in QUANTILES {
+        let value = collector.frame_time_percentile(quantile);
+        sample(&mut out, "reader_frame_time_seconds", &[quantile_label(quantile)], ms(value));
+    }
+    family(&mut out, "reader_decode_time_seconds", "summary", "Image decode time.");
+    for quantile in QUANTILES {
+        let value = collector.decode_time_percentile(quantile);
+        sample(&mut out, "reader_decode_time_seconds", &[quantile_label(quantile)], ms(value));
+    }
+
+    let breakdown = collector.decode_breakdown();
+    if !breakdown.is_empty() {
+        family(
+            &mut out,
+            "reader_decode_label_time_seconds",
+            "summary",
+            "Image decode time per source, format and size class.",
+        );
+        for stats in &breakdown {
+            let kind = ("kind", kind_name(stats.kind).to_string());
+            let label = ("label", stats.label.clone());
+            for (quantile, value) in
+                [(0.5, stats.p50_ms), (0.95, stats.p95_ms), (0.99, stats.p99_ms)]
+            {
+                let labels = [kind.clone(), label.clone(), quantile_label(quantile)];
+                sample(&mut out, "reader_decode_label_time_seconds", &labels, ms(value));
+            }
+            let labels = [kind, label];
+            sample(&mut out, "reader_decode_label_time_seconds_count", &labels, stats.count as f64);
+        }
+    }
+
+    counter(&mut out, "reader_cache_requests", "Image cache lookups.", snapshot.cache_requests);
+    gauge(&mut out, "reader_cache_hit_ratio", "Share of cache lookups that hit.", |out| {
+        sample(out, "reader_cache_hit_ratio", &[], f64::from(snapshot.cache_hit_ratio));
+    });
+    gauge(&mut out, "reader_cache_bytes", "Bytes held by the image cache.", |out| {
+        sample(out, "reader_cache_bytes", &[], snapshot.cache_bytes_used as f64);
+    });
+    gauge(&mut out, "reader_cache_capacity_bytes", "Image cache budget.", |out| {
+        sample(out, "reader_cache_capacity_bytes", &[], snapshot.cache_bytes_capacity as f64);
+    });
+    gauge(&mut out, "reader_decoded_bytes", "Decoded pixel buffers held by the pipeline.", |out| {
+        sample(out, "reader_decoded_bytes", &[], snapshot.decoded_bytes as f64);
+    });
+    if let Some(bytes) = snapshot.process_resident_bytes {
+        gauge(&mut out, "reader_process_resident_bytes", "Resident process memory.", |out| {
+            sample(out, "reader_process_resident_bytes", &[], bytes as f64);
+        });
+    }
+    if let Some(bytes) = snapshot.process_private_bytes {
+        gauge(&mut out, "reader_process_private_bytes", "Private process memory.", |out| {
+            sample(out, "reader_process_private_bytes", &[], bytes as f64);
+        });
+    }
+
+    gauge(&mut out, "reader_prefetch_pending", "Depth of the prefetch queue.", |out| {
+        sample(out, "reader_prefetch_pending", &[], snapshot.prefetch_pending as f64);
+    });
+    counter(
+        &mut out,
+        "reader_tasks_started",
+        "Tasks picked up by a worker.",
+        snapshot.tasks_started,
+    );
+    counter(
+        &mut out,
+        "reader_tasks_completed",
+        "Tasks run to completion.",
+        snapshot.tasks_completed,
+    );
+    counter(&mut out, "reader_tasks_cancelled", "Tasks cancelled.", snapshot.tasks_cancelled);
+    if !snapshot.worker_busy_ratio.is_empty() {
+        gauge(&mut out, "reader_worker_busy_ratio", "Busy fraction of each worker.", |out| {
+            for (worker, ratio) in snapshot.worker_busy_ratio.iter().enumerate() {
+                let labels = [("worker", worker.to_string())];
+                sample(out, "reader_worker_busy_ratio", &labels, f64::from(*ratio));
+            }
+        });
+    }
+
+    out.push_str("# EOF\n");
+    out
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    let _ = writeln!(out, "# HELP {name} {help}");
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, samples: impl FnOnce(&mut String)) {
+    family(out, name, "gauge", help);
+    samples(out);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    family(out, name, "counter", help);
+    let _ = writeln!(out, "{name}_total {value}");
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, String)], value: f64) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        out.push('{');
+        for (index, (key, value)) in labels.iter().enumerate() {
+            if index > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{key}=\"{}\"", escape(value));
+        }
+        out.push('}');
+    }
+    let _ = writeln!(out, " {value}");
+}
+
+fn quantile_label(quantile: f64) -> (&'static str, String) {
+    ("quantile", quantile.to_string())
+}
+
+fn ms(value: f32) -> f64 {
+    f64::from(value) / 1_000.0
+}
+
+fn kind_name(kind: LabelKind) -> &'static str {
+    match kind {
+        LabelKind::Source => "source",
+        LabelKind::Format => "format",
+        LabelKind::SourceFormat => "source-format",
+        LabelKind::Size => "size",
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Serves [`render`] over HTTP on a loopback port until stopped or dropped.
+#[derive(Debug)]
+pub struct MetricsServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    /// Listen on `127.0.0.1:port` (`0` picks a free port).
+    pub fn start(port: u16, collector: Arc<StatsCollector>) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopping = Arc::clone(&stop);
+        let thread = std::thread::Builder::new().name("metrics-server".into()).spawn(move || {
+            for stream in listener.incoming() {
+                if stopping.load(Ordering::Acquire) {
+                    break;
+                }
+                let result = stream.and_then(|stream| respond(stream, &collector));
+                if let Err(err) = result {
+                    tracing::debug!(target: "stats::openmetrics", "metrics request failed: {err}");
+                }
+            }
+        })?;
+        tracing::info!(target: "stats::openmetrics", %addr, "serving metrics");
+        Ok(Self { addr, stop, thread: Some(thread) })
+    }
+
+    /// Address the server listens on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stop serving and wait for the server thread to exit.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        self.stop.store(true, Ordering::Release);
+        // Wake the blocking accept so the thread notices the flag.
+        let _ = TcpStream::connect(self.addr);
+        let _ = thread.join();
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn respond(stream: TcpStream, collector: &StatsCollector) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain the headers; the request has no body we care about.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", CONTENT_TYPE, render(collector)),
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "method not allowed\n".to_string()),
+    };
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::DecodeLabels;
+    use std::io::Read;
+
+    #[test]
+    fn renders_and_serves_metrics() {
+        let collector = Arc::new(StatsCollector::default());
+        collector.record_cache_lookup(true);
+        let labels = DecodeLabels { format: Some("a\"vif"), ..DecodeLabels::default() };
+        collector.record_decode(Duration::from_millis(20), labels);
+
+        let text = render(&collector);
+        assert!(text.contains("# TYPE reader_cache_requests counter\n"));
+        assert!(text.contains("reader_cache_requests_total 1\n"));
+        assert!(text.contains("reader_decode_time_seconds{quantile=\"0.5\"} 0.02"));
+        assert!(text.contains("{kind=\"format\",label=\"a\\\"vif\",quantile=\"0.95\"}"));
+        assert!(text.ends_with("# EOF\n"));
+
+        let server = MetricsServer::start(0, Arc::clone(&collector)).unwrap();
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(CONTENT_TYPE));
+        assert!(response.ends_with("# EOF\n"));
+        server.stop();
+    }
+}
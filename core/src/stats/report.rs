@@ -0,0 +1,299 @@
+//! Cache efficiency reports for tuning cache budgets.
+//!
+//! The cache owner feeds its index and lookup counters into a [`CacheReportBuilder`]; the
+//! resulting [`CacheReport`] lists the largest keys, the namespaces that return the fewest hits
+//! for the space they use, and the hit ratio of each source. Its `Display` output is meant to be
+//! read by people.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Number of keys listed under [`CacheReport::largest`] unless configured otherwise.
+pub const DEFAULT_LARGEST_KEYS: usize = 10;
+
+/// Usage of one cached item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntryUsage {
+    pub key: String,
+    /// Kind of item, e.g. `page` or `thumb`.
+    pub namespace: String,
+    pub source: String,
+    pub bytes: u64,
+    pub hits: u64,
+    pub last_access_ms: Option<u64>,
+}
+
+/// Aggregated usage of one namespace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceUsage {
+    pub namespace: String,
+    pub entries: usize,
+    pub bytes: u64,
+    pub hits: u64,
+    /// Most recent access to any entry of the namespace.
+    pub last_access_ms: Option<u64>,
+}
+
+/// Lookup outcomes of one source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceHitRatio {
+    pub source: String,
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_ratio: f32,
+}
+
+/// Summary of how well the cache budget is spent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheReport {
+    pub entries: usize,
+    pub total_bytes: u64,
+    pub budget_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+    /// Largest entries, largest first.
+    pub largest: Vec<CacheEntryUsage>,
+    /// Namespaces ordered by hits per byte, coldest first.
+    pub namespaces: Vec<NamespaceUsage>,
+    /// Sources ordered by hit ratio, worst first.
+    pub sources: Vec<SourceHitRatio>,
+}
+
+impl CacheReport {
+    pub fn builder() -> CacheReportBuilder {
+        CacheReportBuilder::default()
+    }
+
+    /// Share of lookups that hit, `0.0` without lookups.
+    pub fn hit_ratio(&self) -> f32 {
+        ratio(self.hits, self.misses)
+    }
+}
+
+/// Collects cache index entries and lookup counters into a [`CacheReport`].
+#[derive(Debug, Clone)]
+pub struct CacheReportBuilder {
+    entries: Vec<CacheEntryUsage>,
+    lookups: BTreeMap<String, (u64, u64)>,
+    budget_bytes: u64,
+    largest: usize,
+}
+
+impl Default for CacheReportBuilder {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            lookups: BTreeMap::new(),
+            budget_bytes: 0,
+            largest: DEFAULT_LARGEST_KEYS,
+        }
+    }
+}
+
+impl CacheReportBuilder {
+    /// Set the cache budget the usage is compared against.
+    pub fn with_budget(mut self, bytes: u64) -> Self {
+        self.budget_bytes = bytes;
+        self
+    }
+
+    /// Set how many of the largest keys are listed.
+    pub fn with_largest(mut self, count: usize) -> Self {
+        self.largest = count;
+        self
+    }
+
+    /// Add one entry of the cache index.
+    pub fn record_entry(&mut self, entry: CacheEntryUsage) -> &mut Self {
+        self.entries.push(entry);
+        self
+    }
+
+    /// Add lookup counters of `source`; counters of the same source are summed.
+    pub fn record_lookups(&mut self, source: &str, hits: u64, misses: u64) -> &mut Self {
+        let counters = self.lookups.entry(source.to_string()).or_default();
+        counters.0 += hits;
+        counters.1 += misses;
+        self
+    }
+
+    pub fn build(self) -> CacheReport {
+        let total_bytes = self.entries.iter().map(|entry| entry.bytes).sum();
+
+        let mut namespaces: BTreeMap<&str, NamespaceUsage> = BTreeMap::new();
+        for entry in &self.entries {
+            let usage = namespaces.entry(&entry.namespace).or_insert_with(|| NamespaceUsage {
+                namespace: entry.namespace.clone(),
+                entries: 0,
+                bytes: 0,
+                hits: 0,
+                last_access_ms: None,
+            });
+            usage.entries += 1;
+            usage.bytes += entry.bytes;
+            usage.hits += entry.hits;
+            usage.last_access_ms = usage.last_access_ms.max(entry.last_access_ms);
+        }
+        let mut namespaces: Vec<_> = namespaces.into_values().collect();
+        namespaces.sort_by(|a, b| {
+            hits_per_byte(a)
+                .total_cmp(&hits_per_byte(b))
+                .then(a.last_access_ms.cmp(&b.last_access_ms))
+        });
+
+        let mut sources: Vec<_> = self
+            .lookups
+            .into_iter()
+            .map(|(source, (hits, misses))| SourceHitRatio {
+                source,
+                hits,
+                misses,
+                hit_ratio: ratio(hits, misses),
+            })
+            .collect();
+        sources.sort_by(|a, b| a.hit_ratio.total_cmp(&b.hit_ratio));
+        let hits = sources.iter().map(|source| source.hits).sum();
+        let misses = sources.iter().map(|source| source.misses).sum();
+
+        let entries = self.entries.len();
+        let mut largest = self.entries;
+        largest.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.key.cmp(&b.key)));
+        largest.truncate(self.largest);
+
+        CacheReport {
+            entries,
+            total_bytes,
+            budget_bytes: self.budget_bytes,
+            hits,
+            misses,
+            largest,
+            namespaces,
+            sources,
+        }
+    }
+}
+
+impl fmt::Display for CacheReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Cache efficiency report")?;
+        write!(f, "  usage: {} in {} entries", Bytes(self.total_bytes), self.entries)?;
+        if self.budget_bytes > 0 {
+            let share = self.total_bytes as f64 / self.budget_bytes as f64 * 100.0;
+            write!(f, " ({share:.1}% of {})", Bytes(self.budget_bytes))?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "  hit ratio: {:.1}% ({} hits, {} misses)",
+            self.hit_ratio() * 100.0,
+            self.hits,
+            self.misses
+        )?;
+
+        writeln!(f, "Largest keys:")?;
+        for entry in &self.largest {
+            writeln!(
+                f,
+                "  {:>10}  {} ({}, {} hits)",
+                Bytes(entry.bytes).to_string(),
+                entry.key,
+                entry.namespace,
+                entry.hits
+            )?;
+        }
+        writeln!(f, "Coldest namespaces:")?;
+        for usage in &self.namespaces {
+            writeln!(
+                f,
+                "  {}: {} entries, {}, {} hits",
+                usage.namespace,
+                usage.entries,
+                Bytes(usage.bytes),
+                usage.hits
+            )?;
+        }
+        writeln!(f, "Hit ratio per source:")?;
+        for source in &self.sources {
+            writeln!(
+                f,
+                "  {}: {:.1}% ({} hits, {} misses)",
+                source.source,
+                source.hit_ratio * 100.0,
+                source.hits,
+                source.misses
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn ratio(hits: u64, misses: u64) -> f32 {
+    let lookups = hits + misses;
+    if lookups == 0 { 0.0 } else { hits as f32 / lookups as f32 }
+}
+
+fn hits_per_byte(usage: &NamespaceUsage) -> f64 {
+    usage.hits as f64 / usage.bytes.max(1) as f64
+}
+
+/// Byte count rendered with a binary unit.
+struct Bytes(u64);
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+        let mut value = self.0 as f64;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+        if unit == 0 { write!(f, "{} B", self.0) } else { write!(f, "{value:.1} {}", UNITS[unit]) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, namespace: &str, bytes: u64, hits: u64) -> CacheEntryUsage {
+        CacheEntryUsage {
+            key: key.to_string(),
+            namespace: namespace.to_string(),
+            source: "vol1".to_string(),
+            bytes,
+            hits,
+            last_access_ms: Some(hits),
+        }
+    }
+
+    #[test]
+    fn ranks_keys_namespaces_and_sources() {
+        let mut builder = CacheReport::builder().with_budget(4 * 1024 * 1024).with_largest(2);
+        builder
+            .record_entry(entry("vol1-page-0", "page", 1024 * 1024, 12))
+            .record_entry(entry("vol1-page-1", "page", 512 * 1024, 3))
+            .record_entry(entry("vol1-thumb-0-256", "thumb", 2048, 0))
+            .record_entry(entry("vol1-thumb-1-256", "thumb", 4096, 0))
+            .record_lookups("vol1", 15, 5)
+            .record_lookups("vol2", 1, 9)
+            .record_lookups("vol1", 0, 0);
+        let report = builder.build();
+
+        assert_eq!(report.entries, 4);
+        assert_eq!(report.total_bytes, 1024 * 1024 + 512 * 1024 + 6144);
+        let largest: Vec<_> = report.largest.iter().map(|entry| entry.key.as_str()).collect();
+        assert_eq!(largest, ["vol1-page-0", "vol1-page-1"]);
+        assert_eq!(report.namespaces[0].namespace, "thumb");
+        assert_eq!(report.namespaces[1].last_access_ms, Some(12));
+        assert_eq!(report.sources[0].source, "vol2");
+        assert_eq!(report.sources[1].hit_ratio, 0.75);
+        assert_eq!((report.hits, report.misses), (16, 14));
+
+        let text = report.to_string();
+        assert!(text.contains("usage: 1.5 MiB in 4 entries (37.6% of 4.0 MiB)"), "{text}");
+        assert!(text.contains("     1.0 MiB  vol1-page-0 (page, 12 hits)"), "{text}");
+        assert!(text.contains("  thumb: 2 entries, 6.0 KiB, 0 hits"), "{text}");
+        assert!(text.contains("  vol2: 10.0% (1 hits, 9 misses)"), "{text}");
+    }
+}
@@ -0,0 +1,94 @@
+//! Detection of individual operations that exceed a latency budget.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// Latency budgets above which an operation is reported as slow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowOpThresholds {
+    pub decode: Duration,
+    pub cache_write: Duration,
+}
+
+impl Default for SlowOpThresholds {
+    fn default() -> Self {
+        Self { decode: Duration::from_millis(500), cache_write: Duration::from_millis(200) }
+    }
+}
+
+/// Kind of operation a [`SlowOperation`] refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SlowOpKind {
+    Decode,
+    CacheWrite,
+}
+
+/// An operation that took longer than its threshold.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SlowOperation {
+    pub kind: SlowOpKind,
+    /// Image key (or other identifier) of the offending item, when known.
+    pub key: Option<String>,
+    pub duration_ms: f32,
+    pub threshold_ms: f32,
+    pub timestamp_ms: u64,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct SlowOpMonitor {
+    thresholds: SlowOpThresholds,
+    subscribers: Vec<Sender<SlowOperation>>,
+}
+
+impl SlowOpMonitor {
+    pub(super) fn thresholds(&self) -> SlowOpThresholds {
+        self.thresholds
+    }
+
+    pub(super) fn set_thresholds(&mut self, thresholds: SlowOpThresholds) {
+        self.thresholds = thresholds;
+    }
+
+    pub(super) fn subscribe(&mut self) -> Receiver<SlowOperation> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// Report `duration` if it exceeds the threshold for `kind`.
+    pub(super) fn check(
+        &mut self,
+        kind: SlowOpKind,
+        key: Option<&str>,
+        duration: Duration,
+        timestamp_ms: u64,
+    ) {
+        let threshold = match kind {
+            SlowOpKind::Decode => self.thresholds.decode,
+            SlowOpKind::CacheWrite => self.thresholds.cache_write,
+        };
+        if duration <= threshold {
+            return;
+        }
+
+        let operation = SlowOperation {
+            kind,
+            key: key.map(str::to_string),
+            duration_ms: duration.as_secs_f32() * 1_000.0,
+            threshold_ms: threshold.as_secs_f32() * 1_000.0,
+            timestamp_ms,
+        };
+        tracing::warn!(
+            target: "stats::slow",
+            kind = ?operation.kind,
+            key = operation.key.as_deref().unwrap_or("-"),
+            duration_ms = operation.duration_ms,
+            threshold_ms = operation.threshold_ms,
+            "slow operation"
+        );
+        self.subscribers.retain(|subscriber| subscriber.send(operation.clone()).is_ok());
+    }
+}
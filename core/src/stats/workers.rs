@@ -0,0 +1,83 @@
+//! Busy time of the decode/prefetch workers over a sliding window.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Span over which busy ratios are computed.
+pub const WORKER_WINDOW: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+struct Worker {
+    registered_at: Instant,
+    /// Finished busy periods as (end, length), oldest first.
+    periods: VecDeque<(Instant, Duration)>,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct WorkerActivity {
+    workers: Vec<Worker>,
+}
+
+impl WorkerActivity {
+    pub(super) fn record_busy(&mut self, worker: usize, busy: Duration, now: Instant) {
+        if self.workers.len() <= worker {
+            self.workers.resize_with(worker + 1, || Worker {
+                registered_at: now.checked_sub(busy).unwrap_or(now),
+                periods: VecDeque::new(),
+            });
+        }
+        let entry = &mut self.workers[worker];
+        entry.periods.push_back((now, busy));
+        while entry.periods.front().is_some_and(|(end, _)| now.duration_since(*end) > WORKER_WINDOW)
+        {
+            entry.periods.pop_front();
+        }
+    }
+
+    /// Fraction of the last [`WORKER_WINDOW`] (or of its lifetime, if shorter) each worker spent
+    /// busy, indexed by worker.
+    pub(super) fn busy_ratios(&self, now: Instant) -> Vec<f32> {
+        self.workers
+            .iter()
+            .map(|worker| {
+                let window_start = now.checked_sub(WORKER_WINDOW).unwrap_or(worker.registered_at);
+                let start = window_start.max(worker.registered_at);
+                let span = now.duration_since(start);
+                if span.is_zero() {
+                    return 0.0;
+                }
+                let busy: Duration = worker
+                    .periods
+                    .iter()
+                    .map(|&(end, length)| {
+                        // Only count the part of each period inside the window.
+                        let begin = end.checked_sub(length).unwrap_or(end).max(start);
+                        end.saturating_duration_since(begin)
+                    })
+                    .sum();
+                (busy.as_secs_f32() / span.as_secs_f32()).min(1.0)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busy_ratio_covers_the_recent_window() {
+        let start = Instant::now();
+        let mut activity = WorkerActivity::default();
+        activity.record_busy(1, Duration::from_secs(2), start + Duration::from_secs(2));
+        activity.record_busy(1, Duration::from_secs(1), start + Duration::from_secs(4));
+
+        let ratios = activity.busy_ratios(start + Duration::from_secs(4));
+        assert_eq!(ratios.len(), 2, "workers are indexed by id");
+        assert!((ratios[1] - 0.75).abs() < 0.01);
+
+        // Twenty seconds later only the window counts, and nothing happened in it.
+        let ratios = activity.busy_ratios(start + Duration::from_secs(24));
+        assert_eq!(ratios[1], 0.0);
+    }
+}
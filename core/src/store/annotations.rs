@@ -0,0 +1,275 @@
+//! Free-text notes attached to pages, optionally anchored to a region of the page.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{CoreError, ensure};
+use crate::types::{PageId, SourceId};
+
+use super::events::{EventBus, StoreEvent};
+use super::migrate::{self, Schema};
+use super::{Result, json, profile};
+
+const ANNOTATIONS_FILE: &str = "annotations.json";
+
+/// Schema of the per-profile annotations file.
+pub const SCHEMA: Schema =
+    Schema { file_name: ANNOTATIONS_FILE, current: migrate::LEGACY_VERSION, migrations: &[] };
+
+/// Region of a page in normalised coordinates (`0.0..=1.0` of the page width/height).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnnotationRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl AnnotationRect {
+    fn validate(&self) -> Result<()> {
+        let in_unit = |value: f32| (0.0..=1.0).contains(&value);
+        ensure!(
+            Store,
+            in_unit(self.x) && in_unit(self.y),
+            "annotation rect origin must lie within the page"
+        );
+        ensure!(
+            Store,
+            self.width > 0.0 && self.height > 0.0,
+            "annotation rect must have a positive size"
+        );
+        ensure!(
+            Store,
+            self.x + self.width <= 1.0 + f32::EPSILON && self.y + self.height <= 1.0 + f32::EPSILON,
+            "annotation rect must not extend past the page"
+        );
+        Ok(())
+    }
+}
+
+/// A note attached to a page.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotation {
+    pub id: u64,
+    pub page: PageId,
+    pub text: String,
+    pub rect: Option<AnnotationRect>,
+    pub created_ms: u64,
+    pub updated_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredAnnotation {
+    id: u64,
+    source: String,
+    page: u32,
+    text: String,
+    rect: Option<AnnotationRect>,
+    created_ms: u64,
+    updated_ms: u64,
+}
+
+impl StoredAnnotation {
+    fn to_annotation(&self) -> Annotation {
+        Annotation {
+            id: self.id,
+            page: PageId { source_id: SourceId::new(self.source.clone()), index: self.page },
+            text: self.text.clone(),
+            rect: self.rect,
+            created_ms: self.created_ms,
+            updated_ms: self.updated_ms,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AnnotationsFile {
+    #[serde(default)]
+    version: u32,
+    next_id: u64,
+    entries: Vec<StoredAnnotation>,
+}
+
+/// Per-profile store of page annotations.
+#[derive(Debug)]
+pub struct AnnotationStore {
+    root: PathBuf,
+    lock: Mutex<()>,
+    events: EventBus,
+}
+
+impl AnnotationStore {
+    /// Create a store persisting under `root`, creating the directory if needed.
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self { root, lock: Mutex::new(()), events: EventBus::new() })
+    }
+
+    /// Publish changes on `events` instead of a private bus.
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// List annotations for `source`, optionally limited to one page, ordered by page then id.
+    pub fn list(&self, source: &SourceId, page: Option<u32>) -> Result<Vec<Annotation>> {
+        let _guard = self.lock.lock().expect("annotations mutex poisoned");
+        let file: AnnotationsFile = json::read(&self.path()?)?;
+        let mut annotations: Vec<_> = file
+            .entries
+            .iter()
+            .filter(|entry| entry.source == source.as_str())
+            .filter(|entry| page.is_none_or(|index| entry.page == index))
+            .map(StoredAnnotation::to_annotation)
+            .collect();
+        annotations.sort_by_key(|annotation| (annotation.page.index, annotation.id));
+        Ok(annotations)
+    }
+
+    /// Attach a new note to `page`.
+    pub fn add(
+        &self,
+        page: &PageId,
+        text: impl Into<String>,
+        rect: Option<AnnotationRect>,
+    ) -> Result<Annotation> {
+        let text = text.into();
+        validate(&text, rect.as_ref())?;
+
+        let annotation = self.update(|file| {
+            file.next_id = file.next_id.max(1);
+            let now = now_ms();
+            let stored = StoredAnnotation {
+                id: file.next_id,
+                source: page.source_id.as_str().to_string(),
+                page: page.index,
+                text,
+                rect,
+                created_ms: now,
+                updated_ms: now,
+            };
+            file.next_id += 1;
+            let annotation = stored.to_annotation();
+            file.entries.push(stored);
+            Ok(annotation)
+        })?;
+        self.events.emit(StoreEvent::AnnotationAdded(annotation.clone()));
+        Ok(annotation)
+    }
+
+    /// Replace the text and region of an existing note.
+    pub fn edit(
+        &self,
+        id: u64,
+        text: impl Into<String>,
+        rect: Option<AnnotationRect>,
+    ) -> Result<Annotation> {
+        let text = text.into();
+        validate(&text, rect.as_ref())?;
+
+        let annotation = self.update(|file| {
+            let entry = file
+                .entries
+                .iter_mut()
+                .find(|entry| entry.id == id)
+                .ok_or_else(|| CoreError::Store(anyhow!("annotation {id} does not exist")))?;
+            entry.text = text;
+            entry.rect = rect;
+            entry.updated_ms = now_ms();
+            Ok(entry.to_annotation())
+        })?;
+        self.events.emit(StoreEvent::AnnotationEdited(annotation.clone()));
+        Ok(annotation)
+    }
+
+    /// Delete a note. Returns `false` when no note has the given id.
+    pub fn delete(&self, id: u64) -> Result<bool> {
+        let deleted = self.update(|file| {
+            let before = file.entries.len();
+            file.entries.retain(|entry| entry.id != id);
+            Ok(file.entries.len() != before)
+        })?;
+        if deleted {
+            self.events.emit(StoreEvent::AnnotationDeleted { id });
+        }
+        Ok(deleted)
+    }
+
+    fn update<T>(&self, mutate: impl FnOnce(&mut AnnotationsFile) -> Result<T>) -> Result<T> {
+        let _guard = self.lock.lock().expect("annotations mutex poisoned");
+        let path = self.path()?;
+        let mut file: AnnotationsFile = json::read(&path)?;
+        let result = mutate(&mut file)?;
+        file.version = SCHEMA.current;
+        json::write(&path, &file)?;
+        Ok(result)
+    }
+
+    fn path(&self) -> Result<PathBuf> {
+        Ok(profile::active_dir(&self.root)?.join(SCHEMA.file_name))
+    }
+}
+
+fn validate(text: &str, rect: Option<&AnnotationRect>) -> Result<()> {
+    ensure!(Store, !text.trim().is_empty(), "annotation text must not be empty");
+    if let Some(rect) = rect {
+        rect.validate()?;
+    }
+    Ok(())
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(source: &str, index: u32) -> PageId {
+        PageId { source_id: SourceId::new(source), index }
+    }
+
+    #[test]
+    fn add_edit_list_and_delete() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = AnnotationStore::new(temp.path()).unwrap();
+        let rect = AnnotationRect { x: 0.1, y: 0.2, width: 0.5, height: 0.25 };
+
+        let first = store.add(&page("vol1", 4), "typo in bubble", Some(rect)).unwrap();
+        let second = store.add(&page("vol1", 2), "check lettering", None).unwrap();
+        store.add(&page("vol2", 4), "other source", None).unwrap();
+        assert_ne!(first.id, second.id);
+
+        let all = store.list(&SourceId::new("vol1"), None).unwrap();
+        assert_eq!(all.iter().map(|a| a.page.index).collect::<Vec<_>>(), vec![2, 4]);
+        assert_eq!(store.list(&SourceId::new("vol1"), Some(4)).unwrap()[0].rect, Some(rect));
+
+        let edited = store.edit(first.id, "fixed", None).unwrap();
+        assert_eq!(edited.text, "fixed");
+        assert_eq!(edited.rect, None);
+        assert!(store.edit(999, "missing", None).is_err());
+
+        assert!(store.delete(first.id).unwrap());
+        assert!(!store.delete(first.id).unwrap());
+        assert_eq!(store.list(&SourceId::new("vol1"), None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = AnnotationStore::new(temp.path()).unwrap();
+        let outside = AnnotationRect { x: 0.8, y: 0.0, width: 0.5, height: 0.1 };
+
+        assert!(store.add(&page("s", 0), "   ", None).is_err());
+        assert!(store.add(&page("s", 0), "note", Some(outside)).is_err());
+    }
+}
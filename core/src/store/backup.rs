@@ -0,0 +1,317 @@
+//! Timestamped snapshots of the whole state directory.
+//!
+//! A snapshot copies every store file (the profile registry and each profile's JSON files) into
+//! `backups/<timestamp_ms>/` under the state root. Snapshots are taken before schema migrations
+//! and at most once per day, and only the newest [`DEFAULT_KEEP`] are kept.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::error::CoreError;
+
+use super::{Result, json, profile, recovery};
+
+const BACKUPS_DIR: &str = "backups";
+const MANIFEST_FILE: &str = "backup.json";
+const DAY_MS: u64 = 24 * 60 * 60 * 1_000;
+
+/// Number of snapshots kept by default.
+pub const DEFAULT_KEEP: usize = 7;
+
+/// Why a snapshot was taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BackupReason {
+    Daily,
+    PreMigration,
+    PreRestore,
+    Manual,
+}
+
+/// A snapshot on disk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupInfo {
+    /// Creation time; identifies the snapshot for [`restore`].
+    pub timestamp_ms: u64,
+    pub reason: BackupReason,
+    pub files: usize,
+    pub dir: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    version: u32,
+    reason: StoredReason,
+    files: Vec<PathBuf>,
+}
+
+/// A [`BackupReason`] as written to manifests, kebab-cased as it was first written.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum StoredReason {
+    Daily,
+    PreMigration,
+    PreRestore,
+    Manual,
+}
+
+impl From<StoredReason> for BackupReason {
+    fn from(reason: StoredReason) -> Self {
+        match reason {
+            StoredReason::Daily => BackupReason::Daily,
+            StoredReason::PreMigration => BackupReason::PreMigration,
+            StoredReason::PreRestore => BackupReason::PreRestore,
+            StoredReason::Manual => BackupReason::Manual,
+        }
+    }
+}
+
+impl From<BackupReason> for StoredReason {
+    fn from(reason: BackupReason) -> Self {
+        match reason {
+            BackupReason::Daily => StoredReason::Daily,
+            BackupReason::PreMigration => StoredReason::PreMigration,
+            BackupReason::PreRestore => StoredReason::PreRestore,
+            BackupReason::Manual => StoredReason::Manual,
+        }
+    }
+}
+
+/// Snapshot the stores under `root`, then drop all but the newest `keep` snapshots.
+pub fn create(root: &Path, reason: BackupReason, keep: usize) -> Result<BackupInfo> {
+    let files = store_files(root)?;
+    let mut timestamp_ms = now_ms();
+    let mut dir = backups_dir(root).join(timestamp_ms.to_string());
+    while dir.exists() {
+        timestamp_ms += 1;
+        dir = backups_dir(root).join(timestamp_ms.to_string());
+    }
+
+    for relative in &files {
+        let target = dir.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(root.join(relative), &target)?;
+    }
+    fs::create_dir_all(&dir)?;
+    json::write_bytes(
+        &dir.join(MANIFEST_FILE),
+        &serde_json::to_vec_pretty(&Manifest {
+            version: 1,
+            reason: reason.into(),
+            files: files.clone(),
+        })?,
+    )?;
+
+    tracing::info!(
+        target: "store::backup",
+        dir = %dir.display(),
+        ?reason,
+        files = files.len(),
+        "created store backup"
+    );
+    prune(root, keep)?;
+    Ok(BackupInfo { timestamp_ms, reason, files: files.len(), dir })
+}
+
+/// Take a daily snapshot unless one was taken within the last 24 hours.
+pub fn ensure_daily(root: &Path, keep: usize) -> Result<Option<BackupInfo>> {
+    let latest = list(root)?.into_iter().map(|backup| backup.timestamp_ms).max();
+    if latest.is_some_and(|latest| now_ms().saturating_sub(latest) < DAY_MS) {
+        return Ok(None);
+    }
+    create(root, BackupReason::Daily, keep).map(Some)
+}
+
+/// Snapshots under `root`, newest first.
+pub fn list(root: &Path) -> Result<Vec<BackupInfo>> {
+    let dir = backups_dir(root);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut backups = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let Some(timestamp_ms) = entry.file_name().to_str().and_then(|name| name.parse().ok())
+        else {
+            continue;
+        };
+        // Snapshots without a manifest were interrupted half-way and are ignored.
+        let Ok(bytes) = fs::read(entry.path().join(MANIFEST_FILE)) else {
+            continue;
+        };
+        let Ok(manifest) = serde_json::from_slice::<Manifest>(&bytes) else {
+            continue;
+        };
+        backups.push(BackupInfo {
+            timestamp_ms,
+            reason: manifest.reason.into(),
+            files: manifest.files.len(),
+            dir: entry.path(),
+        });
+    }
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.timestamp_ms));
+    Ok(backups)
+}
+
+/// Replace the current stores with the snapshot taken at `timestamp_ms`.
+///
+/// The current state is snapshotted first, so a restore can itself be undone.
+pub fn restore(root: &Path, timestamp_ms: u64, keep: usize) -> Result<BackupInfo> {
+    let backup = list(root)?
+        .into_iter()
+        .find(|backup| backup.timestamp_ms == timestamp_ms)
+        .ok_or_else(|| CoreError::Store(anyhow!("no backup with timestamp {timestamp_ms}")))?;
+    let manifest: Manifest = serde_json::from_slice(&fs::read(backup.dir.join(MANIFEST_FILE))?)?;
+    // Read everything up front: rotating in the safety snapshot may prune this one.
+    let mut contents = Vec::with_capacity(manifest.files.len());
+    for relative in manifest.files {
+        let data = fs::read(backup.dir.join(&relative))?;
+        contents.push((relative, data));
+    }
+
+    create(root, BackupReason::PreRestore, keep)?;
+
+    for relative in store_files(root)? {
+        if !contents.iter().any(|(restored, _)| *restored == relative) {
+            recovery::remove(&root.join(relative))?;
+        }
+    }
+    for (relative, data) in &contents {
+        recovery::write_verified(&root.join(relative), data)?;
+    }
+
+    tracing::info!(target: "store::backup", timestamp_ms, "restored store backup");
+    Ok(backup)
+}
+
+/// Replace the copy of `relative` in each snapshot under `root` with what `update` makes of it,
+/// leaving copies it returns `None` for as they are. Returns the snapshots that changed.
+pub(crate) fn rewrite_copies(
+    root: &Path,
+    relative: &Path,
+    update: &mut dyn FnMut(Vec<u8>) -> Result<Option<Vec<u8>>>,
+) -> Result<Vec<PathBuf>> {
+    let mut changed = Vec::new();
+    for backup in list(root)? {
+        let copy = backup.dir.join(relative);
+        let data = match fs::read(&copy) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        if let Some(data) = update(data)? {
+            json::write_bytes(&copy, &data)?;
+            changed.push(backup.dir);
+        }
+    }
+    Ok(changed)
+}
+
+/// Add `relative` with `data` to the snapshot in `dir`, replacing any copy it already has.
+pub(crate) fn add_to_snapshot(dir: &Path, relative: &Path, data: &[u8]) -> Result<()> {
+    let manifest_path = dir.join(MANIFEST_FILE);
+    let mut manifest: Manifest = serde_json::from_slice(&fs::read(&manifest_path)?)?;
+    json::write_bytes(&dir.join(relative), data)?;
+    if !manifest.files.iter().any(|file| file == relative) {
+        manifest.files.push(relative.to_path_buf());
+        json::write_bytes(&manifest_path, &serde_json::to_vec_pretty(&manifest)?)?;
+    }
+    Ok(())
+}
+
+/// Store files under `root`, relative to it: the profile registry plus each profile's files.
+fn store_files(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    dirs.extend(profile::list(root)?.iter().map(|name| profile::dir(root, name)));
+    dirs.dedup();
+
+    for dir in dirs {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "json") {
+                let relative = path.strip_prefix(root).map_err(|err| anyhow!(err))?;
+                files.push(relative.to_path_buf());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn prune(root: &Path, keep: usize) -> Result<()> {
+    for backup in list(root)?.into_iter().skip(keep.max(1)) {
+        fs::remove_dir_all(&backup.dir)?;
+    }
+    Ok(())
+}
+
+fn backups_dir(root: &Path) -> PathBuf {
+    root.join(BACKUPS_DIR)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::profile::ProfileName;
+    use crate::store::progress::ProgressStore;
+    use crate::types::{PageId, SourceId};
+
+    fn save(store: &ProgressStore, index: u32) {
+        store.save(&PageId { source_id: SourceId::new("vol1"), index }).unwrap();
+        store.flush().unwrap();
+    }
+
+    fn load(store: &ProgressStore) -> Option<u32> {
+        store.load(&SourceId::new("vol1")).unwrap().map(|page| page.index)
+    }
+
+    #[test]
+    fn snapshots_rotate_and_restore() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let store = ProgressStore::new(root).unwrap();
+        profile::switch(root, &ProfileName::new("kid").unwrap()).unwrap();
+        profile::switch(root, &ProfileName::default()).unwrap();
+        save(&store, 1);
+
+        let first = create(root, BackupReason::Manual, 3).unwrap();
+        assert_eq!(first.files, 2, "profile registry and progress");
+        assert!(ensure_daily(root, 3).unwrap().is_none(), "one snapshot per day");
+
+        save(&store, 7);
+        for _ in 0..3 {
+            create(root, BackupReason::Manual, 3).unwrap();
+        }
+        let backups = list(root).unwrap();
+        assert_eq!(backups.len(), 3);
+        assert!(backups.iter().all(|backup| backup.timestamp_ms != first.timestamp_ms));
+
+        let oldest = backups.last().unwrap().timestamp_ms;
+        save(&store, 9);
+        restore(root, oldest, 3).unwrap();
+        assert_eq!(load(&store), Some(7));
+        assert_eq!(list(root).unwrap()[0].reason, BackupReason::PreRestore);
+        assert!(restore(root, 42, 3).is_err());
+    }
+}
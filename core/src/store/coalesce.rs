@@ -0,0 +1,79 @@
+//! Background flushing for stores that coalesce writes in memory.
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Runs a flush callback on a background thread at a fixed interval.
+///
+/// The callback runs one final time when the handle is stopped or dropped, so buffered writes
+/// are not lost on shutdown.
+#[derive(Debug)]
+pub struct PeriodicFlush {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PeriodicFlush {
+    /// Call `flush` every `interval` until the returned handle is stopped.
+    pub fn spawn(
+        name: &str,
+        interval: Duration,
+        flush: impl Fn() + Send + 'static,
+    ) -> std::io::Result<Self> {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::Builder::new().name(format!("{name}-flush")).spawn(move || {
+            loop {
+                match stopped.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => flush(),
+                    Ok(()) | Err(RecvTimeoutError::Disconnected) => {
+                        flush();
+                        break;
+                    }
+                }
+            }
+        })?;
+        Ok(Self { stop: Some(stop), thread: Some(thread) })
+    }
+
+    /// Stop the background thread after a final flush.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for PeriodicFlush {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn flushes_on_interval_and_on_stop() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let flusher = PeriodicFlush::spawn("test", Duration::from_millis(10), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+
+        thread::sleep(Duration::from_millis(60));
+        let before_stop = calls.load(Ordering::SeqCst);
+        assert!(before_stop >= 1);
+        flusher.stop();
+        assert!(calls.load(Ordering::SeqCst) > before_stop);
+    }
+}
@@ -0,0 +1,333 @@
+//! User-defined collections ("shelves") of sources, independent of the folder layout.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{CoreError, ensure};
+
+use super::events::{EventBus, StoreEvent};
+use super::migrate::{self, Schema};
+use super::{Result, json, profile};
+
+const COLLECTIONS_FILE: &str = "collections.json";
+const MAX_NAME_LEN: usize = 100;
+
+/// Schema of the per-profile collections file.
+pub const SCHEMA: Schema =
+    Schema { file_name: COLLECTIONS_FILE, current: migrate::LEGACY_VERSION, migrations: &[] };
+
+/// A named, ordered list of sources.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Collection {
+    pub id: u64,
+    pub name: String,
+    pub members: Vec<PathBuf>,
+    pub created_ms: u64,
+    pub updated_ms: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CollectionsFile {
+    #[serde(default)]
+    version: u32,
+    next_id: u64,
+    #[serde(with = "stored")]
+    collections: Vec<Collection>,
+}
+
+/// Collections as written to the collections file, which keeps the snake_case fields it was
+/// first written with.
+mod stored {
+    use std::path::PathBuf;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Collection;
+
+    #[derive(Serialize, Deserialize)]
+    struct StoredCollection {
+        id: u64,
+        name: String,
+        members: Vec<PathBuf>,
+        created_ms: u64,
+        updated_ms: u64,
+    }
+
+    pub(super) fn serialize<S: Serializer>(
+        collections: &[Collection],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(collections.iter().map(|collection| StoredCollection {
+            id: collection.id,
+            name: collection.name.clone(),
+            members: collection.members.clone(),
+            created_ms: collection.created_ms,
+            updated_ms: collection.updated_ms,
+        }))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Collection>, D::Error> {
+        let stored = Vec::<StoredCollection>::deserialize(deserializer)?;
+        Ok(stored
+            .into_iter()
+            .map(|collection| Collection {
+                id: collection.id,
+                name: collection.name,
+                members: collection.members,
+                created_ms: collection.created_ms,
+                updated_ms: collection.updated_ms,
+            })
+            .collect())
+    }
+}
+
+/// Per-profile store of collections.
+#[derive(Debug)]
+pub struct CollectionStore {
+    root: PathBuf,
+    lock: Mutex<()>,
+    events: EventBus,
+}
+
+impl CollectionStore {
+    /// Create a store persisting under `root`, creating the directory if needed.
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self { root, lock: Mutex::new(()), events: EventBus::new() })
+    }
+
+    /// Publish changes on `events` instead of a private bus.
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// All collections, by name.
+    pub fn list(&self) -> Result<Vec<Collection>> {
+        let _guard = self.lock.lock().expect("collections mutex poisoned");
+        let mut collections = json::read::<CollectionsFile>(&self.path()?)?.collections;
+        collections.sort_by(|a, b| crate::fs::natural_cmp(&a.name, &b.name));
+        Ok(collections)
+    }
+
+    /// Look up a collection by id.
+    pub fn get(&self, id: u64) -> Result<Option<Collection>> {
+        Ok(self.list()?.into_iter().find(|collection| collection.id == id))
+    }
+
+    /// Create an empty collection. Names must be unique, ignoring case.
+    pub fn create(&self, name: &str) -> Result<Collection> {
+        let name = validate_name(name)?;
+        self.update(|file| {
+            ensure_unique(file, &name, None)?;
+            file.next_id = file.next_id.max(1);
+            let now = now_ms();
+            let collection = Collection {
+                id: file.next_id,
+                name,
+                members: Vec::new(),
+                created_ms: now,
+                updated_ms: now,
+            };
+            file.next_id += 1;
+            file.collections.push(collection.clone());
+            Ok(collection)
+        })
+    }
+
+    /// Rename a collection.
+    pub fn rename(&self, id: u64, name: &str) -> Result<Collection> {
+        let name = validate_name(name)?;
+        self.modify(id, |file, index| {
+            ensure_unique(file, &name, Some(id))?;
+            file.collections[index].name = name;
+            Ok(())
+        })
+    }
+
+    /// Delete a collection. Returns `false` when no collection has the given id.
+    pub fn delete(&self, id: u64) -> Result<bool> {
+        let _guard = self.lock.lock().expect("collections mutex poisoned");
+        let path = self.path()?;
+        let mut file: CollectionsFile = json::read(&path)?;
+        let before = file.collections.len();
+        file.collections.retain(|collection| collection.id != id);
+        if file.collections.len() == before {
+            return Ok(false);
+        }
+        file.version = SCHEMA.current;
+        json::write(&path, &file)?;
+        self.events.emit(StoreEvent::CollectionDeleted { id });
+        Ok(true)
+    }
+
+    /// Insert `source` at `position` (appended when `None`), moving it if already a member.
+    pub fn add_member(
+        &self,
+        id: u64,
+        source: &Path,
+        position: Option<usize>,
+    ) -> Result<Collection> {
+        let source = normalize(source);
+        self.modify(id, |file, index| {
+            let members = &mut file.collections[index].members;
+            members.retain(|member| *member != source);
+            let position = position.unwrap_or(members.len()).min(members.len());
+            members.insert(position, source);
+            Ok(())
+        })
+    }
+
+    /// Remove `source` from a collection.
+    pub fn remove_member(&self, id: u64, source: &Path) -> Result<Collection> {
+        let source = normalize(source);
+        self.modify(id, |file, index| {
+            file.collections[index].members.retain(|member| *member != source);
+            Ok(())
+        })
+    }
+
+    /// Replace the member order. `order` must list exactly the current members.
+    pub fn reorder(&self, id: u64, order: &[PathBuf]) -> Result<Collection> {
+        let order: Vec<_> = order.iter().map(|path| normalize(path)).collect();
+        self.modify(id, |file, index| {
+            let members = &mut file.collections[index].members;
+            let mut expected = members.clone();
+            let mut given = order.clone();
+            expected.sort();
+            given.sort();
+            ensure!(
+                Store,
+                expected == given,
+                "new order must contain exactly the collection's members"
+            );
+            *members = order;
+            Ok(())
+        })
+    }
+
+    fn modify(
+        &self,
+        id: u64,
+        change: impl FnOnce(&mut CollectionsFile, usize) -> Result<()>,
+    ) -> Result<Collection> {
+        self.update(|file| {
+            let index = file
+                .collections
+                .iter()
+                .position(|collection| collection.id == id)
+                .ok_or_else(|| CoreError::Store(anyhow!("collection {id} does not exist")))?;
+            change(file, index)?;
+            let collection = &mut file.collections[index];
+            collection.updated_ms = now_ms();
+            Ok(collection.clone())
+        })
+    }
+
+    fn update(
+        &self,
+        mutate: impl FnOnce(&mut CollectionsFile) -> Result<Collection>,
+    ) -> Result<Collection> {
+        let guard = self.lock.lock().expect("collections mutex poisoned");
+        let path = self.path()?;
+        let mut file: CollectionsFile = json::read(&path)?;
+        let collection = mutate(&mut file)?;
+        file.version = SCHEMA.current;
+        json::write(&path, &file)?;
+        drop(guard);
+
+        self.events.emit(StoreEvent::CollectionChanged(collection.clone()));
+        Ok(collection)
+    }
+
+    fn path(&self) -> Result<PathBuf> {
+        Ok(profile::active_dir(&self.root)?.join(SCHEMA.file_name))
+    }
+}
+
+fn validate_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    ensure!(Store, !name.is_empty(), "collection name must not be empty");
+    ensure!(
+        Store,
+        name.chars().count() <= MAX_NAME_LEN,
+        "collection name must be at most {MAX_NAME_LEN} characters"
+    );
+    Ok(name.to_string())
+}
+
+fn ensure_unique(file: &CollectionsFile, name: &str, except: Option<u64>) -> Result<()> {
+    let taken = file.collections.iter().any(|collection| {
+        Some(collection.id) != except && collection.name.to_lowercase() == name.to_lowercase()
+    });
+    ensure!(Store, !taken, "a collection named {name:?} already exists");
+    Ok(())
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_rename_and_delete() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = CollectionStore::new(temp.path()).unwrap();
+
+        let favourites = store.create("Favorites").unwrap();
+        store.create("To Read").unwrap();
+        assert!(store.create(" favorites ").is_err(), "names are unique ignoring case");
+        assert!(store.create("  ").is_err());
+
+        let renamed = store.rename(favourites.id, "All-time favorites").unwrap();
+        assert_eq!(renamed.name, "All-time favorites");
+        assert!(store.rename(favourites.id, "to read").is_err());
+        let names: Vec<_> = store.list().unwrap().into_iter().map(|c| c.name).collect();
+        assert_eq!(names, vec!["All-time favorites", "To Read"]);
+
+        assert!(store.delete(favourites.id).unwrap());
+        assert!(!store.delete(favourites.id).unwrap());
+        assert!(store.rename(favourites.id, "gone").is_err());
+    }
+
+    #[test]
+    fn keeps_members_ordered() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = CollectionStore::new(temp.path()).unwrap();
+        let shelf = store.create("Shelf").unwrap();
+        let (a, b, c) = (Path::new("/a.cbz"), Path::new("/b.cbz"), Path::new("/c.cbz"));
+
+        store.add_member(shelf.id, a, None).unwrap();
+        store.add_member(shelf.id, b, None).unwrap();
+        let members = store.add_member(shelf.id, c, Some(0)).unwrap().members;
+        assert_eq!(members, vec![c, a, b]);
+
+        // Re-adding moves rather than duplicates.
+        let members = store.add_member(shelf.id, c, Some(99)).unwrap().members;
+        assert_eq!(members, vec![a, b, c]);
+
+        let reordered = store.reorder(shelf.id, &[b.into(), c.into(), a.into()]).unwrap();
+        assert_eq!(reordered.members, vec![b, c, a]);
+        assert!(store.reorder(shelf.id, &[a.into()]).is_err());
+
+        let members = store.remove_member(shelf.id, c).unwrap().members;
+        assert_eq!(members, vec![b, a]);
+        assert_eq!(store.get(shelf.id).unwrap().unwrap().members, vec![b, a]);
+    }
+}
@@ -0,0 +1,482 @@
+//! Opt-in encryption of the stores holding a profile's reading activity.
+//!
+//! Once enabled for a profile, progress, history and annotation files are written as sealed
+//! envelopes (XChaCha20-Poly1305, bound to the file name). The key is derived from a passphrase
+//! with Argon2id or supplied by the caller, typically from the OS keychain, and only lives in
+//! memory after [`unlock_with_passphrase`]/[`unlock_with_key`]. While a profile is locked its
+//! encrypted stores refuse to load or save rather than falling back to plaintext.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+
+use anyhow::{Context, anyhow};
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::error::{CoreError, ensure};
+
+use super::{Result, annotations, backup, history, json, profile, progress, recovery};
+
+const ENCRYPTION_FILE: &str = "encryption.json";
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const CHECK_LABEL: &str = "encryption-check";
+const CHECK_PLAINTEXT: &[u8] = b"local-comic-reader";
+
+/// An encrypted store was read or written while its profile is locked.
+#[derive(Debug, Error)]
+#[error("{} is encrypted and the profile is locked", path.display())]
+pub struct ProfileLocked {
+    pub path: PathBuf,
+}
+
+/// Keys of unlocked profiles, by profile directory.
+static KEYS: LazyLock<Mutex<HashMap<PathBuf, StoreKey>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Store files covered by encryption.
+fn covered_files() -> [&'static str; 3] {
+    [progress::SCHEMA.file_name, history::SCHEMA.file_name, annotations::SCHEMA.file_name]
+}
+
+/// Where the key of an encrypted profile comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeySource {
+    Passphrase,
+    Keychain,
+}
+
+/// Encryption state of the active profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionStatus {
+    pub enabled: bool,
+    pub unlocked: bool,
+    pub source: Option<KeySource>,
+}
+
+/// A 256-bit store key, wiped from memory on drop.
+#[derive(Clone)]
+pub struct StoreKey(Zeroizing<[u8; KEY_LEN]>);
+
+impl StoreKey {
+    /// Generate a random key, e.g. to be kept in the OS keychain.
+    pub fn generate() -> Self {
+        let mut bytes = Zeroizing::new([0u8; KEY_LEN]);
+        OsRng.fill_bytes(bytes.as_mut());
+        Self(bytes)
+    }
+
+    /// Parse a key previously rendered with [`StoreKey::to_hex`].
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let bytes = Zeroizing::new(decode_hex(hex.trim())?);
+        let array: [u8; KEY_LEN] = bytes
+            .as_slice()
+            .try_into()
+            .map_err(|_| CoreError::Store(anyhow!("store key must be 32 bytes")))?;
+        Ok(Self(Zeroizing::new(array)))
+    }
+
+    /// Hex rendering suitable for storing in a keychain entry.
+    pub fn to_hex(&self) -> Zeroizing<String> {
+        Zeroizing::new(encode_hex(self.0.as_ref()))
+    }
+
+    fn from_passphrase(passphrase: &str, salt: &[u8]) -> Result<Self> {
+        ensure!(Store, !passphrase.is_empty(), "passphrase must not be empty");
+        let mut bytes = Zeroizing::new([0u8; KEY_LEN]);
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, bytes.as_mut())
+            .map_err(|err| CoreError::Store(anyhow!("failed to derive store key: {err}")))?;
+        Ok(Self(bytes))
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(self.0.as_ref().into())
+    }
+}
+
+impl fmt::Debug for StoreKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StoreKey(..)")
+    }
+}
+
+/// Sealed file contents. `version` is kept in the clear so migrations can detect old files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Envelope {
+    #[serde(default)]
+    version: u32,
+    sealed: u32,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct EncryptionFile {
+    #[serde(default)]
+    version: u32,
+    source: Option<KeySource>,
+    #[serde(default)]
+    salt: String,
+    check: Option<Envelope>,
+}
+
+#[derive(Deserialize)]
+struct VersionOnly {
+    #[serde(default)]
+    version: u32,
+}
+
+/// Encryption state of the active profile under `root`.
+pub fn status(root: &Path) -> Result<EncryptionStatus> {
+    let dir = profile::active_dir(root)?;
+    let config = read_config(&dir)?;
+    Ok(EncryptionStatus {
+        enabled: config.source.is_some(),
+        unlocked: key_for(&dir).is_some(),
+        source: config.source,
+    })
+}
+
+/// Encrypt the active profile's stores with a key derived from `passphrase`.
+pub fn enable_with_passphrase(root: &Path, passphrase: &str) -> Result<()> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let key = StoreKey::from_passphrase(passphrase, &salt)?;
+    enable(root, KeySource::Passphrase, &salt, key)
+}
+
+/// Encrypt the active profile's stores with a key kept outside the store, e.g. in the keychain.
+pub fn enable_with_key(root: &Path, key: &StoreKey) -> Result<()> {
+    enable(root, KeySource::Keychain, &[], key.clone())
+}
+
+/// Unlock a passphrase-protected profile for the rest of the session.
+pub fn unlock_with_passphrase(root: &Path, passphrase: &str) -> Result<()> {
+    let dir = profile::active_dir(root)?;
+    let config = read_config(&dir)?;
+    ensure!(
+        Store,
+        config.source == Some(KeySource::Passphrase),
+        "profile is not protected by a passphrase"
+    );
+    let key = StoreKey::from_passphrase(passphrase, &decode_hex(&config.salt)?)?;
+    Ok(unlock(&dir, &config, key).context("incorrect passphrase")?)
+}
+
+/// Unlock a keychain-protected profile for the rest of the session.
+pub fn unlock_with_key(root: &Path, key: &StoreKey) -> Result<()> {
+    let dir = profile::active_dir(root)?;
+    let config = read_config(&dir)?;
+    ensure!(
+        Store,
+        config.source == Some(KeySource::Keychain),
+        "profile is not protected by a stored key"
+    );
+    Ok(unlock(&dir, &config, key.clone()).context("stored key does not match this profile")?)
+}
+
+/// Forget the active profile's key; its encrypted stores become unreadable until unlocked.
+pub fn lock(root: &Path) -> Result<()> {
+    let dir = profile::active_dir(root)?;
+    KEYS.lock().expect("keys mutex poisoned").remove(&dir);
+    Ok(())
+}
+
+/// Decrypt the active profile's stores and turn encryption off. The profile must be unlocked.
+pub fn disable(root: &Path) -> Result<()> {
+    let dir = profile::active_dir(root)?;
+    ensure!(Store, read_config(&dir)?.source.is_some(), "encryption is not enabled");
+    ensure!(Store, key_for(&dir).is_some(), "unlock the profile before disabling encryption");
+
+    let mut documents = Vec::new();
+    for name in covered_files() {
+        let path = dir.join(name);
+        let document: Value = json::read(&path)?;
+        documents.push((path, document));
+    }
+
+    recovery::remove(&dir.join(ENCRYPTION_FILE))?;
+    KEYS.lock().expect("keys mutex poisoned").remove(&dir);
+    rewrite(documents)?;
+    tracing::info!(target: "store::crypto", dir = %dir.display(), "store encryption disabled");
+    Ok(())
+}
+
+/// Whether a parsed store document is a sealed envelope.
+pub(crate) fn is_sealed(document: &Value) -> bool {
+    document.get("sealed").is_some()
+}
+
+/// Decrypt `bytes` read from `path` if they are sealed; plaintext passes through unchanged.
+pub(crate) fn open(path: &Path, bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let Ok(envelope) = serde_json::from_slice::<Envelope>(&bytes) else {
+        return Ok(bytes);
+    };
+    let dir = parent(path)?;
+    let Some(key) = key_for(dir) else {
+        return Err(ProfileLocked { path: path.to_path_buf() }.into());
+    };
+    Ok(decrypt(&key, &envelope, &aad(path))
+        .with_context(|| format!("failed to decrypt {}", path.display()))?)
+}
+
+/// Seal `data` destined for `path` when encryption is enabled for its profile.
+pub(crate) fn seal(path: &Path, data: Vec<u8>) -> Result<Vec<u8>> {
+    let is_covered =
+        path.file_name().is_some_and(|name| covered_files().iter().any(|file| name == *file));
+    if !is_covered {
+        return Ok(data);
+    }
+
+    let dir = parent(path)?;
+    match key_for(dir) {
+        Some(key) => seal_document(&key, &data, &aad(path)),
+        None if dir.join(ENCRYPTION_FILE).exists() => {
+            Err(ProfileLocked { path: path.to_path_buf() }.into())
+        }
+        None => Ok(data),
+    }
+}
+
+fn enable(root: &Path, source: KeySource, salt: &[u8], key: StoreKey) -> Result<()> {
+    let dir = profile::active_dir(root)?;
+    ensure!(Store, read_config(&dir)?.source.is_none(), "encryption is already enabled");
+
+    let mut documents = Vec::new();
+    for name in covered_files() {
+        let path = dir.join(name);
+        let document: Value = json::read(&path)?;
+        documents.push((path, document));
+    }
+
+    let config = EncryptionFile {
+        version: 1,
+        source: Some(source),
+        salt: encode_hex(salt),
+        check: Some(encrypt(&key, 0, CHECK_PLAINTEXT, CHECK_LABEL.as_bytes())?),
+    };
+    json::write(&dir.join(ENCRYPTION_FILE), &config)?;
+    KEYS.lock().expect("keys mutex poisoned").insert(dir.clone(), key.clone());
+    rewrite(documents)?;
+    seal_snapshots(root, &dir, &key)?;
+    tracing::info!(target: "store::crypto", dir = %dir.display(), ?source, "store encryption enabled");
+    Ok(())
+}
+
+/// Write documents back through the store helpers and drop the rotated backups in the previous
+/// format; snapshots are sealed separately by [`seal_snapshots`].
+fn rewrite(documents: Vec<(PathBuf, Value)>) -> Result<()> {
+    for (path, document) in documents {
+        if document.is_null() {
+            continue;
+        }
+        json::write(&path, &document)?;
+        recovery::discard_backups(&path)?;
+    }
+    Ok(())
+}
+
+/// Seal the plaintext copies that earlier snapshots hold of the covered files of the profile in
+/// `dir`, and give those snapshots its encryption settings so that restoring one can read them.
+fn seal_snapshots(root: &Path, dir: &Path, key: &StoreKey) -> Result<()> {
+    let profile_dir = dir.strip_prefix(root).map_err(|err| anyhow!(err))?;
+    let mut sealed = Vec::new();
+    for name in covered_files() {
+        let mut seal_copy = |data: Vec<u8>| {
+            if serde_json::from_slice::<Envelope>(&data).is_ok() {
+                return Ok(None);
+            }
+            seal_document(key, &data, name.as_bytes()).map(Some)
+        };
+        sealed.extend(backup::rewrite_copies(root, &profile_dir.join(name), &mut seal_copy)?);
+    }
+    sealed.sort();
+    sealed.dedup();
+
+    let config = std::fs::read(dir.join(ENCRYPTION_FILE))?;
+    for snapshot in &sealed {
+        backup::add_to_snapshot(snapshot, &profile_dir.join(ENCRYPTION_FILE), &config)?;
+    }
+    if !sealed.is_empty() {
+        tracing::info!(target: "store::crypto", snapshots = sealed.len(), "sealed store backups");
+    }
+    Ok(())
+}
+
+/// Seal the store document `data` with `key`, keeping its schema version readable.
+fn seal_document(key: &StoreKey, data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let version = serde_json::from_slice::<VersionOnly>(data)
+        .map(|document| document.version)
+        .unwrap_or_default();
+    let envelope = encrypt(key, version, data, aad)?;
+    Ok(serde_json::to_vec_pretty(&envelope)?)
+}
+
+fn unlock(dir: &Path, config: &EncryptionFile, key: StoreKey) -> Result<()> {
+    let check = config
+        .check
+        .as_ref()
+        .ok_or_else(|| CoreError::Store(anyhow!("encryption settings are damaged")))?;
+    let plaintext = decrypt(&key, check, CHECK_LABEL.as_bytes())?;
+    ensure!(Store, plaintext == CHECK_PLAINTEXT, "key check failed");
+    KEYS.lock().expect("keys mutex poisoned").insert(dir.to_path_buf(), key);
+    Ok(())
+}
+
+fn read_config(dir: &Path) -> Result<EncryptionFile> {
+    json::read(&dir.join(ENCRYPTION_FILE))
+}
+
+fn key_for(dir: &Path) -> Option<StoreKey> {
+    KEYS.lock().expect("keys mutex poisoned").get(dir).cloned()
+}
+
+fn encrypt(key: &StoreKey, version: u32, plaintext: &[u8], aad: &[u8]) -> Result<Envelope> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = key
+        .cipher()
+        .encrypt(&nonce, Payload { msg: plaintext, aad })
+        .map_err(|_| CoreError::Store(anyhow!("encryption failed")))?;
+    Ok(Envelope {
+        version,
+        sealed: 1,
+        nonce: encode_hex(&nonce),
+        ciphertext: encode_hex(&ciphertext),
+    })
+}
+
+fn decrypt(key: &StoreKey, envelope: &Envelope, aad: &[u8]) -> Result<Vec<u8>> {
+    ensure!(Store, envelope.sealed == 1, "unsupported envelope format {}", envelope.sealed);
+    let nonce = decode_hex(&envelope.nonce)?;
+    ensure!(Store, nonce.len() == 24, "envelope nonce has the wrong length");
+    let ciphertext = decode_hex(&envelope.ciphertext)?;
+    key.cipher()
+        .decrypt(XNonce::from_slice(&nonce), Payload { msg: &ciphertext, aad })
+        .map_err(|_| CoreError::Store(anyhow!("wrong key or damaged data")))
+}
+
+fn aad(path: &Path) -> Vec<u8> {
+    path.file_name().map(|name| name.as_encoded_bytes().to_vec()).unwrap_or_default()
+}
+
+fn parent(path: &Path) -> Result<&Path> {
+    path.parent().ok_or_else(|| {
+        CoreError::Io(anyhow!("store path {} does not have a parent directory", path.display()))
+    })
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    ensure!(Store, hex.is_ascii() && hex.len().is_multiple_of(2), "invalid hex string");
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| {
+            u8::from_str_radix(&hex[index..index + 2], 16)
+                .map_err(|_| CoreError::Store(anyhow!("invalid hex string")))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::progress::ProgressStore;
+    use crate::types::{PageId, SourceId};
+
+    fn page(index: u32) -> PageId {
+        PageId { source_id: SourceId::new("vol1"), index }
+    }
+
+    #[test]
+    fn passphrase_round_trip_and_locking() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let store = ProgressStore::new(root).unwrap();
+        store.save(&page(3)).unwrap();
+        store.flush().unwrap();
+
+        enable_with_passphrase(root, "correct horse").unwrap();
+        let raw = std::fs::read_to_string(root.join("progress.json")).unwrap();
+        assert!(raw.contains("ciphertext") && !raw.contains("vol1"));
+        assert!(!root.join("progress.json.bak1").exists());
+        assert_eq!(store.load(&SourceId::new("vol1")).unwrap(), Some(page(3)));
+
+        lock(root).unwrap();
+        assert!(store.load(&SourceId::new("vol1")).is_err());
+        store.save(&page(4)).unwrap();
+        assert!(store.flush().is_err(), "locked profiles keep writes buffered");
+        assert!(unlock_with_passphrase(root, "wrong").is_err());
+        unlock_with_passphrase(root, "correct horse").unwrap();
+        store.save(&page(5)).unwrap();
+        store.flush().unwrap();
+        assert_eq!(
+            status(root).unwrap(),
+            EncryptionStatus { enabled: true, unlocked: true, source: Some(KeySource::Passphrase) }
+        );
+
+        disable(root).unwrap();
+        let raw = std::fs::read_to_string(root.join("progress.json")).unwrap();
+        assert!(raw.contains("vol1"));
+        assert_eq!(store.load(&SourceId::new("vol1")).unwrap(), Some(page(5)));
+        assert!(!status(root).unwrap().enabled);
+    }
+
+    #[test]
+    fn keychain_keys_round_trip_through_hex() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let key = StoreKey::generate();
+        enable_with_key(root, &key).unwrap();
+        lock(root).unwrap();
+
+        assert!(unlock_with_key(root, &StoreKey::generate()).is_err());
+        unlock_with_key(root, &StoreKey::from_hex(&key.to_hex()).unwrap()).unwrap();
+        assert!(status(root).unwrap().unlocked);
+        assert!(enable_with_key(root, &key).is_err(), "already enabled");
+    }
+
+    #[test]
+    fn enabling_seals_earlier_snapshots() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let store = ProgressStore::new(root).unwrap();
+        store.save(&page(3)).unwrap();
+        store.flush().unwrap();
+        let snapshot = backup::create(root, backup::BackupReason::Manual, 3).unwrap();
+
+        enable_with_passphrase(root, "correct horse").unwrap();
+        let mut pending = vec![root.join("backups")];
+        while let Some(dir) = pending.pop() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else {
+                    let raw = std::fs::read_to_string(&path).unwrap();
+                    assert!(!raw.contains("vol1"), "{} holds plaintext", path.display());
+                }
+            }
+        }
+
+        store.save(&page(9)).unwrap();
+        store.flush().unwrap();
+        backup::restore(root, snapshot.timestamp_ms, 3).unwrap();
+        lock(root).unwrap();
+        unlock_with_passphrase(root, "correct horse").unwrap();
+        assert_eq!(store.load(&SourceId::new("vol1")).unwrap(), Some(page(3)));
+    }
+}
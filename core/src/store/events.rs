@@ -0,0 +1,93 @@
+//! Typed change notifications emitted by the stores.
+//!
+//! Stores created with `with_events` publish a [`StoreEvent`] after every successful change.
+//! Each [`EventBus::subscribe`] call gets its own channel, so several consumers (e.g. one per
+//! window) can follow the same stores; subscribers that hang up are dropped on the next event.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use crate::keymap::Keymap;
+use crate::types::PageId;
+
+use super::annotations::Annotation;
+use super::collections::Collection;
+use super::library::LibraryEntry;
+
+/// A change made to one of the stores.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoreEvent {
+    ProgressUpdated { page: PageId },
+    AnnotationAdded(Annotation),
+    AnnotationEdited(Annotation),
+    AnnotationDeleted { id: u64 },
+    LibraryChanged(LibraryEntry),
+    CollectionChanged(Collection),
+    CollectionDeleted { id: u64 },
+    KeymapChanged(Keymap),
+}
+
+/// Fan-out channel shared by the stores of one application instance.
+#[derive(Debug, Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<Sender<StoreEvent>>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Receive every event published from now on.
+    pub fn subscribe(&self) -> Receiver<StoreEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().expect("event bus mutex poisoned").push(sender);
+        receiver
+    }
+
+    pub(crate) fn emit(&self, event: StoreEvent) {
+        let mut subscribers = self.subscribers.lock().expect("event bus mutex poisoned");
+        subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::annotations::AnnotationStore;
+    use crate::store::library::{LibraryStore, ReadingStatus};
+    use crate::store::progress::ProgressStore;
+    use crate::types::SourceId;
+    use std::path::Path;
+
+    #[test]
+    fn stores_publish_changes_to_every_subscriber() {
+        let temp = tempfile::tempdir().unwrap();
+        let bus = EventBus::new();
+        let first = bus.subscribe();
+        let second = bus.subscribe();
+
+        let progress = ProgressStore::new(temp.path()).unwrap().with_events(bus.clone());
+        let annotations = AnnotationStore::new(temp.path()).unwrap().with_events(bus.clone());
+        let library = LibraryStore::new(temp.path()).unwrap().with_events(bus.clone());
+
+        let page = PageId { source_id: SourceId::new("vol1"), index: 2 };
+        progress.save(&page).unwrap();
+        let note = annotations.add(&page, "note", None).unwrap();
+        annotations.delete(note.id).unwrap();
+        library.record_open(Path::new("/books/vol1.cbz"), None).unwrap();
+        library.set_status(Path::new("/books/missing.cbz"), ReadingStatus::Dropped).unwrap();
+
+        let events: Vec<_> = first.try_iter().collect();
+        assert_eq!(events.len(), 4, "no event for an unknown library path: {events:?}");
+        assert_eq!(events[0], StoreEvent::ProgressUpdated { page });
+        assert_eq!(events[1], StoreEvent::AnnotationAdded(note.clone()));
+        assert_eq!(events[2], StoreEvent::AnnotationDeleted { id: note.id });
+        assert!(matches!(&events[3], StoreEvent::LibraryChanged(entry) if entry.title == "vol1"));
+        assert_eq!(second.try_iter().count(), 4);
+
+        drop(second);
+        progress.save(&PageId { source_id: SourceId::new("vol1"), index: 3 }).unwrap();
+        assert_eq!(bus.subscribers.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,312 @@
+//! Reading history: pages read and time spent per day, with aggregate queries.
+//!
+//! Days are counted in UTC since the Unix epoch so the data stays stable when the machine
+//! changes time zone; [`format_day`] renders them as ISO dates for presentation.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::calendar;
+use crate::error::CoreError;
+use crate::types::SourceId;
+
+use super::migrate::{self, Schema};
+use super::{Result, json, profile};
+
+const HISTORY_FILE: &str = "history.json";
+const MS_PER_DAY: u64 = 86_400_000;
+
+/// Schema of the per-profile reading history file.
+pub const SCHEMA: Schema =
+    Schema { file_name: HISTORY_FILE, current: migrate::LEGACY_VERSION, migrations: &[] };
+
+/// Pages and seconds accumulated for some grouping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadingTotals {
+    pub pages: u64,
+    pub seconds: u64,
+}
+
+impl ReadingTotals {
+    fn add(&mut self, pages: u32, time: Duration) {
+        self.pages = self.pages.saturating_add(u64::from(pages));
+        self.seconds = self.seconds.saturating_add(time.as_secs());
+    }
+}
+
+/// Activity recorded on a single day.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DayRecord {
+    pub day: u32,
+    pub totals: ReadingTotals,
+    #[serde(default)]
+    pub sources: BTreeMap<String, ReadingTotals>,
+}
+
+/// Aggregate totals for one series.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeriesTotals {
+    pub series: String,
+    pub totals: ReadingTotals,
+}
+
+/// Dashboard-ready summary of the reading history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadingSummary {
+    pub daily: Vec<DayRecord>,
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    pub lifetime: ReadingTotals,
+    pub series: Vec<SeriesTotals>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct HistoryFile {
+    #[serde(default)]
+    version: u32,
+    days: BTreeMap<u32, DayRecord>,
+    #[serde(default)]
+    series: HashMap<String, ReadingTotals>,
+}
+
+/// Per-profile store of daily reading activity.
+#[derive(Debug)]
+pub struct HistoryStore {
+    root: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl HistoryStore {
+    /// Create a store persisting under `root`, creating the directory if needed.
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self { root, lock: Mutex::new(()) })
+    }
+
+    /// Record `pages` read and `time` spent on `source` right now.
+    pub fn record(
+        &self,
+        source: &SourceId,
+        series: Option<&str>,
+        pages: u32,
+        time: Duration,
+    ) -> Result<()> {
+        self.record_at(now_ms(), source, series, pages, time)
+    }
+
+    /// Record activity at an explicit wall-clock timestamp (milliseconds since the epoch).
+    pub fn record_at(
+        &self,
+        at_ms: u64,
+        source: &SourceId,
+        series: Option<&str>,
+        pages: u32,
+        time: Duration,
+    ) -> Result<()> {
+        if pages == 0 && time.is_zero() {
+            return Ok(());
+        }
+
+        let _guard = self.lock.lock().expect("history mutex poisoned");
+        let path = self.path()?;
+        let mut file: HistoryFile = json::read(&path)?;
+
+        let day = day_of(at_ms);
+        let record =
+            file.days.entry(day).or_insert_with(|| DayRecord { day, ..Default::default() });
+        record.totals.add(pages, time);
+        record.sources.entry(source.as_str().to_string()).or_default().add(pages, time);
+        if let Some(series) = series.filter(|series| !series.is_empty()) {
+            file.series.entry(series.to_string()).or_default().add(pages, time);
+        }
+
+        file.version = SCHEMA.current;
+        json::write(&path, &file)
+    }
+
+    /// Day records between `from_day` and `to_day` inclusive, oldest first.
+    pub fn days(&self, from_day: u32, to_day: u32) -> Result<Vec<DayRecord>> {
+        let file = self.read()?;
+        Ok(file.days.range(from_day..=to_day).map(|(_, record)| record.clone()).collect())
+    }
+
+    /// Totals per series, largest page count first.
+    pub fn series_totals(&self) -> Result<Vec<SeriesTotals>> {
+        let file = self.read()?;
+        Ok(sorted_series(file.series))
+    }
+
+    /// Summarise the last `days` days (including today) plus streaks and lifetime totals.
+    pub fn summary(&self, days: u32) -> Result<ReadingSummary> {
+        self.summary_at(now_ms(), days)
+    }
+
+    /// Same as [`HistoryStore::summary`] relative to an explicit timestamp.
+    pub fn summary_at(&self, at_ms: u64, days: u32) -> Result<ReadingSummary> {
+        let file = self.read()?;
+        let today = day_of(at_ms);
+        let from = today.saturating_sub(days.saturating_sub(1));
+
+        let daily = file.days.range(from..=today).map(|(_, record)| record.clone()).collect();
+        let mut lifetime = ReadingTotals::default();
+        for record in file.days.values() {
+            lifetime.pages = lifetime.pages.saturating_add(record.totals.pages);
+            lifetime.seconds = lifetime.seconds.saturating_add(record.totals.seconds);
+        }
+
+        Ok(ReadingSummary {
+            daily,
+            current_streak: current_streak(&file.days, today),
+            longest_streak: longest_streak(&file.days),
+            lifetime,
+            series: sorted_series(file.series),
+        })
+    }
+
+    fn read(&self) -> Result<HistoryFile> {
+        let _guard = self.lock.lock().expect("history mutex poisoned");
+        json::read(&self.path()?)
+    }
+
+    fn path(&self) -> Result<PathBuf> {
+        Ok(profile::active_dir(&self.root)?.join(SCHEMA.file_name))
+    }
+}
+
+/// UTC day number containing the timestamp.
+pub fn day_of(at_ms: u64) -> u32 {
+    (at_ms / MS_PER_DAY) as u32
+}
+
+/// Render a UTC day number as an ISO `YYYY-MM-DD` date.
+pub fn format_day(day: u32) -> String {
+    let (y, m, d) = calendar::civil_from_days(i64::from(day));
+    format!("{y:04}-{m:02}-{d:02}")
+}
+
+/// Parse an ISO `YYYY-MM-DD` date into a UTC day number.
+pub fn parse_day(date: &str) -> Result<u32> {
+    let invalid = || CoreError::Store(anyhow!("invalid date {date:?}, expected YYYY-MM-DD"));
+    let mut parts = date.trim().splitn(3, '-');
+    let mut next = |len: usize| {
+        parts
+            .next()
+            .filter(|part| part.len() == len && part.bytes().all(|byte| byte.is_ascii_digit()))
+            .and_then(|part| part.parse::<i64>().ok())
+            .ok_or_else(invalid)
+    };
+    let (y, m, d) = (next(4)?, next(2)?, next(2)?);
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return Err(invalid());
+    }
+
+    let day = calendar::days_from_civil(y, m as u32, d as u32);
+    if format_day(day.try_into().map_err(|_| invalid())?) != date.trim() {
+        return Err(invalid());
+    }
+    Ok(day as u32)
+}
+
+fn is_active(days: &BTreeMap<u32, DayRecord>, day: u32) -> bool {
+    days.get(&day).is_some_and(|record| record.totals.pages > 0)
+}
+
+/// Consecutive active days ending today, or yesterday if nothing was read yet today.
+fn current_streak(days: &BTreeMap<u32, DayRecord>, today: u32) -> u32 {
+    let mut day = if is_active(days, today) { today } else { today.saturating_sub(1) };
+    let mut streak = 0;
+    while is_active(days, day) {
+        streak += 1;
+        if day == 0 {
+            break;
+        }
+        day -= 1;
+    }
+    streak
+}
+
+fn longest_streak(days: &BTreeMap<u32, DayRecord>) -> u32 {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<u32> = None;
+    for (&day, record) in days {
+        if record.totals.pages == 0 {
+            run = 0;
+            previous = None;
+            continue;
+        }
+        run = if previous.is_some_and(|prev| prev + 1 == day) { run + 1 } else { 1 };
+        longest = longest.max(run);
+        previous = Some(day);
+    }
+    longest
+}
+
+fn sorted_series(series: HashMap<String, ReadingTotals>) -> Vec<SeriesTotals> {
+    let mut series: Vec<_> =
+        series.into_iter().map(|(series, totals)| SeriesTotals { series, totals }).collect();
+    series
+        .sort_by(|a, b| b.totals.pages.cmp(&a.totals.pages).then_with(|| a.series.cmp(&b.series)));
+    series
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at_day(day: u64) -> u64 {
+        day * MS_PER_DAY + 1_000
+    }
+
+    #[test]
+    fn formats_iso_dates() {
+        assert_eq!(format_day(0), "1970-01-01");
+        assert_eq!(format_day(19_782), "2024-02-29");
+        assert_eq!(parse_day("2024-02-29").unwrap(), 19_782);
+        assert_eq!(parse_day("1970-01-01").unwrap(), 0);
+        assert!(parse_day("2023-02-29").is_err());
+        assert!(parse_day("1969-12-31").is_err());
+        assert!(parse_day("2024-1-01").is_err());
+    }
+
+    #[test]
+    fn aggregates_days_series_and_streaks() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = HistoryStore::new(temp.path()).unwrap();
+        let one = SourceId::new("one");
+        let two = SourceId::new("two");
+        let minute = Duration::from_secs(60);
+
+        store.record_at(at_day(10), &one, Some("Saga"), 5, minute).unwrap();
+        store.record_at(at_day(12), &one, Some("Saga"), 3, minute).unwrap();
+        store.record_at(at_day(13), &two, Some("Other"), 10, minute).unwrap();
+        store.record_at(at_day(13), &one, None, 2, minute).unwrap();
+        store.record_at(at_day(14), &two, Some("Other"), 1, minute).unwrap();
+
+        let summary = store.summary_at(at_day(14), 3).unwrap();
+        assert_eq!(summary.daily.iter().map(|d| d.day).collect::<Vec<_>>(), vec![12, 13, 14]);
+        assert_eq!(summary.daily[1].totals, ReadingTotals { pages: 12, seconds: 120 });
+        assert_eq!(summary.daily[1].sources["one"].pages, 2);
+        assert_eq!(summary.current_streak, 3);
+        assert_eq!(summary.longest_streak, 3);
+        assert_eq!(summary.lifetime.pages, 21);
+        assert_eq!(summary.series[0].series, "Other");
+        assert_eq!(summary.series[0].totals.pages, 11);
+
+        // Nothing read yet on day 15 keeps yesterday's streak alive; a gap breaks it.
+        assert_eq!(store.summary_at(at_day(15), 1).unwrap().current_streak, 3);
+        assert_eq!(store.summary_at(at_day(16), 1).unwrap().current_streak, 0);
+        assert_eq!(store.days(11, 12).unwrap().len(), 1);
+    }
+}
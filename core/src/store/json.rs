@@ -0,0 +1,67 @@
+//! Atomic, checksummed JSON file helpers shared by the individual stores.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use anyhow::anyhow;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tempfile::NamedTempFile;
+
+use crate::error::CoreError;
+
+use super::{Result, crypto, recovery};
+
+/// Read and deserialize `path`, returning the default value when the file does not exist yet.
+///
+/// Damaged files are recovered from their backups, see [`recovery`]; sealed files are
+/// decrypted with the profile key, see [`crypto`].
+pub(crate) fn read<T>(path: &Path) -> Result<T>
+where
+    T: DeserializeOwned + Default,
+{
+    match recovery::read_verified(path)? {
+        Some(bytes) => Ok(serde_json::from_slice(&crypto::open(path, bytes)?)?),
+        None => Ok(T::default()),
+    }
+}
+
+/// Serialize `value` and atomically replace `path` with the result, keeping a backup.
+pub(crate) fn write<T>(path: &Path, value: &T) -> Result<()>
+where
+    T: Serialize,
+{
+    let data = crypto::seal(path, serde_json::to_vec_pretty(value)?)?;
+    Ok(recovery::write_verified(path, &data)?)
+}
+
+/// Atomically replace `path` with `data` via a temp file in the same directory.
+pub(crate) fn write_bytes(path: &Path, data: &[u8]) -> Result<()> {
+    let Some(parent) = path.parent() else {
+        return Err(CoreError::Io(anyhow!(
+            "store path {} does not have a parent directory",
+            path.display()
+        )));
+    };
+
+    fs::create_dir_all(parent)?;
+    let mut temp = NamedTempFile::new_in(parent)?;
+    temp.write_all(data)?;
+    temp.flush()?;
+    match temp.persist(path) {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            if err.error.kind() == io::ErrorKind::AlreadyExists {
+                if let Err(remove_err) = fs::remove_file(path)
+                    && remove_err.kind() != io::ErrorKind::NotFound
+                {
+                    return Err(remove_err.into());
+                }
+                err.file.persist(path).map(|_| ()).map_err(|persist_err| persist_err.error.into())
+            } else {
+                Err(err.error.into())
+            }
+        }
+    }
+}
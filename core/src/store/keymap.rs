@@ -0,0 +1,137 @@
+//! Persisted shortcut bindings, shared by all profiles.
+//!
+//! Only actions whose gestures differ from the defaults are written, so new default bindings
+//! reach users who never touched them.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::keymap::Keymap;
+use crate::types::ActionId;
+
+use super::events::{EventBus, StoreEvent};
+use super::migrate::{self, Schema};
+use super::{Result, json};
+
+const KEYMAP_FILE: &str = "keymap.json";
+
+/// Schema of the keymap file stored at the state root.
+pub const SCHEMA: Schema =
+    Schema { file_name: KEYMAP_FILE, current: migrate::LEGACY_VERSION, migrations: &[] };
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct KeymapFile {
+    #[serde(default)]
+    version: u32,
+    /// Gestures per action id, for the actions that differ from the defaults.
+    #[serde(default)]
+    bindings: BTreeMap<String, Vec<String>>,
+}
+
+/// The keymap in effect, kept in memory for gesture lookups and saved after every change.
+#[derive(Debug)]
+pub struct KeymapStore {
+    root: PathBuf,
+    keymap: Mutex<Keymap>,
+    events: EventBus,
+}
+
+impl KeymapStore {
+    /// Load the keymap saved under `root`, or the defaults when none was saved.
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        let file: KeymapFile = json::read(&root.join(SCHEMA.file_name))?;
+        let keymap = Keymap::with_overrides(
+            file.bindings.iter().map(|(action, gestures)| (action.as_str(), gestures.as_slice())),
+        );
+        Ok(Self { root, keymap: Mutex::new(keymap), events: EventBus::new() })
+    }
+
+    /// Publish changes on `events` instead of a private bus.
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn get(&self) -> Keymap {
+        self.lock().clone()
+    }
+
+    /// Action bound to `gesture`; see [`Keymap::resolve`].
+    pub fn resolve(&self, gesture: &str) -> Option<ActionId> {
+        self.lock().resolve(gesture).cloned()
+    }
+
+    /// Replace the gestures of `action`; see [`Keymap::set_binding`].
+    pub fn set_binding(&self, action: &str, gestures: &[String]) -> Result<Keymap> {
+        self.update(|keymap| keymap.set_binding(action, gestures))
+    }
+
+    /// Restore the defaults of `action`, or of every action when `None`.
+    pub fn reset(&self, action: Option<&str>) -> Result<Keymap> {
+        self.update(|keymap| keymap.reset(action))
+    }
+
+    fn update(
+        &self,
+        change: impl FnOnce(&mut Keymap) -> std::result::Result<(), crate::keymap::KeymapError>,
+    ) -> Result<Keymap> {
+        let mut keymap = self.lock();
+        let mut next = keymap.clone();
+        change(&mut next)?;
+        save(&self.root, &next)?;
+        *keymap = next.clone();
+        drop(keymap);
+        self.events.emit(StoreEvent::KeymapChanged(next.clone()));
+        Ok(next)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Keymap> {
+        self.keymap.lock().expect("keymap mutex poisoned")
+    }
+}
+
+fn save(root: &Path, keymap: &Keymap) -> Result<()> {
+    let bindings = keymap
+        .overrides()
+        .map(|(action, gestures)| {
+            (action.0.clone(), gestures.iter().map(|gesture| gesture.0.clone()).collect())
+        })
+        .collect();
+    let file = KeymapFile { version: SCHEMA.current, bindings };
+    json::write(&root.join(SCHEMA.file_name), &file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persists_changes_and_publishes_them() {
+        let temp = tempfile::tempdir().unwrap();
+        let events = EventBus::new();
+        let changes = events.subscribe();
+        let store = KeymapStore::new(temp.path()).unwrap().with_events(events);
+        assert_eq!(store.get(), Keymap::default());
+
+        store.set_binding("reader.page.next", &["keyboard:Ctrl+KeyN".into()]).unwrap();
+        assert!(matches!(changes.try_recv(), Ok(StoreEvent::KeymapChanged(_))));
+        assert!(store.set_binding("reader.page.next", &["keyboard:KeyB".into()]).is_err());
+        assert!(changes.try_recv().is_err());
+
+        let reopened = KeymapStore::new(temp.path()).unwrap();
+        assert_eq!(
+            reopened.resolve("keyboard:Control+KeyN").map(|action| action.0),
+            Some("reader.page.next".to_string())
+        );
+        assert_eq!(reopened.resolve("keyboard:ArrowRight"), None);
+
+        reopened.reset(None).unwrap();
+        assert_eq!(KeymapStore::new(temp.path()).unwrap().get(), Keymap::default());
+    }
+}
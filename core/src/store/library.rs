@@ -0,0 +1,526 @@
+//! Library of sources the user has opened, with soft-delete and completion status.
+//!
+//! Hiding a source only flags the library entry; files on disk are never touched.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::{PageLayout, ReadingDirection};
+
+use super::events::{EventBus, StoreEvent};
+use super::migrate::{self, Schema};
+use super::{Result, json, profile};
+
+const LIBRARY_FILE: &str = "library.json";
+
+/// Schema of the per-profile library file.
+pub const SCHEMA: Schema =
+    Schema { file_name: LIBRARY_FILE, current: migrate::LEGACY_VERSION, migrations: &[] };
+
+/// How far the reader has got through a source or series.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReadingStatus {
+    #[default]
+    Unread,
+    InProgress,
+    Completed,
+    Dropped,
+}
+
+/// A source known to the library.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryEntry {
+    pub path: PathBuf,
+    pub title: String,
+    pub series: Option<String>,
+    pub status: ReadingStatus,
+    pub added_ms: u64,
+    pub last_opened_ms: u64,
+    /// When the entry was hidden from the library view, if it is hidden.
+    pub hidden_ms: Option<u64>,
+    /// Reading direction chosen for this source.
+    pub direction: ReadingDirection,
+    /// Page layout chosen for this source.
+    pub layout: PageLayout,
+}
+
+/// A [`LibraryEntry`] as written to the library file, which keeps the snake_case fields and
+/// kebab-case statuses it was first written with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredEntry {
+    path: PathBuf,
+    title: String,
+    #[serde(default)]
+    series: Option<String>,
+    #[serde(default)]
+    status: StoredStatus,
+    added_ms: u64,
+    last_opened_ms: u64,
+    #[serde(default)]
+    hidden_ms: Option<u64>,
+    #[serde(default)]
+    direction: ReadingDirection,
+    #[serde(default)]
+    layout: PageLayout,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum StoredStatus {
+    #[default]
+    Unread,
+    InProgress,
+    Completed,
+    Dropped,
+}
+
+impl From<StoredEntry> for LibraryEntry {
+    fn from(entry: StoredEntry) -> Self {
+        let status = match entry.status {
+            StoredStatus::Unread => ReadingStatus::Unread,
+            StoredStatus::InProgress => ReadingStatus::InProgress,
+            StoredStatus::Completed => ReadingStatus::Completed,
+            StoredStatus::Dropped => ReadingStatus::Dropped,
+        };
+        Self {
+            path: entry.path,
+            title: entry.title,
+            series: entry.series,
+            status,
+            added_ms: entry.added_ms,
+            last_opened_ms: entry.last_opened_ms,
+            hidden_ms: entry.hidden_ms,
+            direction: entry.direction,
+            layout: entry.layout,
+        }
+    }
+}
+
+impl From<LibraryEntry> for StoredEntry {
+    fn from(entry: LibraryEntry) -> Self {
+        let status = match entry.status {
+            ReadingStatus::Unread => StoredStatus::Unread,
+            ReadingStatus::InProgress => StoredStatus::InProgress,
+            ReadingStatus::Completed => StoredStatus::Completed,
+            ReadingStatus::Dropped => StoredStatus::Dropped,
+        };
+        Self {
+            path: entry.path,
+            title: entry.title,
+            series: entry.series,
+            status,
+            added_ms: entry.added_ms,
+            last_opened_ms: entry.last_opened_ms,
+            hidden_ms: entry.hidden_ms,
+            direction: entry.direction,
+            layout: entry.layout,
+        }
+    }
+}
+
+impl LibraryEntry {
+    pub fn is_hidden(&self) -> bool {
+        self.hidden_ms.is_some()
+    }
+}
+
+/// Status of every visible source in a series, rolled up for library filters.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeriesStatus {
+    pub series: String,
+    pub status: ReadingStatus,
+    pub unread: u32,
+    pub in_progress: u32,
+    pub completed: u32,
+    pub dropped: u32,
+}
+
+impl SeriesStatus {
+    fn new(series: String) -> Self {
+        Self {
+            series,
+            status: ReadingStatus::Unread,
+            unread: 0,
+            in_progress: 0,
+            completed: 0,
+            dropped: 0,
+        }
+    }
+
+    fn count(&mut self, status: ReadingStatus) {
+        match status {
+            ReadingStatus::Unread => self.unread += 1,
+            ReadingStatus::InProgress => self.in_progress += 1,
+            ReadingStatus::Completed => self.completed += 1,
+            ReadingStatus::Dropped => self.dropped += 1,
+        }
+    }
+
+    /// A series is only unread, completed or dropped when every volume agrees.
+    fn roll_up(&mut self) {
+        let total = self.unread + self.in_progress + self.completed + self.dropped;
+        self.status = if self.unread == total {
+            ReadingStatus::Unread
+        } else if self.completed == total {
+            ReadingStatus::Completed
+        } else if self.dropped == total {
+            ReadingStatus::Dropped
+        } else {
+            ReadingStatus::InProgress
+        };
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LibraryFile {
+    #[serde(default)]
+    version: u32,
+    entries: Vec<StoredEntry>,
+}
+
+/// Per-profile library of imported sources.
+#[derive(Debug)]
+pub struct LibraryStore {
+    root: PathBuf,
+    lock: Mutex<()>,
+    events: EventBus,
+}
+
+impl LibraryStore {
+    /// Create a store persisting under `root`, creating the directory if needed.
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self { root, lock: Mutex::new(()), events: EventBus::new() })
+    }
+
+    /// Publish changes on `events` instead of a private bus.
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Register an opened source, restoring it if it had been hidden.
+    pub fn record_open(&self, path: &Path, series: Option<&str>) -> Result<LibraryEntry> {
+        let path = normalize(path);
+        self.update(|entries| {
+            let now = now_ms();
+            let entry = match entries.iter_mut().position(|entry| entry.path == path) {
+                Some(index) => &mut entries[index],
+                None => {
+                    entries.push(LibraryEntry {
+                        title: title_for(&path),
+                        path: path.clone(),
+                        series: None,
+                        status: ReadingStatus::Unread,
+                        added_ms: now,
+                        last_opened_ms: now,
+                        hidden_ms: None,
+                        direction: ReadingDirection::default(),
+                        layout: PageLayout::default(),
+                    });
+                    entries.last_mut().expect("entry just pushed")
+                }
+            };
+            if let Some(series) = series.filter(|series| !series.is_empty()) {
+                entry.series = Some(series.to_string());
+            }
+            entry.last_opened_ms = now;
+            entry.hidden_ms = None;
+            Some(entry.clone())
+        })
+        .map(|entry| entry.expect("record_open always yields an entry"))
+    }
+
+    /// Look up a single entry by path.
+    pub fn get(&self, path: &Path) -> Result<Option<LibraryEntry>> {
+        let path = normalize(path);
+        Ok(self.read()?.into_iter().find(|entry| entry.path == path))
+    }
+
+    /// List library entries by title, optionally including hidden ones.
+    pub fn list(&self, include_hidden: bool) -> Result<Vec<LibraryEntry>> {
+        let mut entries: Vec<_> =
+            self.read()?.into_iter().filter(|entry| include_hidden || !entry.is_hidden()).collect();
+        entries.sort_by(|a, b| crate::fs::natural_cmp(&a.title, &b.title));
+        Ok(entries)
+    }
+
+    /// Entries for `paths` in that order, e.g. the members of a collection. Unknown paths are
+    /// skipped.
+    pub fn list_paths(&self, paths: &[PathBuf], include_hidden: bool) -> Result<Vec<LibraryEntry>> {
+        let entries = self.read()?;
+        Ok(paths
+            .iter()
+            .map(|path| normalize(path))
+            .filter_map(|path| entries.iter().find(|entry| entry.path == path))
+            .filter(|entry| include_hidden || !entry.is_hidden())
+            .cloned()
+            .collect())
+    }
+
+    /// Hide an entry from the library view. Returns `None` when `path` is unknown.
+    pub fn hide(&self, path: &Path) -> Result<Option<LibraryEntry>> {
+        self.set_hidden(path, Some(now_ms()))
+    }
+
+    /// Restore a hidden entry. Returns `None` when `path` is unknown.
+    pub fn restore(&self, path: &Path) -> Result<Option<LibraryEntry>> {
+        self.set_hidden(path, None)
+    }
+
+    /// Update the status from the reader's position: the last page marks the source completed,
+    /// anything earlier moves an unread source to in-progress. Returns `None` when unknown.
+    pub fn record_position(
+        &self,
+        path: &Path,
+        page_index: u32,
+        page_count: u32,
+    ) -> Result<Option<LibraryEntry>> {
+        let path = normalize(path);
+        let finished = page_count > 0 && page_index.saturating_add(1) >= page_count;
+        self.update(|entries| {
+            let entry = entries.iter_mut().find(|entry| entry.path == path)?;
+            let next = match entry.status {
+                _ if finished => ReadingStatus::Completed,
+                ReadingStatus::Unread => ReadingStatus::InProgress,
+                current => current,
+            };
+            if next == entry.status {
+                return None;
+            }
+            entry.status = next;
+            Some(entry.clone())
+        })
+    }
+
+    /// Override the status of an entry. Returns `None` when `path` is unknown.
+    pub fn set_status(&self, path: &Path, status: ReadingStatus) -> Result<Option<LibraryEntry>> {
+        let path = normalize(path);
+        self.update(|entries| {
+            let entry = entries.iter_mut().find(|entry| entry.path == path)?;
+            entry.status = status;
+            Some(entry.clone())
+        })
+    }
+
+    /// Remember how the source at `path` is read. Returns `None` when `path` is unknown.
+    pub fn set_reading_mode(
+        &self,
+        path: &Path,
+        direction: ReadingDirection,
+        layout: PageLayout,
+    ) -> Result<Option<LibraryEntry>> {
+        let path = normalize(path);
+        self.update(|entries| {
+            let entry = entries.iter_mut().find(|entry| entry.path == path)?;
+            entry.direction = direction;
+            entry.layout = layout;
+            Some(entry.clone())
+        })
+    }
+
+    /// Per-series status of the visible entries, ordered by series name.
+    pub fn series_status(&self) -> Result<Vec<SeriesStatus>> {
+        let mut series: BTreeMap<String, SeriesStatus> = BTreeMap::new();
+        for entry in self.read()?.into_iter().filter(|entry| !entry.is_hidden()) {
+            let Some(name) = entry.series else { continue };
+            series
+                .entry(name.clone())
+                .or_insert_with(|| SeriesStatus::new(name))
+                .count(entry.status);
+        }
+        Ok(series
+            .into_values()
+            .map(|mut status| {
+                status.roll_up();
+                status
+            })
+            .collect())
+    }
+
+    fn set_hidden(&self, path: &Path, hidden_ms: Option<u64>) -> Result<Option<LibraryEntry>> {
+        let path = normalize(path);
+        self.update(|entries| {
+            let entry = entries.iter_mut().find(|entry| entry.path == path)?;
+            if entry.hidden_ms.is_none() || hidden_ms.is_none() {
+                entry.hidden_ms = hidden_ms;
+            }
+            Some(entry.clone())
+        })
+    }
+
+    fn read(&self) -> Result<Vec<LibraryEntry>> {
+        let _guard = self.lock.lock().expect("library mutex poisoned");
+        let file: LibraryFile = json::read(&self.path()?)?;
+        Ok(file.entries.into_iter().map(LibraryEntry::from).collect())
+    }
+
+    /// Apply `mutate` and persist the file only when it reports a changed entry.
+    fn update(
+        &self,
+        mutate: impl FnOnce(&mut Vec<LibraryEntry>) -> Option<LibraryEntry>,
+    ) -> Result<Option<LibraryEntry>> {
+        let guard = self.lock.lock().expect("library mutex poisoned");
+        let path = self.path()?;
+        let mut file: LibraryFile = json::read(&path)?;
+        let mut entries: Vec<LibraryEntry> =
+            std::mem::take(&mut file.entries).into_iter().map(LibraryEntry::from).collect();
+        let Some(changed) = mutate(&mut entries) else {
+            return Ok(None);
+        };
+        file.entries = entries.into_iter().map(StoredEntry::from).collect();
+        file.version = SCHEMA.current;
+        json::write(&path, &file)?;
+        drop(guard);
+
+        self.events.emit(StoreEvent::LibraryChanged(changed.clone()));
+        Ok(Some(changed))
+    }
+
+    fn path(&self) -> Result<PathBuf> {
+        Ok(profile::active_dir(&self.root)?.join(SCHEMA.file_name))
+    }
+}
+
+fn title_for(path: &Path) -> String {
+    path.file_stem()
+        .or_else(|| path.file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string_lossy().to_string())
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hides_and_restores_without_touching_disk() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = LibraryStore::new(temp.path().join("state")).unwrap();
+        let source = temp.path().join("Vol 2.cbz");
+        fs::write(&source, b"zip").unwrap();
+
+        let entry = store.record_open(&source, None).unwrap();
+        assert_eq!(entry.title, "Vol 2");
+        store.record_open(Path::new("/elsewhere/Vol 10.cbz"), None).unwrap();
+
+        let hidden = store.hide(&source).unwrap().expect("known entry");
+        assert!(hidden.is_hidden());
+        assert!(source.exists());
+        assert_eq!(store.list(false).unwrap().len(), 1);
+        assert_eq!(store.list(true).unwrap().len(), 2);
+
+        assert!(!store.restore(&source).unwrap().unwrap().is_hidden());
+        let titles: Vec<_> = store.list(false).unwrap().into_iter().map(|e| e.title).collect();
+        assert_eq!(titles, vec!["Vol 2", "Vol 10"]);
+        assert!(store.hide(Path::new("/unknown.cbz")).unwrap().is_none());
+    }
+
+    #[test]
+    fn reopening_restores_hidden_entry() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = LibraryStore::new(temp.path()).unwrap();
+        let source = Path::new("/library/accident.cbz");
+
+        store.record_open(source, None).unwrap();
+        store.hide(source).unwrap();
+        assert!(!store.record_open(source, None).unwrap().is_hidden());
+        assert!(!store.get(source).unwrap().unwrap().is_hidden());
+    }
+
+    #[test]
+    fn tracks_status_and_rolls_up_series() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = LibraryStore::new(temp.path()).unwrap();
+        let one = Path::new("/saga/vol1.cbz");
+        let two = Path::new("/saga/vol2.cbz");
+        store.record_open(one, Some("saga")).unwrap();
+        store.record_open(two, Some("saga")).unwrap();
+        assert_eq!(store.series_status().unwrap()[0].status, ReadingStatus::Unread);
+
+        let entry = store.record_position(one, 3, 20).unwrap().expect("status changed");
+        assert_eq!(entry.status, ReadingStatus::InProgress);
+        assert!(store.record_position(one, 4, 20).unwrap().is_none());
+        assert_eq!(
+            store.record_position(one, 19, 20).unwrap().unwrap().status,
+            ReadingStatus::Completed
+        );
+        // Paging back through a finished volume keeps it completed.
+        assert!(store.record_position(one, 2, 20).unwrap().is_none());
+
+        let saga = &store.series_status().unwrap()[0];
+        assert_eq!((saga.status, saga.completed, saga.unread), (ReadingStatus::InProgress, 1, 1));
+
+        store.set_status(two, ReadingStatus::Completed).unwrap();
+        assert_eq!(store.series_status().unwrap()[0].status, ReadingStatus::Completed);
+        store.set_status(one, ReadingStatus::Dropped).unwrap();
+        store.set_status(two, ReadingStatus::Dropped).unwrap();
+        assert_eq!(store.series_status().unwrap()[0].status, ReadingStatus::Dropped);
+        assert!(
+            store.set_status(Path::new("/missing.cbz"), ReadingStatus::Dropped).unwrap().is_none()
+        );
+    }
+
+    #[test]
+    fn remembers_the_reading_mode_per_source() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = LibraryStore::new(temp.path()).unwrap();
+        let manga = Path::new("/manga/vol1.cbz");
+        let entry = store.record_open(manga, None).unwrap();
+        assert_eq!(
+            (entry.direction, entry.layout),
+            (ReadingDirection::LeftToRight, PageLayout::Single)
+        );
+
+        store.set_reading_mode(manga, ReadingDirection::RightToLeft, PageLayout::Dual).unwrap();
+        let entry = store.record_open(manga, None).unwrap();
+        assert_eq!(
+            (entry.direction, entry.layout),
+            (ReadingDirection::RightToLeft, PageLayout::Dual)
+        );
+        assert!(
+            store
+                .set_reading_mode(
+                    Path::new("/missing.cbz"),
+                    ReadingDirection::Vertical,
+                    PageLayout::Webtoon
+                )
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn stores_kebab_case_statuses_and_sends_camel_case_ones() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = LibraryStore::new(temp.path()).unwrap();
+        let source = Path::new("/saga/vol1.cbz");
+        store.record_open(source, None).unwrap();
+        let entry = store.record_position(source, 3, 20).unwrap().expect("status changed");
+
+        let stored = fs::read_to_string(temp.path().join(LIBRARY_FILE)).unwrap();
+        assert!(stored.contains("\"status\": \"in-progress\""), "{stored}");
+        assert!(stored.contains("\"last_opened_ms\""), "{stored}");
+
+        let sent = serde_json::to_value(&entry).unwrap();
+        assert_eq!(sent["status"], "inProgress");
+        assert_eq!(sent["lastOpenedMs"], entry.last_opened_ms);
+    }
+}
@@ -0,0 +1,66 @@
+//! Persisted log filter, shared by all profiles.
+//!
+//! The filter uses the `EnvFilter` directive syntax (e.g. `info,reader_core::pipeline=trace`)
+//! and applies unless one of the logging environment variables overrides it.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use super::migrate::{self, Schema};
+use super::{Result, json};
+
+const LOG_SETTINGS_FILE: &str = "log.json";
+
+/// Schema of the log settings file stored at the state root.
+pub const SCHEMA: Schema =
+    Schema { file_name: LOG_SETTINGS_FILE, current: migrate::LEGACY_VERSION, migrations: &[] };
+
+static LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LogSettingsFile {
+    #[serde(default)]
+    version: u32,
+    filter: Option<String>,
+}
+
+/// The stored filter directive, if one was saved.
+pub fn load_filter(root: &Path) -> Result<Option<String>> {
+    let _guard = LOCK.lock().expect("log settings mutex poisoned");
+    let file: LogSettingsFile = json::read(&root.join(SCHEMA.file_name))?;
+    Ok(file.filter)
+}
+
+/// Store `filter`, or clear it with `None`. The directive is validated before it is written.
+pub fn save_filter(root: &Path, filter: Option<&str>) -> Result<()> {
+    let filter = filter.map(str::trim).filter(|filter| !filter.is_empty());
+    if let Some(filter) = filter {
+        crate::log::validate_filter(filter)?;
+    }
+    let _guard = LOCK.lock().expect("log settings mutex poisoned");
+    let file = LogSettingsFile { version: SCHEMA.current, filter: filter.map(str::to_string) };
+    json::write(&root.join(SCHEMA.file_name), &file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_validated_filter() {
+        let temp = tempfile::tempdir().unwrap();
+        assert_eq!(load_filter(temp.path()).unwrap(), None);
+
+        save_filter(temp.path(), Some(" info,reader_core::pipeline=trace ")).unwrap();
+        assert_eq!(
+            load_filter(temp.path()).unwrap().as_deref(),
+            Some("info,reader_core::pipeline=trace")
+        );
+
+        assert!(save_filter(temp.path(), Some("reader_core=loud")).is_err());
+        save_filter(temp.path(), None).unwrap();
+        assert_eq!(load_filter(temp.path()).unwrap(), None);
+    }
+}
@@ -0,0 +1,304 @@
+//! Versioned schema migrations for the JSON store files.
+//!
+//! Every store file carries a top-level `version` number. On startup [`run_all`] walks the known
+//! files of every profile, backs up any file older than its schema, and applies the registered
+//! migrations in order. Failures are reported as [`MigrationError`] and leave the original file
+//! untouched so user data is never silently reset.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::error::CoreError;
+
+use super::backup::{self, BackupReason};
+use super::{
+    annotations, collections, crypto, history, json, keymap, library, log_settings, profile,
+    progress, recent, recovery, settings,
+};
+
+/// Files written before versioning was introduced are treated as this version.
+pub const LEGACY_VERSION: u32 = 1;
+
+const VERSION_FIELD: &str = "version";
+
+/// A single upgrade step from `from` to `from + 1`, applied to the raw JSON document.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    pub apply: fn(&mut Value) -> crate::Result<()>,
+}
+
+/// Describes the current version of a store file and how to reach it.
+#[derive(Debug, Clone, Copy)]
+pub struct Schema {
+    pub file_name: &'static str,
+    pub current: u32,
+    pub migrations: &'static [Migration],
+}
+
+/// Summary of a file that was upgraded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub path: PathBuf,
+    pub from: u32,
+    pub to: u32,
+    pub backup: PathBuf,
+}
+
+/// Typed failure raised while migrating a store file.
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error("reading {path}: {source}")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{path} is not valid JSON: {source}")]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("{path} has schema version {found}, newer than the supported version {supported}")]
+    TooNew { path: PathBuf, found: u32, supported: u32 },
+    #[error("no migration registered to upgrade {path} from version {version}")]
+    MissingStep { path: PathBuf, version: u32 },
+    #[error("backing up {path} to {backup}: {source}")]
+    Backup {
+        path: PathBuf,
+        backup: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("migrating {path} from version {from}: {source}")]
+    Step {
+        path: PathBuf,
+        from: u32,
+        #[source]
+        source: CoreError,
+    },
+    #[error("writing migrated {path}: {source}")]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: CoreError,
+    },
+}
+
+/// Read the schema version stamped into a document, defaulting to [`LEGACY_VERSION`].
+pub fn document_version(document: &Value) -> u32 {
+    document
+        .get(VERSION_FIELD)
+        .and_then(Value::as_u64)
+        .map(|version| version as u32)
+        .unwrap_or(LEGACY_VERSION)
+}
+
+/// Upgrade the file at `path` to `schema.current`, returning a report if anything changed.
+pub fn run(path: &Path, schema: &Schema) -> Result<Option<MigrationReport>, MigrationError> {
+    let bytes = match recovery::read_verified(path) {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return Ok(None),
+        Err(source) => return Err(MigrationError::Read { path: path.to_path_buf(), source }),
+    };
+    let mut document: Value = serde_json::from_slice(&bytes)
+        .map_err(|source| MigrationError::Parse { path: path.to_path_buf(), source })?;
+
+    let found = document_version(&document);
+    if found > schema.current {
+        return Err(MigrationError::TooNew {
+            path: path.to_path_buf(),
+            found,
+            supported: schema.current,
+        });
+    }
+    if found == schema.current {
+        return Ok(None);
+    }
+    if crypto::is_sealed(&document) {
+        tracing::warn!(
+            target: "store::migrate",
+            path = %path.display(),
+            "skipping encrypted store file; it is migrated once the profile can be decrypted"
+        );
+        return Ok(None);
+    }
+
+    let backup = backup_path(path, found);
+    fs::copy(path, &backup).map_err(|source| MigrationError::Backup {
+        path: path.to_path_buf(),
+        backup: backup.clone(),
+        source,
+    })?;
+
+    let mut version = found;
+    while version < schema.current {
+        let step = schema
+            .migrations
+            .iter()
+            .find(|migration| migration.from == version)
+            .ok_or_else(|| MigrationError::MissingStep { path: path.to_path_buf(), version })?;
+        (step.apply)(&mut document).map_err(|source| MigrationError::Step {
+            path: path.to_path_buf(),
+            from: version,
+            source,
+        })?;
+        version += 1;
+        if let Some(object) = document.as_object_mut() {
+            object.insert(VERSION_FIELD.to_string(), Value::from(version));
+        }
+        tracing::info!(
+            target: "store::migrate",
+            path = %path.display(),
+            from = step.from,
+            to = version,
+            "{}",
+            step.description
+        );
+    }
+
+    json::write(path, &document)
+        .map_err(|source| MigrationError::Write { path: path.to_path_buf(), source })?;
+
+    Ok(Some(MigrationReport { path: path.to_path_buf(), from: found, to: version, backup }))
+}
+
+/// Apply all registered schemas to the files under `root` and every profile directory.
+///
+/// A snapshot of the state directory is taken first whenever any file is out of date.
+pub fn run_all(root: &Path) -> crate::Result<Vec<MigrationReport>> {
+    let mut targets = vec![
+        (root.join(profile::SCHEMA.file_name), &profile::SCHEMA),
+        (root.join(log_settings::SCHEMA.file_name), &log_settings::SCHEMA),
+        (root.join(keymap::SCHEMA.file_name), &keymap::SCHEMA),
+    ];
+    for name in profile::list(root)? {
+        let dir = profile::dir(root, &name);
+        targets.extend(profile_schemas().iter().map(|schema| (dir.join(schema.file_name), schema)));
+    }
+
+    if targets.iter().any(|(path, schema)| is_outdated(path, schema)) {
+        backup::create(root, BackupReason::PreMigration, backup::DEFAULT_KEEP)?;
+    }
+
+    let mut reports = Vec::new();
+    for (path, schema) in targets {
+        reports.extend(run(&path, schema)?);
+    }
+    Ok(reports)
+}
+
+/// Whether `path` holds a readable document older than `schema.current`.
+fn is_outdated(path: &Path, schema: &Schema) -> bool {
+    let Ok(Some(bytes)) = recovery::read_verified(path) else {
+        return false;
+    };
+    serde_json::from_slice::<Value>(&bytes).is_ok_and(|document| {
+        !crypto::is_sealed(&document) && document_version(&document) < schema.current
+    })
+}
+
+/// Schemas of the files stored inside each profile directory.
+fn profile_schemas() -> &'static [Schema] {
+    &[
+        progress::SCHEMA,
+        recent::SCHEMA,
+        history::SCHEMA,
+        annotations::SCHEMA,
+        library::SCHEMA,
+        collections::SCHEMA,
+        settings::SCHEMA,
+    ]
+}
+
+fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut name = path.file_name().map(|name| name.to_os_string()).unwrap_or_default();
+    name.push(format!(".v{version}.bak"));
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    fn rename_entries(document: &mut Value) -> crate::Result<()> {
+        let object = document.as_object_mut().ok_or_else(|| anyhow!("expected object"))?;
+        let entries = object.remove("items").unwrap_or(Value::Null);
+        object.insert("entries".to_string(), entries);
+        Ok(())
+    }
+
+    fn add_flag(document: &mut Value) -> crate::Result<()> {
+        document["flag"] = Value::Bool(true);
+        Ok(())
+    }
+
+    fn failing(_document: &mut Value) -> crate::Result<()> {
+        Err(anyhow!("boom").into())
+    }
+
+    const SCHEMA: Schema = Schema {
+        file_name: "demo.json",
+        current: 3,
+        migrations: &[
+            Migration { from: 2, description: "add flag", apply: add_flag },
+            Migration { from: 1, description: "rename items", apply: rename_entries },
+        ],
+    };
+
+    #[test]
+    fn applies_steps_in_order_and_keeps_backup() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("demo.json");
+        fs::write(&path, br#"{"items":[1,2]}"#).unwrap();
+
+        let report = run(&path, &SCHEMA).unwrap().expect("migrated");
+        assert_eq!((report.from, report.to), (1, 3));
+        assert_eq!(fs::read(&report.backup).unwrap(), br#"{"items":[1,2]}"#);
+
+        let migrated: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(migrated["entries"], serde_json::json!([1, 2]));
+        assert_eq!(migrated["flag"], Value::Bool(true));
+        assert_eq!(document_version(&migrated), 3);
+
+        assert!(run(&path, &SCHEMA).unwrap().is_none(), "second run is a no-op");
+    }
+
+    #[test]
+    fn failures_are_typed_and_leave_data_untouched() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("demo.json");
+        let original = br#"{"version":1,"items":[]}"#;
+        fs::write(&path, original).unwrap();
+
+        let schema = Schema {
+            migrations: &[Migration { from: 1, description: "fails", apply: failing }],
+            ..SCHEMA
+        };
+        let err = run(&path, &schema).unwrap_err();
+        assert!(matches!(err, MigrationError::Step { from: 1, .. }));
+        assert_eq!(fs::read(&path).unwrap(), original);
+
+        fs::write(&path, br#"{"version":9}"#).unwrap();
+        assert!(matches!(run(&path, &SCHEMA), Err(MigrationError::TooNew { found: 9, .. })));
+
+        // Unparseable files are set aside by store recovery rather than failing the migration.
+        fs::write(&path, b"{not json").unwrap();
+        assert!(run(&path, &SCHEMA).unwrap().is_none());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn missing_files_are_skipped() {
+        let temp = tempfile::tempdir().unwrap();
+        assert!(run(&temp.path().join("absent.json"), &SCHEMA).unwrap().is_none());
+        assert!(run_all(temp.path()).unwrap().is_empty());
+    }
+}
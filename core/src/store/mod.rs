@@ -1,5 +1,54 @@
 //! Persistent storage for progress, settings, and caches.
 
+pub mod annotations;
+pub mod backup;
+pub mod coalesce;
+pub mod collections;
+pub mod crypto;
+pub mod events;
+pub mod export;
+pub mod history;
+mod json;
+pub mod keymap;
+pub mod library;
+pub mod log_settings;
+pub mod migrate;
+pub mod profile;
 pub mod progress;
+pub mod recent;
+pub mod recovery;
+pub mod settings;
+
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use directories::ProjectDirs;
+
+use crate::error::CoreError;
 
 pub type Result<T> = crate::Result<T>;
+
+const APP_QUALIFIER: &str = "com";
+const APP_ORGANISATION: &str = "LocalComicReader";
+const APP_NAME: &str = "local-comic-reader";
+
+/// Resolve the platform directory holding persisted reader state.
+pub fn state_dir() -> Result<PathBuf> {
+    ProjectDirs::from(APP_QUALIFIER, APP_ORGANISATION, APP_NAME)
+        .map(|dirs| dirs.data_dir().join("state"))
+        .ok_or_else(|| CoreError::Io(anyhow!("unable to resolve application data directory")))
+}
+
+/// Resolve the platform directory holding books downloaded from remote catalogs.
+pub fn downloads_dir() -> Result<PathBuf> {
+    ProjectDirs::from(APP_QUALIFIER, APP_ORGANISATION, APP_NAME)
+        .map(|dirs| dirs.data_dir().join("downloads"))
+        .ok_or_else(|| CoreError::Io(anyhow!("unable to resolve application data directory")))
+}
+
+/// Resolve the platform directory holding the shared image cache.
+pub fn cache_dir() -> Result<PathBuf> {
+    ProjectDirs::from(APP_QUALIFIER, APP_ORGANISATION, APP_NAME)
+        .map(|dirs| dirs.data_dir().join("cache"))
+        .ok_or_else(|| CoreError::Io(anyhow!("unable to resolve application data directory")))
+}
@@ -0,0 +1,166 @@
+//! Named reader profiles so people sharing a machine keep separate state.
+//!
+//! Each profile owns a directory holding its reading state: progress, history, library,
+//! recent sources, annotations, collections and settings. The built-in default profile maps to
+//! the state root itself so data written before profiles existed keeps working unchanged.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ensure;
+
+use super::migrate::{self, Schema};
+use super::{Result, json};
+
+/// Name of the profile used when none has been selected.
+pub const DEFAULT_PROFILE: &str = "default";
+
+const REGISTRY_FILE: &str = "profiles.json";
+
+/// Schema of the profile registry stored at the state root.
+pub const SCHEMA: Schema =
+    Schema { file_name: REGISTRY_FILE, current: migrate::LEGACY_VERSION, migrations: &[] };
+
+const PROFILES_DIR: &str = "profiles";
+const MAX_NAME_LEN: usize = 64;
+
+static REGISTRY_LOCK: Mutex<()> = Mutex::new(());
+
+/// Validated profile name, safe to use as a directory component.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProfileName(String);
+
+impl ProfileName {
+    /// Validate a user-supplied profile name.
+    ///
+    /// Names must be 1–64 characters of ASCII letters, digits, `-`, or `_`.
+    pub fn new(value: impl Into<String>) -> Result<Self> {
+        let value = value.into();
+        ensure!(Store, !value.is_empty(), "profile name must not be empty");
+        ensure!(
+            Store,
+            value.len() <= MAX_NAME_LEN,
+            "profile name exceeds {MAX_NAME_LEN} characters"
+        );
+        ensure!(
+            Store,
+            value.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_'),
+            "profile name {value:?} may only contain letters, digits, '-' and '_'"
+        );
+        Ok(Self(value))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.0 == DEFAULT_PROFILE
+    }
+}
+
+impl Default for ProfileName {
+    fn default() -> Self {
+        Self(DEFAULT_PROFILE.to_string())
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RegistryFile {
+    #[serde(default)]
+    version: u32,
+    active: Option<String>,
+    #[serde(default)]
+    profiles: Vec<String>,
+}
+
+/// Return the currently selected profile under `root`.
+pub fn active(root: &Path) -> Result<ProfileName> {
+    let _guard = REGISTRY_LOCK.lock().expect("profile registry mutex poisoned");
+    let registry: RegistryFile = json::read(&root.join(REGISTRY_FILE))?;
+    match registry.active {
+        Some(name) => ProfileName::new(name),
+        None => Ok(ProfileName::default()),
+    }
+}
+
+/// List all known profiles under `root`, always including the default profile first.
+pub fn list(root: &Path) -> Result<Vec<ProfileName>> {
+    let _guard = REGISTRY_LOCK.lock().expect("profile registry mutex poisoned");
+    let registry: RegistryFile = json::read(&root.join(REGISTRY_FILE))?;
+
+    let mut names = vec![ProfileName::default()];
+    for name in registry.profiles {
+        let name = ProfileName::new(name)?;
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+/// Make `profile` the active profile, registering it and creating its directory if needed.
+pub fn switch(root: &Path, profile: &ProfileName) -> Result<()> {
+    let _guard = REGISTRY_LOCK.lock().expect("profile registry mutex poisoned");
+    let path = root.join(REGISTRY_FILE);
+    let mut registry: RegistryFile = json::read(&path)?;
+
+    fs::create_dir_all(dir(root, profile))?;
+    if !profile.is_default() && !registry.profiles.iter().any(|name| name == profile.as_str()) {
+        registry.profiles.push(profile.as_str().to_string());
+    }
+    registry.active = Some(profile.as_str().to_string());
+    registry.version = SCHEMA.current;
+    json::write(&path, &registry)
+}
+
+/// Directory holding the files owned by `profile`.
+pub fn dir(root: &Path, profile: &ProfileName) -> PathBuf {
+    if profile.is_default() { root.to_path_buf() } else { root.join(PROFILES_DIR).join(&profile.0) }
+}
+
+/// Directory holding the files owned by the active profile.
+pub fn active_dir(root: &Path) -> Result<PathBuf> {
+    Ok(dir(root, &active(root)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_unsafe_names() {
+        assert!(ProfileName::new("").is_err());
+        assert!(ProfileName::new("../escape").is_err());
+        assert!(ProfileName::new("with space").is_err());
+        assert!(ProfileName::new("a".repeat(MAX_NAME_LEN + 1)).is_err());
+        assert!(ProfileName::new("kid_2").is_ok());
+    }
+
+    #[test]
+    fn defaults_to_root_directory() {
+        let temp = tempfile::tempdir().unwrap();
+        assert!(active(temp.path()).unwrap().is_default());
+        assert_eq!(active_dir(temp.path()).unwrap(), temp.path());
+    }
+
+    #[test]
+    fn switching_registers_and_isolates_profiles() {
+        let temp = tempfile::tempdir().unwrap();
+        let kid = ProfileName::new("kid").unwrap();
+
+        switch(temp.path(), &kid).unwrap();
+        assert_eq!(active(temp.path()).unwrap(), kid);
+        assert_eq!(active_dir(temp.path()).unwrap(), temp.path().join("profiles").join("kid"));
+        assert!(active_dir(temp.path()).unwrap().is_dir());
+
+        switch(temp.path(), &ProfileName::default()).unwrap();
+        switch(temp.path(), &kid).unwrap();
+        let names: Vec<_> =
+            list(temp.path()).unwrap().iter().map(|name| name.as_str().to_string()).collect();
+        assert_eq!(names, vec!["default", "kid"]);
+    }
+}
@@ -1,33 +1,47 @@
 //! Persistent storage for reading progress data.
+//!
+//! Page turns are frequent, so [`ProgressStore::save`] only buffers the latest page per source
+//! in memory. Buffered entries are written in one batch by [`ProgressStore::flush`], which the
+//! app runs on an interval (see [`ProgressStore::spawn_flusher`]) and before exit.
 
 use std::collections::HashMap;
 use std::fs;
-use std::io::{self, Write};
-use std::path::PathBuf;
-use std::sync::{Mutex, OnceLock};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use anyhow::anyhow;
-use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
-use tempfile::NamedTempFile;
 
 use crate::types::{PageId, SourceId};
 
-use super::Result;
+use super::coalesce::PeriodicFlush;
+use super::events::{EventBus, StoreEvent};
+use super::migrate::{self, Schema};
+use super::{Result, json, profile};
 
-const APP_QUALIFIER: &str = "com";
-const APP_ORGANISATION: &str = "LocalComicReader";
-const APP_NAME: &str = "local-comic-reader";
+const PROGRESS_FILE: &str = "progress.json";
 
+/// Schema of the per-profile progress file.
+pub const SCHEMA: Schema =
+    Schema { file_name: PROGRESS_FILE, current: migrate::LEGACY_VERSION, migrations: &[] };
+
+/// Reading progress store rooted at an explicit state directory.
+///
+/// Progress is kept per profile; every call resolves the active profile so switching profiles
+/// takes effect immediately.
 #[derive(Debug)]
-struct ProgressStorage {
-    path: PathBuf,
+pub struct ProgressStore {
+    root: PathBuf,
     lock: Mutex<()>,
+    /// Unwritten entries, keyed by the progress file of the profile they were saved under.
+    pending: Mutex<HashMap<PathBuf, HashMap<String, ProgressEntry>>>,
+    events: EventBus,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 struct ProgressFile {
+    #[serde(default)]
+    version: u32,
     entries: HashMap<String, ProgressEntry>,
 }
 
@@ -37,88 +51,159 @@ struct ProgressEntry {
     updated_ms: u64,
 }
 
-static STORAGE: OnceLock<ProgressStorage> = OnceLock::new();
-
-/// Load the last saved page for the given source, if available.
-pub fn load(source: &SourceId) -> Result<Option<PageId>> {
-    let storage = storage()?;
-    let _guard = storage.lock.lock().expect("progress mutex poisoned");
-    let file = read_file(storage)?;
-    Ok(file
-        .entries
-        .get(source.as_str())
-        .map(|entry| PageId { source_id: source.clone(), index: entry.page_index }))
-}
+impl ProgressStore {
+    /// Create a store persisting under `root`, creating the directory if needed.
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self {
+            root,
+            lock: Mutex::new(()),
+            pending: Mutex::new(HashMap::new()),
+            events: EventBus::new(),
+        })
+    }
 
-/// Persist the given page as the latest progress for its source.
-pub fn save(page: &PageId) -> Result<()> {
-    let storage = storage()?;
-    let _guard = storage.lock.lock().expect("progress mutex poisoned");
-    let mut file = read_file(storage)?;
-    file.entries.insert(
-        page.source_id.as_str().to_string(),
-        ProgressEntry { page_index: page.index, updated_ms: now_ms() },
-    );
-    write_file(storage, &file)
-}
+    /// Publish changes on `events` instead of a private bus.
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
 
-fn storage() -> Result<&'static ProgressStorage> {
-    if let Some(storage) = STORAGE.get() {
-        return Ok(storage);
+    /// State directory backing this store.
+    pub fn root(&self) -> &Path {
+        &self.root
     }
 
-    let dir = progress_dir()?;
-    fs::create_dir_all(&dir)?;
-    let path = dir.join("progress.json");
-    let storage = ProgressStorage { path, lock: Mutex::new(()) };
+    /// Load the last saved page for the given source, if available.
+    pub fn load(&self, source: &SourceId) -> Result<Option<PageId>> {
+        let path = self.path()?;
+        let to_page =
+            |entry: &ProgressEntry| PageId { source_id: source.clone(), index: entry.page_index };
 
-    STORAGE.set(storage).map_err(|_| anyhow!("progress storage already initialised"))?;
-    Ok(STORAGE.get().expect("progress storage set"))
-}
+        let pending = self.pending.lock().expect("progress pending mutex poisoned");
+        if let Some(entry) = pending.get(&path).and_then(|entries| entries.get(source.as_str())) {
+            return Ok(Some(to_page(entry)));
+        }
+        drop(pending);
 
-fn progress_dir() -> Result<PathBuf> {
-    ProjectDirs::from(APP_QUALIFIER, APP_ORGANISATION, APP_NAME)
-        .map(|dirs| dirs.data_dir().join("state"))
-        .ok_or_else(|| anyhow!("unable to resolve application data directory"))
-}
+        let _guard = self.lock.lock().expect("progress mutex poisoned");
+        let file: ProgressFile = json::read(&path)?;
+        Ok(file.entries.get(source.as_str()).map(to_page))
+    }
 
-fn read_file(storage: &ProgressStorage) -> Result<ProgressFile> {
-    match fs::read(&storage.path) {
-        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
-        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(ProgressFile::default()),
-        Err(err) => Err(err.into()),
+    /// Record the given page as the latest progress for its source.
+    ///
+    /// The entry is buffered until the next [`ProgressStore::flush`].
+    pub fn save(&self, page: &PageId) -> Result<()> {
+        let path = self.path()?;
+        self.pending
+            .lock()
+            .expect("progress pending mutex poisoned")
+            .entry(path)
+            .or_default()
+            .insert(
+                page.source_id.as_str().to_string(),
+                ProgressEntry { page_index: page.index, updated_ms: now_ms() },
+            );
+        self.events.emit(StoreEvent::ProgressUpdated { page: page.clone() });
+        Ok(())
     }
-}
 
-fn write_file(storage: &ProgressStorage, file: &ProgressFile) -> Result<()> {
-    if let Some(parent) = storage.path.parent() {
-        fs::create_dir_all(parent)?;
-        let data = serde_json::to_vec_pretty(file)?;
-        let mut temp = NamedTempFile::new_in(parent)?;
-        temp.write_all(&data)?;
-        temp.flush()?;
-        let target = storage.path.clone();
-        match temp.persist(&target) {
-            Ok(_) => Ok(()),
-            Err(err) => {
-                if err.error.kind() == io::ErrorKind::AlreadyExists {
-                    if let Err(remove_err) = fs::remove_file(&target) {
-                        if remove_err.kind() != io::ErrorKind::NotFound {
-                            return Err(remove_err.into());
-                        }
+    /// Write all buffered entries, one file rewrite per profile. Returns how many were written.
+    ///
+    /// Entries that fail to write stay buffered for the next attempt.
+    pub fn flush(&self) -> Result<usize> {
+        let batches =
+            std::mem::take(&mut *self.pending.lock().expect("progress pending mutex poisoned"));
+        let mut written = 0;
+        let mut first_error = None;
+
+        for (path, entries) in batches {
+            let count = entries.len();
+            match self.write_batch(&path, &entries) {
+                Ok(()) => written += count,
+                Err(err) => {
+                    // Keep anything saved since the batch was taken; it is newer.
+                    let mut pending = self.pending.lock().expect("progress pending mutex poisoned");
+                    let retained = pending.entry(path).or_default();
+                    for (source, entry) in entries {
+                        retained.entry(source).or_insert(entry);
                     }
-                    err.file
-                        .persist(&target)
-                        .map(|_| ())
-                        .map_err(|persist_err| persist_err.error.into())
-                } else {
-                    Err(err.error.into())
+                    first_error.get_or_insert(err);
                 }
             }
         }
-    } else {
-        Err(anyhow!("progress path {} does not have a parent directory", storage.path.display()))
+
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(written),
+        }
+    }
+
+    /// Flush this store every `interval` on a background thread until the handle is dropped.
+    pub fn spawn_flusher(self: &Arc<Self>, interval: Duration) -> std::io::Result<PeriodicFlush> {
+        let store = Arc::downgrade(self);
+        PeriodicFlush::spawn("progress", interval, move || {
+            if let Some(store) = store.upgrade()
+                && let Err(err) = store.flush()
+            {
+                tracing::warn!(target: "store::progress", "failed to flush progress: {err:#}");
+            }
+        })
+    }
+
+    fn write_batch(&self, path: &Path, entries: &HashMap<String, ProgressEntry>) -> Result<()> {
+        let _guard = self.lock.lock().expect("progress mutex poisoned");
+        let mut file: ProgressFile = json::read(path)?;
+        file.entries.extend(entries.iter().map(|(source, entry)| (source.clone(), entry.clone())));
+        file.version = SCHEMA.current;
+        json::write(path, &file)
+    }
+
+    /// Progress file of the currently active profile.
+    fn path(&self) -> Result<PathBuf> {
+        Ok(profile::active_dir(&self.root)?.join(SCHEMA.file_name))
+    }
+}
+
+impl Drop for ProgressStore {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            tracing::warn!(target: "store::progress", "dropping unsaved progress: {err:#}");
+        }
+    }
+}
+
+static DEFAULT_STORE: OnceLock<ProgressStore> = OnceLock::new();
+
+/// Load progress from the store in the platform state directory.
+pub fn load(source: &SourceId) -> Result<Option<PageId>> {
+    default_store()?.load(source)
+}
+
+/// Save progress to the store in the platform state directory, writing it immediately.
+pub fn save(page: &PageId) -> Result<()> {
+    let store = default_store()?;
+    store.save(page)?;
+    store.flush().map(|_| ())
+}
+
+/// Write progress buffered by the default store, if it was ever used. Returns how many entries
+/// were written.
+pub fn flush_default() -> Result<usize> {
+    DEFAULT_STORE.get().map_or(Ok(0), ProgressStore::flush)
+}
+
+fn default_store() -> Result<&'static ProgressStore> {
+    if let Some(store) = DEFAULT_STORE.get() {
+        return Ok(store);
     }
+
+    let store = ProgressStore::new(super::state_dir()?)?;
+    // A concurrent initialiser may have won the race; either instance points at the same files.
+    let _ = DEFAULT_STORE.set(store);
+    Ok(DEFAULT_STORE.get().expect("progress store set"))
 }
 
 fn now_ms() -> u64 {
@@ -128,41 +213,59 @@ fn now_ms() -> u64 {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
-    use std::sync::OnceLock;
+    use crate::store::profile::ProfileName;
 
-    static TEST_STORAGE: OnceLock<ProgressStorage> = OnceLock::new();
+    fn page(source: &SourceId, index: u32) -> PageId {
+        PageId { source_id: source.clone(), index }
+    }
 
-    fn setup_temp() -> ProgressStorage {
+    #[test]
+    fn writes_and_reads_progress() {
         let dir = tempfile::tempdir().expect("tempdir");
-        ProgressStorage { path: dir.path().join("progress.json"), lock: Mutex::new(()) }
-    }
+        let store = ProgressStore::new(dir.path()).unwrap();
+        let source = SourceId::new("demo");
 
-    fn test_storage() -> &'static ProgressStorage {
-        TEST_STORAGE.get_or_init(|| setup_temp())
+        assert_eq!(store.load(&source).unwrap(), None);
+        store.save(&page(&source, 42)).unwrap();
+        assert_eq!(store.load(&source).unwrap(), Some(page(&source, 42)));
+
+        let reopened = ProgressStore::new(dir.path()).unwrap();
+        assert_eq!(reopened.load(&source).unwrap(), None, "still buffered");
+        assert_eq!(store.flush().unwrap(), 1);
+        assert_eq!(reopened.load(&source).unwrap().map(|page| page.index), Some(42));
     }
 
     #[test]
-    fn writes_and_reads_progress() {
-        let storage = test_storage();
-        if storage.path.exists() {
-            fs::remove_file(&storage.path).unwrap();
-        }
-
+    fn coalesces_saves_until_flushed() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let store = ProgressStore::new(dir.path()).unwrap();
         let source = SourceId::new("demo");
-        let page = PageId { source_id: source.clone(), index: 42 };
 
-        {
-            let mut file = ProgressFile::default();
-            file.entries.insert(
-                source.as_str().to_string(),
-                ProgressEntry { page_index: page.index, updated_ms: now_ms() },
-            );
-            write_file(storage, &file).unwrap();
+        for index in 0..50 {
+            store.save(&page(&source, index)).unwrap();
         }
+        assert!(!dir.path().join(PROGRESS_FILE).exists());
+        assert_eq!(store.flush().unwrap(), 1);
+        assert_eq!(store.flush().unwrap(), 0);
+
+        store.save(&page(&source, 50)).unwrap();
+        drop(store);
+        let reopened = ProgressStore::new(dir.path()).unwrap();
+        assert_eq!(reopened.load(&source).unwrap().map(|page| page.index), Some(50));
+    }
+
+    #[test]
+    fn profiles_keep_separate_progress() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let store = ProgressStore::new(dir.path()).unwrap();
+        let source = SourceId::new("shared");
+
+        store.save(&page(&source, 3)).unwrap();
+        profile::switch(store.root(), &ProfileName::new("kid").unwrap()).unwrap();
+        assert_eq!(store.load(&source).unwrap(), None);
+        store.save(&page(&source, 9)).unwrap();
 
-        let stored = read_file(storage).unwrap();
-        let entry = stored.entries.get(source.as_str()).unwrap();
-        assert_eq!(entry.page_index, 42);
+        profile::switch(store.root(), &ProfileName::default()).unwrap();
+        assert_eq!(store.load(&source).unwrap().map(|page| page.index), Some(3));
     }
 }
@@ -0,0 +1,239 @@
+//! Most-recently-opened list backing the File → Open Recent menu.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::migrate::{self, Schema};
+use super::{Result, json, profile};
+
+const RECENT_FILE: &str = "recent.json";
+
+/// Default number of unpinned entries kept in the list.
+pub const DEFAULT_CAPACITY: usize = 20;
+
+/// Schema of the per-profile recent list file.
+pub const SCHEMA: Schema =
+    Schema { file_name: RECENT_FILE, current: migrate::LEGACY_VERSION, migrations: &[] };
+
+/// Persisted record of an opened path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecentEntry {
+    pub path: PathBuf,
+    pub pinned: bool,
+    pub opened_ms: u64,
+}
+
+/// Entry returned to callers, revalidated against the file system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecentItem {
+    pub entry: RecentEntry,
+    pub exists: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RecentFile {
+    #[serde(default)]
+    version: u32,
+    entries: Vec<RecentEntry>,
+}
+
+/// MRU list of opened sources; pinned entries sort first and are never evicted.
+#[derive(Debug)]
+pub struct RecentStore {
+    root: PathBuf,
+    capacity: usize,
+    lock: Mutex<()>,
+}
+
+impl RecentStore {
+    /// Create a store persisting under `root` with the default capacity.
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        Self::with_capacity(root, DEFAULT_CAPACITY)
+    }
+
+    /// Create a store keeping at most `capacity` unpinned entries.
+    pub fn with_capacity(root: impl Into<PathBuf>, capacity: usize) -> Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self { root, capacity, lock: Mutex::new(()) })
+    }
+
+    /// Record that `path` was just opened, moving it to the front of the list.
+    pub fn record_open(&self, path: &Path) -> Result<()> {
+        let path = normalize(path);
+        self.update(|entries| {
+            let pinned = match entries.iter().position(|entry| entry.path == path) {
+                Some(index) => entries.remove(index).pinned,
+                None => false,
+            };
+            entries.insert(0, RecentEntry { path, pinned, opened_ms: now_ms() });
+            true
+        })
+    }
+
+    /// Pin or unpin an entry. Returns `false` when `path` is not in the list.
+    pub fn pin(&self, path: &Path, pinned: bool) -> Result<bool> {
+        let path = normalize(path);
+        let mut found = false;
+        self.update(|entries| {
+            if let Some(entry) = entries.iter_mut().find(|entry| entry.path == path) {
+                entry.pinned = pinned;
+                found = true;
+            }
+            found
+        })?;
+        Ok(found)
+    }
+
+    /// Remove an entry. Returns `false` when `path` is not in the list.
+    pub fn remove(&self, path: &Path) -> Result<bool> {
+        let path = normalize(path);
+        let mut found = false;
+        self.update(|entries| {
+            let before = entries.len();
+            entries.retain(|entry| entry.path != path);
+            found = entries.len() != before;
+            found
+        })?;
+        Ok(found)
+    }
+
+    /// Drop unpinned entries whose path no longer exists, returning how many were removed.
+    pub fn prune_missing(&self) -> Result<usize> {
+        let mut removed = 0;
+        self.update(|entries| {
+            let before = entries.len();
+            entries.retain(|entry| entry.pinned || entry.path.exists());
+            removed = before - entries.len();
+            removed > 0
+        })?;
+        Ok(removed)
+    }
+
+    /// Empty the list, keeping pinned entries when `keep_pinned` is set, and return how many
+    /// entries were removed.
+    pub fn clear(&self, keep_pinned: bool) -> Result<usize> {
+        let mut removed = 0;
+        self.update(|entries| {
+            let before = entries.len();
+            entries.retain(|entry| keep_pinned && entry.pinned);
+            removed = before - entries.len();
+            removed > 0
+        })?;
+        Ok(removed)
+    }
+
+    /// List entries, pinned first, each flagged with whether its path still exists.
+    pub fn list(&self) -> Result<Vec<RecentItem>> {
+        let _guard = self.lock.lock().expect("recent mutex poisoned");
+        let file: RecentFile = json::read(&self.path()?)?;
+        Ok(file
+            .entries
+            .into_iter()
+            .map(|entry| {
+                let exists = entry.path.exists();
+                RecentItem { entry, exists }
+            })
+            .collect())
+    }
+
+    fn update<F>(&self, mutate: F) -> Result<()>
+    where
+        F: FnOnce(&mut Vec<RecentEntry>) -> bool,
+    {
+        let _guard = self.lock.lock().expect("recent mutex poisoned");
+        let path = self.path()?;
+        let mut file: RecentFile = json::read(&path)?;
+        if !mutate(&mut file.entries) {
+            return Ok(());
+        }
+
+        order_and_cap(&mut file.entries, self.capacity);
+        file.version = SCHEMA.current;
+        json::write(&path, &file)
+    }
+
+    fn path(&self) -> Result<PathBuf> {
+        Ok(profile::active_dir(&self.root)?.join(SCHEMA.file_name))
+    }
+}
+
+/// Stable-partition pinned entries to the front and evict the oldest unpinned overflow.
+fn order_and_cap(entries: &mut Vec<RecentEntry>, capacity: usize) {
+    let (pinned, unpinned): (Vec<_>, Vec<_>) = entries.drain(..).partition(|entry| entry.pinned);
+    entries.extend(pinned);
+    entries.extend(unpinned.into_iter().take(capacity));
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(store: &RecentStore) -> Vec<PathBuf> {
+        store.list().unwrap().into_iter().map(|item| item.entry.path).collect()
+    }
+
+    #[test]
+    fn most_recent_first_and_capped() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = RecentStore::with_capacity(temp.path(), 2).unwrap();
+
+        store.record_open(Path::new("/comics/a.cbz")).unwrap();
+        store.record_open(Path::new("/comics/b.cbz")).unwrap();
+        store.record_open(Path::new("/comics/a.cbz")).unwrap();
+        store.record_open(Path::new("/comics/c.cbz")).unwrap();
+
+        assert_eq!(paths(&store), vec![PathBuf::from("/comics/c.cbz"), "/comics/a.cbz".into()]);
+    }
+
+    #[test]
+    fn pinned_entries_survive_eviction() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = RecentStore::with_capacity(temp.path(), 1).unwrap();
+
+        store.record_open(Path::new("/comics/keep.cbz")).unwrap();
+        assert!(store.pin(Path::new("/comics/keep.cbz"), true).unwrap());
+        store.record_open(Path::new("/comics/x.cbz")).unwrap();
+        store.record_open(Path::new("/comics/y.cbz")).unwrap();
+
+        assert_eq!(paths(&store), vec![PathBuf::from("/comics/keep.cbz"), "/comics/y.cbz".into()]);
+        assert!(!store.pin(Path::new("/comics/unknown.cbz"), true).unwrap());
+
+        assert_eq!(store.clear(true).unwrap(), 1);
+        assert_eq!(paths(&store), vec![PathBuf::from("/comics/keep.cbz")]);
+        assert_eq!(store.clear(false).unwrap(), 1);
+        assert!(paths(&store).is_empty());
+    }
+
+    #[test]
+    fn revalidates_existence() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = RecentStore::new(temp.path().join("state")).unwrap();
+        let present = temp.path().join("present.cbz");
+        fs::write(&present, b"zip").unwrap();
+
+        store.record_open(Path::new("/definitely/missing.cbz")).unwrap();
+        store.record_open(&present).unwrap();
+
+        let items = store.list().unwrap();
+        assert!(items[0].exists);
+        assert!(!items[1].exists);
+
+        assert_eq!(store.prune_missing().unwrap(), 1);
+        assert_eq!(store.list().unwrap().len(), 1);
+        assert!(store.remove(&present).unwrap());
+        assert!(store.list().unwrap().is_empty());
+    }
+}
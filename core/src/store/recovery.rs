@@ -0,0 +1,251 @@
+//! Integrity checks for store files with fallback to rotating backups.
+//!
+//! Every write records a blake3 checksum next to the file (`<file>.b3`) and keeps the previous
+//! good copies as `<file>.bak1`..`<file>.bak{BACKUP_SLOTS}`. A file that fails its checksum or no
+//! longer parses is replaced by the newest valid backup; when none is usable the damaged file is
+//! set aside as `<file>.corrupt-<ms>` and the store starts empty. Either way a [`RecoveryReport`]
+//! is queued for the UI instead of failing every subsequent load and save.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::json;
+
+/// Number of previous versions kept for each store file.
+pub const BACKUP_SLOTS: u32 = 2;
+
+const CHECKSUM_SUFFIX: &str = ".b3";
+
+static REPORTS: Mutex<Vec<RecoveryReport>> = Mutex::new(Vec::new());
+
+/// What was done about a damaged store file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecoveryAction {
+    /// The file was replaced with the contents of this backup.
+    RestoredBackup { backup: PathBuf },
+    /// No usable backup existed; the damaged file was moved aside and the store reset.
+    Reset { quarantined: PathBuf },
+}
+
+/// A damaged store file that was recovered while loading.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveryReport {
+    pub path: PathBuf,
+    pub reason: String,
+    pub action: RecoveryAction,
+    pub at_ms: u64,
+}
+
+/// Recoveries performed since startup or the last [`clear`].
+pub fn reports() -> Vec<RecoveryReport> {
+    REPORTS.lock().expect("recovery mutex poisoned").clone()
+}
+
+/// Forget the queued recovery reports, e.g. once the user has acknowledged them.
+pub fn clear() {
+    REPORTS.lock().expect("recovery mutex poisoned").clear();
+}
+
+/// Read `path`, recovering from a backup when it is damaged. `None` means there is no data.
+pub(crate) fn read_verified(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    let reason = match verify(path)? {
+        Ok(bytes) => return Ok(bytes),
+        Err(reason) => reason,
+    };
+
+    for slot in 1..=BACKUP_SLOTS {
+        let backup = backup_path(path, slot);
+        if let Ok(Some(bytes)) = verify(&backup)? {
+            write_with_checksum(path, &bytes)?;
+            report(path, reason, RecoveryAction::RestoredBackup { backup });
+            return Ok(Some(bytes));
+        }
+    }
+
+    let quarantined = sibling(path, &format!(".corrupt-{}", now_ms()));
+    fs::rename(path, &quarantined)?;
+    remove_if_exists(&sibling(path, CHECKSUM_SUFFIX))?;
+    report(path, reason, RecoveryAction::Reset { quarantined });
+    Ok(None)
+}
+
+/// Rotate the current good copy into the backup slots, then replace `path` with `data`.
+pub(crate) fn write_verified(path: &Path, data: &[u8]) -> io::Result<()> {
+    if matches!(verify(path)?, Ok(Some(_))) {
+        rotate(path)?;
+    }
+    write_with_checksum(path, data)
+}
+
+/// Delete `path` together with its checksum and backups.
+pub(crate) fn remove(path: &Path) -> io::Result<()> {
+    remove_if_exists(path)?;
+    remove_if_exists(&sibling(path, CHECKSUM_SUFFIX))?;
+    discard_backups(path)
+}
+
+/// Delete the backups of `path`, e.g. after its contents were re-encrypted.
+pub(crate) fn discard_backups(path: &Path) -> io::Result<()> {
+    for slot in 1..=BACKUP_SLOTS {
+        let backup = backup_path(path, slot);
+        remove_if_exists(&backup)?;
+        remove_if_exists(&sibling(&backup, CHECKSUM_SUFFIX))?;
+    }
+    Ok(())
+}
+
+/// Check a file against its checksum and make sure it still parses as JSON.
+///
+/// Files written before checksums existed have no sidecar and are only checked for parsing.
+fn verify(path: &Path) -> io::Result<Result<Option<Vec<u8>>, String>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Ok(None)),
+        Err(err) => return Err(err),
+    };
+
+    match fs::read_to_string(sibling(path, CHECKSUM_SUFFIX)) {
+        Ok(expected) if expected.trim() != blake3::hash(&bytes).to_hex().as_str() => {
+            return Ok(Err("checksum mismatch".to_string()));
+        }
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+
+    match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(_) => Ok(Ok(Some(bytes))),
+        Err(err) => Ok(Err(format!("unparseable contents: {err}"))),
+    }
+}
+
+fn rotate(path: &Path) -> io::Result<()> {
+    for slot in (1..BACKUP_SLOTS).rev() {
+        let from = backup_path(path, slot);
+        if from.exists() {
+            let to = backup_path(path, slot + 1);
+            fs::rename(&from, &to)?;
+            rename_if_exists(&sibling(&from, CHECKSUM_SUFFIX), &sibling(&to, CHECKSUM_SUFFIX))?;
+        }
+    }
+    let newest = backup_path(path, 1);
+    fs::copy(path, &newest)?;
+    match fs::copy(sibling(path, CHECKSUM_SUFFIX), sibling(&newest, CHECKSUM_SUFFIX)) {
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            remove_if_exists(&sibling(&newest, CHECKSUM_SUFFIX))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn write_with_checksum(path: &Path, data: &[u8]) -> io::Result<()> {
+    let checksum = blake3::hash(data).to_hex();
+    json::write_bytes(path, data).map_err(io::Error::other)?;
+    json::write_bytes(&sibling(path, CHECKSUM_SUFFIX), checksum.as_bytes())
+        .map_err(io::Error::other)
+}
+
+fn report(path: &Path, reason: String, action: RecoveryAction) {
+    tracing::warn!(
+        target: "store::recovery",
+        path = %path.display(),
+        reason = %reason,
+        action = ?action,
+        "recovered damaged store file"
+    );
+    let report = RecoveryReport { path: path.to_path_buf(), reason, action, at_ms: now_ms() };
+    REPORTS.lock().expect("recovery mutex poisoned").push(report);
+}
+
+fn backup_path(path: &Path, slot: u32) -> PathBuf {
+    sibling(path, &format!(".bak{slot}"))
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name: OsString = path.file_name().map(|name| name.to_os_string()).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => remove_if_exists(to),
+        result => result,
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reports_for(path: &Path) -> Vec<RecoveryReport> {
+        reports().into_iter().filter(|report| report.path == path).collect()
+    }
+
+    #[test]
+    fn truncated_file_falls_back_to_latest_backup() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("progress.json");
+        write_verified(&path, br#"{"n":1}"#).unwrap();
+        write_verified(&path, br#"{"n":2}"#).unwrap();
+        write_verified(&path, br#"{"n":3}"#).unwrap();
+        assert!(backup_path(&path, BACKUP_SLOTS).exists());
+
+        fs::write(&path, br#"{"n":"#).unwrap();
+        assert_eq!(read_verified(&path).unwrap().unwrap(), br#"{"n":2}"#);
+        let recovered = reports_for(&path);
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(
+            recovered[0].action,
+            RecoveryAction::RestoredBackup { backup: backup_path(&path, 1) }
+        );
+
+        // The restored copy carries a fresh checksum and reads cleanly afterwards.
+        assert_eq!(read_verified(&path).unwrap().unwrap(), br#"{"n":2}"#);
+        assert_eq!(reports_for(&path).len(), 1);
+    }
+
+    #[test]
+    fn checksum_mismatch_without_backups_resets_store() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("history.json");
+        write_verified(&path, br#"{"days":{}}"#).unwrap();
+        fs::write(&path, br#"{"days":[]}"#).unwrap();
+
+        assert!(read_verified(&path).unwrap().is_none());
+        assert!(!path.exists());
+        let recovered = reports_for(&path);
+        assert_eq!(recovered[0].reason, "checksum mismatch");
+        let RecoveryAction::Reset { quarantined } = &recovered[0].action else {
+            panic!("expected reset, got {:?}", recovered[0].action);
+        };
+        assert!(quarantined.exists());
+    }
+
+    #[test]
+    fn legacy_file_without_checksum_is_accepted() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("recent.json");
+        fs::write(&path, br#"{"entries":[]}"#).unwrap();
+
+        assert_eq!(read_verified(&path).unwrap().unwrap(), br#"{"entries":[]}"#);
+        assert!(reports_for(&path).is_empty());
+        assert!(read_verified(&temp.path().join("absent.json")).unwrap().is_none());
+    }
+}
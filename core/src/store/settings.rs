@@ -0,0 +1,222 @@
+//! Persisted cache and pipeline tuning.
+//!
+//! Each profile keeps its own `settings.json` for the reading preferences (prefetch distance and
+//! resize filter). The machine knobs (cache budget, decode threads and the decode sandbox) are
+//! always read from the file at the state root, which is also the default profile's file, so
+//! every profile shares them.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::pipeline::pool::WorkerPool;
+use crate::pipeline::resize::ResizeFilter;
+use crate::types::{CacheBudget, PrefetchPolicy};
+
+use super::migrate::{self, Schema};
+use super::{Result, json, profile};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// Schema of the pipeline settings file stored in each profile directory.
+pub const SCHEMA: Schema =
+    Schema { file_name: SETTINGS_FILE, current: migrate::LEGACY_VERSION, migrations: &[] };
+
+/// Accepted range of [`PipelineSettings::cache_budget_mb`].
+pub const CACHE_BUDGET_MB: std::ops::RangeInclusive<u32> = 128..=2048;
+/// Largest accepted prefetch distance in either direction.
+pub const MAX_PREFETCH_PAGES: u32 = 8;
+/// Largest accepted [`PipelineSettings::decode_threads`].
+pub const MAX_DECODE_THREADS: usize = 16;
+
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Tuning applied to the image cache and the prefetch pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PipelineSettings {
+    /// Size the image cache on disk is trimmed to.
+    pub cache_budget_mb: u32,
+    /// Pages prefetched after the current one when the frontend does not ask for a window.
+    pub prefetch_ahead: u32,
+    /// Pages prefetched before the current one when the frontend does not ask for a window.
+    pub prefetch_behind: u32,
+    /// Prefetch worker threads; `0` picks one per spare core.
+    pub decode_threads: usize,
+    /// Resampling filter used when pages are scaled to their display size.
+    pub resize_filter: ResizeFilter,
+    /// Decode pages in a separate helper process; see [`crate::codec::sandbox`].
+    pub sandbox_decode: bool,
+}
+
+impl Default for PipelineSettings {
+    fn default() -> Self {
+        Self {
+            cache_budget_mb: (CacheBudget::default().disk_bytes_max / (1024 * 1024)) as u32,
+            prefetch_ahead: 2,
+            prefetch_behind: 2,
+            decode_threads: 0,
+            resize_filter: ResizeFilter::default(),
+            sandbox_decode: false,
+        }
+    }
+}
+
+impl PipelineSettings {
+    pub fn cache_budget_bytes(&self) -> u64 {
+        u64::from(self.cache_budget_mb) * 1024 * 1024
+    }
+
+    /// Cache budget with [`PipelineSettings::cache_budget_mb`] on disk and the default memory
+    /// budget, which bounds the entries the app's image cache keeps in memory.
+    pub fn cache_budget(&self) -> CacheBudget {
+        CacheBudget { disk_bytes_max: self.cache_budget_bytes(), ..CacheBudget::default() }
+    }
+
+    /// Prefetch worker count to run, resolving `0` to [`WorkerPool::default_threads`].
+    pub fn worker_threads(&self) -> usize {
+        match self.decode_threads {
+            0 => WorkerPool::default_threads(),
+            threads => threads,
+        }
+    }
+
+    /// These settings with the machine knobs taken from `machine`.
+    fn with_machine(self, machine: &PipelineSettings) -> Self {
+        Self {
+            cache_budget_mb: machine.cache_budget_mb,
+            decode_threads: machine.decode_threads,
+            sandbox_decode: machine.sandbox_decode,
+            ..self
+        }
+    }
+
+    pub fn prefetch_policy(&self) -> PrefetchPolicy {
+        PrefetchPolicy { ahead: self.prefetch_ahead, behind: self.prefetch_behind }
+    }
+
+    /// Reject values outside the ranges the cache and pipeline are tuned for.
+    pub fn validate(&self) -> std::result::Result<(), InvalidSettings> {
+        let invalid = |field, reason: String| Err(InvalidSettings { field, reason });
+        if !CACHE_BUDGET_MB.contains(&self.cache_budget_mb) {
+            return invalid(
+                "cacheBudgetMb",
+                format!(
+                    "must be between {} and {}",
+                    CACHE_BUDGET_MB.start(),
+                    CACHE_BUDGET_MB.end()
+                ),
+            );
+        }
+        if self.prefetch_ahead > MAX_PREFETCH_PAGES || self.prefetch_behind > MAX_PREFETCH_PAGES {
+            return invalid("prefetch", format!("must be at most {MAX_PREFETCH_PAGES} pages"));
+        }
+        if self.decode_threads > MAX_DECODE_THREADS {
+            return invalid("decodeThreads", format!("must be at most {MAX_DECODE_THREADS}"));
+        }
+        Ok(())
+    }
+}
+
+/// A setting outside its accepted range.
+#[derive(Debug, Error)]
+#[error("invalid setting {field}: {reason}")]
+pub struct InvalidSettings {
+    pub field: &'static str,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SettingsFile {
+    #[serde(default)]
+    version: u32,
+    #[serde(default)]
+    pipeline: PipelineSettings,
+}
+
+/// The active profile's settings with the shared machine knobs, or the defaults for whatever
+/// was never saved.
+pub fn load(root: &Path) -> Result<PipelineSettings> {
+    let _guard = LOCK.lock().expect("settings mutex poisoned");
+    let machine: SettingsFile = json::read(&root.join(SCHEMA.file_name))?;
+    let dir = profile::active_dir(root)?;
+    if dir == root {
+        return Ok(machine.pipeline);
+    }
+    let file: SettingsFile = json::read(&dir.join(SCHEMA.file_name))?;
+    Ok(file.pipeline.with_machine(&machine.pipeline))
+}
+
+/// Validate and store `settings`: the reading preferences for the active profile and the
+/// machine knobs for all of them.
+pub fn save(root: &Path, settings: &PipelineSettings) -> Result<()> {
+    settings.validate()?;
+    let _guard = LOCK.lock().expect("settings mutex poisoned");
+    let dir = profile::active_dir(root)?;
+    if dir != root {
+        let path = root.join(SCHEMA.file_name);
+        let machine: SettingsFile = json::read(&path)?;
+        let pipeline = machine.pipeline.with_machine(settings);
+        json::write(&path, &SettingsFile { version: SCHEMA.current, pipeline })?;
+    }
+    let file = SettingsFile { version: SCHEMA.current, pipeline: *settings };
+    json::write(&dir.join(SCHEMA.file_name), &file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::CoreError;
+
+    #[test]
+    fn stores_validated_settings() {
+        let temp = tempfile::tempdir().unwrap();
+        assert_eq!(load(temp.path()).unwrap(), PipelineSettings::default());
+        assert_eq!(PipelineSettings::default().cache_budget_mb, 512);
+
+        let settings = PipelineSettings {
+            cache_budget_mb: 1024,
+            decode_threads: 2,
+            resize_filter: ResizeFilter::CatmullRom,
+            sandbox_decode: true,
+            ..PipelineSettings::default()
+        };
+        save(temp.path(), &settings).unwrap();
+        assert_eq!(load(temp.path()).unwrap(), settings);
+
+        let too_small = PipelineSettings { cache_budget_mb: 16, ..settings };
+        let err = save(temp.path(), &too_small).unwrap_err();
+        assert!(matches!(err, CoreError::Store(_)));
+        assert!(err.inner().downcast_ref::<InvalidSettings>().is_some());
+        assert_eq!(load(temp.path()).unwrap(), settings);
+    }
+
+    #[test]
+    fn keeps_reading_preferences_per_profile() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let adult = PipelineSettings {
+            prefetch_ahead: 4,
+            resize_filter: ResizeFilter::CatmullRom,
+            ..PipelineSettings::default()
+        };
+        save(root, &adult).unwrap();
+
+        profile::switch(root, &profile::ProfileName::new("kid").unwrap()).unwrap();
+        assert_eq!(load(root).unwrap(), PipelineSettings::default());
+        let kid = PipelineSettings {
+            cache_budget_mb: 1024,
+            prefetch_ahead: 1,
+            resize_filter: ResizeFilter::Nearest,
+            ..PipelineSettings::default()
+        };
+        save(root, &kid).unwrap();
+        assert_eq!(load(root).unwrap(), kid);
+
+        profile::switch(root, &profile::ProfileName::default()).unwrap();
+        let shared = load(root).unwrap();
+        assert_eq!(shared, PipelineSettings { cache_budget_mb: 1024, ..adult });
+    }
+}
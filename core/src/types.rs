@@ -1,9 +1,18 @@
 //! Shared data structures exchanged between the core, Tauri shell, and UI layers.
 
 use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::pipeline::resize::ResizeFilter;
+
+pub use crate::cache::key::{ImageKey, ImageVariant};
 
 /// Identifier for an opened source (folder, archive, etc.).
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+///
+/// Sources opened from disk get ids from [`crate::fs::source_id_for`], which are stable across
+/// launches so progress and cache entries keyed by them stay valid.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
 pub struct SourceId(String);
 
 impl SourceId {
@@ -17,20 +26,23 @@ impl SourceId {
 }
 
 /// Page identifier combines the parent source with the page index.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PageId {
     pub source_id: SourceId,
     pub index: u32,
 }
 
 /// High level description of a source.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub enum Source {
     Folder { root: PathBuf, entries: Vec<PathBuf> },
     Archive { path: PathBuf, kind: ArchiveKind, entries: Vec<ArchiveEntry> },
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub enum ArchiveKind {
     Zip,
     Rar,
@@ -39,7 +51,8 @@ pub enum ArchiveKind {
     Unknown,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ArchiveEntry {
     pub path: PathBuf,
     pub size_bytes: u64,
@@ -47,25 +60,80 @@ pub struct ArchiveEntry {
 }
 
 /// Metadata about an individual page, independent of rendering params.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PageMeta {
     pub id: PageId,
     pub rel_path: PathBuf,
+    /// Content type judged by the file extension; see [`crate::fs::mime_for`].
+    pub mime: String,
+    /// Size of the stored file, uncompressed for archive entries.
+    pub size_bytes: u64,
+    /// Last modification of the file, or of the whole archive for archive entries. Serialized
+    /// as milliseconds since the Unix epoch.
+    #[serde(with = "unix_ms")]
+    pub modified: Option<SystemTime>,
     pub width: u32,
     pub height: u32,
     pub is_double_spread: bool,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub enum FitMode {
     FitWidth,
     FitHeight,
     FitContain,
     Original,
     Fill,
+    /// Fit tall strips such as webtoons to the width and other pages into the viewport; see
+    /// [`crate::pipeline::fit::resolve`].
+    FitSmart,
+}
+
+/// Order in which a source's pages are read. Library entries persist it, so it always
+/// serializes, like [`ResizeFilter`].
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Default, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum ReadingDirection {
+    /// Pages advance to the right, as in western comics.
+    #[default]
+    LeftToRight,
+    /// Pages advance to the left, as in manga.
+    RightToLeft,
+    /// Pages advance downwards.
+    Vertical,
+}
+
+/// How many pages are shown at once and how they are arranged.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Default, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum PageLayout {
+    /// One page at a time.
+    #[default]
+    Single,
+    /// Two facing pages composed into a spread.
+    Dual,
+    /// Pages joined into one continuous strip.
+    Webtoon,
+}
+
+impl PageLayout {
+    /// Pages a single turn moves past.
+    pub fn pages_per_view(self) -> u32 {
+        match self {
+            PageLayout::Dual => 2,
+            PageLayout::Single | PageLayout::Webtoon => 1,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct RenderParams {
     pub fit: FitMode,
     pub viewport_w: u32,
@@ -73,6 +141,13 @@ pub struct RenderParams {
     pub scale: f32,
     pub rotation: i16,
     pub dpi: f32,
+    /// Resampling filter used when the page is scaled down.
+    #[serde(default)]
+    pub filter: ResizeFilter,
+    #[serde(default)]
+    pub direction: ReadingDirection,
+    #[serde(default)]
+    pub layout: PageLayout,
 }
 
 impl Default for RenderParams {
@@ -84,47 +159,38 @@ impl Default for RenderParams {
             scale: 1.0,
             rotation: 0,
             dpi: 96.0,
+            filter: ResizeFilter::default(),
+            direction: ReadingDirection::default(),
+            layout: PageLayout::default(),
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct ImageKey {
-    pub cache_key: String,
-}
-
-impl ImageKey {
-    pub fn new(cache_key: impl Into<String>) -> Self {
-        Self { cache_key: cache_key.into() }
-    }
-
-    /// Derive a child key by appending a suffix separated with `::`.
-    pub fn derive(&self, suffix: impl AsRef<str>) -> Self {
-        let mut derived = self.cache_key.clone();
-        derived.push_str("::");
-        derived.push_str(suffix.as_ref());
-        Self { cache_key: derived }
-    }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Byte limits of the in-memory and on-disk image caches; missing fields take their defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 pub struct CacheBudget {
-    pub bytes_max: usize,
+    /// Limit of decoded and resized pages held by [`crate::cache::memory::MemoryCache`].
+    pub memory_bytes_max: usize,
+    /// Limit of encoded entries stored by [`crate::cache::disk::DiskCache`].
+    pub disk_bytes_max: u64,
 }
 
 impl Default for CacheBudget {
     fn default() -> Self {
-        Self { bytes_max: 512 * 1024 * 1024 }
+        Self { memory_bytes_max: 256 * 1024 * 1024, disk_bytes_max: 512 * 1024 * 1024 }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ImageDimensions {
     pub width: u32,
     pub height: u32,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PrefetchPolicy {
     pub ahead: u32,
     pub behind: u32,
@@ -136,14 +202,17 @@ impl Default for PrefetchPolicy {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
 pub struct ActionId(pub String);
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
 pub struct InputGesture(pub String);
 
 /// Token identifying an in-flight asynchronous request.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
 pub struct RequestToken(u64);
 
 impl RequestToken {
@@ -156,7 +225,8 @@ impl RequestToken {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SeriesMeta {
     pub title: Option<String>,
     pub series: Option<String>,
@@ -172,3 +242,71 @@ pub struct AppState {
     pub current_page: Option<PageId>,
     pub cache_budget: CacheBudget,
 }
+
+/// (De)serialize an optional [`SystemTime`] as milliseconds since the Unix epoch.
+mod unix_ms {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        time: &Option<SystemTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let ms = time.and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+        ms.map(|since| since.as_millis() as u64).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<SystemTime>, D::Error> {
+        let ms = Option::<u64>::deserialize(deserializer)?;
+        Ok(ms.map(|ms| UNIX_EPOCH + Duration::from_millis(ms)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn serializes_in_camel_case_for_the_ui() {
+        let meta = PageMeta {
+            id: PageId { source_id: SourceId::new("src-1"), index: 4 },
+            rel_path: PathBuf::from("ch01/005.png"),
+            mime: "image/png".to_string(),
+            size_bytes: 2048,
+            modified: Some(SystemTime::UNIX_EPOCH + std::time::Duration::from_millis(1_700_000)),
+            width: 1200,
+            height: 1800,
+            is_double_spread: false,
+        };
+        let value = serde_json::to_value(&meta).unwrap();
+        assert_eq!(value["id"], json!({ "sourceId": "src-1", "index": 4 }));
+        assert_eq!(value["relPath"], json!("ch01/005.png"));
+        assert_eq!(value["modified"], json!(1_700_000));
+        assert_eq!(serde_json::from_value::<PageMeta>(value).unwrap(), meta);
+        assert_eq!(serde_json::to_value(FitMode::FitContain).unwrap(), json!("fitContain"));
+    }
+
+    #[test]
+    fn render_params_from_the_ui_default_the_pipeline_fields() {
+        let params: RenderParams = serde_json::from_value(json!({
+            "fit": "fitWidth",
+            "viewportW": 800,
+            "viewportH": 600,
+            "scale": 1.5,
+            "rotation": 90,
+            "dpi": 96.0,
+        }))
+        .unwrap();
+        assert_eq!(params.fit, FitMode::FitWidth);
+        assert_eq!((params.viewport_w, params.rotation), (800, 90));
+        assert_eq!(params.filter, ResizeFilter::default());
+        assert_eq!(
+            (params.direction, params.layout),
+            (ReadingDirection::LeftToRight, PageLayout::Single)
+        );
+    }
+}
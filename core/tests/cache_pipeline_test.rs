@@ -17,15 +17,16 @@ fn decoded(width: u32, height: u32, value: u8) -> DecodedImage {
 
 #[test]
 fn memory_cache_evicts_least_recently_used() {
-    let mut cache = MemoryCache::new(CacheBudget { bytes_max: 64 });
-    let key1 = ImageKey::new("entry::1");
-    let key2 = ImageKey::new("entry::2");
-    let key3 = ImageKey::new("entry::3");
-
+    let mut cache =
+        MemoryCache::new(CacheBudget { memory_bytes_max: 64, ..CacheBudget::default() });
     let page1 = page("src", 1);
     let page2 = page("src", 2);
     let page3 = page("src", 3);
 
+    let key1 = ImageKey::original(&page1);
+    let key2 = ImageKey::original(&page2);
+    let key3 = ImageKey::original(&page3);
+
     cache.insert(key1.clone(), CacheEntry::new(page1.clone(), vec![1; 32])).unwrap();
     cache.insert(key2.clone(), CacheEntry::new(page2.clone(), vec![2; 32])).unwrap();
 
@@ -42,9 +43,10 @@ fn memory_cache_evicts_least_recently_used() {
 
 #[test]
 fn memory_cache_retain_validates_page_mapping() {
-    let mut cache = MemoryCache::new(CacheBudget { bytes_max: 64 });
-    let key = ImageKey::new("retain");
+    let mut cache =
+        MemoryCache::new(CacheBudget { memory_bytes_max: 64, ..CacheBudget::default() });
     let page_actual = page("src", 7);
+    let key = ImageKey::original(&page_actual);
     cache.insert(key.clone(), CacheEntry::new(page_actual.clone(), vec![5; 16])).unwrap();
 
     assert!(cache.retain(&key, &page_actual).unwrap());
@@ -57,7 +59,7 @@ fn memory_cache_retain_validates_page_mapping() {
 #[test]
 fn mip_chain_obeys_min_dimension() {
     let image = decoded(64, 40, 200);
-    let base_key = ImageKey::new("mip::base");
+    let base_key = ImageKey::original(&page("src", 0));
     let config = MipChainConfig { min_dimension: 8, ..Default::default() };
     let chain = build_chain(&base_key, &image, config).expect("build chain");
 
@@ -72,7 +74,7 @@ fn mip_chain_obeys_min_dimension() {
 #[test]
 fn tiling_produces_overlapping_slices() {
     let image = decoded(512, 4096, 90);
-    let base_key = ImageKey::new("tile::base");
+    let base_key = ImageKey::original(&page("src", 0));
     let config = TileConfig { aspect_ratio_threshold: 3.0, max_tile_height: 1024, overlap: 128 };
     let tiles = slice_vertical(&image, &base_key, config).expect("slice vertical");
 
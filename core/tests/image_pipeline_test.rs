@@ -10,6 +10,9 @@ fn stub_meta(name: &str) -> PageMeta {
     PageMeta {
         id: PageId { source_id: SourceId::new("tests"), index: 0 },
         rel_path: name.into(),
+        mime: String::new(),
+        size_bytes: 0,
+        modified: None,
         width: 0,
         height: 0,
         is_double_spread: false,
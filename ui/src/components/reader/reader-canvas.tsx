@@ -2,7 +2,7 @@ import { useCallback, useEffect, useLayoutEffect, useMemo, useRef, useState } fr
 import type { PointerEvent as ReactPointerEvent, WheelEvent as ReactWheelEvent } from 'react'
 
 import type { FitMode, PageMeta } from '@/ipc'
-import { buildRenderParams, getPageUrl } from '@/ipc'
+import { buildRenderParams, getPageUrl, resolveFit } from '@/ipc'
 import { cn } from '@/lib/utils'
 
 import { READER_MAX_ZOOM, READER_MIN_ZOOM, READER_ZOOM_STEP, type Rotation } from './view-model'
@@ -200,7 +200,8 @@ export function ReaderCanvas({
           viewportW: Math.round(containerSize.width),
           viewportH: Math.round(containerSize.height),
           scale: zoom,
-          rotation
+          rotation,
+          dpi: 96 * (window.devicePixelRatio || 1)
         })
         const url = await getPageUrl(page.id, params)
         const response = await fetch(url, { signal: controller.signal })
@@ -260,35 +261,51 @@ export function ReaderCanvas({
     }
   }, [page, containerSize.width, containerSize.height, workerReady, fitMode, rotation, zoom])
 
+  // Pages arrive from the backend already rotated, so only the metadata fallback needs turning.
   const naturalSize = useMemo<Size>(() => {
     if (imageSize.width > 0 && imageSize.height > 0) {
       return imageSize
     }
     if (page) {
-      return { width: page.width || 1, height: page.height || 1 }
+      const width = page.width || 1
+      const height = page.height || 1
+      return rotation % 180 === 0 ? { width, height } : { width: height, height: width }
     }
     return { width: 1, height: 1 }
-  }, [imageSize, page])
+  }, [imageSize, page, rotation])
 
-  const rotatedNaturalSize = useMemo<Size>(() => {
-    if (rotation % 180 === 0) {
-      return naturalSize
+  // `fitSmart` depends on the page shape; the core decides which concrete mode it becomes.
+  const [resolvedFit, setResolvedFit] = useState<FitMode>(fitMode === 'fitSmart' ? 'fitContain' : fitMode)
+  useEffect(() => {
+    if (fitMode !== 'fitSmart') {
+      setResolvedFit(fitMode)
+      return
     }
-    return { width: naturalSize.height, height: naturalSize.width }
-  }, [naturalSize, rotation])
+    let disposed = false
+    resolveFit(fitMode, naturalSize.width, naturalSize.height)
+      .then((mode) => {
+        if (!disposed) {
+          setResolvedFit(mode)
+        }
+      })
+      .catch((err) => console.debug('Unable to resolve the smart fit mode.', err))
+    return () => {
+      disposed = true
+    }
+  }, [fitMode, naturalSize.width, naturalSize.height])
 
   const baseScale = useMemo(() => {
     if (containerSize.width === 0 || containerSize.height === 0) {
       return 1
     }
     const { width: viewportW, height: viewportH } = containerSize
-    const { width: contentW, height: contentH } = rotatedNaturalSize
+    const { width: contentW, height: contentH } = naturalSize
     if (contentW <= 0 || contentH <= 0) {
       return 1
     }
     const widthRatio = viewportW / contentW
     const heightRatio = viewportH / contentH
-    switch (fitMode) {
+    switch (resolvedFit) {
       case 'fitWidth':
         return widthRatio
       case 'fitHeight':
@@ -301,7 +318,7 @@ export function ReaderCanvas({
       default:
         return Math.min(widthRatio, heightRatio)
     }
-  }, [containerSize, fitMode, rotatedNaturalSize])
+  }, [containerSize, resolvedFit, naturalSize])
 
   const scheduleRender = useCallback(() => {
     if (workerReady && workerRef.current) {
@@ -315,7 +332,6 @@ export function ReaderCanvas({
         baseScale,
         scale: zoom,
         offset,
-        rotation,
         devicePixelRatio: window.devicePixelRatio || 1
       })
       return
@@ -379,17 +395,13 @@ export function ReaderCanvas({
       const viewportCenterY = displayHeight / 2
       const centerX = viewportCenterX + offset.x
       const centerY = viewportCenterY + offset.y
-      const rotationRadians = ((rotation % 360) * Math.PI) / 180
 
       context.save()
       context.translate(centerX, centerY)
-      if (rotationRadians !== 0) {
-        context.rotate(rotationRadians)
-      }
       context.drawImage(image, -drawWidth / 2, -drawHeight / 2, drawWidth, drawHeight)
       context.restore()
     })
-  }, [workerReady, containerSize.width, containerSize.height, baseScale, zoom, offset, rotation, bitmap])
+  }, [workerReady, containerSize.width, containerSize.height, baseScale, zoom, offset, bitmap])
 
   useEffect(() => {
     scheduleRender()
@@ -428,29 +440,13 @@ export function ReaderCanvas({
         return offset
       }
 
-      const rotationRadians = ((rotation % 360) * Math.PI) / 180
-      const cos = Math.cos(rotationRadians)
-      const sin = Math.sin(rotationRadians)
-
       const centerX = viewportCenterX + offset.x
       const centerY = viewportCenterY + offset.y
 
-      const dx = focalPoint.x - centerX
-      const dy = focalPoint.y - centerY
-
-      const invCos = cos
-      const invSin = -sin
-
-      const rotatedX = dx * invCos - dy * invSin
-      const rotatedY = dx * invSin + dy * invCos
-
       const scaleRatio = targetScale / currentScale
 
-      const nextRotatedX = rotatedX * scaleRatio
-      const nextRotatedY = rotatedY * scaleRatio
-
-      const nextDx = nextRotatedX * cos - nextRotatedY * sin
-      const nextDy = nextRotatedX * sin + nextRotatedY * cos
+      const nextDx = (focalPoint.x - centerX) * scaleRatio
+      const nextDy = (focalPoint.y - centerY) * scaleRatio
 
       const nextCenterX = focalPoint.x - nextDx
       const nextCenterY = focalPoint.y - nextDy
@@ -460,7 +456,7 @@ export function ReaderCanvas({
         y: nextCenterY - viewportCenterY
       }
     },
-    [baseScale, containerSize.height, containerSize.width, imageSize.height, imageSize.width, offset, rotation, zoom]
+    [baseScale, containerSize.height, containerSize.width, imageSize.height, imageSize.width, offset, zoom]
   )
 
   const handleWheel = useCallback(
@@ -288,6 +288,7 @@ export function ReaderOverlay({
       { value: 'fitWidth' as FitMode, label: 'Fit width', icon: <FitWidthGlyph /> },
       { value: 'fitHeight' as FitMode, label: 'Fit height', icon: <FitHeightGlyph /> },
       { value: 'fitContain' as FitMode, label: 'Contain', icon: <FitContainGlyph /> },
+      { value: 'fitSmart' as FitMode, label: 'Smart', icon: <FitContainGlyph /> },
       { value: 'fill' as FitMode, label: 'Fill', icon: <FitFillGlyph /> },
       { value: 'original' as FitMode, label: 'Original', icon: <FitOriginalGlyph /> }
     ],
@@ -2,8 +2,10 @@ import { invoke, convertFileSrc } from '@tauri-apps/api/core'
 
 import { createBrowserBridge } from './mock'
 import type {
+  FitMode,
   PageId,
   PageMeta,
+  PageRange,
   PerfStats,
   PrefetchPolicy,
   RenderParams,
@@ -14,12 +16,15 @@ import type {
 interface IpcBridge {
   openPath(path: string): Promise<SourceId>
   listPages(sourceId: SourceId): Promise<PageMeta[]>
+  listPagesRange(sourceId: SourceId, offset: number, limit: number): Promise<PageRange>
+  resolveFit(mode: FitMode, width: number, height: number): Promise<FitMode>
   getPageUrl(page: PageId, params: RenderParams): Promise<string>
   getThumbUrl(page: PageId, longest: number): Promise<string>
   prefetch(center: PageId, policy: PrefetchPolicy): Promise<RequestToken>
   cancel(token: RequestToken): Promise<void>
   saveProgress(sourceId: SourceId, page: number): Promise<void>
   queryProgress(sourceId: SourceId): Promise<number>
+  setLocale(locale: string): Promise<string>
   fetchStats(): Promise<PerfStats>
 }
 
@@ -62,6 +67,18 @@ export async function listPages(sourceId: SourceId): Promise<PageMeta[]> {
   return callBridge((activeBridge) => activeBridge.listPages(sourceId))
 }
 
+export async function listPagesRange(
+  sourceId: SourceId,
+  offset: number,
+  limit: number
+): Promise<PageRange> {
+  return callBridge((activeBridge) => activeBridge.listPagesRange(sourceId, offset, limit))
+}
+
+export async function resolveFit(mode: FitMode, width: number, height: number): Promise<FitMode> {
+  return callBridge((activeBridge) => activeBridge.resolveFit(mode, width, height))
+}
+
 export async function getPageUrl(page: PageId, params: RenderParams): Promise<string> {
   return callBridge((activeBridge) => activeBridge.getPageUrl(page, params))
 }
@@ -86,6 +103,10 @@ export async function queryProgress(sourceId: SourceId): Promise<number> {
   return callBridge((activeBridge) => activeBridge.queryProgress(sourceId))
 }
 
+export async function setLocale(locale: string): Promise<string> {
+  return callBridge((activeBridge) => activeBridge.setLocale(locale))
+}
+
 export async function fetchStats(): Promise<PerfStats> {
   return callBridge((activeBridge) => activeBridge.fetchStats())
 }
@@ -114,6 +135,12 @@ function createTauriBridge(): IpcBridge {
     async listPages(sourceId) {
       return invoke<PageMeta[]>('list_pages', { source_id: sourceId, sourceId })
     },
+    async listPagesRange(sourceId, offset, limit) {
+      return invoke<PageRange>('list_pages_range', { source_id: sourceId, sourceId, offset, limit })
+    },
+    async resolveFit(mode, width, height) {
+      return invoke<FitMode>('resolve_fit', { mode, width, height })
+    },
     async getPageUrl(page, params) {
       const path = await invoke<string>('get_page_url', { page, params })
       return convertFileSrc(path)
@@ -126,8 +153,8 @@ function createTauriBridge(): IpcBridge {
       await invoke<void>('prefetch', { center, policy })
       return buildPrefetchToken(center)
     },
-    async cancel(token) {
-      await invoke<void>('cancel', { token })
+    async cancel() {
+      await invoke<void>('cancel_prefetch')
     },
    async saveProgress(sourceId, page) {
       await invoke<void>('save_progress', { source_id: sourceId, sourceId, page })
@@ -135,6 +162,9 @@ function createTauriBridge(): IpcBridge {
     async queryProgress(sourceId) {
       return invoke<number>('query_progress', { source_id: sourceId, sourceId })
     },
+    async setLocale(locale) {
+      return invoke<string>('set_locale', { locale })
+    },
     async fetchStats() {
       return invoke<PerfStats>('stats')
     }
@@ -1,6 +1,8 @@
 import type {
+  FitMode,
   PageId,
   PageMeta,
+  PageRange,
   PerfStats,
   PrefetchPolicy,
   RenderParams,
@@ -25,12 +27,15 @@ interface SourceState {
 interface BrowserBridge {
   openPath(path: string): Promise<SourceId>
   listPages(sourceId: SourceId): Promise<PageMeta[]>
+  listPagesRange(sourceId: SourceId, offset: number, limit: number): Promise<PageRange>
+  resolveFit(mode: FitMode, width: number, height: number): Promise<FitMode>
   getPageUrl(page: PageId, params: RenderParams): Promise<string>
   getThumbUrl(page: PageId, longest: number): Promise<string>
   prefetch(center: PageId, policy: PrefetchPolicy): Promise<RequestToken>
   cancel(token: RequestToken): Promise<void>
   saveProgress(sourceId: SourceId, page: number): Promise<void>
   queryProgress(sourceId: SourceId): Promise<number>
+  setLocale(locale: string): Promise<string>
   fetchStats(): Promise<PerfStats>
 }
 
@@ -49,6 +54,9 @@ function generatePages(sourceId: SourceId, count = 1200): PageMeta[] {
     return {
       id: { sourceId, index },
       relPath: `demo-bundle/page_${String(index + 1).padStart(4, '0')}.png`,
+      mime: 'image/png',
+      sizeBytes: 0,
+      modified: null,
       width: baseWidth,
       height: baseHeight,
       isDoubleSpread
@@ -101,6 +109,21 @@ export function createBrowserBridge(): BrowserBridge {
       const pages = source.pages.map((page) => ({ ...page, id: { ...page.id } }))
       return Promise.resolve(pages)
     },
+    listPagesRange(sourceId, offset, limit) {
+      const source = state.sourcesById.get(sourceId)
+      if (!source) {
+        return Promise.reject(new Error('unknown source'))
+      }
+      const start = Math.min(Math.max(offset, 0), source.pages.length)
+      const pages = source.pages
+        .slice(start, start + Math.max(limit, 0))
+        .map((page) => ({ ...page, id: { ...page.id } }))
+      return Promise.resolve({ total: source.pages.length, offset: start, pages })
+    },
+    resolveFit(mode) {
+      // The placeholder pages are never tall strips.
+      return Promise.resolve(mode === 'fitSmart' ? 'fitContain' : mode)
+    },
     getPageUrl(page, _params) {
       void _params
       return Promise.resolve(encodeDataUrl(PLACEHOLDER_PAGE_BASE64, `page-${page.sourceId}-${page.index}`))
@@ -127,6 +150,10 @@ export function createBrowserBridge(): BrowserBridge {
     queryProgress(sourceId) {
       return Promise.resolve(state.progressBySource.get(sourceId) ?? 0)
     },
+    setLocale(locale) {
+      const language = locale.split(/[-_]/)[0].toLowerCase()
+      return Promise.resolve(['en', 'de', 'fr', 'ja'].includes(language) ? language : 'en')
+    },
     fetchStats() {
       const cachedPages = Array.from(state.sourcesById.values()).reduce(
         (sum, source) => sum + source.pages.length,
@@ -1,7 +1,11 @@
 export type SourceId = string
 export type RequestToken = string
 
-export type FitMode = 'fitWidth' | 'fitHeight' | 'fitContain' | 'original' | 'fill'
+export type FitMode = 'fitWidth' | 'fitHeight' | 'fitContain' | 'original' | 'fill' | 'fitSmart'
+
+export type ReadingDirection = 'leftToRight' | 'rightToLeft' | 'vertical'
+
+export type PageLayout = 'single' | 'dual' | 'webtoon'
 
 export interface PageId {
   sourceId: SourceId
@@ -11,11 +15,21 @@ export interface PageId {
 export interface PageMeta {
   id: PageId
   relPath: string
+  mime: string
+  sizeBytes: number
+  /** Milliseconds since the Unix epoch; for archive pages, when the archive was modified. */
+  modified: number | null
   width: number
   height: number
   isDoubleSpread: boolean
 }
 
+export interface PageRange {
+  total: number
+  offset: number
+  pages: PageMeta[]
+}
+
 export interface RenderParams {
   fit: FitMode
   viewportW: number
@@ -23,6 +37,8 @@ export interface RenderParams {
   scale: number
   rotation: number
   dpi: number
+  direction?: ReadingDirection
+  layout?: PageLayout
 }
 
 export interface PrefetchPolicy {
@@ -36,3 +52,22 @@ export interface PerfStats {
   cachedPages: number
   pendingPrefetch: number
 }
+
+export type ErrorCode =
+  | 'notFound'
+  | 'permissionDenied'
+  | 'unsupportedFormat'
+  | 'corrupt'
+  | 'locked'
+  | 'invalidInput'
+  | 'io'
+  | 'internal'
+
+/** Rejection value of every backend command. */
+export interface CommandError {
+  code: ErrorCode
+  message: string
+  /** Friendly message for `code` in the locale chosen with `setLocale`. */
+  userMessage: string
+  detail: string | null
+}
@@ -19,7 +19,6 @@ interface RenderMessage {
   baseScale: number
   scale: number
   offset: { x: number; y: number }
-  rotation: number
   devicePixelRatio: number
 }
 
@@ -76,7 +75,7 @@ function clearSurface() {
 }
 
 function performRender(params: RenderMessage) {
-  const { viewportWidth, viewportHeight, baseScale, scale, offset, rotation, devicePixelRatio } = params
+  const { viewportWidth, viewportHeight, baseScale, scale, offset, devicePixelRatio } = params
   lastViewportWidth = viewportWidth
   lastViewportHeight = viewportHeight
 
@@ -102,13 +101,9 @@ function performRender(params: RenderMessage) {
   const viewportCenterY = viewportHeight / 2
   const centerX = viewportCenterX + offset.x
   const centerY = viewportCenterY + offset.y
-  const rotationRadians = ((rotation % 360) * Math.PI) / 180
 
   context.save()
   context.translate(centerX, centerY)
-  if (rotationRadians !== 0) {
-    context.rotate(rotationRadians)
-  }
   context.drawImage(currentBitmap, -drawWidth / 2, -drawHeight / 2, drawWidth, drawHeight)
   context.restore()
 }